@@ -0,0 +1,129 @@
+use crate::reranker::RerankerConfig;
+use crate::search_engine::SearchEngine;
+use crate::search_index::{SearchOptions, SearchResult};
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Async facade over a `SearchEngine` for servers and editor plugins running on tokio.
+/// Each call runs on tokio's blocking thread pool, so ONNX inference and index IO never
+/// hold up the async worker threads. Cloning is cheap and shares the engine, including
+/// the reranker model `search` keeps loaded.
+#[derive(Clone)]
+pub struct AsyncSearchEngine {
+    engine: Arc<SearchEngine>,
+}
+
+impl AsyncSearchEngine {
+    pub fn new(engine: SearchEngine) -> Self {
+        Self {
+            engine: Arc::new(engine),
+        }
+    }
+
+    /// The wrapped engine, for calls that are cheap enough to make on an async thread
+    pub fn engine(&self) -> &SearchEngine {
+        &self.engine
+    }
+
+    /// Runs `f` with the engine on the blocking thread pool
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&SearchEngine) -> Result<T> + Send + 'static,
+    {
+        let engine = Arc::clone(&self.engine);
+        tokio::task::spawn_blocking(move || f(&engine))
+            .await
+            .map_err(|e| anyhow::anyhow!("Search engine task failed: {}", e))?
+    }
+
+    /// Async `SearchEngine::search`
+    pub async fn search(&self, query: &str, options: SearchOptions) -> Result<Vec<SearchResult>> {
+        let query = query.to_string();
+        self.run(move |engine| engine.search(&query, &options))
+            .await
+    }
+
+    /// Async `SearchEngine::search_with_reranker`
+    pub async fn search_with_reranker(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+        filetype: Option<&str>,
+        reranker_config: RerankerConfig,
+        context_lines: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let query = query.to_string();
+        let filetype = filetype.map(str::to_string);
+        self.run(move |engine| {
+            engine.search_with_reranker(
+                &query,
+                limit,
+                filetype.as_deref(),
+                reranker_config,
+                context_lines,
+            )
+        })
+        .await
+    }
+
+    /// Async `SearchEngine::search_with_options`
+    pub async fn search_with_options(
+        &self,
+        query: &str,
+        options: SearchOptions,
+        reranker_config: RerankerConfig,
+    ) -> Result<Vec<SearchResult>> {
+        let query = query.to_string();
+        self.run(move |engine| engine.search_with_options(&query, &options, reranker_config))
+            .await
+    }
+
+    /// Async `SearchEngine::ensure_index_updated`
+    pub async fn ensure_index_updated(&self) -> Result<()> {
+        self.run(SearchEngine::ensure_index_updated).await
+    }
+
+    /// Async `SearchEngine::rebuild_index`
+    pub async fn rebuild_index(&self) -> Result<()> {
+        self.run(SearchEngine::rebuild_index).await
+    }
+}
+
+impl From<SearchEngine> for AsyncSearchEngine {
+    fn from(engine: SearchEngine) -> Self {
+        Self::new(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_async_index_update_and_search() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "needle in a haystack").unwrap();
+        let engine = SearchEngine::builder(temp_dir.path())
+            .with_reranker_config(RerankerConfig {
+                enabled: false,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        let engine = AsyncSearchEngine::new(engine);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let results = runtime.block_on(async {
+            engine.rebuild_index().await?;
+            fs::write(temp_dir.path().join("more.txt"), "another needle").unwrap();
+            engine.ensure_index_updated().await?;
+            engine.search("needle", SearchOptions::default()).await
+        });
+        assert_eq!(results.unwrap().len(), 2);
+    }
+}
@@ -1,33 +1,167 @@
 use anyhow::Result;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
 use std::collections::HashMap;
 use std::path::Path;
 
 // Re-export from language_processor for now to avoid circular imports
-pub use crate::language_processor::{utils, ChunkType, CodeChunk, LanguageProcessor};
+use crate::config::ChunkingConfig;
+use crate::file_scanner::SNIFF_BYTES;
+pub use crate::language_processor::{
+    utils, ChunkType, CodeChunk, FileReferences, LanguageProcessor,
+};
 
-// Import Java language processor only
-use crate::languages::java::JavaProcessor;
+use crate::languages::{
+    BashProcessor, GroovyProcessor, HclProcessor, JavaProcessor, PerlProcessor, ProtoProcessor,
+    SqlProcessor,
+};
+
+/// Reads a source file as text with its byte order mark stripped and CRLF line endings
+/// converted to LF, so chunk boundaries, line numbers and snippet offsets agree no matter
+/// how the file was saved. Files that aren't UTF-8 are decoded, see `detect_encoding`.
+pub fn read_source<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
+    let bytes = std::fs::read(path)?;
+    Ok(decode_source(&bytes))
+}
+
+/// Decodes the bytes of a source file in the encoding `detect_encoding` finds, without
+/// its byte order mark. Bytes that aren't valid in that encoding become U+FFFD.
+pub fn decode_source(bytes: &[u8]) -> String {
+    let (encoding, bom_length) = detect_encoding(bytes);
+    let (content, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+    normalize_source(&content)
+}
+
+/// The encoding of a source file and the length of its byte order mark. Byte order marks
+/// tell UTF-8 and UTF-16 apart, and UTF-16 without one is recognized by the NUL bytes of
+/// its ASCII characters. Other files are UTF-8 when they are valid UTF-8, and otherwise
+/// taken to be Windows-1252, the superset of Latin-1 that legacy code is mostly saved in.
+pub fn detect_encoding(bytes: &[u8]) -> (&'static Encoding, usize) {
+    if let Some(found) = Encoding::for_bom(bytes) {
+        return found;
+    }
+    if let Some(encoding) = utf16_without_bom(bytes) {
+        return (encoding, 0);
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => (UTF_8, 0),
+        // A character cut off at the end, as in the sniffed start of a file, is fine
+        Err(e) if e.error_len().is_none() => (UTF_8, 0),
+        Err(_) => (WINDOWS_1252, 0),
+    }
+}
+
+/// UTF-16 text without a byte order mark has a NUL byte in most of its first code units,
+/// always in the same half, where binary files have them all over the place
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    let units = bytes[..bytes.len().min(SNIFF_BYTES)].chunks_exact(2);
+    let count = units.len();
+    let (mut high_first, mut high_last) = (0, 0);
+    for unit in units {
+        high_first += usize::from(unit[0] == 0);
+        high_last += usize::from(unit[1] == 0);
+    }
+    if count == 0 {
+        None
+    } else if high_last * 2 > count && high_first * 10 < count {
+        Some(UTF_16LE)
+    } else if high_first * 2 > count && high_last * 10 < count {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+pub fn normalize_source(content: &str) -> String {
+    content
+        .strip_prefix('\u{feff}')
+        .unwrap_or(content)
+        .replace("\r\n", "\n")
+}
 
 pub struct CodeChunker {
     processors: HashMap<String, Box<dyn LanguageProcessor>>,
+    /// Extension of the processor handling extensionless scripts, by `#!` interpreter
+    interpreters: HashMap<String, String>,
+    debug_trace: bool,
 }
 
 impl CodeChunker {
     pub fn new() -> Result<Self> {
-        let processors = vec![Box::new(JavaProcessor::new()?)]
-            .into_iter()
-            .flat_map(|processor| {
-                processor
-                    .get_file_extensions()
-                    .iter()
-                    .map(|ext| (ext.to_string(), processor.clone_box().unwrap()))
-                    .collect::<Vec<_>>()
-            })
-            .collect();
-
-        let chunker = Self { processors };
-
-        Ok(chunker)
+        Self::with_config(&ChunkingConfig::default())
+    }
+
+    /// Creates a chunker honoring per-language overrides from probe.yml:
+    /// disabled languages fall back to plain-text chunking and extra extensions
+    /// are routed to an existing language processor.
+    pub fn with_config(config: &ChunkingConfig) -> Result<Self> {
+        let available: Vec<Box<dyn LanguageProcessor>> = vec![
+            Box::new(JavaProcessor::new()?),
+            Box::new(BashProcessor::new()?),
+            Box::new(SqlProcessor::new()?),
+            Box::new(HclProcessor::new()?),
+            Box::new(ProtoProcessor::new()?),
+            Box::new(GroovyProcessor::new()?),
+            Box::new(PerlProcessor::new()?),
+        ];
+
+        let is_disabled = |language: &str| {
+            config
+                .disabled_languages
+                .iter()
+                .any(|disabled| disabled.eq_ignore_ascii_case(language))
+        };
+
+        let mut processors = HashMap::new();
+        let mut interpreters = HashMap::new();
+        for processor in available.iter() {
+            if is_disabled(processor.language_name()) {
+                continue;
+            }
+            for ext in processor.get_file_extensions() {
+                processors.insert(ext.to_string(), processor.clone_box()?);
+            }
+            if let Some(ext) = processor.get_file_extensions().first() {
+                for interpreter in processor.interpreters() {
+                    interpreters.insert(interpreter.to_string(), ext.to_string());
+                }
+            }
+        }
+
+        for (ext, language) in &config.extensions {
+            let processor = available
+                .iter()
+                .find(|processor| processor.language_name().eq_ignore_ascii_case(language))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Unknown language '{}' for extension '{}'", language, ext)
+                })?;
+            if is_disabled(processor.language_name()) {
+                continue;
+            }
+            processors.insert(
+                ext.trim_start_matches('.').to_string(),
+                processor.clone_box()?,
+            );
+        }
+
+        Ok(Self {
+            processors,
+            interpreters,
+            debug_trace: false,
+        })
+    }
+
+    /// Prints every produced chunk to stderr, useful for debugging chunk boundaries
+    pub fn with_debug_trace(mut self, debug_trace: bool) -> Self {
+        self.debug_trace = debug_trace;
+        self
+    }
+
+    /// Name of the language processor that handles the file, or None for plain-text chunking
+    pub fn language_for(&self, file_path: &Path) -> Option<&str> {
+        let extension = file_path.extension().and_then(|ext| ext.to_str())?;
+        self.processors
+            .get(extension)
+            .map(|processor| processor.language_name())
     }
 
     pub fn chunk_code_for_indexing(
@@ -35,11 +169,51 @@ impl CodeChunker {
         file_path: &Path,
         content: &str,
     ) -> Result<Vec<CodeChunk>> {
+        let chunks = self.chunk_code(file_path, content)?;
+
+        if self.debug_trace {
+            for chunk in &chunks {
+                eprintln!(
+                    "[chunker] {}: {:?} '{}' (lines {}-{})",
+                    file_path.display(),
+                    chunk.chunk_type,
+                    chunk.name,
+                    chunk.start_line + 1,
+                    chunk.end_line + 1
+                );
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Methods defined in the file and the calls they make, empty for languages without
+    /// a processor
+    pub fn extract_references(
+        &mut self,
+        file_path: &Path,
+        content: &str,
+    ) -> Result<FileReferences> {
         let extension = file_path
             .extension()
             .and_then(|ext| ext.to_str())
             .unwrap_or("");
 
+        match self.processors.get_mut(extension) {
+            Some(processor) => processor.extract_references(content),
+            None => Ok(FileReferences::default()),
+        }
+    }
+
+    fn chunk_code(&mut self, file_path: &Path, content: &str) -> Result<Vec<CodeChunk>> {
+        let extension = match file_path.extension().and_then(|ext| ext.to_str()) {
+            Some(extension) => extension,
+            // Scripts without an extension are recognized by their `#!` line
+            None => shebang_interpreter(content)
+                .and_then(|interpreter| self.interpreters.get(interpreter))
+                .map_or("", String::as_str),
+        };
+
         if let Some(processor) = self.processors.get_mut(extension) {
             processor.chunk_code(content)
         } else if !content.trim().is_empty() {
@@ -50,6 +224,7 @@ impl CodeChunker {
                 name: "file".to_string(),
                 content: content.to_string(),
                 declaration: "".to_string(),
+                doc: String::new(),
             }])
         } else {
             Ok(vec![])
@@ -57,6 +232,20 @@ impl CodeChunker {
     }
 }
 
+/// Name of the interpreter on a script's `#!` line, e.g. `bash` for `#!/bin/bash` and
+/// `#!/usr/bin/env bash`
+fn shebang_interpreter(content: &str) -> Option<&str> {
+    let line = content.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        // Skip options such as `env -S`
+        words.find(|word| !word.starts_with('-'))
+    } else {
+        Some(program)
+    }
+}
+
 impl Default for CodeChunker {
     fn default() -> Self {
         Self::new().unwrap()
@@ -1,17 +1,325 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use tantivy::tokenizer::Language;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub stemming: StemmingConfig,
+    #[serde(default)]
+    pub index: IndexConfig,
+    #[serde(default)]
+    pub chunking: ChunkingConfig,
+    #[serde(default)]
+    pub embeddings: EmbeddingsConfig,
+    #[serde(default)]
+    pub scoring: ScoringConfig,
+    #[serde(default)]
+    pub query: QueryConfig,
+    #[serde(default)]
+    pub duplicates: DuplicatesConfig,
+    /// Named sets of command-line flags, e.g. `api: "-t java -n 10"` used as `probe api <query>`
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    /// Saved searches with `{0}`, `{1}`, ... placeholders, e.g.
+    /// `handler-for: "name:{0}Handler --kind method"` run as `probe run handler-for Payment`
+    #[serde(default)]
+    pub recipes: HashMap<String, String>,
+    /// Additional indexes of the same directory with their own file filters and stemming,
+    /// selected with `--profile <name>` and stored under `<index dir>/<name>`
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StemmingConfig {
     pub language: String,
     pub enabled: bool,
+    /// Per-path stemmer languages, checked in order before falling back to `language`
+    #[serde(default)]
+    pub overrides: Vec<StemmingOverride>,
+}
+
+/// Stems files matching a gitignore-style glob (e.g. `docs/**/*.md`) with another language
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StemmingOverride {
+    pub pattern: String,
+    pub language: String,
+}
+
+/// A named index of the project, e.g. one of just the documentation stemmed as prose
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    /// Gitignore-style globs of the files to index, in addition to `index.include`
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Gitignore-style globs to exclude, in addition to `index.ignore`
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Replaces the top-level stemming settings
+    #[serde(default)]
+    pub stemming: Option<StemmingConfig>,
+}
+
+/// Settings controlling which files end up in the index
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexConfig {
+    /// Gitignore-style globs of the only files to index (default: every file)
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Gitignore-style globs for files and directories to exclude from indexing
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Where to store the index, relative to the project root (default: `.probe`)
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    /// Follow symlinked files and directories instead of skipping them
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Only index files git knows about: tracked files, plus untracked files that aren't
+    /// ignored
+    #[serde(default)]
+    pub git_files_only: bool,
+    /// URL (or path) of an index archive published with `probe index export`, downloaded
+    /// instead of indexing from scratch when there is no local index yet
+    #[serde(default)]
+    pub remote: Option<String>,
+    /// How searches tell which files changed since they were indexed
+    #[serde(default)]
+    pub change_detection: ChangeDetection,
+    /// Documents indexed between commits (default: 10000). Smaller batches keep more of an
+    /// interrupted run, larger ones index slightly faster.
+    #[serde(default)]
+    pub commit_batch_size: Option<usize>,
+    /// Memory budget of the index writer in MB (default: 50)
+    #[serde(default)]
+    pub writer_heap_mb: Option<usize>,
+    /// Rough cap on the memory indexing uses in MB, lowering `writer_heap_mb` to fit and
+    /// committing more often on repositories with large files (default: no cap)
+    #[serde(default)]
+    pub max_memory_mb: Option<usize>,
+    /// Threads chunking files while indexing (default: the number of CPUs)
+    #[serde(default)]
+    pub threads: Option<usize>,
+}
+
+impl IndexConfig {
+    pub fn commit_batch_size(&self) -> usize {
+        self.commit_batch_size
+            .unwrap_or(crate::search_index::DEFAULT_COMMIT_BATCH_SIZE)
+    }
+
+    pub fn writer_heap_bytes(&self) -> usize {
+        self.writer_heap_mb
+            .unwrap_or(crate::search_index::DEFAULT_WRITER_HEAP_MB)
+            * 1024
+            * 1024
+    }
+
+    pub fn memory_limit_bytes(&self) -> Option<usize> {
+        self.max_memory_mb.map(|mb| mb * 1024 * 1024)
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, usize::from))
+            .max(1)
+    }
+}
+
+/// How files are compared with the index metadata to find the ones to reindex
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeDetection {
+    /// Size and modification time, falling back to a content hash when only the
+    /// modification time differs, so `git checkout` and `touch` don't cause reindexing
+    #[default]
+    Hash,
+    /// Size and modification time only, never reading file contents
+    Mtime,
+}
+
+/// Settings controlling how files are split into chunks
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChunkingConfig {
+    /// Languages whose structural chunking is turned off, falling back to plain-text chunking
+    #[serde(default)]
+    pub disabled_languages: Vec<String>,
+    /// Extra file extensions mapped to an existing language processor (e.g. `jav: java`)
+    #[serde(default)]
+    pub extensions: HashMap<String, String>,
+}
+
+/// Field boosts and score multipliers used to rank full-text results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoringConfig {
+    /// Boost of matches in chunk declarations (signatures, doc comments)
+    pub declaration_boost: f32,
+    /// Boost of matches in chunk names
+    pub name_boost: f32,
+    /// Boost of matches in the doc comments of chunks, which are also part of declarations
+    /// for most languages
+    pub doc_boost: f32,
+    /// Boost of matches in chunk bodies
+    pub body_boost: f32,
+    /// Score multiplier for files whose path contains "test"; above 1 up-ranks them
+    pub test_penalty: f32,
+    /// Score multiplier for class, interface and struct chunks
+    pub type_penalty: f32,
+    /// Score multipliers for files matching gitignore-style globs, all matching rules apply
+    pub downrank_paths: Vec<PathWeight>,
+    /// Days after which the boost of a recently changed file halves; no boost when unset
+    pub recency_half_life_days: Option<f32>,
+    /// Combine the full-text scores with reranker and embedding scores on a common [0, 1]
+    /// scale; when unset, reranker scores replace full-text ones and `--hybrid` fuses ranks
+    pub fusion: Option<FusionConfig>,
+}
+
+/// How full-text scores are combined with the scores of the reranker or, for `--hybrid`
+/// searches, the embedding similarities
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FusionConfig {
+    pub strategy: FusionStrategy,
+    /// Share (0-1) of the full-text score in `rrf` and `weighted_sum` fusion, the other
+    /// ranking gets the rest
+    pub full_text_weight: f32,
+}
+
+impl FusionConfig {
+    /// Weights of the full-text ranking and the other one
+    pub fn weights(&self) -> [f32; 2] {
+        let full_text = self.full_text_weight.clamp(0.0, 1.0);
+        [full_text, 1.0 - full_text]
+    }
+}
+
+impl Default for FusionConfig {
+    fn default() -> Self {
+        Self {
+            strategy: FusionStrategy::default(),
+            full_text_weight: 0.5,
+        }
+    }
+}
+
+/// How the rankings of several scorers are combined, see `scoring::fuse`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FusionStrategy {
+    /// Reciprocal rank fusion: only the ranks count, not how far apart the scores are
+    #[default]
+    Rrf,
+    /// Weighted sum of the scores, each scaled to [0, 1]
+    WeightedSum,
+    /// The best of the scores, each scaled to [0, 1]
+    Max,
+}
+
+/// Multiplies the score of files matching `pattern` (e.g. `vendor/**`) by `factor`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathWeight {
+    pub pattern: String,
+    pub factor: f32,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            declaration_boost: 3.0,
+            name_boost: 2.5,
+            doc_boost: 1.5,
+            body_boost: 1.0,
+            test_penalty: 0.5,
+            type_penalty: 0.6,
+            downrank_paths: Vec::new(),
+            recency_half_life_days: None,
+            fusion: None,
+        }
+    }
+}
+
+/// How the words of a query are expanded before searching
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryConfig {
+    /// Also match the parts of camelCase and snake_case query words on their own
+    pub split_identifiers: bool,
+    /// Also match what common abbreviations stand for, e.g. `config` for `cfg`
+    pub builtin_abbreviations: bool,
+    /// Abbreviations and what they stand for (e.g. `lc: lifecycle`), replacing built-in ones
+    pub abbreviations: HashMap<String, String>,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self {
+            split_identifiers: true,
+            builtin_abbreviations: false,
+            abbreviations: HashMap::new(),
+        }
+    }
+}
+
+/// Near-identical chunks, such as generated or copy-pasted code, that would otherwise
+/// fill whole pages of results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DuplicatesConfig {
+    /// Store a MinHash fingerprint of every chunk while indexing and let searches act on
+    /// near-duplicates of better results
+    pub enabled: bool,
+    pub action: DuplicateAction,
+    /// Minimum estimated similarity (0-1) of two chunks' tokens to treat them as duplicates
+    pub similarity: f64,
+    /// Chunks shorter than this many lines are never treated as duplicates
+    pub min_lines: usize,
+    /// Score multiplier of duplicates when `action` is `downrank`
+    pub penalty: f32,
+}
+
+impl Default for DuplicatesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            action: DuplicateAction::default(),
+            similarity: 0.9,
+            min_lines: 5,
+            penalty: 0.3,
+        }
+    }
+}
+
+/// What searches do with a near-duplicate of a better-ranked result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateAction {
+    /// Leave it out, listing its path on the result it duplicates
+    #[default]
+    Collapse,
+    /// Keep it with its score multiplied by `penalty`
+    Downrank,
+}
+
+/// Settings for the chunk embeddings used by `--hybrid` searches
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbeddingsConfig {
+    /// Embed every chunk at index time, which makes indexing considerably slower
+    #[serde(default)]
+    pub enabled: bool,
+    /// Embedding model name (default: `all-minilm-l6-v2`)
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl EmbeddingsConfig {
+    pub fn model_name(&self) -> &str {
+        self.model
+            .as_deref()
+            .unwrap_or(crate::embeddings::DEFAULT_EMBEDDING_MODEL)
+    }
 }
 
 impl Default for Config {
@@ -20,7 +328,17 @@ impl Default for Config {
             stemming: StemmingConfig {
                 language: "english".to_string(),
                 enabled: true,
+                overrides: Vec::new(),
             },
+            index: IndexConfig::default(),
+            chunking: ChunkingConfig::default(),
+            embeddings: EmbeddingsConfig::default(),
+            scoring: ScoringConfig::default(),
+            query: QueryConfig::default(),
+            duplicates: DuplicatesConfig::default(),
+            aliases: HashMap::new(),
+            recipes: HashMap::new(),
+            profiles: HashMap::new(),
         }
     }
 }
@@ -41,29 +359,76 @@ impl Config {
         }
     }
 
-    pub fn get_language(&self) -> Result<Language> {
-        if !self.stemming.enabled {
-            return Ok(Language::English); // Default fallback, but stemming will be disabled
+    /// The configuration of the index named `profile`: its file filters added to the
+    /// `index` section and its stemming settings, if any, replacing the top-level ones
+    pub fn for_profile(&self, profile: &str) -> Result<Config> {
+        let settings = self.profiles.get(profile).ok_or_else(|| {
+            let mut names: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            anyhow::anyhow!(
+                "Unknown profile '{}', probe.yml defines: {}",
+                profile,
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            )
+        })?;
+        if !is_profile_name(profile) {
+            return Err(anyhow::anyhow!(
+                "Invalid profile name '{}', use letters, digits, '-' and '_'",
+                profile
+            ));
         }
 
-        match self.stemming.language.to_lowercase().as_str() {
-            "english" | "en" => Ok(Language::English),
-            "french" | "fr" => Ok(Language::French),
-            "german" | "de" => Ok(Language::German),
-            "italian" | "it" => Ok(Language::Italian),
-            "portuguese" | "pt" => Ok(Language::Portuguese),
-            "spanish" | "es" => Ok(Language::Spanish),
-            "dutch" | "nl" => Ok(Language::Dutch),
-            "danish" | "da" => Ok(Language::Danish),
-            "finnish" | "fi" => Ok(Language::Finnish),
-            "hungarian" | "hu" => Ok(Language::Hungarian),
-            "norwegian" | "no" => Ok(Language::Norwegian),
-            "romanian" | "ro" => Ok(Language::Romanian),
-            "russian" | "ru" => Ok(Language::Russian),
-            "swedish" | "sv" => Ok(Language::Swedish),
-            "tamil" | "ta" => Ok(Language::Tamil),
-            "turkish" | "tr" => Ok(Language::Turkish),
-            other => Err(anyhow::anyhow!("Unsupported language: {}", other)),
+        let mut config = self.clone();
+        config.index.include.extend_from_slice(&settings.include);
+        config.index.ignore.extend_from_slice(&settings.ignore);
+        if let Some(stemming) = &settings.stemming {
+            config.stemming = stemming.clone();
         }
+        Ok(config)
+    }
+
+    /// Stemmer language of the index. It's still used with stemming turned off, by queries
+    /// that turn it back on, falling back to English if it's not one probe knows.
+    pub fn get_language(&self) -> Result<Language> {
+        match parse_language(&self.stemming.language) {
+            Err(_) if !self.stemming.enabled => Ok(Language::English),
+            language => language,
+        }
+    }
+}
+
+/// Profile indexes are directories inside the main index directory, so their names must
+/// not be paths or clash with the index's own files, which all have an extension
+pub fn is_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Parses a stemmer language given by name or ISO 639-1 code (e.g. "german" or "de")
+pub fn parse_language(name: &str) -> Result<Language> {
+    match name.to_lowercase().as_str() {
+        "english" | "en" => Ok(Language::English),
+        "french" | "fr" => Ok(Language::French),
+        "german" | "de" => Ok(Language::German),
+        "italian" | "it" => Ok(Language::Italian),
+        "portuguese" | "pt" => Ok(Language::Portuguese),
+        "spanish" | "es" => Ok(Language::Spanish),
+        "dutch" | "nl" => Ok(Language::Dutch),
+        "danish" | "da" => Ok(Language::Danish),
+        "finnish" | "fi" => Ok(Language::Finnish),
+        "hungarian" | "hu" => Ok(Language::Hungarian),
+        "norwegian" | "no" => Ok(Language::Norwegian),
+        "romanian" | "ro" => Ok(Language::Romanian),
+        "russian" | "ru" => Ok(Language::Russian),
+        "swedish" | "sv" => Ok(Language::Swedish),
+        "tamil" | "ta" => Ok(Language::Tamil),
+        "turkish" | "tr" => Ok(Language::Turkish),
+        other => Err(anyhow::anyhow!("Unsupported language: {}", other)),
     }
 }
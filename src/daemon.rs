@@ -0,0 +1,243 @@
+use crate::config::Config;
+use crate::reranker::RerankerConfig;
+use crate::search_engine::SearchEngine;
+use crate::search_index::{SearchOptions, SearchResult};
+use crate::syntax::strip_highlights;
+use crate::terminal;
+use crate::verbosity::Verbosity;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long a client may take to send its request before the connection is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a client waits for the daemon's answer, which includes updating the index,
+/// before searching by itself
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A search sent by a `probe` invocation to `probe daemon`, as one line of JSON
+#[derive(Debug, Serialize, Deserialize)]
+struct Request {
+    query: String,
+    options: SearchOptions,
+    /// `settings_key` of the client, which must match the daemon's
+    settings: String,
+}
+
+/// The daemon's answer to a `Request`, as one line of JSON
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Results(Vec<SearchResult>),
+    Error(String),
+    /// The daemon was started with other settings (or another probe version), so the
+    /// client has to search by itself
+    OtherSettings,
+}
+
+/// Identifies everything that changes search results besides the query and its options:
+/// the probe version, probe.yml and the reranker settings. Maps are serialized with sorted
+/// keys, so equal settings give equal keys in every process.
+pub(crate) fn settings_key(config: &Config, reranker_config: &RerankerConfig) -> String {
+    let custom_model = reranker_config.custom_model.as_deref().and_then(|name| {
+        reranker_config
+            .probe_config
+            .as_ref()?
+            .get_custom_model(name)
+    });
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "config": config,
+        "reranker": {
+            "enabled": reranker_config.enabled,
+            "model": format!("{:?}", reranker_config.model),
+            "custom_model": reranker_config.custom_model,
+            "custom_model_config": custom_model,
+            "min_candidates": reranker_config.min_candidates,
+            "candidate_multiplier": reranker_config.candidate_multiplier,
+            "score_cutoff": reranker_config.score_cutoff,
+        },
+    })
+    .to_string()
+}
+
+/// Answers one search on `stream` with the engine's loaded reranker, updating the index
+/// first like an in-process search would. Returns the query for logging.
+pub(crate) fn handle_connection(
+    engine: &SearchEngine,
+    stream: UnixStream,
+    settings: &str,
+) -> Result<String> {
+    // Accepted sockets inherit non-blocking mode from the listener on some platforms
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let (query, response) = match serde_json::from_str::<Request>(&line) {
+        Ok(request) if request.settings != settings => (request.query, Response::OtherSettings),
+        Ok(request) => {
+            let results = engine
+                .ensure_index_updated()
+                .and_then(|()| engine.search(&request.query, &request.options));
+            let response = match results {
+                Ok(results) => Response::Results(results),
+                Err(e) => Response::Error(format!("{e:#}")),
+            };
+            (request.query, response)
+        }
+        Err(e) => (
+            String::new(),
+            Response::Error(format!("Malformed request: {e}")),
+        ),
+    };
+    write_line(&stream, &response)?;
+    Ok(query)
+}
+
+/// Sends a search to the daemon listening on `socket`. `None` when no daemon is running, it
+/// runs with other settings or it fails to answer, in which case the caller searches by
+/// itself.
+pub(crate) fn search(
+    socket: &Path,
+    query: &str,
+    options: &SearchOptions,
+    settings: String,
+    verbosity: Verbosity,
+) -> Result<Option<Vec<SearchResult>>> {
+    let Ok(stream) = UnixStream::connect(socket) else {
+        return Ok(None);
+    };
+    let request = Request {
+        query: query.to_string(),
+        options: options.clone(),
+        settings,
+    };
+
+    // A hung, crashed or failing daemon shouldn't keep this process from searching
+    match exchange(&stream, &request) {
+        Ok(Response::Results(mut results)) => {
+            // The daemon highlights matches whether or not this process shows colors
+            if !terminal::use_colors() {
+                for result in &mut results {
+                    result.snippet = strip_highlights(&result.snippet).0;
+                }
+            }
+            Ok(Some(results))
+        }
+        Ok(_) => Ok(None), // Other settings
+        Err(e) => {
+            if verbosity.is_verbose() {
+                eprintln!(
+                    "Warning: the daemon on {} failed to search: {e:#}. Searching without it.",
+                    socket.display()
+                );
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Sends `request` on `stream` and reads the daemon's response, an error if the daemon
+/// reports one
+fn exchange(stream: &UnixStream, request: &Request) -> Result<Response> {
+    stream.set_read_timeout(Some(SEARCH_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    write_line(stream, request)?;
+
+    let mut line = String::new();
+    if BufReader::new(stream).read_line(&mut line)? == 0 {
+        return Err(anyhow!("the daemon closed the connection"));
+    }
+    match serde_json::from_str(&line)? {
+        Response::Error(message) => Err(anyhow!(message)),
+        response => Ok(response),
+    }
+}
+
+/// Whether a daemon is accepting connections on `socket`, rather than the socket file
+/// being left behind by one that didn't shut down
+pub(crate) fn is_running(socket: &Path) -> bool {
+    UnixStream::connect(socket).is_ok()
+}
+
+fn write_line<T: Serialize>(mut stream: &UnixStream, value: &T) -> Result<()> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_key_changes_with_settings() {
+        let config = Config::default();
+        let reranker_config = RerankerConfig::default();
+        let key = settings_key(&config, &reranker_config);
+        assert_eq!(key, settings_key(&config.clone(), &reranker_config.clone()));
+
+        let no_rerank = RerankerConfig {
+            enabled: false,
+            ..RerankerConfig::default()
+        };
+        assert_ne!(key, settings_key(&config, &no_rerank));
+
+        let mut scoring = Config::default();
+        scoring.scoring.test_penalty = 1.0;
+        assert_ne!(key, settings_key(&scoring, &reranker_config));
+    }
+
+    #[test]
+    fn test_search_without_daemon() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let socket = temp_dir.path().join("daemon.sock");
+        assert!(!is_running(&socket));
+        let results = search(
+            &socket,
+            "query",
+            &SearchOptions::default(),
+            String::new(),
+            Verbosity::Quiet,
+        );
+        assert!(results.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_search_falls_back_when_daemon_fails() {
+        use std::os::unix::net::UnixListener;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let socket = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        // A daemon that dies after reading the request, then one that answers garbage
+        let daemon = std::thread::spawn(move || {
+            for answer in ["", "not json\n"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                stream.write_all(answer.as_bytes()).unwrap();
+            }
+        });
+
+        for _ in 0..2 {
+            let results = search(
+                &socket,
+                "query",
+                &SearchOptions::default(),
+                String::new(),
+                Verbosity::Quiet,
+            );
+            assert!(results.unwrap().is_none());
+        }
+        daemon.join().unwrap();
+    }
+}
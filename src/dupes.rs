@@ -0,0 +1,397 @@
+use crate::config::{DuplicateAction, DuplicatesConfig};
+use crate::search_index::SearchResult;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+/// Tokens per shingle. Short enough to survive renamed variables, long enough that
+/// unrelated functions sharing common boilerplate don't look alike.
+const SHINGLE_SIZE: usize = 5;
+/// MinHash signature length, split into `BANDS` bands for locality-sensitive hashing
+const SIGNATURE_SIZE: usize = 64;
+const BANDS: usize = 16;
+const ROWS_PER_BAND: usize = SIGNATURE_SIZE / BANDS;
+
+#[derive(Debug, Clone)]
+pub struct DupesOptions {
+    /// Minimum estimated Jaccard similarity of two chunks' token shingles, between 0 and 1
+    pub threshold: f64,
+    /// Chunks shorter than this many lines are ignored
+    pub min_lines: usize,
+}
+
+impl Default for DupesOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.85,
+            min_lines: 5,
+        }
+    }
+}
+
+/// Where a chunk came from, with 0-based inclusive line numbers like `CodeChunk`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkLocation {
+    pub path: PathBuf,
+    pub name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug)]
+pub struct DuplicateCluster {
+    pub chunks: Vec<ChunkLocation>,
+    /// Lowest estimated similarity among the pairs that linked the cluster together
+    pub similarity: f64,
+}
+
+/// Groups near-identical chunks. Each chunk's token shingles are summarized by a MinHash
+/// signature and only chunks sharing a band of their signature are compared, so this stays
+/// close to linear in the number of chunks. Clusters are sorted largest first.
+pub fn find_duplicates(
+    chunks: Vec<(ChunkLocation, String)>,
+    options: &DupesOptions,
+) -> Vec<DuplicateCluster> {
+    let (locations, signatures): (Vec<_>, Vec<_>) = chunks
+        .into_iter()
+        .filter(|(location, _)| location.end_line + 1 - location.start_line >= options.min_lines)
+        .filter_map(|(location, text)| Some((location, signature(&text)?)))
+        .unzip();
+
+    let mut buckets: HashMap<(usize, &[u64]), Vec<usize>> = HashMap::new();
+    for (i, signature) in signatures.iter().enumerate() {
+        for (band, rows) in signature.chunks(ROWS_PER_BAND).enumerate() {
+            buckets.entry((band, rows)).or_default().push(i);
+        }
+    }
+
+    let mut clusters = UnionFind::new(locations.len());
+    let mut similarity = vec![1.0f64; locations.len()];
+    let mut compared = std::collections::HashSet::new();
+    for members in buckets.values() {
+        for (position, &a) in members.iter().enumerate() {
+            for &b in &members[position + 1..] {
+                if !compared.insert((a, b)) {
+                    continue;
+                }
+                let estimate = estimate_similarity(&signatures[a], &signatures[b]);
+                if estimate >= options.threshold {
+                    clusters.union(a, b);
+                    similarity[a] = similarity[a].min(estimate);
+                    similarity[b] = similarity[b].min(estimate);
+                }
+            }
+        }
+    }
+
+    let mut grouped: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..locations.len() {
+        grouped.entry(clusters.find(i)).or_default().push(i);
+    }
+    let mut result: Vec<DuplicateCluster> = grouped
+        .into_values()
+        .filter(|members| members.len() > 1)
+        .map(|members| {
+            let mut chunks: Vec<_> = members.iter().map(|&i| locations[i].clone()).collect();
+            chunks.sort_by(|a, b| (&a.path, a.start_line).cmp(&(&b.path, b.start_line)));
+            DuplicateCluster {
+                chunks,
+                similarity: members.iter().map(|&i| similarity[i]).fold(1.0, f64::min),
+            }
+        })
+        .collect();
+    result.sort_by(|a, b| {
+        b.chunks.len().cmp(&a.chunks.len()).then_with(|| {
+            let first = |cluster: &DuplicateCluster| {
+                (cluster.chunks[0].path.clone(), cluster.chunks[0].start_line)
+            };
+            first(a).cmp(&first(b))
+        })
+    });
+    result
+}
+
+/// Drops or down-ranks search results that are near-duplicates of a better-ranked one, as
+/// `config.action` says. `results` come best first, each with the fingerprint stored for
+/// it at index time (see `fingerprint`); collapsed results are listed in the `duplicates`
+/// of the result they duplicate.
+pub(crate) fn resolve_duplicates(
+    results: Vec<(SearchResult, Option<Vec<u64>>)>,
+    config: &DuplicatesConfig,
+) -> Vec<SearchResult> {
+    let mut kept: Vec<(SearchResult, Option<Vec<u64>>)> = Vec::with_capacity(results.len());
+    let mut downranked = Vec::new();
+    for (mut result, signature) in results {
+        let lines = match (result.start_line, result.end_line) {
+            (Some(start), Some(end)) => (end + 1).saturating_sub(start),
+            _ => 0,
+        };
+        let signature = signature.filter(|_| lines >= config.min_lines);
+        let original = signature.as_ref().and_then(|signature| {
+            kept.iter().position(|(other, other_signature)| {
+                other_signature.as_ref().is_some_and(|other_signature| {
+                    estimate_similarity(other_signature, signature) >= config.similarity
+                }) && !nested(other, &result)
+            })
+        });
+
+        match (original, config.action) {
+            (None, _) => kept.push((result, signature)),
+            (Some(original), DuplicateAction::Collapse) => {
+                let original = &mut kept[original].0;
+                if result.path != original.path && !original.duplicates.contains(&result.path) {
+                    original.duplicates.push(result.path);
+                }
+            }
+            (Some(_), DuplicateAction::Downrank) => {
+                result.score *= config.penalty;
+                downranked.push(result);
+            }
+        }
+    }
+
+    let mut results: Vec<_> = kept
+        .into_iter()
+        .map(|(result, _)| result)
+        .chain(downranked)
+        .collect();
+    results.sort_by(SearchResult::rank_order);
+    results
+}
+
+/// Whether two results are overlapping chunks of one file, like a class and its method,
+/// rather than copies of each other
+fn nested(a: &SearchResult, b: &SearchResult) -> bool {
+    let overlapping = match (a.start_line, a.end_line, b.start_line, b.end_line) {
+        (Some(a_start), Some(a_end), Some(b_start), Some(b_end)) => {
+            a_start <= b_end && b_start <= a_end
+        }
+        _ => false,
+    };
+    a.path == b.path && overlapping
+}
+
+/// The MinHash signature of a chunk's text as stored in the index, `None` for chunks too
+/// short to shingle
+pub(crate) fn fingerprint(text: &str) -> Option<Vec<u8>> {
+    let signature = signature(text)?;
+    Some(signature.iter().flat_map(|min| min.to_le_bytes()).collect())
+}
+
+/// Reads a signature stored by `fingerprint`, `None` if it has the wrong length
+pub(crate) fn parse_fingerprint(bytes: &[u8]) -> Option<Vec<u64>> {
+    if bytes.len() != SIGNATURE_SIZE * 8 {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(8)
+            .map(|min| u64::from_le_bytes(min.try_into().expect("chunks are 8 bytes")))
+            .collect(),
+    )
+}
+
+/// Identifiers, numbers and individual punctuation characters; whitespace and layout
+/// don't affect similarity
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut word_start = None;
+    for (i, c) in text.char_indices() {
+        if c.is_alphanumeric() || c == '_' {
+            word_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = word_start.take() {
+            tokens.push(&text[start..i]);
+        }
+        if !c.is_whitespace() {
+            tokens.push(&text[i..i + c.len_utf8()]);
+        }
+    }
+    if let Some(start) = word_start {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+/// MinHash signature over the chunk's token shingles, `None` for chunks too short to shingle
+fn signature(text: &str) -> Option<Vec<u64>> {
+    let tokens = tokens(text);
+    if tokens.len() < SHINGLE_SIZE {
+        return None;
+    }
+
+    let mut signature = vec![u64::MAX; SIGNATURE_SIZE];
+    for shingle in tokens.windows(SHINGLE_SIZE) {
+        let mut hasher = DefaultHasher::new();
+        shingle.hash(&mut hasher);
+        let hash = hasher.finish();
+        for (i, min) in signature.iter_mut().enumerate() {
+            *min = (*min).min(permute(hash, i as u64));
+        }
+    }
+    Some(signature)
+}
+
+/// One of `SIGNATURE_SIZE` independent hash functions, derived from a shingle hash with
+/// the splitmix64 finalizer
+fn permute(hash: u64, seed: u64) -> u64 {
+    let mut z = hash ^ seed.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn estimate_similarity(a: &[u64], b: &[u64]) -> f64 {
+    let equal = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    equal as f64 / a.len() as f64
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(size: usize) -> Self {
+        Self {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        // Point the whole path at the root so later lookups are short
+        let mut node = i;
+        while self.parents[node] != root {
+            node = std::mem::replace(&mut self.parents[node], root);
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents[b] = a;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(path: &str, start_line: usize, text: &str) -> (ChunkLocation, String) {
+        (
+            ChunkLocation {
+                path: PathBuf::from(path),
+                name: String::new(),
+                start_line,
+                end_line: start_line + text.lines().count() - 1,
+            },
+            text.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_find_duplicates_clusters_near_identical_chunks() {
+        let original = "int total(int[] values) {\n    int sum = 0;\n    for (int value : values) {\n        sum += value;\n    }\n    return sum;\n}";
+        let reformatted = "int total(int[] values) {\n  int sum = 0;\n  for (int value : values) { sum += value; }\n  return sum;\n}\n\n";
+        let unrelated = "String greet(String name) {\n    if (name == null) {\n        throw new IllegalArgumentException();\n    }\n    return \"Hello \" + name;\n}";
+
+        let clusters = find_duplicates(
+            vec![
+                chunk("b/Stats.java", 10, reformatted),
+                chunk("a/Math.java", 3, original),
+                chunk("c/Greeter.java", 1, unrelated),
+            ],
+            &DupesOptions::default(),
+        );
+
+        assert_eq!(clusters.len(), 1);
+        let paths: Vec<_> = clusters[0]
+            .chunks
+            .iter()
+            .map(|chunk| chunk.path.to_str().unwrap())
+            .collect();
+        assert_eq!(paths, vec!["a/Math.java", "b/Stats.java"]);
+        assert!(clusters[0].similarity >= 0.85);
+    }
+
+    #[test]
+    fn test_find_duplicates_ignores_short_chunks() {
+        let text = "int one() {\n    return 1;\n}";
+        let clusters = find_duplicates(
+            vec![chunk("a.java", 0, text), chunk("b.java", 0, text)],
+            &DupesOptions::default(),
+        );
+        assert!(clusters.is_empty());
+    }
+
+    fn result(path: &str, score: f32, start_line: usize, text: &str) -> SearchResult {
+        SearchResult {
+            path: PathBuf::from(path),
+            score,
+            snippet: text.to_string(),
+            chunk_type: Some("Method".to_string()),
+            chunk_name: None,
+            start_line: Some(start_line),
+            end_line: Some(start_line + 6),
+            snippet_line: None,
+            fuzzy: false,
+            duplicates: Vec::new(),
+        }
+    }
+
+    fn with_fingerprint(result: SearchResult) -> (SearchResult, Option<Vec<u64>>) {
+        let signature = parse_fingerprint(&fingerprint(&result.snippet).unwrap());
+        (result, signature)
+    }
+
+    #[test]
+    fn test_resolve_duplicates_collapses_or_downranks_copies() {
+        let original = "int total(int[] values) {\n    int sum = 0;\n    for (int value : values) {\n        sum += value;\n    }\n    return sum;\n}";
+        let unrelated = "String greet(String name) {\n    if (name == null) {\n        throw new IllegalArgumentException();\n    }\n    return \"Hello \" + name;\n}";
+        let results = || {
+            vec![
+                with_fingerprint(result("a/Math.java", 3.0, 0, original)),
+                with_fingerprint(result("gen/Math.java", 2.0, 0, original)),
+                with_fingerprint(result("a/Greeter.java", 1.0, 0, unrelated)),
+                with_fingerprint(result("gen/Copy.java", 0.5, 10, original)),
+            ]
+        };
+
+        let collapsed = resolve_duplicates(results(), &DuplicatesConfig::default());
+        let paths: Vec<_> = collapsed.iter().map(|r| r.path.to_str().unwrap()).collect();
+        assert_eq!(paths, vec!["a/Math.java", "a/Greeter.java"]);
+        assert_eq!(
+            collapsed[0].duplicates,
+            vec![
+                PathBuf::from("gen/Math.java"),
+                PathBuf::from("gen/Copy.java")
+            ]
+        );
+
+        let config = DuplicatesConfig {
+            action: DuplicateAction::Downrank,
+            ..Default::default()
+        };
+        let downranked = resolve_duplicates(results(), &config);
+        let ranked: Vec<_> = downranked
+            .iter()
+            .map(|r| (r.path.to_str().unwrap(), r.score))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                ("a/Math.java", 3.0),
+                ("a/Greeter.java", 1.0),
+                ("gen/Math.java", 2.0 * 0.3),
+                ("gen/Copy.java", 0.5 * 0.3),
+            ]
+        );
+    }
+}
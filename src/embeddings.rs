@@ -0,0 +1,203 @@
+use crate::paths;
+use crate::search_index::ChunkText;
+use anyhow::{Context, Result};
+use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Chunk vectors, stored next to the index metadata
+pub const VECTORS_FILE: &str = "vectors.bin";
+
+pub const DEFAULT_EMBEDDING_MODEL: &str = "all-minilm-l6-v2";
+
+/// Parse embedding model from string
+pub fn parse_embedding_model(model_str: &str) -> Result<EmbeddingModel> {
+    match model_str.to_lowercase().as_str() {
+        "all-minilm-l6-v2" | "sentence-transformers/all-minilm-l6-v2" => {
+            Ok(EmbeddingModel::AllMiniLML6V2)
+        }
+        "bge-small-en-v1.5" | "baai/bge-small-en-v1.5" => Ok(EmbeddingModel::BGESmallENV15),
+        "jina-embeddings-v2-base-code" | "jinaai/jina-embeddings-v2-base-code" => {
+            Ok(EmbeddingModel::JinaEmbeddingsV2BaseCode)
+        }
+        _ => Err(anyhow::anyhow!("Unknown embedding model: {}", model_str)),
+    }
+}
+
+/// Get list of available embedding models
+pub fn available_embedding_models() -> Vec<(&'static str, &'static str)> {
+    vec![
+        (
+            "all-minilm-l6-v2",
+            "sentence-transformers/all-MiniLM-L6-v2 (default)",
+        ),
+        ("bge-small-en-v1.5", "BAAI/bge-small-en-v1.5"),
+        (
+            "jina-embeddings-v2-base-code",
+            "jinaai/jina-embeddings-v2-base-code",
+        ),
+    ]
+}
+
+/// Wrapper around the fastembed text embedding model
+pub struct Embedder {
+    model: TextEmbedding,
+    name: String,
+}
+
+impl Embedder {
+    pub fn new(model_name: &str, show_download_progress: bool) -> Result<Self> {
+        let model = TextEmbedding::try_new(
+            InitOptions::new(parse_embedding_model(model_name)?)
+                .with_show_download_progress(show_download_progress)
+                .with_cache_dir(paths::model_cache_dir()),
+        )
+        .context("Failed to initialize embedding model")?;
+        Ok(Self {
+            model,
+            name: model_name.to_lowercase(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn embed(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.model
+            .embed(texts, None)
+            .context("Failed to embed text")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkVector {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub vector: Vec<f32>,
+}
+
+/// Embeddings of the indexed chunks, keyed by index path
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VectorStore {
+    /// Name of the model the vectors were computed with
+    model: String,
+    files: HashMap<String, Vec<ChunkVector>>,
+}
+
+impl VectorStore {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match fs::read(&path) {
+            Ok(data) => Ok(bincode::deserialize(&data)?),
+            Err(_) => Ok(Self::default()), // No embeddings computed yet
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        paths::write_atomic(path.as_ref(), bincode::serialize(self)?)
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.files.values().map(Vec::len).sum()
+    }
+
+    /// Drops all vectors and starts over with another model
+    pub fn reset(&mut self, model: &str) {
+        self.model = model.to_string();
+        self.files.clear();
+    }
+
+    pub fn remove_file(&mut self, path: &str) {
+        self.files.remove(path);
+    }
+
+    /// Moves the vectors of every file that `rewrite` maps to a new index path there
+    pub fn rewrite_paths(&mut self, rewrite: impl Fn(&str) -> Option<String>) {
+        self.files = std::mem::take(&mut self.files)
+            .into_iter()
+            .map(|(path, chunks)| (rewrite(&path).unwrap_or(path), chunks))
+            .collect();
+    }
+
+    /// Adds the vectors of the given chunks. Vectors already stored for their files are
+    /// kept, so callers remove changed files first.
+    pub fn insert(&mut self, chunks: Vec<ChunkText>, vectors: Vec<Vec<f32>>) {
+        for (chunk, vector) in chunks.into_iter().zip(vectors) {
+            self.files.entry(chunk.path).or_default().push(ChunkVector {
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+                vector,
+            });
+        }
+    }
+
+    /// The `limit` chunks most similar to `query`, as index path, start and end line, most
+    /// similar first
+    pub fn nearest(&self, query: &[f32], limit: usize) -> Vec<((String, usize, usize), f32)> {
+        let mut scored: Vec<_> = self
+            .files
+            .iter()
+            .flat_map(|(path, chunks)| {
+                chunks.iter().map(move |chunk| {
+                    (
+                        (path.clone(), chunk.start_line, chunk.end_line),
+                        cosine_similarity(query, &chunk.vector),
+                    )
+                })
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_chunks() {
+        let mut store = VectorStore::default();
+        store.reset(DEFAULT_EMBEDDING_MODEL);
+        let chunk = |path: &str, start_line| ChunkText {
+            path: path.to_string(),
+            start_line,
+            end_line: start_line + 1,
+            text: String::new(),
+        };
+        store.insert(
+            vec![chunk("a.rs", 0), chunk("a.rs", 5), chunk("b.rs", 0)],
+            vec![vec![1.0, 0.0], vec![0.6, 0.8], vec![0.0, 1.0]],
+        );
+
+        let nearest = store.nearest(&[0.0, 2.0], 2);
+        assert_eq!(nearest[0].0, ("b.rs".to_string(), 0, 1));
+        assert_eq!(nearest[1].0, ("a.rs".to_string(), 5, 6));
+        assert!((nearest[0].1 - 1.0).abs() < 1e-6);
+
+        store.remove_file("b.rs");
+        assert_eq!(store.chunk_count(), 2);
+    }
+}
@@ -1,6 +1,42 @@
+use crate::code_chunker::detect_encoding;
+use crate::git;
+use crate::paths;
+use crate::search_index::SkipReason;
 use anyhow::Result;
-use ignore::WalkBuilder;
+use encoding_rs::{UTF_16BE, UTF_16LE};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{DirEntry, Walk, WalkBuilder};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
 use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Ignore files with `.gitignore` syntax that only apply to probe, read in every directory
+pub const PROBE_IGNORE_FILE: &str = ".probeignore";
+
+/// Bytes at the start of a file checked before it is read in full
+pub const SNIFF_BYTES: usize = 8 * 1024;
+
+/// Checks the first 8KB of a file, so binaries without a telling extension (executables,
+/// images, archives) are skipped without reading them in full
+pub fn sniff_file(path: &Path) -> std::io::Result<Option<SkipReason>> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    File::open(path)?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut head)?;
+    Ok(sniff(&head))
+}
+
+/// Why the first bytes of a file rule out indexing it: a NUL byte means it is binary,
+/// unless it's UTF-16 text. Text in other encodings is decoded by `read_source`.
+pub fn sniff(head: &[u8]) -> Option<SkipReason> {
+    let (encoding, _) = detect_encoding(head);
+    if encoding != UTF_16LE && encoding != UTF_16BE && head.contains(&0) {
+        return Some(SkipReason::Binary);
+    }
+    None
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct IndexedFile {
@@ -8,41 +44,197 @@ pub struct IndexedFile {
     pub relative_path: PathBuf,
 }
 
+/// The files git lists under the scanned root and every directory containing one of them,
+/// relative to the root
+struct GitFiles {
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl GitFiles {
+    fn new(files: HashSet<PathBuf>) -> Self {
+        let dirs = files
+            .iter()
+            .flat_map(|file| file.ancestors().skip(1))
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .collect();
+        Self { files, dirs }
+    }
+
+    fn contains(&self, entry: &DirEntry, relative_path: &Path) -> bool {
+        let relative_path = FileScanner::normalize_relative_path(relative_path);
+        if entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir())
+        {
+            self.dirs.contains(&relative_path)
+        } else {
+            self.files.contains(&relative_path)
+        }
+    }
+}
+
 pub struct FileScanner {
     root_dir: PathBuf,
+    overrides: Override,
+    /// Files to index, matched after the walk so they don't override ignore rules
+    includes: Override,
+    excluded_dirs: Vec<PathBuf>,
+    follow_symlinks: bool,
+    git_files_only: bool,
 }
 
 impl FileScanner {
     pub fn new<P: AsRef<Path>>(root_dir: P) -> Self {
         Self {
-            root_dir: root_dir.as_ref().to_path_buf(),
+            root_dir: paths::simplify_path(root_dir.as_ref()),
+            overrides: Override::empty(),
+            includes: Override::empty(),
+            excluded_dirs: Vec::new(),
+            follow_symlinks: false,
+            git_files_only: false,
         }
     }
 
-    pub fn scan_files(&self) -> Result<Vec<PathBuf>> {
-        let mut files = Vec::new();
+    /// Excludes files matching the given gitignore-style globs (e.g. `**/fixtures/**`, `*.min.js`)
+    /// in addition to whatever `.gitignore` already excludes.
+    pub fn with_ignore_globs(mut self, globs: &[String]) -> Result<Self> {
+        let mut builder = OverrideBuilder::new(&self.root_dir);
+        for glob in globs {
+            // Override globs whitelist by default, a leading '!' turns them into ignores
+            builder.add(&format!("!{glob}"))?;
+        }
+        self.overrides = builder.build()?;
+        Ok(self)
+    }
 
-        let walker = WalkBuilder::new(&self.root_dir)
+    /// Only yields files matching one of the given gitignore-style globs (e.g. `*.md`,
+    /// `docs/**`). Ignore globs and `.gitignore` still apply to them.
+    pub fn with_include_globs(mut self, globs: &[String]) -> Result<Self> {
+        let mut builder = OverrideBuilder::new(&self.root_dir);
+        for glob in globs {
+            builder.add(glob)?;
+        }
+        self.includes = builder.build()?;
+        Ok(self)
+    }
+
+    /// Skips the given directory during the walk, e.g. an index directory configured
+    /// to live inside the project under a name other than `.probe`.
+    pub fn with_excluded_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.excluded_dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Follows symlinked files and directories instead of skipping them. Files reachable
+    /// through several links are yielded once and symlink cycles are not descended into.
+    pub fn with_follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Lets git decide which files are indexed: the files it tracks, including ones that
+    /// `.gitignore` matches but that were added anyway, plus untracked files that aren't
+    /// ignored. `.probeignore` files and ignore globs still apply on top. Outside of git
+    /// repositories this falls back to the usual `.gitignore` filtering.
+    pub fn with_git_files_only(mut self, git_files_only: bool) -> Self {
+        self.git_files_only = git_files_only;
+        self
+    }
+
+    /// Files git lists under the root if restricted to them, read when a walk starts so
+    /// that files added since are picked up
+    fn git_files(&self) -> Option<Arc<GitFiles>> {
+        if !self.git_files_only {
+            return None;
+        }
+        match git::listed_files(&self.root_dir) {
+            Ok(files) => Some(Arc::new(GitFiles::new(files))),
+            Err(e) => {
+                eprintln!(
+                    "Warning: failed to list the files known to git, using .gitignore instead: {e}"
+                );
+                None
+            }
+        }
+    }
+
+    fn walker(&self) -> Walk {
+        self.walker_in(&self.root_dir, None)
+    }
+
+    /// Walks `dir` (the root or a directory under it) with the same filtering as a full scan
+    fn walker_in(&self, dir: &Path, max_depth: Option<usize>) -> Walk {
+        // Resolve when the walk starts since excluded directories may be created after construction
+        let excluded_dirs: Vec<PathBuf> = self
+            .excluded_dirs
+            .iter()
+            .map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.clone()))
+            .collect();
+        let follow_symlinks = self.follow_symlinks;
+        let git_files = self.git_files();
+        // Git already applied the ignore rules to the files it lists
+        let use_gitignore = git_files.is_none();
+        let root_dir = self.root_dir.clone();
+
+        WalkBuilder::new(dir)
+            .max_depth(max_depth)
             .hidden(false) // Include hidden files by default
-            .git_ignore(true) // Respect .gitignore
-            .git_global(true) // Respect global gitignore
-            .git_exclude(true) // Respect .git/info/exclude
-            .filter_entry(|entry| {
+            .git_ignore(use_gitignore) // Respect .gitignore
+            .git_global(use_gitignore) // Respect global gitignore
+            .git_exclude(use_gitignore) // Respect .git/info/exclude
+            .add_custom_ignore_filename(PROBE_IGNORE_FILE) // Takes precedence over .gitignore
+            .overrides(self.overrides.clone()) // Respect index.ignore from probe.yml
+            .follow_links(follow_symlinks) // The walker reports cycles as loop errors
+            .filter_entry(move |entry| {
+                if !follow_symlinks && entry.path_is_symlink() {
+                    return false;
+                }
                 // Exclude .probe and .git directories to avoid indexing our own files and git internals
                 if let Some(name) = entry.file_name().to_str() {
-                    if (name == ".probe" || name == ".git") && entry.path().is_dir() {
+                    let is_named = |dir: &str| {
+                        if paths::CASE_INSENSITIVE {
+                            name.eq_ignore_ascii_case(dir)
+                        } else {
+                            name == dir
+                        }
+                    };
+                    if (is_named(".probe") || is_named(".git")) && entry.path().is_dir() {
                         return false;
                     }
                 }
+                if let Some(git_files) = &git_files {
+                    let relative_path = entry.path().strip_prefix(&root_dir);
+                    if entry.depth() > 0
+                        && !relative_path.is_ok_and(|path| git_files.contains(entry, path))
+                    {
+                        return false;
+                    }
+                }
+                if !excluded_dirs.is_empty() && entry.path().is_dir() {
+                    if let Ok(canonical) = entry.path().canonicalize() {
+                        return !excluded_dirs.contains(&canonical);
+                    }
+                }
                 true
             })
-            .build();
+            .build()
+    }
+
+    pub fn scan_files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let mut seen = HashSet::new();
 
-        for result in walker {
-            let entry = result?;
+        for result in self.walker() {
+            let entry = match result {
+                Ok(entry) => entry,
+                Err(e) if Self::is_symlink_loop(&e) => continue,
+                Err(e) => return Err(e.into()),
+            };
             let path = entry.path();
 
-            if path.is_file() && self.should_index_file(path) {
+            if path.is_file() && self.should_index_file(path) && self.first_visit(&mut seen, path) {
                 files.push(path.to_path_buf());
             }
         }
@@ -52,31 +244,20 @@ impl FileScanner {
 
     /// Returns an iterator over files to be indexed, yielding each file as soon as it is discovered and filtered.
     pub fn iter_files(&self) -> impl Iterator<Item = PathBuf> + '_ {
-        let walker = WalkBuilder::new(&self.root_dir)
-            .hidden(false)
-            .git_ignore(true)
-            .git_global(true)
-            .git_exclude(true)
-            .filter_entry(|entry| {
-                // Exclude .probe and .git directories to avoid indexing our own files and git internals
-                if let Some(name) = entry.file_name().to_str() {
-                    if (name == ".probe" || name == ".git") && entry.path().is_dir() {
-                        return false;
-                    }
-                }
-                true
-            })
-            .build();
-
-        walker.filter_map(move |result| match result {
+        let mut seen = HashSet::new();
+        self.walker().filter_map(move |result| match result {
             Ok(entry) => {
                 let path = entry.path();
-                if path.is_file() && self.should_index_file(path) {
+                if path.is_file()
+                    && self.should_index_file(path)
+                    && self.first_visit(&mut seen, path)
+                {
                     Some(path.to_path_buf())
                 } else {
                     None
                 }
             }
+            Err(e) if Self::is_symlink_loop(&e) => None,
             Err(e) => {
                 eprintln!("Error walking directory: {e}");
                 None
@@ -98,7 +279,85 @@ impl FileScanner {
         })
     }
 
+    /// Lists the indexable files and the directories the walk descends into under `dir`, a
+    /// directory inside the root. Only `dir`'s direct children are listed unless `recursive`
+    /// is set. Directories are returned relative to the root, `dir` itself included.
+    pub fn scan_dir(&self, dir: &Path, recursive: bool) -> (Vec<IndexedFile>, Vec<PathBuf>) {
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        let mut seen = HashSet::new();
+
+        for result in self.walker_in(dir, (!recursive).then_some(1)) {
+            let entry = match result {
+                Ok(entry) => entry,
+                Err(e) if Self::is_symlink_loop(&e) => continue,
+                Err(e) => {
+                    eprintln!("Error walking directory: {e}");
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let Some(relative_path) = path
+                .strip_prefix(&self.root_dir)
+                .ok()
+                .map(Self::normalize_relative_path)
+            else {
+                continue;
+            };
+
+            if path.is_dir() {
+                dirs.push(relative_path);
+            } else if path.is_file()
+                && self.should_index_file(path)
+                && self.first_visit(&mut seen, path)
+            {
+                files.push(IndexedFile {
+                    disk_path: path.to_path_buf(),
+                    relative_path,
+                });
+            }
+        }
+
+        (files, dirs)
+    }
+
+    /// Deduplicates files by canonical path, since followed symlinks can make the same
+    /// file reachable under several paths
+    fn first_visit(&self, seen: &mut HashSet<PathBuf>, path: &Path) -> bool {
+        if !self.follow_symlinks {
+            return true;
+        }
+        match path.canonicalize() {
+            Ok(canonical) => seen.insert(canonical),
+            Err(_) => true,
+        }
+    }
+
+    /// A symlink pointing at one of its ancestors, which the walker refuses to descend into
+    fn is_symlink_loop(error: &ignore::Error) -> bool {
+        match error {
+            ignore::Error::Loop { .. } => true,
+            ignore::Error::WithPath { err, .. }
+            | ignore::Error::WithDepth { err, .. }
+            | ignore::Error::WithLineNumber { err, .. } => Self::is_symlink_loop(err),
+            _ => false,
+        }
+    }
+
     fn should_index_file(&self, path: &Path) -> bool {
+        // Files matching no include glob come back as ignored, no globs match nothing
+        if self.includes.matched(path, false).is_ignore() {
+            return false;
+        }
+
+        // Device names like `NUL` or `con.txt` can't be opened on Windows
+        if cfg!(windows) {
+            let file_name = path.file_name().map(|name| name.to_string_lossy());
+            if file_name.is_some_and(|name| paths::is_reserved_name(&name)) {
+                return false;
+            }
+        }
+
         // Skip binary files and very large files
         if let Some(ext) = path.extension() {
             let ext = ext.to_string_lossy().to_lowercase();
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Runs `git` in `dir` and returns its standard output
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow!("Failed to run git: {e}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// Files under `dir` that git tracks, plus untracked files that aren't ignored, relative
+/// to `dir`
+pub fn listed_files(dir: &Path) -> Result<HashSet<PathBuf>> {
+    let output = git(
+        dir,
+        &[
+            "ls-files",
+            "-z",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+        ],
+    )?;
+    Ok(output
+        .split(|&byte| byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+        .collect())
+}
+
+/// The commit checked out in the repository containing `dir`, `None` outside of git
+/// repositories and in repositories without commits
+pub fn head_commit(dir: &Path) -> Option<String> {
+    let output = git(dir, &["rev-parse", "--verify", "--quiet", "HEAD"]).ok()?;
+    let commit = String::from_utf8(output).ok()?.trim().to_string();
+    (!commit.is_empty()).then_some(commit)
+}
+
+/// Number of commits on HEAD that `commit` doesn't contain, `None` if `commit` is unknown
+pub fn commits_since(dir: &Path, commit: &str) -> Option<usize> {
+    let output = git(dir, &["rev-list", "--count", &format!("{commit}..HEAD")]).ok()?;
+    String::from_utf8(output).ok()?.trim().parse().ok()
+}
+
+/// When each file under `dir` was last changed by a commit within the last `days` days,
+/// relative to `dir`. Files with uncommitted changes and untracked files count as changed
+/// now; files without a recent commit are left out.
+pub fn last_changed(dir: &Path, days: u64) -> Result<HashMap<PathBuf, SystemTime>> {
+    let since = format!("--since={days}.days");
+    let log = git(
+        dir,
+        &[
+            "-c",
+            "core.quotePath=false",
+            "log",
+            &since,
+            "--relative",
+            "--name-only",
+            "--format=@%ct",
+        ],
+    )?;
+    let mut changed = HashMap::new();
+    let now = SystemTime::now();
+    for args in [
+        &["diff", "--name-only", "--relative", "HEAD"][..],
+        &["ls-files", "--others", "--exclude-standard"][..],
+    ] {
+        let output = git(dir, &[&["-c", "core.quotePath=false"][..], args].concat())?;
+        for path in String::from_utf8_lossy(&output).lines() {
+            changed.insert(PathBuf::from(path), now);
+        }
+    }
+
+    // Newest commits come first, so the first time a path shows up is its last change
+    let mut commit_time = now;
+    for line in String::from_utf8_lossy(&log).lines() {
+        if let Some(timestamp) = line.strip_prefix('@') {
+            let seconds = timestamp.parse().unwrap_or_default();
+            commit_time = UNIX_EPOCH + Duration::from_secs(seconds);
+        } else if !line.is_empty() {
+            changed.entry(PathBuf::from(line)).or_insert(commit_time);
+        }
+    }
+    Ok(changed)
+}
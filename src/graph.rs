@@ -0,0 +1,208 @@
+use crate::language_processor::FileReferences;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// A method or function defined in the indexed files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphNode {
+    /// `<path>:<qualified name>`, unique across the repository
+    pub id: String,
+    pub name: String,
+    pub path: PathBuf,
+    /// 1-based line of the definition
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    /// Number of call sites from `from` to `to`
+    pub calls: usize,
+}
+
+/// Callers/callees graph between the symbols defined in the repository. Calls are matched
+/// to definitions by method name only, so overloads and same-named methods of unrelated
+/// types all become callees; calls into code outside the repository are left out.
+#[derive(Debug, Default, Serialize)]
+pub struct CallGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl CallGraph {
+    pub fn build(files: Vec<(PathBuf, FileReferences)>) -> Self {
+        let mut nodes = Vec::new();
+        for (path, references) in &files {
+            for symbol in &references.definitions {
+                nodes.push(GraphNode {
+                    id: node_id(path, &symbol.name),
+                    name: symbol.name.clone(),
+                    path: path.clone(),
+                    line: symbol.line + 1,
+                });
+            }
+        }
+        let mut by_simple_name: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, node) in nodes.iter().enumerate() {
+            by_simple_name
+                .entry(simple_name(&node.name))
+                .or_default()
+                .push(i);
+        }
+
+        let mut edges: BTreeMap<(String, String), usize> = BTreeMap::new();
+        for (path, references) in &files {
+            for call in &references.calls {
+                let Some(candidates) = by_simple_name.get(call.callee.as_str()) else {
+                    continue;
+                };
+                // A method of the caller's own type shadows same-named methods elsewhere
+                let caller_type = enclosing_type(&call.caller);
+                let local: Vec<usize> = candidates
+                    .iter()
+                    .copied()
+                    .filter(|&i| {
+                        nodes[i].path == *path && enclosing_type(&nodes[i].name) == caller_type
+                    })
+                    .collect();
+                let targets = if local.is_empty() {
+                    candidates.as_slice()
+                } else {
+                    local.as_slice()
+                };
+
+                let from = node_id(path, &call.caller);
+                for &target in targets {
+                    *edges
+                        .entry((from.clone(), nodes[target].id.clone()))
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        Self {
+            nodes,
+            edges: edges
+                .into_iter()
+                .map(|((from, to), calls)| GraphEdge { from, to, calls })
+                .collect(),
+        }
+    }
+
+    /// Graphviz rendering, e.g. for `probe graph | dot -Tsvg > calls.svg`
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph calls {\n    node [shape=box];\n");
+        for node in &self.nodes {
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [label=\"{}\", tooltip=\"{}:{}\"];",
+                escape(&node.id),
+                escape(&node.name),
+                escape(&node.path.display().to_string()),
+                node.line
+            );
+        }
+        for edge in &self.edges {
+            let label = if edge.calls > 1 {
+                format!(" [label=\"{}\"]", edge.calls)
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -> \"{}\"{};",
+                escape(&edge.from),
+                escape(&edge.to),
+                label
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn node_id(path: &Path, name: &str) -> String {
+    format!("{}:{}", path.display(), name)
+}
+
+fn simple_name(qualified: &str) -> &str {
+    qualified.rsplit('.').next().unwrap_or(qualified)
+}
+
+fn enclosing_type(qualified: &str) -> &str {
+    qualified.rsplit_once('.').map_or("", |(owner, _)| owner)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language_processor::{CallSite, Symbol};
+
+    fn references(definitions: &[&str], calls: &[(&str, &str)]) -> FileReferences {
+        FileReferences {
+            definitions: definitions
+                .iter()
+                .map(|name| Symbol {
+                    name: name.to_string(),
+                    line: 0,
+                })
+                .collect(),
+            calls: calls
+                .iter()
+                .map(|(caller, callee)| CallSite {
+                    caller: caller.to_string(),
+                    callee: callee.to_string(),
+                    line: 0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_call_graph_resolves_calls_by_name() {
+        let graph = CallGraph::build(vec![
+            (
+                PathBuf::from("Shop.java"),
+                references(
+                    &["Shop.open", "Shop.close"],
+                    &[
+                        ("Shop.open", "close"),
+                        ("Shop.open", "close"),
+                        ("Shop.open", "total"),
+                        ("Shop.open", "println"),
+                    ],
+                ),
+            ),
+            (
+                PathBuf::from("Cart.java"),
+                references(&["Cart.total", "Cart.close"], &[]),
+            ),
+        ]);
+
+        assert_eq!(graph.nodes.len(), 4);
+        let edges: Vec<_> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str(), edge.calls))
+            .collect();
+        // `close` resolves to Shop's own method, `println` is external
+        assert_eq!(
+            edges,
+            vec![
+                ("Shop.java:Shop.open", "Cart.java:Cart.total", 1),
+                ("Shop.java:Shop.open", "Shop.java:Shop.close", 2),
+            ]
+        );
+        assert!(graph
+            .to_dot()
+            .contains("\"Shop.java:Shop.open\" -> \"Shop.java:Shop.close\" [label=\"2\"];"));
+    }
+}
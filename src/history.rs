@@ -0,0 +1,198 @@
+use crate::paths::write_atomic;
+use crate::search_index::SearchResult;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Name of the history file in the index directory
+pub const HISTORY_FILE: &str = "history.json";
+
+/// Searches remembered for `probe history` and `probe !N`
+pub const MAX_ENTRIES: usize = 100;
+
+/// Most recent searches whose results are kept for `probe last`; older ones only keep
+/// their command line, so the file stays small
+pub const KEPT_RESULTS: usize = 10;
+
+/// A search run from the command line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Number of the search, counting up from 1 across the whole history
+    pub id: u64,
+    pub time: SystemTime,
+    pub query: String,
+    /// Command-line arguments after the program name, to run the search again
+    pub args: Vec<String>,
+    /// Whether the results were shown with `--group-by-file`
+    pub grouped: bool,
+    pub result_count: usize,
+    /// The results as shown, `None` once `KEPT_RESULTS` newer searches were recorded
+    pub results: Option<Vec<SearchResult>>,
+}
+
+/// Recent searches of a project, oldest first, kept in its index directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchHistory {
+    entries: Vec<HistoryEntry>,
+}
+
+impl SearchHistory {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data)
+                .with_context(|| format!("Failed to read search history {}", path.display())),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_atomic(path.as_ref(), serde_json::to_string(self)?)
+    }
+
+    /// Adds a search with its results, forgetting the oldest ones beyond `MAX_ENTRIES`.
+    /// Returns the number of the new entry.
+    pub fn record(
+        &mut self,
+        query: &str,
+        args: Vec<String>,
+        grouped: bool,
+        results: &[SearchResult],
+    ) -> u64 {
+        let id = self.entries.last().map_or(1, |entry| entry.id + 1);
+        self.entries.push(HistoryEntry {
+            id,
+            time: SystemTime::now(),
+            query: query.to_string(),
+            args,
+            grouped,
+            result_count: results.len(),
+            results: Some(results.to_vec()),
+        });
+
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+        let without_results = self.entries.len().saturating_sub(KEPT_RESULTS);
+        for entry in &mut self.entries[..without_results] {
+            entry.results = None;
+        }
+        id
+    }
+
+    /// Searches from oldest to newest
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// The search numbered `id`, if it's still remembered
+    pub fn get(&self, id: u64) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    pub fn last(&self) -> Option<&HistoryEntry> {
+        self.entries.last()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Parses a `!N` reference to a search in the history, or `!!` for the last one
+/// (`Some(None)`). Returns `None` for anything else.
+pub fn parse_reference(arg: &str) -> Option<Option<u64>> {
+    let reference = arg.strip_prefix('!')?;
+    if reference == "!" {
+        return Some(None);
+    }
+    reference.parse().ok().map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn result(path: &str) -> SearchResult {
+        SearchResult {
+            path: PathBuf::from(path),
+            score: 1.0,
+            snippet: "fn main() {}".to_string(),
+            chunk_type: Some("function".to_string()),
+            chunk_name: Some("main".to_string()),
+            start_line: Some(0),
+            end_line: Some(0),
+            snippet_line: Some(0),
+            fuzzy: false,
+            duplicates: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(HISTORY_FILE);
+        assert!(SearchHistory::load(&path).unwrap().is_empty());
+
+        let mut history = SearchHistory::default();
+        let args = vec!["main".to_string(), "-n".to_string(), "5".to_string()];
+        assert_eq!(
+            history.record("main", args.clone(), false, &[result("a.rs")]),
+            1
+        );
+        assert_eq!(history.record("parse", vec!["parse".into()], true, &[]), 2);
+        history.save(&path).unwrap();
+
+        let history = SearchHistory::load(&path).unwrap();
+        assert_eq!(history.len(), 2);
+        let first = history.get(1).unwrap();
+        assert_eq!(first.args, args);
+        assert_eq!(first.result_count, 1);
+        assert_eq!(
+            first.results.as_ref().unwrap()[0].path,
+            PathBuf::from("a.rs")
+        );
+        assert!(history.last().unwrap().grouped);
+    }
+
+    #[test]
+    fn test_history_forgets_old_searches() {
+        let mut history = SearchHistory::default();
+        for i in 0..MAX_ENTRIES + 5 {
+            history.record(&format!("query {i}"), Vec::new(), false, &[result("a.rs")]);
+        }
+
+        assert_eq!(history.len(), MAX_ENTRIES);
+        // Numbers keep counting up after old searches are dropped
+        assert_eq!(history.entries()[0].id, 6);
+        assert!(history.get(5).is_none());
+
+        let kept: Vec<_> = history
+            .entries()
+            .iter()
+            .filter(|entry| entry.results.is_some())
+            .collect();
+        assert_eq!(kept.len(), KEPT_RESULTS);
+        assert_eq!(kept[0].id, (MAX_ENTRIES + 5 - KEPT_RESULTS + 1) as u64);
+        assert_eq!(history.entries()[0].result_count, 1);
+    }
+
+    #[test]
+    fn test_parse_reference() {
+        assert_eq!(parse_reference("!3"), Some(Some(3)));
+        assert_eq!(parse_reference("!!"), Some(None));
+        assert_eq!(parse_reference("!main"), None);
+        assert_eq!(parse_reference("main"), None);
+    }
+}
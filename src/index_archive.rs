@@ -0,0 +1,437 @@
+use crate::file_scanner::IndexedFile;
+use crate::history::HISTORY_FILE;
+use crate::metadata::content_hash;
+use crate::search_index::{index_path, INDEX_SCHEMA_VERSION};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const MANIFEST_FILE: &str = "manifest.json";
+/// Directory inside the archive holding the index files
+const INDEX_PREFIX: &str = "index";
+
+/// Describes the index packaged by `probe index export`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub schema_version: u32,
+    /// Version of probe that exported the index
+    pub probe_version: String,
+    /// Hash over every indexed file's path and contents, equal for identical working trees
+    pub root_hash: String,
+    /// SHA-256 of each indexed file's contents, keyed by its `/`-separated relative path
+    pub files: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn new(files: &[IndexedFile]) -> Result<Self> {
+        let files = hash_files(files)
+            .into_iter()
+            .filter_map(|(path, hash)| Some((path, hash?)))
+            .collect();
+        Ok(Self {
+            schema_version: INDEX_SCHEMA_VERSION,
+            probe_version: env!("CARGO_PKG_VERSION").to_string(),
+            root_hash: root_hash(&files),
+            files,
+        })
+    }
+
+    /// Fails for archives whose index layout this version of probe can't read
+    pub fn check_compatible(&self) -> Result<()> {
+        if self.schema_version != INDEX_SCHEMA_VERSION {
+            return Err(anyhow::anyhow!(
+                "The archive holds an index in schema version {} (exported by probe {}), but this probe reads version {}. Run `probe rebuild` instead.",
+                self.schema_version,
+                self.probe_version,
+                INDEX_SCHEMA_VERSION
+            ));
+        }
+        Ok(())
+    }
+
+    /// Moves the hashes of files under a rewritten prefix to their new paths
+    pub fn rewrite_paths(&mut self, rewrites: &[PrefixRewrite]) {
+        self.files = std::mem::take(&mut self.files)
+            .into_iter()
+            .map(|(path, hash)| (rewrite_path(rewrites, &path).unwrap_or(path), hash))
+            .collect();
+        self.root_hash = root_hash(&self.files);
+    }
+}
+
+/// Replaces a leading directory of indexed paths on import, e.g. when CI indexed the
+/// repository from its root and the importing project is one of its subdirectories.
+/// Written as `FROM=TO`; either side may be empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixRewrite {
+    from: String,
+    to: String,
+}
+
+impl PrefixRewrite {
+    /// `path` with `from` replaced by `to`, `None` if it isn't under `from`
+    pub fn apply(&self, path: &str) -> Option<String> {
+        let rest = if self.from.is_empty() {
+            path
+        } else if path == self.from {
+            ""
+        } else {
+            path.strip_prefix(&self.from)?.strip_prefix('/')?
+        };
+        Some(match (self.to.is_empty(), rest.is_empty()) {
+            (true, _) => rest.to_string(),
+            (false, true) => self.to.clone(),
+            (false, false) => format!("{}/{rest}", self.to),
+        })
+    }
+}
+
+impl FromStr for PrefixRewrite {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (from, to) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Expected FROM=TO, got '{s}'"))?;
+        // Same form as index paths: `/`-separated, relative, no trailing separator
+        let normalize = |prefix: &str| {
+            let prefix = prefix.replace('\\', "/");
+            let prefix = prefix.trim_start_matches("./").trim_matches('/');
+            index_path(Path::new(prefix))
+        };
+        Ok(Self {
+            from: normalize(from),
+            to: normalize(to),
+        })
+    }
+}
+
+/// `path` rewritten by the first rewrite whose prefix it is under
+pub fn rewrite_path(rewrites: &[PrefixRewrite], path: &str) -> Option<String> {
+    rewrites.iter().find_map(|rewrite| rewrite.apply(path))
+}
+
+/// Content hashes of `files` by index path, `None` for files that can't be read
+pub fn hash_files(files: &[IndexedFile]) -> Vec<(String, Option<String>)> {
+    files
+        .par_iter()
+        .map(|file| {
+            let hash = std::fs::read(&file.disk_path)
+                .ok()
+                .map(|content| content_hash(&content));
+            (index_path(&file.relative_path), hash)
+        })
+        .collect()
+}
+
+fn root_hash(files: &BTreeMap<String, String>) -> String {
+    let mut hasher = Sha256::new();
+    for (path, hash) in files {
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        hasher.update(hash.as_bytes());
+        hasher.update([b'\n']);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Packages the files of `index_dir` and `manifest` into a zstd-compressed tar at
+/// `archive_path`. Lock files, leftover temporary files and an interrupted rebuild's
+/// checkpoint are left out.
+pub fn write_archive(index_dir: &Path, manifest: &Manifest, archive_path: &Path) -> Result<()> {
+    let file = File::create(archive_path)
+        .with_context(|| format!("Failed to create {}", archive_path.display()))?;
+    let mut archive = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+
+    // The manifest goes first so imports can check it before unpacking the index
+    let manifest_json = serde_json::to_vec_pretty(manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, MANIFEST_FILE, manifest_json.as_slice())?;
+
+    let mut entries: Vec<_> = std::fs::read_dir(index_dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !entry.file_type()?.is_file()
+            || name.ends_with(".lock")
+            || name.ends_with(".tmp")
+            || name == "resume.json"
+            || name == HISTORY_FILE
+        {
+            continue;
+        }
+        archive.append_path_with_name(entry.path(), format!("{INDEX_PREFIX}/{name}"))?;
+    }
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Unpacks an archive written by `write_archive`, putting the index files into `dir`
+pub fn read_archive(archive_path: &Path, dir: &Path) -> Result<Manifest> {
+    let file = File::open(archive_path)
+        .with_context(|| format!("Failed to open {}", archive_path.display()))?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut entries = archive.entries()?;
+
+    let not_an_index = || {
+        anyhow::anyhow!(
+            "{} is not an index exported by `probe index export`",
+            archive_path.display()
+        )
+    };
+    let mut first = match entries.next() {
+        Some(Ok(entry)) if entry.path()?.as_ref() == Path::new(MANIFEST_FILE) => entry,
+        _ => return Err(not_an_index()),
+    };
+    let manifest: Manifest =
+        serde_json::from_reader(&mut first).with_context(|| format!("Invalid {MANIFEST_FILE}"))?;
+    manifest.check_compatible()?;
+
+    std::fs::create_dir_all(dir)?;
+    for entry in entries {
+        let mut entry = entry?;
+        // Only regular files, no links or devices that could point outside `dir`
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        // Only plain file names under the index prefix, nothing that could escape `dir`
+        let name = match path.strip_prefix(INDEX_PREFIX) {
+            Ok(name) if name.components().count() == 1 => name.to_path_buf(),
+            _ => continue,
+        };
+        entry.unpack(dir.join(name))?;
+    }
+    Ok(manifest)
+}
+
+/// Writes `<archive>.sha256` in `sha256sum` format next to the archive, so `fetch_archive`
+/// can verify downloads of it
+pub fn write_checksum(archive_path: &Path) -> Result<PathBuf> {
+    let hash = copy_hashing(File::open(archive_path)?, &mut std::io::sink())?;
+    let file_name = archive_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let checksum_path = checksum_path(archive_path);
+    std::fs::write(&checksum_path, format!("{hash}  {file_name}\n"))?;
+    Ok(checksum_path)
+}
+
+fn checksum_path(archive_path: &Path) -> PathBuf {
+    let mut name = OsString::from(archive_path.as_os_str());
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// Fetches the archive at `source`, an http(s) URL or a local path, into `dest`. When a
+/// `<source>.sha256` file is published next to it, the download must match that checksum.
+pub fn fetch_archive(source: &str, dest: &Path) -> Result<()> {
+    let reader = open_source(source)?.ok_or_else(|| anyhow::anyhow!("{source} not found"))?;
+    let hash = copy_hashing(reader, &mut File::create(dest)?)?;
+
+    if let Some(mut reader) = open_source(&format!("{source}.sha256"))? {
+        let mut checksum = String::new();
+        reader.read_to_string(&mut checksum)?;
+        let expected = checksum.split_whitespace().next().unwrap_or_default();
+        if !expected.eq_ignore_ascii_case(&hash) {
+            return Err(anyhow::anyhow!(
+                "Checksum mismatch for {source}: expected {expected}, downloaded {hash}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Opens an http(s) URL or a local file, `None` if there is nothing there
+fn open_source(source: &str) -> Result<Option<Box<dyn Read>>> {
+    if is_url(source) {
+        return match ureq::get(source).call() {
+            Ok(response) => Ok(Some(Box::new(response.into_reader()))),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(anyhow::anyhow!("Failed to download {source}: {e}")),
+        };
+    }
+    let path = source.strip_prefix("file://").unwrap_or(source);
+    match File::open(path) {
+        Ok(file) => Ok(Some(Box::new(file))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow::anyhow!("Failed to read {path}: {e}")),
+    }
+}
+
+/// Copies `reader` into `writer`, returning the SHA-256 of everything copied
+fn copy_hashing(mut reader: impl Read, writer: &mut impl Write) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+    }
+    writer.flush()?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Resolves `index.remote` for the project in `root_dir`: URLs are kept as they are and
+/// relative paths are taken from the project root
+pub fn resolve_source(source: &str, root_dir: &Path) -> String {
+    if is_url(source) || source.starts_with("file://") {
+        return source.to_string();
+    }
+    root_dir.join(source).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("Main.java");
+        std::fs::write(&source, "class Main {}\n").unwrap();
+        let files = vec![IndexedFile {
+            disk_path: source,
+            relative_path: PathBuf::from("src").join("Main.java"),
+        }];
+        let manifest = Manifest::new(&files).unwrap();
+        assert!(manifest.files.contains_key("src/Main.java"));
+
+        let index_dir = temp_dir.path().join(".probe");
+        std::fs::create_dir(&index_dir).unwrap();
+        std::fs::write(index_dir.join("meta.json"), "{}").unwrap();
+        std::fs::write(index_dir.join(".tantivy-writer.lock"), "").unwrap();
+        let archive_path = temp_dir.path().join("index.tar.zst");
+        write_archive(&index_dir, &manifest, &archive_path).unwrap();
+
+        let restored = temp_dir.path().join("restored");
+        let read = read_archive(&archive_path, &restored).unwrap();
+        assert_eq!(read.root_hash, manifest.root_hash);
+        assert!(restored.join("meta.json").exists());
+        assert!(!restored.join(".tantivy-writer.lock").exists());
+    }
+
+    #[test]
+    fn test_read_archive_skips_links() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest = Manifest::new(&[]).unwrap();
+        let archive_path = temp_dir.path().join("index.tar.zst");
+        let file = File::create(&archive_path).unwrap();
+        let mut archive = tar::Builder::new(zstd::Encoder::new(file, 0).unwrap());
+        let manifest_json = serde_json::to_vec(&manifest).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_json.len() as u64);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, MANIFEST_FILE, manifest_json.as_slice())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        archive
+            .append_link(
+                &mut header,
+                format!("{INDEX_PREFIX}/vectors.bin"),
+                "/etc/passwd",
+            )
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_cksum();
+        archive
+            .append_data(
+                &mut header,
+                format!("{INDEX_PREFIX}/meta.json"),
+                b"{}".as_slice(),
+            )
+            .unwrap();
+        archive.into_inner().unwrap().finish().unwrap();
+
+        let restored = temp_dir.path().join("restored");
+        read_archive(&archive_path, &restored).unwrap();
+        assert!(restored.join("vectors.bin").symlink_metadata().is_err());
+        assert!(restored.join("meta.json").exists());
+    }
+
+    #[test]
+    fn test_prefix_rewrite() {
+        let rewrite: PrefixRewrite = "./app/=".parse().unwrap();
+        assert_eq!(rewrite.apply("app/src/Main.java").unwrap(), "src/Main.java");
+        assert_eq!(rewrite.apply("application/Main.java"), None);
+
+        let rewrite: PrefixRewrite = "=services/api".parse().unwrap();
+        assert_eq!(
+            rewrite.apply("Main.java").unwrap(),
+            "services/api/Main.java"
+        );
+
+        let rewrites = vec![
+            "old/lib=new/lib".parse().unwrap(),
+            "old=legacy".parse().unwrap(),
+        ];
+        assert_eq!(
+            rewrite_path(&rewrites, "old/lib/a.rs").unwrap(),
+            "new/lib/a.rs"
+        );
+        assert_eq!(rewrite_path(&rewrites, "old/b.rs").unwrap(), "legacy/b.rs");
+        assert_eq!(rewrite_path(&rewrites, "other/c.rs"), None);
+        assert!("no-separator".parse::<PrefixRewrite>().is_err());
+    }
+
+    #[test]
+    fn test_fetch_archive_verifies_checksum() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_path = temp_dir.path().join("index.tar.zst");
+        std::fs::write(&archive_path, "archive contents").unwrap();
+        let source = archive_path.to_str().unwrap();
+        let dest = temp_dir.path().join("downloaded");
+
+        // Without a published checksum the download is taken as is
+        fetch_archive(source, &dest).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"archive contents");
+
+        write_checksum(&archive_path).unwrap();
+        fetch_archive(&format!("file://{source}"), &dest).unwrap();
+
+        std::fs::write(&archive_path, "tampered contents").unwrap();
+        assert!(fetch_archive(source, &dest).is_err());
+        assert!(fetch_archive(&format!("{source}.missing"), &dest).is_err());
+    }
+
+    #[test]
+    fn test_root_hash_depends_on_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("a.txt");
+        let files = vec![IndexedFile {
+            disk_path: path.clone(),
+            relative_path: PathBuf::from("a.txt"),
+        }];
+
+        std::fs::write(&path, "one").unwrap();
+        let first = Manifest::new(&files).unwrap().root_hash;
+        assert_eq!(Manifest::new(&files).unwrap().root_hash, first);
+        std::fs::write(&path, "two").unwrap();
+        assert_ne!(Manifest::new(&files).unwrap().root_hash, first);
+    }
+}
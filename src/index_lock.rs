@@ -0,0 +1,120 @@
+use anyhow::Result;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Name of the lock file in the lock directory next to the index
+const LOCK_FILE: &str = "index.lock";
+
+/// Advisory lock that keeps probe processes from replacing an index while others write to
+/// it. Rebuilds and imports hold it exclusively until the new index is swapped in, while
+/// incremental updates share it. Released when dropped, or when the process exits.
+pub struct IndexLock {
+    file: File,
+    exclusive: bool,
+}
+
+impl IndexLock {
+    /// Takes the lock in `lock_dir` to replace the index, waiting for incremental updates
+    /// to finish. Returns `None` if another process is replacing the index.
+    pub fn for_rebuild(lock_dir: &Path) -> Result<Option<Self>> {
+        let mut file = open(lock_dir)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                // Only rebuilds write to the file; if it can't be read, it's locked by one
+                let updating =
+                    fs::read_to_string(lock_path(lock_dir)).is_ok_and(|pid| pid.is_empty());
+                if !updating {
+                    return Ok(None);
+                }
+                file.lock()?;
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        Ok(Some(Self {
+            file,
+            exclusive: true,
+        }))
+    }
+
+    /// Takes the lock in `lock_dir` to update the index in place. Returns `None` if another
+    /// process is replacing the index.
+    pub fn for_update(lock_dir: &Path) -> Result<Option<Self>> {
+        let file = open(lock_dir)?;
+        match file.try_lock_shared() {
+            Ok(()) => Ok(Some(Self {
+                file,
+                exclusive: false,
+            })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Whether another process is replacing the index locked in `lock_dir`
+    pub fn rebuild_in_progress(lock_dir: &Path) -> Result<bool> {
+        Ok(Self::for_update(lock_dir)?.is_none())
+    }
+
+    /// Process id of the probe replacing the index locked in `lock_dir`, if known
+    pub fn holder(lock_dir: &Path) -> Option<u32> {
+        fs::read_to_string(lock_path(lock_dir))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        if self.exclusive {
+            // An empty file tells waiting rebuilds that only updates may hold the lock
+            let _ = self.file.set_len(0);
+        }
+    }
+}
+
+fn lock_path(lock_dir: &Path) -> PathBuf {
+    lock_dir.join(LOCK_FILE)
+}
+
+fn open(lock_dir: &Path) -> Result<File> {
+    fs::create_dir_all(lock_dir)?;
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_path(lock_dir))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rebuilds_exclude_each_other_and_updates() {
+        let temp_dir = TempDir::new().unwrap();
+        let lock_dir = temp_dir.path().join(".probe.lock");
+
+        let rebuild = IndexLock::for_rebuild(&lock_dir).unwrap().unwrap();
+        assert_eq!(IndexLock::holder(&lock_dir), Some(std::process::id()));
+        assert!(IndexLock::for_rebuild(&lock_dir).unwrap().is_none());
+        assert!(IndexLock::for_update(&lock_dir).unwrap().is_none());
+        assert!(IndexLock::rebuild_in_progress(&lock_dir).unwrap());
+
+        drop(rebuild);
+        assert_eq!(IndexLock::holder(&lock_dir), None);
+        let first = IndexLock::for_update(&lock_dir).unwrap().unwrap();
+        let second = IndexLock::for_update(&lock_dir).unwrap();
+        assert!(second.is_some());
+        assert!(!IndexLock::rebuild_in_progress(&lock_dir).unwrap());
+        drop((first, second));
+        assert!(IndexLock::for_rebuild(&lock_dir).unwrap().is_some());
+    }
+}
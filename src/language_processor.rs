@@ -8,6 +8,9 @@ pub struct CodeChunk {
     pub name: String,
     pub content: String,
     pub declaration: String,
+    /// Text of the doc comments above the definition without their comment markers, empty
+    /// when it has none
+    pub doc: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -21,21 +24,66 @@ pub enum ChunkType {
     Other,
 }
 
+/// A method or function defined in a file, with its enclosing types, e.g. `Shop.Cart.total`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// 0-based line of the definition
+    pub line: usize,
+}
+
+/// A call made from inside a method or function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallSite {
+    /// Qualified name of the enclosing definition
+    pub caller: String,
+    /// Name of the called method as written, without its receiver
+    pub callee: String,
+    /// 0-based line of the call
+    pub line: usize,
+}
+
+/// Definitions and call sites of one file, used to build the call graph
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileReferences {
+    pub definitions: Vec<Symbol>,
+    pub calls: Vec<CallSite>,
+}
+
 pub trait LanguageProcessor: Send + Sync {
+    /// Returns the language name used to refer to this processor in configuration
+    fn language_name(&self) -> &str;
+
     /// Returns the file extensions this processor handles
     fn get_file_extensions(&self) -> &[&str];
 
+    /// Returns the interpreters whose scripts this processor handles when a file has no
+    /// extension, matched against its `#!` line
+    fn interpreters(&self) -> &[&str] {
+        &[]
+    }
+
     /// Chunks the given code content for indexing
     fn chunk_code(&mut self, content: &str) -> Result<Vec<CodeChunk>>;
 
+    /// Extracts defined methods and the calls they make. Languages without call
+    /// extraction contribute nothing to the call graph.
+    fn extract_references(&mut self, _content: &str) -> Result<FileReferences> {
+        Ok(FileReferences::default())
+    }
+
     /// Creates a new boxed instance of this processor
     fn clone_box(&self) -> Result<Box<dyn LanguageProcessor>>;
 }
 
 /// Utility functions shared across language processors
 pub mod utils {
+    use super::{ChunkType, CodeChunk};
     use tree_sitter::{Node, TreeCursor};
 
+    /// Number of lines per chunk when splitting regions that couldn't be parsed
+    pub const WINDOW_LINES: usize = 40;
+
     /// Finds the first child node of the current node in the cursor that matches one of the provided kinds.
     /// Returns the node if found, or None otherwise.
     pub fn find_child_node<'a>(cursor: &mut TreeCursor<'a>, kinds: &[&str]) -> Option<Node<'a>> {
@@ -55,4 +103,163 @@ pub mod utils {
         }
         None
     }
+
+    /// Line ranges (inclusive) of the outermost ERROR nodes in the tree
+    pub fn error_regions(root: Node) -> Vec<(usize, usize)> {
+        let mut regions = Vec::new();
+        if root.has_error() {
+            collect_error_regions(root, &mut regions);
+        }
+        regions
+    }
+
+    fn collect_error_regions(node: Node, regions: &mut Vec<(usize, usize)>) {
+        if node.kind() == "ERROR" {
+            regions.push((node.start_position().row, node.end_position().row));
+            return;
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            if child.has_error() {
+                collect_error_regions(child, regions);
+            }
+        }
+    }
+
+    /// Splits the lines of the given regions that no chunk covers into fixed-size windows,
+    /// so code the parser couldn't make sense of is still indexed.
+    pub fn window_uncovered_lines(
+        content: &str,
+        regions: &[(usize, usize)],
+        chunks: &[CodeChunk],
+    ) -> Vec<CodeChunk> {
+        let lines: Vec<&str> = content.lines().collect();
+        let is_covered = |line: usize| {
+            chunks
+                .iter()
+                .any(|chunk| chunk.start_line <= line && line <= chunk.end_line)
+        };
+
+        let mut windows = Vec::new();
+        for &(start, end) in regions {
+            let mut span_start = None;
+            // One past the region end so a trailing span is always flushed
+            for line in start..=end + 1 {
+                let uncovered = line <= end && line < lines.len() && !is_covered(line);
+                match (uncovered, span_start) {
+                    (true, None) => span_start = Some(line),
+                    (false, Some(span)) => {
+                        push_windows(&lines, span, line - 1, &mut windows);
+                        span_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        windows
+    }
+
+    /// 0-based line of every byte offset, by binary search over line starts
+    pub struct LineIndex {
+        starts: Vec<usize>,
+    }
+
+    impl LineIndex {
+        pub fn new(content: &str) -> Self {
+            let starts = std::iter::once(0)
+                .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+                .collect();
+            Self { starts }
+        }
+
+        pub fn line_of(&self, offset: usize) -> usize {
+            match self.starts.binary_search(&offset) {
+                Ok(line) => line,
+                Err(next) => next - 1,
+            }
+        }
+
+        /// Byte offset of the start of `line`
+        pub fn line_start(&self, line: usize) -> usize {
+            self.starts[line]
+        }
+    }
+
+    /// Comment lines starting with one of `markers` directly above `line`, after
+    /// `previous_end`. Returns the first of them, or `line` itself if there are none.
+    pub fn comment_start(
+        lines: &[&str],
+        line: usize,
+        previous_end: Option<usize>,
+        markers: &[&str],
+    ) -> usize {
+        let mut start = line;
+        while start > 0 && previous_end.is_none_or(|end| start - 1 > end) {
+            let above = lines[start - 1].trim_start();
+            if !markers.iter().any(|marker| above.starts_with(marker)) {
+                break;
+            }
+            start -= 1;
+        }
+        start
+    }
+
+    /// The text of doc comments without their comment markers, e.g. `Parses the config`
+    /// for `/** Parses the config */` or `# Parses the config`
+    pub fn doc_text(comments: &str) -> String {
+        comments
+            .lines()
+            .map(|line| {
+                let line = line.trim();
+                let line = line.strip_suffix("*/").unwrap_or(line);
+                ["/**", "/*", "///", "//!", "//", "--", "#", "*"]
+                    .iter()
+                    .find_map(|marker| line.strip_prefix(marker))
+                    .unwrap_or(line)
+                    .trim()
+            })
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Line ranges of the paragraphs of a file, separated by blank lines
+    pub fn paragraphs(lines: &[&str]) -> Vec<(usize, usize)> {
+        let mut paragraphs = Vec::new();
+        let mut start = None;
+        for (line, text) in lines.iter().enumerate() {
+            match (text.trim().is_empty(), start) {
+                (false, None) => start = Some(line),
+                (true, Some(first)) => {
+                    paragraphs.push((first, line - 1));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(first) = start {
+            paragraphs.push((first, lines.len() - 1));
+        }
+        paragraphs
+    }
+
+    fn push_windows(lines: &[&str], start: usize, end: usize, windows: &mut Vec<CodeChunk>) {
+        for window_start in (start..=end).step_by(WINDOW_LINES) {
+            let window_end = (window_start + WINDOW_LINES - 1).min(end);
+            let text = lines[window_start..=window_end].join("\n");
+            if text.trim().is_empty() {
+                continue;
+            }
+            windows.push(CodeChunk {
+                start_line: window_start,
+                end_line: window_end,
+                chunk_type: ChunkType::Other,
+                name: format!("lines {}-{}", window_start + 1, window_end + 1),
+                content: text,
+                declaration: String::new(),
+                doc: String::new(),
+            });
+        }
+    }
 }
@@ -0,0 +1,157 @@
+use crate::language_processor::utils;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use anyhow::{Context, Result};
+use tree_sitter::{Node, Parser};
+
+pub struct BashProcessor {
+    parser: Parser,
+}
+
+impl BashProcessor {
+    pub fn new() -> Result<Self> {
+        let language = tree_sitter_bash::language();
+        let mut parser = Parser::new();
+        parser
+            .set_language(language)
+            .context("Failed to set Bash language")?;
+
+        Ok(Self { parser })
+    }
+
+    /// Turns every top-level function definition into a chunk, together with the comments
+    /// right above it. Returns the chunks and the line ranges of the remaining top-level
+    /// statements, grouped into blocks separated by blank lines and functions.
+    fn collect_chunks(&self, root: Node, content: &str) -> (Vec<CodeChunk>, Vec<(usize, usize)>) {
+        let mut chunks = Vec::new();
+        let mut blocks = Vec::new();
+        // Consecutive top-level statements and comments not yet assigned to a chunk
+        let mut block: Vec<Node> = Vec::new();
+
+        let mut cursor = root.walk();
+        for node in root.children(&mut cursor) {
+            let starts_new_block = block
+                .last()
+                .is_some_and(|last| node.start_position().row > last_row(*last) + 1);
+            if starts_new_block {
+                flush_block(&mut block, &mut blocks);
+            }
+
+            if node.kind() != "function_definition" {
+                block.push(node);
+                continue;
+            }
+
+            // Comments directly above the function document it
+            let mut doc_start = block.len();
+            let mut next_row = node.start_position().row;
+            while doc_start > 0 {
+                let previous = block[doc_start - 1];
+                if previous.kind() != "comment" || last_row(previous) + 1 != next_row {
+                    break;
+                }
+                next_row = previous.start_position().row;
+                doc_start -= 1;
+            }
+            let docs = block.split_off(doc_start);
+            flush_block(&mut block, &mut blocks);
+
+            if let Some(chunk) = self.function_chunk(node, docs.first().copied(), content) {
+                chunks.push(chunk);
+            }
+        }
+        flush_block(&mut block, &mut blocks);
+
+        (chunks, blocks)
+    }
+
+    /// A function chunk whose declaration runs from its first doc comment (or the function
+    /// itself) to the opening of its body
+    fn function_chunk(
+        &self,
+        node: Node,
+        first_doc: Option<Node>,
+        content: &str,
+    ) -> Option<CodeChunk> {
+        let name = node
+            .child_by_field_name("name")?
+            .utf8_text(content.as_bytes())
+            .ok()?
+            .to_string();
+        let start_node = first_doc.unwrap_or(node);
+        let declaration_start = start_node.start_byte() - start_node.start_position().column;
+
+        let (declaration, body) = match node.child_by_field_name("body") {
+            Some(body) => {
+                // Keep the opening `{` or `(` of the body in the declaration
+                let body_start = body.start_byte() + 1;
+                (
+                    &content[declaration_start..body_start],
+                    &content[body_start..body.end_byte()],
+                )
+            }
+            None => (&content[declaration_start..node.end_byte()], ""),
+        };
+
+        Some(CodeChunk {
+            start_line: start_node.start_position().row,
+            end_line: last_row(node),
+            chunk_type: ChunkType::Function,
+            name,
+            content: body.to_string(),
+            declaration: declaration.trim_end().to_string(),
+            doc: utils::doc_text(&content[declaration_start..node.start_byte()]),
+        })
+    }
+}
+
+/// Records the line range of the pending block, if any, and starts a new one
+fn flush_block(block: &mut Vec<Node>, blocks: &mut Vec<(usize, usize)>) {
+    if let (Some(first), Some(last)) = (block.first(), block.last()) {
+        blocks.push((first.start_position().row, last_row(*last)));
+    }
+    block.clear();
+}
+
+/// Last line of a node, not counting a trailing newline it may end with
+fn last_row(node: Node) -> usize {
+    let end = node.end_position();
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+
+impl LanguageProcessor for BashProcessor {
+    fn language_name(&self) -> &str {
+        "bash"
+    }
+
+    fn get_file_extensions(&self) -> &[&str] {
+        &["sh", "bash"]
+    }
+
+    fn interpreters(&self) -> &[&str] {
+        &["sh", "bash", "dash", "ksh"]
+    }
+
+    fn chunk_code(&mut self, content: &str) -> Result<Vec<CodeChunk>> {
+        let tree = self
+            .parser
+            .parse(content, None)
+            .context("Failed to parse shell script")?;
+
+        let (mut chunks, blocks) = self.collect_chunks(tree.root_node(), content);
+
+        // Top-level commands are indexed in blocks, long ones split into windows
+        let windows = utils::window_uncovered_lines(content, &blocks, &chunks);
+        chunks.extend(windows);
+        chunks.sort_by_key(|chunk| chunk.start_line);
+
+        Ok(chunks)
+    }
+
+    fn clone_box(&self) -> Result<Box<dyn LanguageProcessor>> {
+        Ok(Box::new(BashProcessor::new()?))
+    }
+}
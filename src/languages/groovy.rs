@@ -0,0 +1,563 @@
+use crate::language_processor::utils;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use anyhow::Result;
+
+/// Splits Groovy sources and Gradle build scripts without a grammar, as there's no
+/// tree-sitter grammar for Groovy among the ones probe builds with. Classes and their
+/// methods are chunked like Java's, with the class declarations shown above a method. At
+/// the top level of a script, functions, task definitions like `task docs(type: Copy)` or
+/// `tasks.register('docs')` and blocks like `dependencies { ... }` become chunks named
+/// after them. Other statements are indexed in blocks separated by blank lines.
+pub struct GroovyProcessor;
+
+impl GroovyProcessor {
+    pub fn new() -> Result<Self> {
+        Ok(Self)
+    }
+}
+
+/// Starts of the comment lines documenting a definition
+const COMMENT_MARKERS: &[&str] = &["//", "/*", "*"];
+
+/// Words that may precede a declaration without changing what it declares
+const MODIFIERS: &[&str] = &[
+    "public",
+    "protected",
+    "private",
+    "static",
+    "final",
+    "abstract",
+    "synchronized",
+    "native",
+    "transient",
+    "volatile",
+    "strictfp",
+    "default",
+];
+
+/// Statements whose blocks define nothing
+const CONTROL_KEYWORDS: &[&str] = &[
+    "if", "else", "for", "while", "do", "switch", "try", "catch", "finally", "return", "new",
+    "throw", "assert",
+];
+
+/// Calls defining or configuring a Gradle task named by their first argument
+const TASK_CALLS: &[&str] = &[
+    "task",
+    "tasks.register",
+    "tasks.create",
+    "tasks.named",
+    "tasks.getByName",
+    "tasks.maybeCreate",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Class,
+    Interface,
+    Method,
+    /// A method defined at the top level of a script
+    Function,
+    Task,
+    /// Any other top-level call with a closure, like `dependencies { ... }`
+    Block,
+}
+
+impl Kind {
+    fn chunk_type(self) -> ChunkType {
+        match self {
+            Kind::Class => ChunkType::Class,
+            Kind::Interface => ChunkType::Interface,
+            Kind::Method => ChunkType::Method,
+            Kind::Function | Kind::Task => ChunkType::Function,
+            Kind::Block => ChunkType::Module,
+        }
+    }
+}
+
+/// A class, method, task or block definition
+struct Definition {
+    kind: Kind,
+    name: String,
+    /// Byte offset of the first annotation, modifier or word of the definition
+    start: usize,
+    /// Byte offset just past the `{` opening the body
+    header_end: usize,
+    /// Byte offset just past the `}` closing the body
+    end: usize,
+    /// Methods and nested classes of a class
+    children: Vec<Definition>,
+}
+
+/// Reads the definitions of a file, skipping comments and strings
+struct Parser<'a> {
+    content: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn byte(&self) -> Option<u8> {
+        self.content.as_bytes().get(self.pos).copied()
+    }
+
+    /// Moves past the character at the current position
+    fn advance(&mut self) {
+        self.pos += self.content[self.pos..]
+            .chars()
+            .next()
+            .map_or(1, char::len_utf8);
+    }
+
+    /// Moves past a comment at the current position, up to the newline ending a line
+    /// comment. Returns whether there was one.
+    fn skip_comment(&mut self) -> bool {
+        let rest = &self.content[self.pos..];
+        if rest.starts_with("//") {
+            self.pos += rest.find('\n').unwrap_or(rest.len());
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            self.pos += comment.find("*/").map_or(rest.len(), |n| n + 4);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Moves past whitespace and comments
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = &self.content[self.pos..];
+            self.pos += rest.len() - rest.trim_start().len();
+            if !self.skip_comment() {
+                return;
+            }
+        }
+    }
+
+    /// Moves past the string starting at the current position, including triple-quoted
+    /// ones spanning several lines
+    fn skip_string(&mut self) {
+        let rest = &self.content[self.pos..];
+        for quotes in ["\"\"\"", "'''"] {
+            if let Some(text) = rest.strip_prefix(quotes) {
+                self.pos += text.find(quotes).map_or(rest.len(), |n| n + 6);
+                return;
+            }
+        }
+
+        let bytes = self.content.as_bytes();
+        let quote = bytes[self.pos];
+        self.pos += 1;
+        while let Some(byte) = self.byte() {
+            match byte {
+                b'\\' => self.pos += 2,
+                b'\n' => return, // Unterminated
+                _ if byte == quote => {
+                    self.pos += 1;
+                    return;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        self.pos = self.pos.min(bytes.len());
+    }
+
+    /// Moves past the `}` closing the block whose `{` is just before the current position
+    fn skip_block(&mut self) {
+        let mut depth = 1;
+        while let Some(byte) = self.byte() {
+            match byte {
+                b'"' | b'\'' => {
+                    self.skip_string();
+                    continue;
+                }
+                b'/' if self.skip_comment() => continue,
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Reads the statement starting at the current position up to the `{` opening its
+    /// block and moves past it, returning the offset of the `{`. Returns `None` for
+    /// statements without a block, like `apply plugin: 'java'`, leaving the position at
+    /// their end. Closures passed as arguments or assigned, as in `def run = { ... }`,
+    /// are part of the statement.
+    fn statement(&mut self) -> Option<usize> {
+        let start = self.pos;
+        let mut depth = 0usize;
+        let mut assignment = false;
+        while let Some(byte) = self.byte() {
+            match byte {
+                b'\n' if depth == 0 => {
+                    if !self.continues(start) {
+                        return None;
+                    }
+                    self.pos += 1;
+                }
+                b'"' | b'\'' => self.skip_string(),
+                b'/' if self.skip_comment() => {}
+                b'(' | b'[' => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                b')' | b']' => {
+                    depth = depth.saturating_sub(1);
+                    self.pos += 1;
+                }
+                b'{' if depth == 0 && !assignment => {
+                    self.pos += 1;
+                    return Some(self.pos - 1);
+                }
+                b'{' => {
+                    self.pos += 1;
+                    self.skip_block();
+                }
+                b'}' | b';' if depth == 0 => return None,
+                b'=' if depth == 0 => {
+                    // Not part of `==`, `!=`, `<=`, `>=` or `=~`
+                    let previous = self.content.as_bytes()[..self.pos].last();
+                    let next = self.content.as_bytes().get(self.pos + 1);
+                    if !matches!(previous, Some(b'=' | b'!' | b'<' | b'>'))
+                        && !matches!(next, Some(b'=' | b'~'))
+                    {
+                        assignment = true;
+                    }
+                    self.pos += 1;
+                }
+                _ => self.advance(),
+            }
+        }
+        None
+    }
+
+    /// Whether the statement starting at `start` goes on past the newline at the current
+    /// position: after annotations on lines of their own, before a `{` on the next line,
+    /// or before `extends`, `implements`, `throws` or a chained call
+    fn continues(&self, start: usize) -> bool {
+        let header = strip_annotations(&self.content[start..self.pos]);
+        if header.trim().is_empty() || header.trim_end().ends_with(',') {
+            return true;
+        }
+        let mut next = Parser {
+            content: self.content,
+            pos: self.pos,
+        };
+        next.skip_trivia();
+        let rest = &self.content[next.pos..];
+        rest.starts_with('{')
+            || rest.starts_with('.')
+            || ["extends", "implements", "throws"]
+                .iter()
+                .any(|keyword| leading_word(rest) == *keyword)
+    }
+
+    /// Reads definitions up to the `}` closing the enclosing body, or the end of the file,
+    /// and moves past it. `class_name` is the name of the class whose body is read, `None`
+    /// at the top level of a script.
+    fn definitions(&mut self, class_name: Option<&str>) -> Vec<Definition> {
+        let mut definitions = Vec::new();
+        loop {
+            self.skip_trivia();
+            let start = self.pos;
+            match self.byte() {
+                None => return definitions,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return definitions;
+                }
+                Some(b';') => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let Some(open) = self.statement() else {
+                continue;
+            };
+            let Some((kind, name)) = classify(&self.content[start..open], class_name) else {
+                self.skip_block();
+                continue;
+            };
+            let header_end = self.pos;
+            let children = match kind {
+                Kind::Class | Kind::Interface => self.definitions(Some(&name)),
+                _ => {
+                    self.skip_block();
+                    Vec::new()
+                }
+            };
+            definitions.push(Definition {
+                kind,
+                name,
+                start,
+                header_end,
+                end: self.pos,
+                children,
+            });
+        }
+    }
+}
+
+/// The first word of `text`, e.g. `class` for `class Greeter`
+fn leading_word(text: &str) -> &str {
+    let len = text
+        .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '$')
+        .unwrap_or(text.len());
+    &text[..len]
+}
+
+/// `header` without the annotations it starts with, like `@CompileStatic` or
+/// `@Grab('org.slf4j:slf4j-api:2.0.9')`
+fn strip_annotations(header: &str) -> &str {
+    let mut rest = header.trim_start();
+    while let Some(annotation) = rest.strip_prefix('@') {
+        if leading_word(annotation) == "interface" {
+            break;
+        }
+        let name_len = annotation
+            .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '.')
+            .unwrap_or(annotation.len());
+        rest = &annotation[name_len..];
+        if rest.starts_with('(') {
+            let mut depth = 0;
+            let end = rest.find(|c: char| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            });
+            rest = end.map_or("", |end| &rest[end + 1..]);
+        }
+        rest = rest.trim_start();
+    }
+    rest
+}
+
+/// What the statement `header`, which opens a block, defines and its name. Inside a class
+/// named `class_name` only classes, methods and constructors are definitions.
+fn classify(header: &str, class_name: Option<&str>) -> Option<(Kind, String)> {
+    let mut header = strip_annotations(header).trim();
+    while let Some(modifier) = MODIFIERS.iter().find(|m| leading_word(header) == **m) {
+        header = header[modifier.len()..].trim_start();
+    }
+    let first = leading_word(header);
+    let after_first = header[first.len()..].trim_start();
+    match first {
+        "class" | "enum" | "record" => {
+            return Some((Kind::Class, leading_word(after_first).to_string()))
+                .filter(|(_, name)| !name.is_empty());
+        }
+        "interface" | "trait" => {
+            return Some((Kind::Interface, leading_word(after_first).to_string()))
+                .filter(|(_, name)| !name.is_empty());
+        }
+        "" if header.starts_with("@interface") => {
+            let name = leading_word(header["@interface".len()..].trim_start());
+            return (!name.is_empty()).then(|| (Kind::Interface, name.to_string()));
+        }
+        // Blocks like `static { ... }` and closures
+        "" => return None,
+        _ if CONTROL_KEYWORDS.contains(&first) => return None,
+        _ => {}
+    }
+
+    // A name and parameters after a return type or `def`, or a constructor
+    if let Some(paren) = header.find('(') {
+        let before = header[..paren].trim_end();
+        // Spock features and other tests may be named by a string, like `def "adds up"()`
+        let quoted = before.strip_suffix(['"', '\'']).and_then(|rest| {
+            let open = rest.rfind(['"', '\''])?;
+            Some((
+                rest[..open].split_whitespace().count() + 1,
+                &rest[open + 1..],
+            ))
+        });
+        let words: Vec<&str> = before.split_whitespace().collect();
+        let (word_count, name) = match (quoted, words.last()) {
+            (Some(quoted), _) => quoted,
+            (None, Some(&name)) if leading_word(name) == name => (words.len(), name),
+            _ => (0, ""),
+        };
+        let kind = match class_name {
+            _ if name.is_empty() => None,
+            Some(class_name) if word_count >= 2 || name == class_name => Some(Kind::Method),
+            None if word_count >= 2 && first != "task" => Some(Kind::Function),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            return Some((kind, name.to_string()));
+        }
+    }
+    if class_name.is_some() {
+        return None;
+    }
+
+    let callee_len = header
+        .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '$' && c != '.')
+        .unwrap_or(header.len());
+    let (callee, arguments) = header.split_at(callee_len);
+    if TASK_CALLS.contains(&callee) {
+        let arguments = arguments.trim_start();
+        let arguments = arguments
+            .strip_prefix('(')
+            .unwrap_or(arguments)
+            .trim_start();
+        let name = match arguments.chars().next()? {
+            quote @ ('"' | '\'') => arguments[1..].split(quote).next()?,
+            _ => leading_word(arguments),
+        };
+        return (!name.is_empty()).then(|| (Kind::Task, name.to_string()));
+    }
+    Some((
+        Kind::Block,
+        header.split_whitespace().collect::<Vec<_>>().join(" "),
+    ))
+}
+
+/// Builds the chunks of a file's definitions
+struct Chunks<'a> {
+    content: &'a str,
+    lines: Vec<&'a str>,
+    line_index: utils::LineIndex,
+    chunks: Vec<CodeChunk>,
+}
+
+impl Chunks<'_> {
+    /// Offset of the start of a definition's line, or of its first word when something
+    /// else precedes it on that line
+    fn line_start(&self, definition: &Definition) -> usize {
+        let line_start = self
+            .line_index
+            .line_start(self.line_index.line_of(definition.start));
+        if self.content[line_start..definition.start].trim().is_empty() {
+            line_start
+        } else {
+            definition.start
+        }
+    }
+
+    /// Offset where a definition's text starts, including the comments above it
+    fn text_start(&self, definition: &Definition, previous_end: Option<usize>) -> usize {
+        let first_line = self.line_index.line_of(definition.start);
+        let docs_start =
+            utils::comment_start(&self.lines, first_line, previous_end, COMMENT_MARKERS);
+        if docs_start < first_line {
+            self.line_index.line_start(docs_start)
+        } else {
+            self.line_start(definition)
+        }
+    }
+
+    /// Adds chunks for `definitions` and their children. `enclosing` holds the headers of
+    /// the enclosing classes, which start the declarations.
+    fn add(
+        &mut self,
+        definitions: &[Definition],
+        mut previous_end: Option<usize>,
+        enclosing: &mut Vec<String>,
+    ) {
+        for definition in definitions {
+            let text_start = self.text_start(definition, previous_end);
+            let end_line = self.line_index.line_of(definition.end - 1);
+            let header_line = self.line_index.line_of(definition.header_end - 1);
+            previous_end = Some(end_line);
+
+            let mut declaration = enclosing.join("\n");
+            if !declaration.is_empty() {
+                declaration.push('\n');
+            }
+            declaration.push_str(self.content[text_start..definition.header_end].trim_end());
+
+            // Methods and nested classes are chunks of their own, so a class's body leaves
+            // them out, like Java's
+            let mut body = String::new();
+            let mut offset = definition.header_end;
+            let mut child_previous_end = Some(header_line);
+            for child in &definition.children {
+                let child_start = self.text_start(child, child_previous_end);
+                child_previous_end = Some(self.line_index.line_of(child.end - 1));
+                body.push_str(&self.content[offset..child_start]);
+                offset = child.end;
+            }
+            body.push_str(&self.content[offset..definition.end]);
+            let body: Vec<&str> = body
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .collect();
+
+            self.chunks.push(CodeChunk {
+                start_line: self.line_index.line_of(text_start),
+                end_line,
+                chunk_type: definition.kind.chunk_type(),
+                name: definition.name.clone(),
+                content: body.join("\n"),
+                declaration,
+                doc: utils::doc_text(&self.content[text_start..definition.start]),
+            });
+
+            if !definition.children.is_empty() {
+                // Enclosing headers are shown without their comments, like `class Greeter {`
+                let header = &self.content[self.line_start(definition)..definition.header_end];
+                enclosing.push(header.to_string());
+                self.add(&definition.children, Some(header_line), enclosing);
+                enclosing.pop();
+            }
+        }
+    }
+}
+
+impl LanguageProcessor for GroovyProcessor {
+    fn language_name(&self) -> &str {
+        "groovy"
+    }
+
+    fn get_file_extensions(&self) -> &[&str] {
+        &["groovy", "gradle", "gvy"]
+    }
+
+    fn interpreters(&self) -> &[&str] {
+        &["groovy"]
+    }
+
+    fn chunk_code(&mut self, content: &str) -> Result<Vec<CodeChunk>> {
+        // A `#!/usr/bin/env groovy` line isn't a comment
+        let pos = if content.starts_with("#!") {
+            content.find('\n').unwrap_or(content.len())
+        } else {
+            0
+        };
+        let definitions = Parser { content, pos }.definitions(None);
+        let lines: Vec<&str> = content.lines().collect();
+        let mut chunks = Chunks {
+            content,
+            line_index: utils::LineIndex::new(content),
+            lines,
+            chunks: Vec::new(),
+        };
+        chunks.add(&definitions, None, &mut Vec::new());
+        let Chunks {
+            lines, mut chunks, ..
+        } = chunks;
+
+        // Other top-level statements are indexed in blocks, long ones split into windows
+        let windows = utils::window_uncovered_lines(content, &utils::paragraphs(&lines), &chunks);
+        chunks.extend(windows);
+        chunks.sort_by_key(|chunk| chunk.start_line);
+
+        Ok(chunks)
+    }
+
+    fn clone_box(&self) -> Result<Box<dyn LanguageProcessor>> {
+        Ok(Box::new(GroovyProcessor::new()?))
+    }
+}
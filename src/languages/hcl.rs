@@ -0,0 +1,275 @@
+use crate::language_processor::utils;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use anyhow::Result;
+
+/// Splits Terraform and other HCL files into their top-level blocks without a grammar,
+/// as there's no tree-sitter grammar for HCL among the ones probe builds with. Blocks
+/// become chunks named after their Terraform address, like `aws_instance.web` or
+/// `module.vpc`; top-level attributes, as in `.tfvars` files, are indexed in blocks
+/// separated by blank lines.
+pub struct HclProcessor;
+
+impl HclProcessor {
+    pub fn new() -> Result<Self> {
+        Ok(Self)
+    }
+}
+
+/// Starts of the comment lines documenting a block
+const COMMENT_MARKERS: &[&str] = &["#", "//", "/*", "*"];
+
+/// A top-level block, like `resource "aws_instance" "web" { ... }`
+struct Block {
+    /// Byte offset of the block type
+    start: usize,
+    /// Byte offset of the `{` opening the body
+    open: usize,
+    /// Byte offset just past the `}` closing the body
+    end: usize,
+    /// Block type followed by its labels, without quotes
+    words: Vec<String>,
+}
+
+/// Finds the top-level blocks of the file. Understands comments, quoted strings with
+/// `${...}` and `%{...}` template sequences, and heredocs; braces and brackets in
+/// attribute values don't start blocks.
+fn top_level_blocks(content: &str) -> Vec<Block> {
+    let bytes = content.as_bytes();
+    let mut blocks = Vec::new();
+    // Type and labels of the block whose header is being read, `None` after an `=` or
+    // anything else that can't be part of a header
+    let mut header: Option<(usize, Vec<String>)> = Some((0, Vec::new()));
+    let mut open_block: Option<(usize, usize, Vec<String>)> = None;
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let rest = &bytes[i..];
+        if rest.starts_with(b"#") || rest.starts_with(b"//") {
+            i = content[i..].find('\n').map_or(content.len(), |n| i + n);
+            continue;
+        }
+        if rest.starts_with(b"/*") {
+            i = content[i + 2..]
+                .find("*/")
+                .map_or(content.len(), |n| i + 2 + n + 2);
+            continue;
+        }
+        if rest.starts_with(b"<<") {
+            i = skip_heredoc(content, i);
+            header = None;
+            continue;
+        }
+
+        match byte {
+            b'\n' if depth == 0 => header = Some((i + 1, Vec::new())),
+            _ if byte.is_ascii_whitespace() => {}
+            b'"' => {
+                let end = skip_string(content, i);
+                if depth == 0 {
+                    let label = &content[i + 1..end];
+                    let label = label.strip_suffix('"').unwrap_or(label);
+                    // Labels follow the block type
+                    if let Some((_, words)) = header.as_mut().filter(|(_, w)| !w.is_empty()) {
+                        words.push(label.to_string());
+                    } else {
+                        header = None;
+                    }
+                }
+                i = end;
+                continue;
+            }
+            b'{' if depth == 0 => {
+                match header.take() {
+                    Some((start, words)) if !words.is_empty() => {
+                        open_block = Some((start, i, words));
+                    }
+                    _ => open_block = None,
+                }
+                depth += 1;
+            }
+            b'{' | b'[' | b'(' => depth += 1,
+            b'}' | b']' | b')' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    if let Some((start, open, words)) = open_block.take() {
+                        blocks.push(Block {
+                            start,
+                            open,
+                            end: i + 1,
+                            words,
+                        });
+                    }
+                }
+            }
+            _ if depth == 0 && (byte.is_ascii_alphabetic() || byte == b'_') => {
+                let word_end = content[i..]
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '-')
+                    .map_or(content.len(), |n| i + n);
+                if let Some((start, words)) = header.as_mut() {
+                    if words.is_empty() {
+                        *start = i;
+                    }
+                    words.push(content[i..word_end].to_string());
+                }
+                i = word_end;
+                continue;
+            }
+            _ if depth == 0 => header = None,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    blocks
+}
+
+/// Offset just past the string whose `"` is at `start`, skipping over template
+/// sequences, which may contain strings of their own
+fn skip_string(content: &str, start: usize) -> usize {
+    let bytes = content.as_bytes();
+    let mut i = start + 1;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        if rest.starts_with(b"$${") || rest.starts_with(b"%%{") {
+            i += 3; // Escaped, a literal `${`
+        } else if rest.starts_with(b"${") || rest.starts_with(b"%{") {
+            i = skip_template(content, i + 2);
+        } else {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' => return i + 1,
+                b'\n' => return i, // Unterminated
+                _ => i += 1,
+            }
+        }
+    }
+    bytes.len()
+}
+
+/// Offset just past the `}` closing a template sequence whose body starts at `start`
+fn skip_template(content: &str, start: usize) -> usize {
+    let bytes = content.as_bytes();
+    let mut depth = 1;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i = skip_string(content, i);
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Offset just past the line ending a `<<EOT` or `<<-EOT` heredoc starting at `start`.
+/// Anything else starting with `<<` is skipped over.
+fn skip_heredoc(content: &str, start: usize) -> usize {
+    let rest = &content[start + 2..];
+    let rest = rest.strip_prefix('-').unwrap_or(rest);
+    let marker_end = rest
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(rest.len());
+    let marker = &rest[..marker_end];
+    if marker.is_empty() {
+        return start + 2;
+    }
+    let Some(body) = rest[marker_end..].find('\n') else {
+        return content.len();
+    };
+
+    let mut offset = content.len() - rest.len() + marker_end + body + 1;
+    for line in content[offset..].split_inclusive('\n') {
+        offset += line.len();
+        if line.trim() == marker {
+            // The newline ends the statement, so leave it for the caller
+            return offset - usize::from(line.ends_with('\n'));
+        }
+    }
+    content.len()
+}
+
+/// Kind and Terraform address of a block, e.g. `data.aws_ami.ubuntu` for
+/// `data "aws_ami" "ubuntu"`. Blocks Terraform doesn't address are named by type and labels.
+fn block_name(words: &[String]) -> (ChunkType, String) {
+    let kind = words[0].as_str();
+    let labels = words[1..].join(".");
+    match kind {
+        _ if labels.is_empty() => (ChunkType::Other, kind.to_string()),
+        "resource" => (ChunkType::Struct, labels),
+        "data" => (ChunkType::Struct, format!("data.{labels}")),
+        "module" => (ChunkType::Module, format!("module.{labels}")),
+        "variable" => (ChunkType::Other, format!("var.{labels}")),
+        _ => (ChunkType::Other, format!("{kind}.{labels}")),
+    }
+}
+
+impl LanguageProcessor for HclProcessor {
+    fn language_name(&self) -> &str {
+        "hcl"
+    }
+
+    fn get_file_extensions(&self) -> &[&str] {
+        &["tf", "tfvars", "hcl"]
+    }
+
+    fn chunk_code(&mut self, content: &str) -> Result<Vec<CodeChunk>> {
+        let lines: Vec<&str> = content.lines().collect();
+        let line_index = utils::LineIndex::new(content);
+
+        let mut chunks = Vec::new();
+        let mut previous_end = None;
+        for block in top_level_blocks(content) {
+            let first_line = line_index.line_of(block.start);
+            let last_line = line_index.line_of(block.end - 1);
+            let docs_start =
+                utils::comment_start(&lines, first_line, previous_end, COMMENT_MARKERS);
+            previous_end = Some(last_line);
+
+            // The declaration is the header up to the end of the line opening the body,
+            // with the comments above it
+            let declaration_start = if docs_start < first_line {
+                line_index.line_start(docs_start)
+            } else {
+                block.start
+            };
+            let header_end = content[block.open..block.end]
+                .find('\n')
+                .map_or(block.end, |n| block.open + n);
+            let (chunk_type, name) = block_name(&block.words);
+            chunks.push(CodeChunk {
+                start_line: docs_start,
+                end_line: last_line,
+                chunk_type,
+                name,
+                content: content[header_end..block.end].to_string(),
+                declaration: content[declaration_start..header_end]
+                    .trim_end()
+                    .to_string(),
+                doc: utils::doc_text(&lines[docs_start..first_line].join("\n")),
+            });
+        }
+
+        // Top-level attributes are indexed in blocks, long ones split into windows
+        let windows = utils::window_uncovered_lines(content, &utils::paragraphs(&lines), &chunks);
+        chunks.extend(windows);
+        chunks.sort_by_key(|chunk| chunk.start_line);
+
+        Ok(chunks)
+    }
+
+    fn clone_box(&self) -> Result<Box<dyn LanguageProcessor>> {
+        Ok(Box::new(HclProcessor::new()?))
+    }
+}
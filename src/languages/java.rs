@@ -1,5 +1,7 @@
 use crate::language_processor::utils;
-use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use crate::language_processor::{
+    CallSite, ChunkType, CodeChunk, FileReferences, LanguageProcessor, Symbol,
+};
 use anyhow::{Context, Result};
 use tree_sitter::{Node, Parser};
 
@@ -65,6 +67,7 @@ impl JavaProcessor {
                     name: container_name.clone(),
                     content: class_content,
                     declaration,
+                    doc: self.doc_comments(node, content),
                 });
 
                 stack.push((node, container_name));
@@ -87,6 +90,7 @@ impl JavaProcessor {
                         name: method_name,
                         content: body,
                         declaration,
+                        doc: self.doc_comments(node, content),
                     });
                 }
                 // If method has no name (lambda/anonymous), we ignore it
@@ -98,6 +102,63 @@ impl JavaProcessor {
         }
     }
 
+    /// Collects method definitions qualified with their enclosing types, and the method
+    /// invocations inside each of them
+    fn collect_references(
+        &self,
+        node: Node,
+        content: &str,
+        scope: &mut Vec<String>,
+        caller: Option<&String>,
+        references: &mut FileReferences,
+    ) {
+        let mut is_container = false;
+        let mut method = None;
+        match node.kind() {
+            "class_declaration"
+            | "interface_declaration"
+            | "record_declaration"
+            | "enum_declaration" => {
+                scope.push(self.get_container_name(node, content));
+                is_container = true;
+            }
+            "method_declaration" | "constructor_declaration" => {
+                if let Some(name) = self.get_method_name(node, content) {
+                    scope.push(name);
+                    let qualified = scope.join(".");
+                    scope.pop();
+                    references.definitions.push(Symbol {
+                        name: qualified.clone(),
+                        line: node.start_position().row,
+                    });
+                    method = Some(qualified);
+                }
+            }
+            "method_invocation" => {
+                let callee = node
+                    .child_by_field_name("name")
+                    .and_then(|name| name.utf8_text(content.as_bytes()).ok());
+                if let (Some(caller), Some(callee)) = (caller, callee) {
+                    references.calls.push(CallSite {
+                        caller: caller.clone(),
+                        callee: callee.to_string(),
+                        line: node.start_position().row,
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        let caller = method.as_ref().or(caller);
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.collect_references(child, content, scope, caller, references);
+        }
+        if is_container {
+            scope.pop();
+        }
+    }
+
     fn get_container_name(&self, container_node: Node, content: &str) -> String {
         let mut cursor = container_node.walk();
         if let Some(identifier_node) = utils::find_child_node(&mut cursor, &["identifier"]) {
@@ -360,6 +421,12 @@ impl JavaProcessor {
         (start_byte, start_node)
     }
 
+    /// Text of the comments right above a declaration, like its JavaDoc
+    fn doc_comments(&self, node: Node, content: &str) -> String {
+        let (start_byte, _) = self.find_container_start_with_comments(node);
+        utils::doc_text(&content[start_byte..node.start_byte()])
+    }
+
     /// Finds the index of the first character of the class/interface body, which you can also think of as the end of the declaration block.
     fn find_container_body_start(&self, container_node: Node) -> Option<usize> {
         // In Java class/interface declarations, the body is typically the last child
@@ -389,6 +456,10 @@ impl JavaProcessor {
 }
 
 impl LanguageProcessor for JavaProcessor {
+    fn language_name(&self) -> &str {
+        "java"
+    }
+
     fn get_file_extensions(&self) -> &[&str] {
         &["java"]
     }
@@ -405,9 +476,33 @@ impl LanguageProcessor for JavaProcessor {
 
         self.collect_chunks_recursively(root_node, content, &mut stack, &mut chunks);
 
+        // Tree-sitter recovers around syntax errors, but code inside ERROR nodes may not
+        // belong to any class or method chunk; index it in plain windows instead of losing it
+        let error_regions = utils::error_regions(root_node);
+        let windows = utils::window_uncovered_lines(content, &error_regions, &chunks);
+        chunks.extend(windows);
+        chunks.sort_by_key(|chunk| chunk.start_line);
+
         Ok(chunks)
     }
 
+    fn extract_references(&mut self, content: &str) -> Result<FileReferences> {
+        let tree = self
+            .parser
+            .parse(content, None)
+            .context("Failed to parse Java file")?;
+
+        let mut references = FileReferences::default();
+        self.collect_references(
+            tree.root_node(),
+            content,
+            &mut Vec::new(),
+            None,
+            &mut references,
+        );
+        Ok(references)
+    }
+
     fn clone_box(&self) -> Result<Box<dyn LanguageProcessor>> {
         Ok(Box::new(JavaProcessor::new()?))
     }
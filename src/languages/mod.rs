@@ -1,7 +1,19 @@
+pub mod bash;
+pub mod groovy;
+pub mod hcl;
 pub mod java;
+pub mod perl;
+pub mod proto;
+pub mod sql;
 
 #[cfg(test)]
 mod tests;
 
-// Re-export the Java language processor for easy access
+// Re-export the language processors for easy access
+pub use bash::BashProcessor;
+pub use groovy::GroovyProcessor;
+pub use hcl::HclProcessor;
 pub use java::JavaProcessor;
+pub use perl::PerlProcessor;
+pub use proto::ProtoProcessor;
+pub use sql::SqlProcessor;
@@ -0,0 +1,714 @@
+use crate::language_processor::utils;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use anyhow::Result;
+
+/// Splits Perl scripts and modules into their packages and subs without a grammar, as
+/// there's no tree-sitter grammar for Perl among the ones probe builds with. Subs inside a
+/// package become method chunks whose declaration starts with the package, and the POD
+/// block and comments right above a sub are part of its declaration and doc. Other
+/// statements, and the POD after `__END__`, are indexed in blocks separated by blank lines.
+pub struct PerlProcessor;
+
+impl PerlProcessor {
+    pub fn new() -> Result<Self> {
+        Ok(Self)
+    }
+}
+
+/// Starts of the comment lines documenting a definition
+const COMMENT_MARKERS: &[&str] = &["#"];
+
+/// Words after which a `/` starts a pattern rather than dividing
+const PATTERN_KEYWORDS: &[&str] = &[
+    "if", "unless", "while", "until", "and", "or", "not", "xor", "return", "split", "grep", "map",
+    "join", "push", "unshift", "print", "when", "eq", "ne", "lt", "gt", "le", "ge", "cmp", "x",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Package,
+    Sub,
+}
+
+/// A package or sub definition
+struct Definition {
+    kind: Kind,
+    name: String,
+    /// Byte offset of the `package` or `sub` keyword
+    start: usize,
+    /// Byte offset just past the `{` opening the body, or the `;` ending `package Name;`
+    header_end: usize,
+    /// Byte offset just past the `}` closing the body. A `package Name;` statement ends
+    /// where the next one starts, or at the end of its enclosing block.
+    end: usize,
+    /// Subs of a package
+    children: Vec<Definition>,
+}
+
+/// Reads the definitions of a file, skipping comments, strings, patterns, heredocs and POD
+struct Parser<'a> {
+    content: &'a str,
+    pos: usize,
+    /// Whether the last token ends an operand, so a `/` after it divides rather than
+    /// starting a pattern
+    operand: bool,
+    /// Terminators of the heredocs started on the current line, whose bodies follow it,
+    /// and whether they may be indented, as for `<<~EOF`
+    heredocs: Vec<(String, bool)>,
+    /// Byte ranges of the POD blocks
+    pods: Vec<(usize, usize)>,
+    /// Byte offset of `__END__` or `__DATA__`, after which there's no code
+    code_end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(content: &'a str) -> Self {
+        Self {
+            content,
+            pos: 0,
+            operand: false,
+            heredocs: Vec::new(),
+            pods: Vec::new(),
+            code_end: content.len(),
+        }
+    }
+
+    fn byte(&self) -> Option<u8> {
+        self.content.as_bytes().get(self.pos).copied()
+    }
+
+    fn byte_at(&self, pos: usize) -> Option<u8> {
+        self.content.as_bytes().get(pos).copied()
+    }
+
+    /// Moves past the character at the current position
+    fn advance(&mut self) {
+        self.pos += self.content[self.pos..]
+            .chars()
+            .next()
+            .map_or(1, char::len_utf8);
+    }
+
+    fn at_line_start(&self, pos: usize) -> bool {
+        pos == 0 || self.content.as_bytes()[pos - 1] == b'\n'
+    }
+
+    /// Moves past whitespace, comments, POD blocks and the bodies of heredocs started on
+    /// the lines left behind
+    fn skip_trivia(&mut self) {
+        while let Some(byte) = self.byte() {
+            match byte {
+                b'\n' => {
+                    self.pos += 1;
+                    self.skip_heredoc_bodies();
+                }
+                b'#' => {
+                    let rest = &self.content[self.pos..];
+                    self.pos += rest.find('\n').unwrap_or(rest.len());
+                }
+                b'=' if self.at_line_start(self.pos)
+                    && self
+                        .byte_at(self.pos + 1)
+                        .is_some_and(|b| b.is_ascii_alphabetic()) =>
+                {
+                    self.skip_pod();
+                }
+                _ if byte.is_ascii_whitespace() => self.pos += 1,
+                _ => return,
+            }
+        }
+    }
+
+    /// Moves past the POD block starting at the current position, up to the end of its
+    /// `=cut` line
+    fn skip_pod(&mut self) {
+        let start = self.pos;
+        loop {
+            let rest = &self.content[self.pos..];
+            let line_len = rest.find('\n').unwrap_or(rest.len());
+            let is_cut = rest[..line_len]
+                .strip_prefix("=cut")
+                .is_some_and(|after| !after.starts_with(|c: char| c.is_alphanumeric()));
+            self.pos += line_len;
+            if is_cut || self.pos == self.content.len() {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.pods.push((start, self.pos));
+    }
+
+    /// Moves past the bodies of the heredocs started on the line before the current
+    /// position, up to their terminator lines
+    fn skip_heredoc_bodies(&mut self) {
+        for (terminator, indented) in std::mem::take(&mut self.heredocs) {
+            while self.pos < self.content.len() {
+                let rest = &self.content[self.pos..];
+                let line_len = rest.find('\n').map_or(rest.len(), |n| n + 1);
+                let line = rest[..line_len].trim_end_matches(['\n', '\r']);
+                self.pos += line_len;
+                let line = if indented { line.trim_start() } else { line };
+                if line == terminator {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Reads a heredoc marker like `<<"EOF"`, `<<'EOF'`, `<<EOF` or `<<~EOF` at the
+    /// current position, noting its terminator. Returns `false` for a `<<` shift.
+    fn heredoc(&mut self) -> bool {
+        let marker = &self.content[self.pos + 2..];
+        let (indented, marker) = match marker.strip_prefix('~') {
+            Some(marker) => (true, marker),
+            None => (false, marker),
+        };
+        let (terminator, len) = match marker.as_bytes().first() {
+            Some(&quote @ (b'"' | b'\'')) => {
+                let Some(close) = marker[1..].find(quote as char) else {
+                    return false;
+                };
+                (&marker[1..close + 1], close + 2)
+            }
+            Some(b) if b.is_ascii_alphabetic() || *b == b'_' => {
+                let len = marker
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(marker.len());
+                (&marker[..len], len)
+            }
+            _ => return false,
+        };
+        if terminator.contains('\n') {
+            return false;
+        }
+        self.heredocs.push((terminator.to_string(), indented));
+        self.pos += 2 + usize::from(indented) + len;
+        true
+    }
+
+    /// Moves past the text between the delimiter at the current position and the one
+    /// closing it, as for strings and patterns. Brackets used as delimiters nest.
+    fn skip_delimited(&mut self) {
+        let Some(open) = self.byte() else {
+            return;
+        };
+        let close = match open {
+            b'(' => b')',
+            b'[' => b']',
+            b'{' => b'}',
+            b'<' => b'>',
+            _ => open,
+        };
+        let mut depth = 0usize;
+        self.pos += 1;
+        while let Some(byte) = self.byte() {
+            self.pos += 1;
+            match byte {
+                b'\\' => self.pos += 1,
+                _ if byte == close && depth == 0 => return,
+                _ if byte == close => depth -= 1,
+                _ if byte == open => depth += 1,
+                _ => {}
+            }
+        }
+        self.pos = self.pos.min(self.content.len());
+    }
+
+    /// Whether the word `q`, `qq`, `qw`, `qr`, `m`, `s`, `tr` or `y` starting at `start`
+    /// and ending at the current position quotes something, rather than being a hash key
+    /// like in `$opts{s}` or `y => 1`, a method name or a file test like `-s $path`.
+    /// Returns the offset of its delimiter.
+    fn quote_delimiter(&self, start: usize) -> Option<usize> {
+        if self.content[..start].ends_with('-') {
+            return None;
+        }
+        let mut pos = self.pos;
+        while matches!(self.byte_at(pos), Some(b' ' | b'\t')) {
+            pos += 1;
+        }
+        let delimiter = self.byte_at(pos)?;
+        let quotes = match delimiter {
+            b'=' => self.byte_at(pos + 1) != Some(b'>'),
+            // After whitespace, `#` starts a comment
+            b'#' => pos == self.pos,
+            b',' | b';' | b')' | b'}' => false,
+            _ => delimiter.is_ascii_punctuation(),
+        };
+        quotes.then_some(pos)
+    }
+
+    /// Moves past the quote-like operator `word` whose delimiter is at the current
+    /// position, including the replacement of `s`, `tr` and `y` and any modifiers
+    fn skip_quote_like(&mut self, word: &str) {
+        let open = self.byte();
+        self.skip_delimited();
+        if matches!(word, "s" | "tr" | "y") {
+            if matches!(open, Some(b'(' | b'[' | b'{' | b'<')) {
+                self.skip_trivia();
+                self.skip_delimited();
+            } else if self.pos > 0 && self.byte_at(self.pos - 1) == open {
+                // The closing delimiter of the pattern opens the replacement
+                self.pos -= 1;
+                self.skip_delimited();
+            }
+        }
+        while self.byte().is_some_and(|b| b.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Reads a name like `Foo::Bar` or `new` at the current position
+    fn identifier(&mut self) -> &'a str {
+        let start = self.pos;
+        let rest = &self.content[start..];
+        let mut len = 0;
+        loop {
+            len += rest[len..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len() - len);
+            let more = rest[len..]
+                .strip_prefix("::")
+                .is_some_and(|after| after.starts_with(|c: char| c.is_ascii_alphanumeric()));
+            if !more {
+                break;
+            }
+            len += 2;
+        }
+        self.pos += len;
+        &rest[..len]
+    }
+
+    /// Moves past the variable whose sigil is at the current position, like `$name`,
+    /// `@Foo::list`, `$#items` or `$/`. Braces after a sigil, as in `@{$list}`, are left
+    /// to be read as a block.
+    fn variable(&mut self) {
+        let sigil = self.byte();
+        self.pos += 1;
+        match self.byte() {
+            Some(b) if b.is_ascii_alphabetic() || b == b'_' || b == b':' => {
+                self.identifier();
+            }
+            Some(b) if b.is_ascii_digit() => {
+                while self.byte().is_some_and(|b| b.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+            }
+            Some(b'^') if sigil == Some(b'$') => self.pos = (self.pos + 2).min(self.content.len()),
+            // Punctuation variables like `$'`, `$"` or `$/`, whose characters would
+            // otherwise start strings and patterns
+            Some(b)
+                if sigil == Some(b'$')
+                    && b.is_ascii_punctuation()
+                    && !matches!(b, b'{' | b'$' | b'(' | b'[') =>
+            {
+                self.pos += 1;
+            }
+            _ => {}
+        }
+        self.operand = true;
+    }
+
+    /// Reads definitions up to the `}` closing the enclosing block, or the end of the
+    /// code, and moves past it. Subs defined inside other blocks, like `BEGIN { ... }`,
+    /// belong to the enclosing package.
+    fn definitions(&mut self) -> Vec<Definition> {
+        let mut definitions = Vec::new();
+        // A `package Name;` statement, owning the subs up to the next one
+        let mut package: Option<Definition> = None;
+
+        loop {
+            self.skip_trivia();
+            let start = self.pos;
+            let Some(byte) = self.byte() else {
+                break;
+            };
+            match byte {
+                b'}' => break,
+                b'{' => {
+                    self.pos += 1;
+                    self.operand = false;
+                    for definition in self.definitions() {
+                        add(&mut definitions, &mut package, definition);
+                    }
+                    self.operand = true;
+                }
+                b'\'' | b'"' | b'`' => {
+                    self.skip_delimited();
+                    self.operand = true;
+                }
+                b'$' | b'@' => self.variable(),
+                b'%' | b'&' | b'*' if !self.operand => self.variable(),
+                b'/' if !self.operand => {
+                    self.skip_quote_like("m");
+                    self.operand = true;
+                }
+                b'/' if self.byte_at(self.pos + 1) == Some(b'/') => {
+                    // `//`, defined-or
+                    self.pos += 2;
+                    self.operand = false;
+                }
+                b'<' if self.byte_at(self.pos + 1) == Some(b'<') && self.heredoc() => {
+                    self.operand = true;
+                }
+                b')' | b']' => {
+                    self.pos += 1;
+                    self.operand = true;
+                }
+                _ if byte.is_ascii_digit() => {
+                    while self
+                        .byte()
+                        .is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_')
+                    {
+                        self.pos += 1;
+                    }
+                    self.operand = true;
+                }
+                _ if byte.is_ascii_alphabetic() || byte == b'_' => {
+                    let word = self.identifier();
+                    self.operand = !PATTERN_KEYWORDS.contains(&word);
+                    match word {
+                        "__END__" | "__DATA__" if self.at_line_start(start) => {
+                            self.code_end = start;
+                            self.pos = self.content.len();
+                            break;
+                        }
+                        "sub" => {
+                            if let Some(sub) = self.sub(start) {
+                                add(&mut definitions, &mut package, sub);
+                            }
+                        }
+                        "package" => match self.package(start) {
+                            Some(definition) if definition.header_end == definition.end => {
+                                if let Some(mut previous) = package.take() {
+                                    previous.end = start;
+                                    definitions.push(previous);
+                                }
+                                package = Some(definition);
+                            }
+                            Some(definition) => add(&mut definitions, &mut package, definition),
+                            None => {}
+                        },
+                        "q" | "qq" | "qw" | "qr" | "m" | "s" | "tr" | "y" => {
+                            if let Some(delimiter) = self.quote_delimiter(start) {
+                                self.pos = delimiter;
+                                self.skip_quote_like(word);
+                                self.operand = true;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {
+                    self.advance();
+                    self.operand = false;
+                }
+            }
+        }
+
+        if let Some(mut package) = package {
+            package.end = self.pos.min(self.code_end);
+            definitions.push(package);
+        }
+        // Past the `}` closing the block
+        if self.byte() == Some(b'}') {
+            self.pos += 1;
+        }
+        // A `package Name;` statement is added after the blocks read while it was current
+        definitions.sort_by_key(|definition| definition.start);
+        definitions
+    }
+
+    /// Reads a named sub after its `sub` keyword, which starts at `start`, up to the `}`
+    /// closing its body. `None` for anonymous subs and declarations without a body.
+    fn sub(&mut self, start: usize) -> Option<Definition> {
+        let after_keyword = self.pos;
+        self.skip_trivia();
+        let name = self.identifier();
+        if name.is_empty() {
+            self.pos = after_keyword;
+            return None;
+        }
+
+        // A prototype or signature and attributes may come before the body
+        let mut depth = 0usize;
+        loop {
+            self.skip_trivia();
+            match self.byte()? {
+                b'(' => depth += 1,
+                b')' => depth = depth.saturating_sub(1),
+                b'{' if depth == 0 => break,
+                b';' | b'}' if depth == 0 => return None,
+                b'\'' | b'"' => {
+                    self.skip_delimited();
+                    continue;
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+        self.pos += 1;
+        let header_end = self.pos;
+        // Named subs inside a sub are left in its body
+        self.operand = false;
+        self.definitions();
+        self.operand = true;
+        Some(Definition {
+            kind: Kind::Sub,
+            name: name.to_string(),
+            start,
+            header_end,
+            end: self.pos,
+            children: Vec::new(),
+        })
+    }
+
+    /// Reads a package after its `package` keyword, which starts at `start`: up to the `;`
+    /// of a `package Name;` statement, whose `end` is left at its `header_end`, or up to
+    /// the `}` closing a `package Name { ... }` block
+    fn package(&mut self, start: usize) -> Option<Definition> {
+        self.skip_trivia();
+        let name = self.identifier();
+        if name.is_empty() {
+            return None;
+        }
+        // An optional version, like `package Foo 1.02;`
+        self.skip_trivia();
+        while self
+            .byte()
+            .is_some_and(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'_')
+        {
+            self.pos += 1;
+        }
+        self.skip_trivia();
+
+        let byte = self.byte()?;
+        if !matches!(byte, b';' | b'{') {
+            return None;
+        }
+        self.pos += 1;
+        let header_end = self.pos;
+        self.operand = false;
+        let children = if byte == b'{' {
+            self.definitions()
+        } else {
+            Vec::new()
+        };
+        Some(Definition {
+            kind: Kind::Package,
+            name: name.to_string(),
+            start,
+            header_end,
+            end: self.pos,
+            children,
+        })
+    }
+}
+
+/// Adds a definition to the subs of the current `package Name;` statement, if it's a sub
+/// and there's one, or else to `definitions`
+fn add(
+    definitions: &mut Vec<Definition>,
+    package: &mut Option<Definition>,
+    definition: Definition,
+) {
+    match package {
+        Some(package) if definition.kind == Kind::Sub => package.children.push(definition),
+        _ => definitions.push(definition),
+    }
+}
+
+/// The text of the comments and POD blocks documenting a definition, without comment
+/// markers and POD commands, keeping the titles of `=head` and `=item` commands
+fn doc_text(text: &str) -> String {
+    let mut in_pod = false;
+    let mut doc = Vec::new();
+    for line in text.lines() {
+        let command = line
+            .strip_prefix('=')
+            .filter(|command| command.starts_with(|c: char| c.is_ascii_alphabetic()));
+        if let Some(command) = command {
+            let (name, title) = command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, ""));
+            in_pod = name != "cut";
+            if name.starts_with("head") || name == "item" {
+                doc.push(title.trim().to_string());
+            }
+        } else if in_pod {
+            doc.push(line.trim().to_string());
+        } else {
+            doc.push(utils::doc_text(line));
+        }
+    }
+    doc.retain(|line| !line.is_empty());
+    doc.join("\n")
+}
+
+/// Builds the chunks of a file's definitions
+struct Chunks<'a> {
+    content: &'a str,
+    lines: Vec<&'a str>,
+    line_index: utils::LineIndex,
+    /// Line ranges of the POD blocks
+    pods: Vec<(usize, usize)>,
+    chunks: Vec<CodeChunk>,
+}
+
+impl Chunks<'_> {
+    /// Offset of the start of a definition's line, or of its keyword when something else
+    /// precedes it on that line
+    fn line_start(&self, definition: &Definition) -> usize {
+        let line_start = self
+            .line_index
+            .line_start(self.line_index.line_of(definition.start));
+        if self.content[line_start..definition.start].trim().is_empty() {
+            line_start
+        } else {
+            definition.start
+        }
+    }
+
+    /// Offset where a definition's text starts, including the comments and the POD block
+    /// above it. Blank lines may separate the POD block from the definition.
+    fn text_start(&self, definition: &Definition, previous_end: Option<usize>) -> usize {
+        let line = self.line_index.line_of(definition.start);
+        let mut first_line = utils::comment_start(&self.lines, line, previous_end, COMMENT_MARKERS);
+        let mut above = first_line;
+        while above > 0 && self.lines[above - 1].trim().is_empty() {
+            above -= 1;
+        }
+        let pod = self.pods.iter().find(|&&(start, end)| {
+            end + 1 == above && previous_end.is_none_or(|previous| start > previous)
+        });
+        if let Some(&(start, _)) = pod {
+            first_line = start;
+        }
+        // A `#!/usr/bin/perl` line isn't a comment
+        if first_line == 0 && self.lines.first().is_some_and(|l| l.starts_with("#!")) {
+            first_line = line.min(1);
+        }
+
+        if first_line < line {
+            self.line_index.line_start(first_line)
+        } else {
+            self.line_start(definition)
+        }
+    }
+
+    /// Adds chunks for `definitions` and their children. `package` is the header of the
+    /// enclosing package, which starts the declarations.
+    fn add(&mut self, definitions: &[Definition], previous_end: Option<usize>, package: &str) {
+        let mut previous_end = previous_end;
+        for (index, definition) in definitions.iter().enumerate() {
+            let text_start = self.text_start(definition, previous_end);
+            let header_line = self.line_index.line_of(definition.header_end - 1);
+
+            // A `package Name;` statement ends before the comments of the next package
+            let mut end = definition.end;
+            if let Some(next) = definitions.get(index + 1) {
+                end = end.min(self.text_start(next, Some(header_line)));
+            }
+            let end = self.content[..end]
+                .trim_end()
+                .len()
+                .max(definition.header_end);
+            let end_line = self.line_index.line_of(end - 1);
+            previous_end = Some(end_line);
+
+            let mut declaration = package.to_string();
+            if !declaration.is_empty() {
+                declaration.push('\n');
+            }
+            declaration.push_str(self.content[text_start..definition.header_end].trim_end());
+
+            // Subs are chunks of their own, so a package's body leaves them out
+            let mut body = String::new();
+            let mut offset = definition.header_end;
+            let mut child_previous_end = Some(header_line);
+            for child in &definition.children {
+                let child_start = self.text_start(child, child_previous_end);
+                child_previous_end = Some(self.line_index.line_of(child.end - 1));
+                body.push_str(&self.content[offset..child_start]);
+                offset = child.end;
+            }
+            body.push_str(&self.content[offset.min(end)..end]);
+            let body: Vec<&str> = body
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .collect();
+
+            let chunk_type = match definition.kind {
+                Kind::Package => ChunkType::Module,
+                Kind::Sub if package.is_empty() => ChunkType::Function,
+                Kind::Sub => ChunkType::Method,
+            };
+            self.chunks.push(CodeChunk {
+                start_line: self.line_index.line_of(text_start),
+                end_line,
+                chunk_type,
+                name: definition.name.clone(),
+                content: body.join("\n"),
+                declaration,
+                doc: doc_text(&self.content[text_start..definition.start]),
+            });
+
+            if !definition.children.is_empty() {
+                // The package is shown without its comments, like `package Shop::Cart;`
+                let header = &self.content[self.line_start(definition)..definition.header_end];
+                self.add(&definition.children, Some(header_line), header);
+            }
+        }
+    }
+}
+
+impl LanguageProcessor for PerlProcessor {
+    fn language_name(&self) -> &str {
+        "perl"
+    }
+
+    fn get_file_extensions(&self) -> &[&str] {
+        &["pl", "pm"]
+    }
+
+    fn interpreters(&self) -> &[&str] {
+        &["perl"]
+    }
+
+    fn chunk_code(&mut self, content: &str) -> Result<Vec<CodeChunk>> {
+        let mut parser = Parser::new(content);
+        let mut definitions = Vec::new();
+        // A stray `}` ends a read early, and the rest is read on
+        while parser.pos < content.len() {
+            definitions.extend(parser.definitions());
+        }
+        let line_index = utils::LineIndex::new(content);
+        let pods = parser
+            .pods
+            .iter()
+            .map(|&(start, end)| (line_index.line_of(start), line_index.line_of(end)))
+            .collect();
+        let lines: Vec<&str> = content.lines().collect();
+        let mut chunks = Chunks {
+            content,
+            line_index,
+            lines,
+            pods,
+            chunks: Vec::new(),
+        };
+        chunks.add(&definitions, None, "");
+        let Chunks {
+            lines, mut chunks, ..
+        } = chunks;
+
+        // Other statements are indexed in blocks, long ones split into windows
+        let windows = utils::window_uncovered_lines(content, &utils::paragraphs(&lines), &chunks);
+        chunks.extend(windows);
+        chunks.sort_by_key(|chunk| chunk.start_line);
+
+        Ok(chunks)
+    }
+
+    fn clone_box(&self) -> Result<Box<dyn LanguageProcessor>> {
+        Ok(Box::new(PerlProcessor::new()?))
+    }
+}
@@ -0,0 +1,360 @@
+use crate::language_processor::utils;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use anyhow::Result;
+
+/// Splits Protocol Buffers schemas into their messages, enums, services and RPCs without a
+/// grammar, as there's no tree-sitter grammar for Protobuf among the ones probe builds
+/// with. Nested types are named after their parent, like `Order.Item`. RPCs become method
+/// chunks whose declaration includes the service they belong to, so a search for an RPC
+/// shows it inside its service. Top-level statements like `package` and `import` are
+/// indexed in blocks separated by blank lines.
+pub struct ProtoProcessor;
+
+impl ProtoProcessor {
+    pub fn new() -> Result<Self> {
+        Ok(Self)
+    }
+}
+
+/// Starts of the comment lines documenting a definition
+const COMMENT_MARKERS: &[&str] = &["//", "/*", "*"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Message,
+    Enum,
+    Service,
+    Rpc,
+}
+
+impl Kind {
+    fn from_keyword(word: &str) -> Option<Self> {
+        match word {
+            "message" => Some(Kind::Message),
+            "enum" => Some(Kind::Enum),
+            "service" => Some(Kind::Service),
+            "rpc" => Some(Kind::Rpc),
+            _ => None,
+        }
+    }
+
+    fn chunk_type(self) -> ChunkType {
+        match self {
+            Kind::Message | Kind::Enum => ChunkType::Struct,
+            Kind::Service => ChunkType::Interface,
+            Kind::Rpc => ChunkType::Method,
+        }
+    }
+}
+
+/// A message, enum, service or RPC definition
+struct Definition {
+    kind: Kind,
+    name: String,
+    /// Byte offset of the keyword
+    start: usize,
+    /// Byte offset just past the `{` opening the body, or the `;` ending an RPC without one
+    header_end: usize,
+    /// Byte offset just past the `}` closing the body, or the `;`
+    end: usize,
+    children: Vec<Definition>,
+}
+
+/// Reads the definitions of a schema, skipping comments and strings
+struct Parser<'a> {
+    content: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn byte(&self) -> Option<u8> {
+        self.content.as_bytes().get(self.pos).copied()
+    }
+
+    /// Moves past the character at the current position
+    fn advance(&mut self) {
+        self.pos += self.content[self.pos..]
+            .chars()
+            .next()
+            .map_or(1, char::len_utf8);
+    }
+
+    /// Moves past whitespace and comments
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = &self.content[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if let Some(comment) = trimmed.strip_prefix("/*") {
+                self.pos += comment.find("*/").map_or(trimmed.len(), |n| n + 4);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn skip_string(&mut self) {
+        let bytes = self.content.as_bytes();
+        let quote = bytes[self.pos];
+        self.pos += 1;
+        while let Some(byte) = self.byte() {
+            match byte {
+                b'\\' => self.pos += 2,
+                b'\n' => return, // Unterminated
+                _ if byte == quote => {
+                    self.pos += 1;
+                    return;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        self.pos = self.pos.min(bytes.len());
+    }
+
+    /// Reads an identifier, possibly dotted, at the current position
+    fn identifier(&mut self) -> &'a str {
+        let rest = &self.content[self.pos..];
+        let len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '.')
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    /// Reads definitions up to the `}` closing the enclosing body, or the end of the file,
+    /// and moves past it. Definitions are only recognized at the start of a statement, so
+    /// a field named `message` isn't taken for one.
+    fn definitions(&mut self) -> Vec<Definition> {
+        let mut definitions = Vec::new();
+        let mut statement_start = true;
+        loop {
+            self.skip_trivia();
+            let Some(byte) = self.byte() else {
+                return definitions;
+            };
+            match byte {
+                b'}' => {
+                    self.pos += 1;
+                    return definitions;
+                }
+                b'{' => {
+                    // `oneof` bodies and option values hold no definitions
+                    self.pos += 1;
+                    self.definitions();
+                    statement_start = true;
+                }
+                b';' => {
+                    self.pos += 1;
+                    statement_start = true;
+                }
+                b'"' | b'\'' => {
+                    self.skip_string();
+                    statement_start = false;
+                }
+                _ if byte.is_ascii_alphabetic() || byte == b'_' => {
+                    let start = self.pos;
+                    let kind = Kind::from_keyword(self.identifier());
+                    if let Some(kind) = kind.filter(|_| statement_start) {
+                        if let Some(definition) = self.definition(kind, start) {
+                            definitions.push(definition);
+                            continue;
+                        }
+                    }
+                    statement_start = false;
+                }
+                _ => {
+                    self.advance();
+                    statement_start = false;
+                }
+            }
+        }
+    }
+
+    /// Reads the rest of a definition whose keyword starts at `start`
+    fn definition(&mut self, kind: Kind, start: usize) -> Option<Definition> {
+        self.skip_trivia();
+        let name = self.identifier().to_string();
+        if name.is_empty() {
+            return None;
+        }
+        // Up to the body, past an RPC's request and response types and any options
+        loop {
+            self.skip_trivia();
+            match self.byte()? {
+                b'{' => {
+                    self.pos += 1;
+                    let header_end = self.pos;
+                    let children = self.definitions();
+                    return Some(Definition {
+                        kind,
+                        name,
+                        start,
+                        header_end,
+                        end: self.pos,
+                        children,
+                    });
+                }
+                b';' if kind == Kind::Rpc => {
+                    self.pos += 1;
+                    return Some(Definition {
+                        kind,
+                        name,
+                        start,
+                        header_end: self.pos,
+                        end: self.pos,
+                        children: Vec::new(),
+                    });
+                }
+                b'}' | b';' => return None,
+                b'"' | b'\'' => self.skip_string(),
+                _ => self.advance(),
+            }
+        }
+    }
+}
+
+/// Builds the chunks of a schema's definitions
+struct Chunks<'a> {
+    content: &'a str,
+    lines: Vec<&'a str>,
+    line_index: utils::LineIndex,
+    chunks: Vec<CodeChunk>,
+}
+
+impl Chunks<'_> {
+    /// Offset of the start of a definition's line, or of its keyword when something else
+    /// precedes it on that line
+    fn line_start(&self, definition: &Definition) -> usize {
+        let line_start = self
+            .line_index
+            .line_start(self.line_index.line_of(definition.start));
+        if self.content[line_start..definition.start].trim().is_empty() {
+            line_start
+        } else {
+            definition.start
+        }
+    }
+
+    /// Offset where a definition's text starts, including the comments above it
+    fn text_start(&self, definition: &Definition, previous_end: Option<usize>) -> usize {
+        let first_line = self.line_index.line_of(definition.start);
+        let docs_start =
+            utils::comment_start(&self.lines, first_line, previous_end, COMMENT_MARKERS);
+        if docs_start < first_line {
+            self.line_index.line_start(docs_start)
+        } else {
+            self.line_start(definition)
+        }
+    }
+
+    /// Adds chunks for `definitions` and their children. `scope` holds the names of the
+    /// enclosing types and `enclosing` their headers, which start the declarations.
+    fn add(
+        &mut self,
+        definitions: &[Definition],
+        mut previous_end: Option<usize>,
+        scope: &mut Vec<String>,
+        enclosing: &mut Vec<String>,
+    ) {
+        for definition in definitions {
+            let text_start = self.text_start(definition, previous_end);
+            let start_line = self.line_index.line_of(text_start);
+            let end_line = self.line_index.line_of(definition.end - 1);
+            let header_line = self.line_index.line_of(definition.header_end - 1);
+            previous_end = Some(end_line);
+
+            let mut declaration = enclosing.join("\n");
+            if !declaration.is_empty() {
+                declaration.push('\n');
+            }
+            declaration.push_str(self.content[text_start..definition.header_end].trim_end());
+
+            // Nested types are chunks of their own, so the body leaves them out. RPCs stay
+            // in the service's body, which lists the methods it offers.
+            let mut body = String::new();
+            let mut offset = definition.header_end;
+            let mut child_previous_end = Some(header_line);
+            for child in &definition.children {
+                let child_start = self.text_start(child, child_previous_end);
+                child_previous_end = Some(self.line_index.line_of(child.end - 1));
+                if child.kind == Kind::Rpc {
+                    continue;
+                }
+                body.push_str(&self.content[offset..child_start]);
+                offset = child.end;
+            }
+            body.push_str(&self.content[offset..definition.end]);
+            let body: Vec<&str> = body
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .collect();
+
+            let name = match definition.kind {
+                Kind::Rpc => definition.name.clone(),
+                _ => scope
+                    .iter()
+                    .map(String::as_str)
+                    .chain([definition.name.as_str()])
+                    .collect::<Vec<_>>()
+                    .join("."),
+            };
+            self.chunks.push(CodeChunk {
+                start_line,
+                end_line,
+                chunk_type: definition.kind.chunk_type(),
+                name,
+                content: body.join("\n"),
+                declaration,
+                doc: utils::doc_text(&self.content[text_start..definition.start]),
+            });
+
+            if !definition.children.is_empty() {
+                scope.push(definition.name.clone());
+                // Enclosing headers are shown without their comments, like `service Greeter {`
+                let header = &self.content[self.line_start(definition)..definition.header_end];
+                enclosing.push(header.to_string());
+                self.add(&definition.children, Some(header_line), scope, enclosing);
+                enclosing.pop();
+                scope.pop();
+            }
+        }
+    }
+}
+
+impl LanguageProcessor for ProtoProcessor {
+    fn language_name(&self) -> &str {
+        "proto"
+    }
+
+    fn get_file_extensions(&self) -> &[&str] {
+        &["proto"]
+    }
+
+    fn chunk_code(&mut self, content: &str) -> Result<Vec<CodeChunk>> {
+        let definitions = Parser { content, pos: 0 }.definitions();
+        let lines: Vec<&str> = content.lines().collect();
+        let mut chunks = Chunks {
+            content,
+            line_index: utils::LineIndex::new(content),
+            lines,
+            chunks: Vec::new(),
+        };
+        chunks.add(&definitions, None, &mut Vec::new(), &mut Vec::new());
+        let Chunks {
+            lines, mut chunks, ..
+        } = chunks;
+
+        // Top-level statements are indexed in blocks, long ones split into windows
+        let windows = utils::window_uncovered_lines(content, &utils::paragraphs(&lines), &chunks);
+        chunks.extend(windows);
+        chunks.sort_by_key(|chunk| chunk.start_line);
+
+        Ok(chunks)
+    }
+
+    fn clone_box(&self) -> Result<Box<dyn LanguageProcessor>> {
+        Ok(Box::new(ProtoProcessor::new()?))
+    }
+}
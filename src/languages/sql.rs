@@ -0,0 +1,350 @@
+use crate::language_processor::utils;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use anyhow::Result;
+use std::ops::Range;
+
+/// Splits SQL scripts into statements without a grammar, as dialects differ too much
+/// for a single one. Statements creating tables, views, types, functions, procedures,
+/// triggers and packages become chunks named after their object; everything else, like
+/// inserts in migrations, is indexed in blocks separated by blank lines.
+pub struct SqlProcessor;
+
+impl SqlProcessor {
+    pub fn new() -> Result<Self> {
+        Ok(Self)
+    }
+}
+
+/// Words that may appear between `CREATE` and the kind of object created
+const CREATE_MODIFIERS: &[&str] = &[
+    "OR",
+    "REPLACE",
+    "ALTER",
+    "TEMP",
+    "TEMPORARY",
+    "GLOBAL",
+    "LOCAL",
+    "UNLOGGED",
+    "MATERIALIZED",
+    "RECURSIVE",
+    "CONSTRAINT",
+    "EDITIONABLE",
+    "NONEDITIONABLE",
+    "FORCE",
+    "SQL",
+    "SECURITY",
+    "DEFINER",
+    "INVOKER",
+];
+
+/// Starts of the comment lines documenting a statement
+const COMMENT_MARKERS: &[&str] = &["--", "/*", "*", "#"];
+
+/// Keywords closing a block that `END` doesn't close on its own, as in `END IF`
+const END_QUALIFIERS: &[&str] = &["IF", "LOOP", "WHILE", "REPEAT", "FOR"];
+
+fn object_chunk_type(kind: &str) -> Option<ChunkType> {
+    match kind {
+        "TABLE" | "VIEW" | "TYPE" => Some(ChunkType::Struct),
+        "FUNCTION" | "PROCEDURE" | "PROC" | "TRIGGER" => Some(ChunkType::Function),
+        "PACKAGE" => Some(ChunkType::Module),
+        _ => None,
+    }
+}
+
+/// Whether a statement's first words create a routine, whose `BEGIN ... END` body may
+/// contain `;`
+fn creates_routine(words: &[String]) -> bool {
+    words.first().is_some_and(|word| word == "CREATE")
+        && words.iter().any(|word| {
+            matches!(
+                word.as_str(),
+                "FUNCTION" | "PROCEDURE" | "PROC" | "TRIGGER" | "PACKAGE" | "EVENT"
+            )
+        })
+}
+
+/// Splits the script into the byte ranges of its statements, each including its
+/// terminator. Understands comments, quoted strings and identifiers, PostgreSQL dollar
+/// quoting, `BEGIN ... END` bodies of routines, MySQL `DELIMITER` lines and `GO` or `/`
+/// lines separating batches.
+fn split_statements(content: &str) -> Vec<Range<usize>> {
+    let bytes = content.as_bytes();
+    let mut statements = Vec::new();
+    let mut delimiter = ";".to_string();
+    let mut start: Option<usize> = None;
+    // Last significant byte of the pending statement, for statements ending at a batch
+    // separator rather than a delimiter
+    let mut last = 0;
+    let mut words: Vec<String> = Vec::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    // SQL*Plus scripts end PL/SQL routines with a `/` line, as their declarations before
+    // `BEGIN` end in `;` too
+    let slash_batches = content.lines().any(|line| line.trim() == "/");
+
+    let mut finish = |start: &mut Option<usize>, end: usize, words: &mut Vec<String>| {
+        if let Some(start) = start.take() {
+            statements.push(start..end);
+        }
+        words.clear();
+    };
+
+    while i < bytes.len() {
+        if i == 0 || bytes[i - 1] == b'\n' {
+            let line_end = content[i..].find('\n').map_or(content.len(), |n| i + n);
+            let line = content[i..line_end].trim();
+            let new_delimiter = line
+                .get(..10)
+                .filter(|keyword| keyword.eq_ignore_ascii_case("DELIMITER "))
+                .map(|_| line[10..].trim());
+            if new_delimiter.is_some() || is_batch_separator(line) {
+                // A separator belongs to the batch it ends
+                let end = if new_delimiter.is_some() {
+                    last
+                } else {
+                    line_end
+                };
+                finish(&mut start, end, &mut words);
+                depth = 0;
+                if let Some(new_delimiter) = new_delimiter.filter(|d| !d.is_empty()) {
+                    delimiter = new_delimiter.to_string();
+                }
+                i = line_end;
+                continue;
+            }
+        }
+
+        let byte = bytes[i];
+        if byte.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        if content[i..].starts_with("--") {
+            i = content[i..].find('\n').map_or(content.len(), |n| i + n);
+            continue;
+        }
+        if content[i..].starts_with("/*") {
+            i = content[i + 2..]
+                .find("*/")
+                .map_or(content.len(), |n| i + 2 + n + 2);
+            continue;
+        }
+
+        // A custom delimiter ends the statement even inside a routine body
+        let in_body = depth > 0 || (slash_batches && creates_routine(&words));
+        if content[i..].starts_with(delimiter.as_str()) && (!in_body || delimiter != ";") {
+            let end = i + delimiter.len();
+            finish(&mut start, end, &mut words);
+            depth = 0;
+            i = end;
+            continue;
+        }
+
+        start.get_or_insert(i);
+        let token_start = i;
+        match byte {
+            b'\'' | b'"' | b'`' | b'[' => {
+                let closing = if byte == b'[' { b']' } else { byte };
+                i = bytes[i + 1..]
+                    .iter()
+                    .position(|&b| b == closing)
+                    .map_or(bytes.len(), |n| i + 1 + n + 1);
+            }
+            b'$' => match dollar_quote_tag(&content[i..]) {
+                Some(tag) => {
+                    let body = i + tag.len();
+                    i = content[body..]
+                        .find(tag)
+                        .map_or(content.len(), |n| body + n + tag.len());
+                }
+                None => i += 1,
+            },
+            _ if byte.is_ascii_alphanumeric() || byte == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                let word = content[token_start..i].to_ascii_uppercase();
+                if words.len() < 8 {
+                    words.push(word.clone());
+                }
+                if creates_routine(&words) {
+                    match word.as_str() {
+                        "BEGIN" | "CASE" => depth += 1,
+                        "END" => {
+                            let rest = &content[i..];
+                            let next = next_word(rest).to_ascii_uppercase();
+                            // `END IF` closes a block that wasn't counted
+                            if !END_QUALIFIERS.contains(&next.as_str()) {
+                                depth = depth.saturating_sub(1);
+                            }
+                            // Skip the `CASE` of `END CASE` so it doesn't open a block
+                            if next == "CASE" {
+                                i += rest.len() - rest.trim_start().len() + next.len();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => i += content[i..].chars().next().map_or(1, char::len_utf8),
+        }
+        last = i.min(content.len());
+    }
+    finish(&mut start, last, &mut words);
+
+    statements
+}
+
+/// `GO` (SQL Server) or `/` (Oracle) alone on a line
+fn is_batch_separator(line: &str) -> bool {
+    line == "/" || line.eq_ignore_ascii_case("GO")
+}
+
+/// The `$tag$` opening a PostgreSQL dollar-quoted string, if the text starts with one
+fn dollar_quote_tag(text: &str) -> Option<&str> {
+    let end = text[1..].find('$')? + 1;
+    let tag = &text[1..end];
+    let valid = tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !tag.starts_with(|c: char| c.is_ascii_digit());
+    valid.then(|| &text[..=end])
+}
+
+/// The word the text continues with after whitespace, or an empty string
+fn next_word(text: &str) -> &str {
+    let text = text.trim_start();
+    let end = text
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
+/// The kind and name of the object a statement creates, like `public.users` for
+/// `CREATE TABLE IF NOT EXISTS "public"."users" (...)`
+fn created_object(statement: &str) -> Option<(ChunkType, String)> {
+    let mut rest = statement;
+    if next_keyword(&mut rest) != "CREATE" {
+        return None;
+    }
+    let mut kind = next_keyword(&mut rest);
+    // Modifiers, and MySQL's `DEFINER=user` and `ALGORITHM=MERGE` options
+    while CREATE_MODIFIERS.contains(&kind.as_str()) || kind.contains('=') {
+        kind = next_keyword(&mut rest);
+    }
+    let chunk_type = object_chunk_type(&kind)?;
+
+    // `IF NOT EXISTS`, and the `BODY` of an Oracle package
+    let mut name_start = rest;
+    while matches!(
+        next_keyword(&mut rest).as_str(),
+        "IF" | "NOT" | "EXISTS" | "BODY"
+    ) {
+        name_start = rest;
+    }
+
+    let name = qualified_name(name_start.trim_start());
+    (!name.is_empty()).then_some((chunk_type, name))
+}
+
+/// Takes the next word up to whitespace or `(` off the text, uppercased
+fn next_keyword(rest: &mut &str) -> String {
+    let text = rest.trim_start();
+    let end = text
+        .find(|c: char| c.is_whitespace() || c == '(')
+        .unwrap_or(text.len());
+    let (word, remainder) = text.split_at(end);
+    *rest = remainder;
+    word.to_ascii_uppercase()
+}
+
+/// Reads a possibly quoted and schema-qualified name, dropping the quotes
+fn qualified_name(text: &str) -> String {
+    let mut name = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '"' | '`' | '[' => {
+                let closing = if c == '[' { ']' } else { c };
+                chars.next();
+                for c in chars.by_ref() {
+                    if c == closing {
+                        break;
+                    }
+                    name.push(c);
+                }
+            }
+            '.' => {
+                name.push('.');
+                chars.next();
+            }
+            _ if c.is_alphanumeric() || c == '_' || c == '$' => {
+                name.push(c);
+                chars.next();
+            }
+            _ => break,
+        }
+    }
+    name
+}
+
+impl LanguageProcessor for SqlProcessor {
+    fn language_name(&self) -> &str {
+        "sql"
+    }
+
+    fn get_file_extensions(&self) -> &[&str] {
+        &["sql"]
+    }
+
+    fn chunk_code(&mut self, content: &str) -> Result<Vec<CodeChunk>> {
+        let lines: Vec<&str> = content.lines().collect();
+        let line_index = utils::LineIndex::new(content);
+
+        let mut chunks = Vec::new();
+        let mut previous_end = None;
+        for statement in split_statements(content) {
+            let first_line = line_index.line_of(statement.start);
+            let last_line = line_index.line_of(statement.end.saturating_sub(1));
+            let docs_start =
+                utils::comment_start(&lines, first_line, previous_end, COMMENT_MARKERS);
+            previous_end = Some(last_line);
+
+            let text = &content[statement.clone()];
+            let Some((chunk_type, name)) = created_object(text) else {
+                continue;
+            };
+
+            // The declaration is the statement's first line, with the comments above it
+            let declaration_start = if docs_start < first_line {
+                line_index.line_start(docs_start)
+            } else {
+                statement.start
+            };
+            let header_end = text
+                .find('\n')
+                .map_or(statement.end, |n| statement.start + n);
+            chunks.push(CodeChunk {
+                start_line: docs_start,
+                end_line: last_line,
+                chunk_type,
+                name,
+                content: content[header_end..statement.end].to_string(),
+                declaration: content[declaration_start..header_end]
+                    .trim_end()
+                    .to_string(),
+                doc: utils::doc_text(&lines[docs_start..first_line].join("\n")),
+            });
+        }
+
+        // Other statements are indexed in blocks, long ones split into windows
+        let windows = utils::window_uncovered_lines(content, &utils::paragraphs(&lines), &chunks);
+        chunks.extend(windows);
+        chunks.sort_by_key(|chunk| chunk.start_line);
+
+        Ok(chunks)
+    }
+
+    fn clone_box(&self) -> Result<Box<dyn LanguageProcessor>> {
+        Ok(Box::new(SqlProcessor::new()?))
+    }
+}
@@ -0,0 +1,84 @@
+use crate::code_chunker::CodeChunker;
+use crate::language_processor::{ChunkType, LanguageProcessor};
+use crate::languages::bash::BashProcessor;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+use std::path::Path;
+
+const DEPLOY_SCRIPT: &str = indoc! {r#"
+    #!/usr/bin/env bash
+    set -euo pipefail
+
+    # Uploads the build to the given host
+    # and restarts the service
+    upload() {
+        scp build.tar.gz "$1:/srv/app"
+        ssh "$1" systemctl restart app
+    }
+
+    function cleanup {
+        rm -rf build
+    }
+
+    for host in "$@"; do
+        upload "$host"
+    done
+    cleanup
+"#};
+
+#[test]
+fn test_bash_function_and_block_chunking() {
+    let mut processor = BashProcessor::new().expect("Failed to create BashProcessor");
+    let chunks = processor
+        .chunk_code(DEPLOY_SCRIPT)
+        .expect("Failed to chunk shell script");
+
+    let summary: Vec<_> = chunks
+        .iter()
+        .map(|chunk| {
+            (
+                chunk.chunk_type.clone(),
+                chunk.name.as_str(),
+                chunk.start_line,
+                chunk.end_line,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (ChunkType::Other, "lines 1-2", 0, 1),
+            (ChunkType::Function, "upload", 3, 8),
+            (ChunkType::Function, "cleanup", 10, 12),
+            (ChunkType::Other, "lines 15-18", 14, 17),
+        ]
+    );
+
+    assert_eq!(
+        chunks[1].declaration,
+        "# Uploads the build to the given host\n# and restarts the service\nupload() {"
+    );
+    assert!(chunks[1].content.contains("systemctl restart app"));
+    assert!(!chunks[1].content.contains("upload() {"));
+    assert_eq!(chunks[2].declaration, "function cleanup {");
+    assert!(chunks[3].content.starts_with("for host in"));
+}
+
+#[test]
+fn test_bash_scripts_recognized_by_shebang() {
+    let mut chunker = CodeChunker::new().unwrap();
+
+    let chunks = chunker
+        .chunk_code_for_indexing(Path::new("bin/deploy"), DEPLOY_SCRIPT)
+        .unwrap();
+    assert!(chunks
+        .iter()
+        .any(|chunk| chunk.chunk_type == ChunkType::Function && chunk.name == "upload"));
+
+    // Other extensionless files are still indexed as a whole
+    let chunks = chunker
+        .chunk_code_for_indexing(Path::new("Procfile"), "web: ./server\n")
+        .unwrap();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].name, "file");
+}
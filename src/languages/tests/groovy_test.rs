@@ -0,0 +1,175 @@
+use crate::code_chunker::CodeChunker;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use crate::languages::groovy::GroovyProcessor;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+use std::path::Path;
+
+const BUILD_GRADLE: &str = indoc! {r#"
+    plugins {
+        id 'java'
+        id "org.springframework.boot" version "3.2.0"
+    }
+
+    group = 'com.example'
+    version = '1.0'
+
+    // Libraries the app depends on
+    dependencies {
+        implementation 'org.slf4j:slf4j-api:2.0.9' // { not a brace
+        testImplementation "org.spockframework:spock-core:${spockVersion}"
+    }
+
+    task copyDocs(type: Copy) {
+        from 'docs'
+        into "$buildDir/docs"
+    }
+
+    tasks.register('integrationTest', Test) {
+        useJUnitPlatform()
+    }
+
+    tasks.withType(JavaCompile).configureEach {
+        options.encoding = 'UTF-8'
+    }
+
+    if (project.hasProperty('ci')) {
+        println 'CI build'
+    }
+
+    def versionSuffix() {
+        return System.getenv('SUFFIX') ?: ''
+    }
+"#};
+
+const GREETER: &str = indoc! {r#"
+    package shop
+
+    /**
+     * Greets people
+     */
+    @CompileStatic
+    class Greeter extends Base
+            implements Serializable {
+        String name
+        def handlers = [fallback: { -> 'hi' }]
+
+        Greeter(String name) {
+            this.name = name
+        }
+
+        @Override
+        String greet(String who = "world") {
+            if (who) {
+                return "Hello, ${who}!"
+            }
+        }
+    }
+
+    class GreeterSpec extends Specification {
+        def "greets by name"() {
+            expect:
+            new Greeter('a').greet('b') == 'Hello, b!'
+        }
+    }
+"#};
+
+fn summarize(chunks: &[CodeChunk]) -> Vec<(ChunkType, &str, usize, usize)> {
+    chunks
+        .iter()
+        .map(|chunk| {
+            (
+                chunk.chunk_type.clone(),
+                chunk.name.as_str(),
+                chunk.start_line,
+                chunk.end_line,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_gradle_blocks_and_tasks() {
+    let mut processor = GroovyProcessor::new().expect("Failed to create GroovyProcessor");
+    let chunks = processor
+        .chunk_code(BUILD_GRADLE)
+        .expect("Failed to chunk build script");
+
+    assert_eq!(
+        summarize(&chunks),
+        vec![
+            (ChunkType::Module, "plugins", 0, 3),
+            (ChunkType::Other, "lines 6-7", 5, 6),
+            (ChunkType::Module, "dependencies", 8, 12),
+            (ChunkType::Function, "copyDocs", 14, 17),
+            (ChunkType::Function, "integrationTest", 19, 21),
+            (
+                ChunkType::Module,
+                "tasks.withType(JavaCompile).configureEach",
+                23,
+                25
+            ),
+            (ChunkType::Other, "lines 28-30", 27, 29),
+            (ChunkType::Function, "versionSuffix", 31, 33),
+        ]
+    );
+
+    // Braces in comments and strings don't end blocks
+    let dependencies = &chunks[2];
+    assert_eq!(
+        dependencies.declaration,
+        "// Libraries the app depends on\ndependencies {"
+    );
+    assert_eq!(dependencies.doc, "Libraries the app depends on");
+    assert!(dependencies.content.contains("spock-core"));
+    assert_eq!(chunks[3].declaration, "task copyDocs(type: Copy) {");
+}
+
+#[test]
+fn test_groovy_classes_and_methods() {
+    let mut processor = GroovyProcessor::new().unwrap();
+    let chunks = processor.chunk_code(GREETER).unwrap();
+
+    assert_eq!(
+        summarize(&chunks),
+        vec![
+            (ChunkType::Other, "lines 1-1", 0, 0),
+            (ChunkType::Class, "Greeter", 2, 21),
+            (ChunkType::Method, "Greeter", 11, 13),
+            (ChunkType::Method, "greet", 15, 20),
+            (ChunkType::Class, "GreeterSpec", 23, 28),
+            (ChunkType::Method, "greets by name", 24, 27),
+        ]
+    );
+
+    // Methods are left out of the class's body and shown inside its declaration
+    let class = &chunks[1];
+    assert_eq!(class.doc, "Greets people");
+    assert!(class
+        .declaration
+        .ends_with("class Greeter extends Base\n        implements Serializable {"));
+    assert_eq!(
+        class.content,
+        "    String name\n    def handlers = [fallback: { -> 'hi' }]\n}"
+    );
+    assert_eq!(
+        chunks[3].declaration,
+        "@CompileStatic\nclass Greeter extends Base\n        implements Serializable {\n    @Override\n    String greet(String who = \"world\") {"
+    );
+}
+
+#[test]
+fn test_gradle_and_groovy_files_use_groovy_processor() {
+    let mut chunker = CodeChunker::new().unwrap();
+    for path in ["build.gradle", "src/Greeter.groovy"] {
+        assert_eq!(chunker.language_for(Path::new(path)), Some("groovy"));
+    }
+
+    let script = "#!/usr/bin/env groovy\ndef greet(name) {\n    println \"Hello, $name\"\n}\n";
+    let chunks = chunker
+        .chunk_code_for_indexing(Path::new("bin/greet"), script)
+        .unwrap();
+    assert!(chunks
+        .iter()
+        .any(|chunk| chunk.chunk_type == ChunkType::Function && chunk.name == "greet"));
+}
@@ -0,0 +1,124 @@
+use crate::code_chunker::CodeChunker;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use crate::languages::hcl::HclProcessor;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+use std::path::Path;
+
+const MAIN_TF: &str = indoc! {r#"
+    terraform {
+      required_version = ">= 1.5"
+    }
+
+    # Region every resource is created in
+    variable "region" {
+      type    = string
+      default = "eu-west-1"
+    }
+
+    resource "aws_instance" "web" {
+      ami  = data.aws_ami.ubuntu.id
+      tags = { Name = "web-${var.region}" }
+
+      user_data = <<-EOT
+        #!/bin/bash
+        echo "}"
+      EOT
+
+      lifecycle {
+        create_before_destroy = true
+      }
+    }
+
+    data "aws_ami" "ubuntu" {
+      most_recent = true
+    }
+
+    module "vpc" {
+      source = "terraform-aws-modules/vpc/aws"
+      azs    = ["${var.region}a", "${var.region}b"]
+    }
+
+    output "instance_id" { value = aws_instance.web.id }
+"#};
+
+fn summarize(chunks: &[CodeChunk]) -> Vec<(ChunkType, &str, usize, usize)> {
+    chunks
+        .iter()
+        .map(|chunk| {
+            (
+                chunk.chunk_type.clone(),
+                chunk.name.as_str(),
+                chunk.start_line,
+                chunk.end_line,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_hcl_block_chunking() {
+    let mut processor = HclProcessor::new().expect("Failed to create HclProcessor");
+    let chunks = processor
+        .chunk_code(MAIN_TF)
+        .expect("Failed to chunk Terraform file");
+
+    assert_eq!(
+        summarize(&chunks),
+        vec![
+            (ChunkType::Other, "terraform", 0, 2),
+            (ChunkType::Other, "var.region", 4, 8),
+            (ChunkType::Struct, "aws_instance.web", 10, 22),
+            (ChunkType::Struct, "data.aws_ami.ubuntu", 24, 26),
+            (ChunkType::Module, "module.vpc", 28, 31),
+            (ChunkType::Other, "output.instance_id", 33, 33),
+        ]
+    );
+
+    assert_eq!(
+        chunks[1].declaration,
+        "# Region every resource is created in\nvariable \"region\" {"
+    );
+    // Braces in strings and heredocs don't end the block, nested blocks stay inside it
+    assert!(chunks[2].content.contains("echo \"}\""));
+    assert!(chunks[2].content.contains("create_before_destroy = true"));
+    assert!(chunks[2].content.ends_with("  }\n}"));
+}
+
+#[test]
+fn test_hcl_top_level_attributes() {
+    let mut processor = HclProcessor::new().unwrap();
+    let tfvars = indoc! {r#"
+        region = "eu-west-1"
+        tags = {
+          team = "platform"
+        }
+
+        instance_count = 3
+    "#};
+    let chunks = processor.chunk_code(tfvars).unwrap();
+    assert_eq!(
+        summarize(&chunks),
+        vec![
+            (ChunkType::Other, "lines 1-4", 0, 3),
+            (ChunkType::Other, "lines 6-6", 5, 5),
+        ]
+    );
+}
+
+#[test]
+fn test_terraform_files_use_hcl_processor() {
+    let mut chunker = CodeChunker::new().unwrap();
+    assert_eq!(
+        chunker.language_for(Path::new("infra/main.tf")),
+        Some("hcl")
+    );
+    assert_eq!(chunker.language_for(Path::new("prod.tfvars")), Some("hcl"));
+
+    let chunks = chunker
+        .chunk_code_for_indexing(Path::new("infra/main.tf"), MAIN_TF)
+        .unwrap();
+    assert!(chunks
+        .iter()
+        .any(|chunk| chunk.chunk_type == ChunkType::Module && chunk.name == "module.vpc"));
+}
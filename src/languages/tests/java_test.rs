@@ -593,3 +593,124 @@ fn test_java_record_declaration_chunking() {
     assert_eq!(chunks[2].chunk_type, ChunkType::Method);
     assert_eq!(chunks[2].name, "isAdult");
 }
+
+#[test]
+fn test_java_syntax_errors_fall_back_to_windows() {
+    let java_code = indoc! {r#"
+        class Valid {
+            void ok() {
+                run();
+            }
+        }
+
+        %%% brokenFragment @@@ ((
+        int x = = 3;
+    "#};
+
+    let mut processor = JavaProcessor::new().expect("Failed to create JavaProcessor");
+    let chunks = processor
+        .chunk_code(java_code)
+        .expect("Failed to chunk Java code");
+
+    // The well-formed part is still chunked structurally
+    assert!(chunks
+        .iter()
+        .any(|c| c.chunk_type == ChunkType::Method && c.name == "ok"));
+
+    // The broken region is indexed as a plain window instead of being dropped
+    let windows: Vec<_> = chunks
+        .iter()
+        .filter(|c| c.chunk_type == ChunkType::Other)
+        .collect();
+    assert!(
+        windows.iter().any(|c| c.content.contains("brokenFragment")),
+        "Expected a window chunk for the broken region, got: {chunks:?}"
+    );
+    // Lines already covered by the method chunk aren't duplicated in windows
+    assert!(windows.iter().all(|c| c.end_line < 1 || c.start_line > 3));
+}
+
+#[test]
+fn test_java_extract_references() {
+    let java_code = indoc! {r#"
+        class Shop {
+            void open() {
+                Cart cart = new Cart();
+                cart.total();
+                log("opened");
+            }
+
+            class Cart {
+                int total() {
+                    return sum(items());
+                }
+            }
+        }
+    "#};
+
+    let mut processor = JavaProcessor::new().expect("Failed to create JavaProcessor");
+    let references = processor.extract_references(java_code).unwrap();
+
+    let definitions: Vec<_> = references
+        .definitions
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), symbol.line))
+        .collect();
+    assert_eq!(definitions, vec![("Shop.open", 1), ("Shop.Cart.total", 8)]);
+
+    let calls: Vec<_> = references
+        .calls
+        .iter()
+        .map(|call| (call.caller.as_str(), call.callee.as_str()))
+        .collect();
+    assert_eq!(
+        calls,
+        vec![
+            ("Shop.open", "total"),
+            ("Shop.open", "log"),
+            ("Shop.Cart.total", "sum"),
+            ("Shop.Cart.total", "items"),
+        ]
+    );
+}
+
+#[test]
+fn test_java_doc_comments() {
+    let java_code = indoc! {r#"
+        /**
+         * Keeps track of the carts of a shop
+         */
+        class Shop {
+            // Carts currently open
+            int count;
+
+            /**
+             * Works out the price including taxes.
+             * @param cart the items to pay for
+             */
+            int total(Cart cart) {
+                return 0;
+            }
+
+            void clear() {}
+        }
+    "#};
+
+    let mut processor = JavaProcessor::new().unwrap();
+    let chunks = processor.chunk_code(java_code).unwrap();
+    let docs: Vec<(&str, &str)> = chunks
+        .iter()
+        .map(|chunk| (chunk.name.as_str(), chunk.doc.as_str()))
+        .collect();
+    assert_eq!(
+        docs,
+        vec![
+            ("Shop", "Keeps track of the carts of a shop"),
+            (
+                "total",
+                "Works out the price including taxes.\n@param cart the items to pay for"
+            ),
+            ("clear", ""),
+        ]
+    );
+}
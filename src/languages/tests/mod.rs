@@ -1 +1,7 @@
+mod bash_test;
+mod groovy_test;
+mod hcl_test;
 mod java_test;
+mod perl_test;
+mod proto_test;
+mod sql_test;
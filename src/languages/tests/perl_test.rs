@@ -0,0 +1,129 @@
+use crate::code_chunker::CodeChunker;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use crate::languages::perl::PerlProcessor;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+use std::path::Path;
+
+const CART_PM: &str = indoc! {r#"
+    package Shop::Cart;
+
+    use strict;
+    use warnings;
+
+    =head2 new
+
+    Creates an empty cart.
+
+    =cut
+
+    sub new {
+        my ($class, %args) = @_;
+        return bless { items => [], %args }, $class;
+    }
+
+    # Adds an item, ignoring braces in { strings
+    sub add {
+        my ($self, $item) = @_;
+        my $label = "} not a brace";
+        $item->{name} =~ s/\{//g;
+        print <<"EOT";
+    Added } $item->{name}
+    EOT
+        push @{ $self->{items} }, $item;
+    }
+
+    sub total {
+        my $self = shift;
+        my $sum = 0;
+        $sum += $_->{price} / 100 for @{ $self->{items} };
+        return $sum;
+    }
+
+    package Shop::Cart::Item {
+        sub price { $_[0]{price} }
+    }
+
+    1;
+
+    __END__
+
+    =head1 NAME
+
+    Shop::Cart - a shopping cart
+"#};
+
+fn summarize(chunks: &[CodeChunk]) -> Vec<(ChunkType, &str, usize, usize)> {
+    chunks
+        .iter()
+        .map(|chunk| {
+            (
+                chunk.chunk_type.clone(),
+                chunk.name.as_str(),
+                chunk.start_line,
+                chunk.end_line,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_perl_packages_and_subs() {
+    let mut processor = PerlProcessor::new().expect("Failed to create PerlProcessor");
+    let chunks = processor
+        .chunk_code(CART_PM)
+        .expect("Failed to chunk Perl module");
+
+    assert_eq!(
+        summarize(&chunks),
+        vec![
+            (ChunkType::Module, "Shop::Cart", 0, 32),
+            (ChunkType::Method, "new", 5, 14),
+            (ChunkType::Method, "add", 16, 25),
+            (ChunkType::Method, "total", 27, 32),
+            (ChunkType::Module, "Shop::Cart::Item", 34, 36),
+            (ChunkType::Method, "price", 35, 35),
+            (ChunkType::Other, "lines 39-39", 38, 38),
+            (ChunkType::Other, "lines 41-41", 40, 40),
+            (ChunkType::Other, "lines 43-43", 42, 42),
+            (ChunkType::Other, "lines 45-45", 44, 44),
+        ]
+    );
+
+    // The POD above a sub is part of its declaration, after the package
+    let new = &chunks[1];
+    assert_eq!(
+        new.declaration,
+        "package Shop::Cart;\n=head2 new\n\nCreates an empty cart.\n\n=cut\n\nsub new {"
+    );
+    assert_eq!(new.doc, "new\nCreates an empty cart.");
+
+    // Braces in strings, patterns and heredocs don't end subs
+    let add = &chunks[2];
+    assert_eq!(add.doc, "Adds an item, ignoring braces in { strings");
+    assert!(add.content.contains("push @{ $self->{items} }, $item;"));
+
+    // Subs are left out of the package's body
+    assert_eq!(chunks[0].content, "use strict;\nuse warnings;");
+    assert_eq!(
+        chunks[5].declaration,
+        "package Shop::Cart::Item {\n    sub price {"
+    );
+}
+
+#[test]
+fn test_perl_files_use_perl_processor() {
+    let mut chunker = CodeChunker::new().unwrap();
+    for path in ["lib/Shop/Cart.pm", "bin/checkout.pl"] {
+        assert_eq!(chunker.language_for(Path::new(path)), Some("perl"));
+    }
+
+    let script =
+        "#!/usr/bin/env perl\nuse strict;\n\nsub greet {\n    print \"Hello, $_[0]\\n\";\n}\n";
+    let chunks = chunker
+        .chunk_code_for_indexing(Path::new("bin/greet"), script)
+        .unwrap();
+    assert!(chunks
+        .iter()
+        .any(|chunk| chunk.chunk_type == ChunkType::Function && chunk.name == "greet"));
+}
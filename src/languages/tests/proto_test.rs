@@ -0,0 +1,133 @@
+use crate::code_chunker::CodeChunker;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use crate::languages::proto::ProtoProcessor;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+use std::path::Path;
+
+const ORDERS_PROTO: &str = indoc! {r#"
+    syntax = "proto3";
+
+    package shop.v1;
+
+    import "google/api/annotations.proto";
+
+    // An order placed by a customer
+    message Order {
+      string id = 1;
+      // A line of the order
+      message Item {
+        string sku = 1;
+        int32 quantity = 2;
+      }
+      repeated Item items = 2;
+      enum Status {
+        STATUS_UNSPECIFIED = 0;
+        STATUS_PAID = 1;
+      }
+      oneof payment {
+        string card = 3;
+        string message = 4;
+      }
+    }
+
+    service OrderService {
+      // Places a new order
+      rpc CreateOrder(CreateOrderRequest) returns (Order) {
+        option (google.api.http) = { post: "/v1/orders" body: "*" };
+      }
+      rpc StreamOrders(StreamOrdersRequest) returns (stream Order);
+    }
+"#};
+
+fn summarize(chunks: &[CodeChunk]) -> Vec<(ChunkType, &str, usize, usize)> {
+    chunks
+        .iter()
+        .map(|chunk| {
+            (
+                chunk.chunk_type.clone(),
+                chunk.name.as_str(),
+                chunk.start_line,
+                chunk.end_line,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_proto_definition_chunking() {
+    let mut processor = ProtoProcessor::new().expect("Failed to create ProtoProcessor");
+    let chunks = processor
+        .chunk_code(ORDERS_PROTO)
+        .expect("Failed to chunk proto file");
+
+    assert_eq!(
+        summarize(&chunks),
+        vec![
+            (ChunkType::Other, "lines 1-1", 0, 0),
+            (ChunkType::Other, "lines 3-3", 2, 2),
+            (ChunkType::Other, "lines 5-5", 4, 4),
+            (ChunkType::Struct, "Order", 6, 23),
+            (ChunkType::Struct, "Order.Item", 9, 13),
+            (ChunkType::Struct, "Order.Status", 15, 18),
+            (ChunkType::Interface, "OrderService", 25, 31),
+            (ChunkType::Method, "CreateOrder", 26, 29),
+            (ChunkType::Method, "StreamOrders", 30, 30),
+        ]
+    );
+
+    // Nested types are left out of their parent's body, the field named `message` isn't one
+    let order = &chunks[3];
+    assert_eq!(
+        order.declaration,
+        "// An order placed by a customer\nmessage Order {"
+    );
+    assert!(order.content.contains("repeated Item items = 2;"));
+    assert!(order.content.contains("string message = 4;"));
+    assert!(!order.content.contains("sku"));
+    assert_eq!(
+        chunks[4].declaration,
+        "message Order {\n  // A line of the order\n  message Item {"
+    );
+}
+
+#[test]
+fn test_proto_rpcs_are_shown_in_their_service() {
+    let mut processor = ProtoProcessor::new().unwrap();
+    let chunks = processor.chunk_code(ORDERS_PROTO).unwrap();
+
+    let create = &chunks[7];
+    assert_eq!(
+        create.declaration,
+        "service OrderService {\n  // Places a new order\n  rpc CreateOrder(CreateOrderRequest) returns (Order) {"
+    );
+    assert_eq!(
+        create.content,
+        "    option (google.api.http) = { post: \"/v1/orders\" body: \"*\" };\n  }"
+    );
+
+    // RPCs without options have no body
+    let stream = &chunks[8];
+    assert_eq!(
+        stream.declaration,
+        "service OrderService {\n  rpc StreamOrders(StreamOrdersRequest) returns (stream Order);"
+    );
+    assert_eq!(stream.content, "");
+    assert!(chunks[6].content.contains("rpc StreamOrders"));
+}
+
+#[test]
+fn test_proto_files_use_proto_processor() {
+    let mut chunker = CodeChunker::new().unwrap();
+    assert_eq!(
+        chunker.language_for(Path::new("api/orders.proto")),
+        Some("proto")
+    );
+
+    let chunks = chunker
+        .chunk_code_for_indexing(Path::new("api/orders.proto"), ORDERS_PROTO)
+        .unwrap();
+    assert!(chunks
+        .iter()
+        .any(|chunk| chunk.chunk_type == ChunkType::Method && chunk.name == "CreateOrder"));
+}
@@ -0,0 +1,133 @@
+use crate::code_chunker::CodeChunker;
+use crate::language_processor::{ChunkType, CodeChunk, LanguageProcessor};
+use crate::languages::sql::SqlProcessor;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+use std::path::Path;
+
+const MIGRATION: &str = indoc! {r#"
+    -- Schema for the shop
+
+    -- Customers who placed at least one order
+    CREATE TABLE IF NOT EXISTS "public"."customers" (
+        id serial PRIMARY KEY,
+        email text NOT NULL
+    );
+
+    INSERT INTO customers (email) VALUES ('a@example.com');
+    INSERT INTO customers (email) VALUES ('it''s; fine');
+
+    CREATE OR REPLACE FUNCTION order_total(order_id int) RETURNS numeric AS $$
+    BEGIN
+        RETURN (SELECT sum(price) FROM items WHERE items.order_id = order_id);
+    END;
+    $$ LANGUAGE plpgsql;
+
+    CREATE VIEW big_orders AS SELECT * FROM orders WHERE total > 100;
+
+    DELIMITER //
+    CREATE PROCEDURE archive_orders()
+    BEGIN
+        IF (SELECT count(*) FROM orders) > 0 THEN
+            DELETE FROM orders;
+        END IF;
+    END //
+    DELIMITER ;
+"#};
+
+fn summarize(chunks: &[CodeChunk]) -> Vec<(ChunkType, &str, usize, usize)> {
+    chunks
+        .iter()
+        .map(|chunk| {
+            (
+                chunk.chunk_type.clone(),
+                chunk.name.as_str(),
+                chunk.start_line,
+                chunk.end_line,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_sql_statement_chunking() {
+    let mut processor = SqlProcessor::new().expect("Failed to create SqlProcessor");
+    let chunks = processor
+        .chunk_code(MIGRATION)
+        .expect("Failed to chunk SQL script");
+
+    assert_eq!(
+        summarize(&chunks),
+        vec![
+            (ChunkType::Other, "lines 1-1", 0, 0),
+            (ChunkType::Struct, "public.customers", 2, 6),
+            (ChunkType::Other, "lines 9-10", 8, 9),
+            (ChunkType::Function, "order_total", 11, 15),
+            (ChunkType::Struct, "big_orders", 17, 17),
+            (ChunkType::Other, "lines 20-20", 19, 19),
+            (ChunkType::Function, "archive_orders", 20, 25),
+            (ChunkType::Other, "lines 27-27", 26, 26),
+        ]
+    );
+
+    assert_eq!(
+        chunks[1].declaration,
+        "-- Customers who placed at least one order\n\
+         CREATE TABLE IF NOT EXISTS \"public\".\"customers\" ("
+    );
+    assert!(chunks[1].content.contains("email text NOT NULL"));
+    // Semicolons inside strings and routine bodies don't end statements
+    assert!(chunks[2].content.contains("it''s; fine"));
+    assert!(chunks[3].content.ends_with("$$ LANGUAGE plpgsql;"));
+    assert!(chunks[6].content.ends_with("END //"));
+}
+
+#[test]
+fn test_sql_batch_separators() {
+    let mut processor = SqlProcessor::new().unwrap();
+
+    let sql_server = indoc! {"
+        CREATE PROCEDURE dbo.CleanUp
+        AS
+        BEGIN
+            DELETE FROM logs;
+        END
+        GO
+    "};
+    let chunks = processor.chunk_code(sql_server).unwrap();
+    assert_eq!(
+        summarize(&chunks),
+        vec![(ChunkType::Function, "dbo.CleanUp", 0, 5)]
+    );
+
+    // Declarations before BEGIN end in `;` too, so PL/SQL runs until the `/` line
+    let oracle = indoc! {"
+        CREATE OR REPLACE PROCEDURE raise_salary(p_id NUMBER) IS
+          v_count NUMBER;
+        BEGIN
+          UPDATE employees SET salary = salary * 1.1 WHERE id = p_id;
+        END raise_salary;
+        /
+    "};
+    let chunks = processor.chunk_code(oracle).unwrap();
+    assert_eq!(
+        summarize(&chunks),
+        vec![(ChunkType::Function, "raise_salary", 0, 5)]
+    );
+}
+
+#[test]
+fn test_sql_files_use_sql_processor() {
+    let mut chunker = CodeChunker::new().unwrap();
+    assert_eq!(
+        chunker.language_for(Path::new("db/V1__init.sql")),
+        Some("sql")
+    );
+
+    let chunks = chunker
+        .chunk_code_for_indexing(Path::new("db/V1__init.sql"), MIGRATION)
+        .unwrap();
+    assert!(chunks
+        .iter()
+        .any(|chunk| chunk.chunk_type == ChunkType::Struct && chunk.name == "public.customers"));
+}
@@ -1,20 +1,52 @@
+#[cfg(feature = "tokio")]
+pub mod async_engine;
 pub mod code_chunker;
 pub mod config;
+#[cfg(unix)]
+pub mod daemon;
+pub mod dupes;
+pub mod embeddings;
 pub mod file_scanner;
+pub mod git;
+pub mod graph;
+pub mod history;
+pub mod index_archive;
+pub mod index_lock;
 pub mod language_processor;
 pub mod languages;
+pub mod lsp;
 pub mod metadata;
+pub mod models;
+pub mod outline;
+pub mod paths;
+pub mod query_expansion;
 pub mod reranker;
+pub mod scan_search;
+pub mod scoring;
 pub mod search_engine;
 pub mod search_index;
+pub mod server;
+pub mod syntax;
+pub mod terminal;
+pub mod verbosity;
+pub mod watch;
 
+#[cfg(feature = "tokio")]
+pub use async_engine::AsyncSearchEngine;
 pub use code_chunker::CodeChunker;
 pub use config::Config;
 pub use file_scanner::{FileScanner, IndexedFile};
 pub use language_processor::{ChunkType, CodeChunk};
 pub use reranker::{available_models, parse_reranker_model, ProbeConfig, Reranker, RerankerConfig};
-pub use search_engine::SearchEngine;
-pub use search_index::SearchIndex;
+pub use search_engine::{
+    BenchReport, IndexHealth, IndexStats, IndexStatus, OptimizeReport, SearchEngine,
+    SearchEngineBuilder, StaleFile, VerifyReport, WhyReport,
+};
+pub use search_index::{
+    group_by_file, ChunkCounts, ChunkExplanation, ChunkRange, FileGroup, LanguageRules, QueryMode,
+    ScoreRules, SearchIndex, SearchOptions, SearchResult, SegmentStats,
+};
+pub use verbosity::Verbosity;
 
 #[cfg(test)]
 mod tests {
@@ -42,6 +74,756 @@ mod tests {
         assert!(files.contains(&test_file));
     }
 
+    #[test]
+    fn test_file_scanner_ignore_globs() {
+        let temp_dir = TempDir::new().unwrap();
+        let kept_file = temp_dir.path().join("app.js");
+        let minified_file = temp_dir.path().join("app.min.js");
+        let fixture_file = temp_dir
+            .path()
+            .join("tests")
+            .join("fixtures")
+            .join("data.js");
+        fs::create_dir_all(fixture_file.parent().unwrap()).unwrap();
+        fs::write(&kept_file, "function app() {}").unwrap();
+        fs::write(&minified_file, "function a(){}").unwrap();
+        fs::write(&fixture_file, "const data = 1;").unwrap();
+
+        let scanner = file_scanner::FileScanner::new(temp_dir.path())
+            .with_ignore_globs(&["**/fixtures/**".to_string(), "*.min.js".to_string()])
+            .unwrap();
+        let files = scanner.scan_files().unwrap();
+
+        assert!(files.contains(&kept_file));
+        assert!(!files.contains(&minified_file));
+        assert!(!files.contains(&fixture_file));
+    }
+
+    #[test]
+    fn test_file_scanner_include_globs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("docs/drafts")).unwrap();
+        fs::write(root.join(".gitignore"), "drafts/\n").unwrap();
+        fs::write(root.join("README.md"), "# Readme").unwrap();
+        fs::write(root.join("docs/guide.md"), "# Guide").unwrap();
+        fs::write(root.join("docs/drafts/idea.md"), "# Idea").unwrap();
+        fs::write(root.join("docs/build.rs"), "fn main() {}").unwrap();
+
+        let files = file_scanner::FileScanner::new(root)
+            .with_include_globs(&["*.md".to_string()])
+            .unwrap()
+            .scan_files()
+            .unwrap();
+
+        // Includes narrow down the files to index but don't bring back ignored ones
+        assert_eq!(files.len(), 2, "{files:?}");
+        assert!(files.contains(&root.join("README.md")));
+        assert!(files.contains(&root.join("docs/guide.md")));
+    }
+
+    #[test]
+    fn test_file_scanner_probeignore() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        // .gitignore only applies inside a git repository, .probeignore everywhere
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("vendor")).unwrap();
+        fs::create_dir_all(root.join("src/generated")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join(".probeignore"), "vendor/\n!keep.log\n").unwrap();
+        fs::write(root.join("src/generated/.probeignore"), "*.java\n").unwrap();
+        fs::write(root.join("vendor/lib.js"), "function lib() {}").unwrap();
+        fs::write(root.join("src/generated/Api.java"), "class Api {}").unwrap();
+        fs::write(root.join("src/Main.java"), "class Main {}").unwrap();
+        fs::write(root.join("debug.log"), "debug").unwrap();
+        fs::write(root.join("keep.log"), "kept").unwrap();
+
+        let files = file_scanner::FileScanner::new(root).scan_files().unwrap();
+        assert!(files.contains(&root.join("src/Main.java")));
+        assert!(files.contains(&root.join("keep.log")));
+        assert!(!files.contains(&root.join("debug.log")));
+        assert!(!files.contains(&root.join("vendor/lib.js")));
+        assert!(!files.contains(&root.join("src/generated/Api.java")));
+    }
+
+    #[cfg(unix)]
+    /// Runs git in `dir`, returning false if it fails or isn't installed
+    fn run_git(dir: &std::path::Path, args: &[&str]) -> bool {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args([
+                "-c",
+                "user.name=probe",
+                "-c",
+                "user.email=probe@example.com",
+            ])
+            .args(args)
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    #[test]
+    fn test_file_scanner_git_files_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        if !run_git(root, &["init", "-q"]) {
+            return; // git is not installed
+        }
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\nbuild/\n").unwrap();
+        fs::write(root.join("tracked.txt"), "tracked").unwrap();
+        fs::write(root.join("forced.log"), "added despite .gitignore").unwrap();
+        fs::write(root.join("debug.log"), "ignored").unwrap();
+        fs::write(root.join("build/out.txt"), "ignored").unwrap();
+        assert!(run_git(root, &["add", ".gitignore", "tracked.txt"]));
+        assert!(run_git(root, &["add", "-f", "forced.log"]));
+        fs::write(root.join("untracked.txt"), "not added yet").unwrap();
+
+        let scan = |git_files_only| {
+            let mut files: Vec<_> = file_scanner::FileScanner::new(root)
+                .with_git_files_only(git_files_only)
+                .iter_indexed_files()
+                .map(|file| file.relative_path.to_string_lossy().replace('\\', "/"))
+                .collect();
+            files.sort();
+            files
+        };
+        assert_eq!(scan(false), [".gitignore", "tracked.txt", "untracked.txt"]);
+        assert_eq!(
+            scan(true),
+            [".gitignore", "forced.log", "tracked.txt", "untracked.txt"]
+        );
+    }
+
+    #[test]
+    fn test_index_records_git_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        if !run_git(root, &["init", "-q"]) {
+            return; // git is not installed
+        }
+        fs::write(root.join(".gitignore"), ".probe/\n").unwrap();
+        fs::write(root.join("notes.txt"), "needle").unwrap();
+        assert!(run_git(root, &["add", "."]));
+        assert!(run_git(root, &["commit", "-qm", "First"]));
+
+        let engine = SearchEngine::new(root).unwrap();
+        engine.rebuild_index().unwrap();
+        let stats = engine.index_stats().unwrap();
+        assert_eq!(stats.commit, git::head_commit(root));
+        assert_eq!(stats.commits_behind, Some(0));
+
+        assert!(run_git(
+            root,
+            &["commit", "-q", "--allow-empty", "-m", "Second"]
+        ));
+        assert_eq!(engine.index_stats().unwrap().commits_behind, Some(1));
+        engine.ensure_index_updated().unwrap();
+        assert_eq!(engine.index_stats().unwrap().commits_behind, Some(0));
+    }
+
+    #[test]
+    fn test_binary_files_are_sniffed() {
+        use search_index::SkipReason;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        // An executable without extension, and a text file with a character cut by the
+        // end of the sniffed bytes
+        fs::write(root.join("tool"), b"\x7fELF\x02\x01\x01\0\0\0needle").unwrap();
+        let mut text = "a".repeat(file_scanner::SNIFF_BYTES - 1);
+        text.push_str("é needle");
+        fs::write(root.join("notes.txt"), &text).unwrap();
+        fs::write(root.join("latin1.txt"), b"caf\xe9 needle").unwrap();
+
+        assert_eq!(
+            file_scanner::sniff_file(&root.join("tool")).unwrap(),
+            Some(SkipReason::Binary)
+        );
+        assert_eq!(
+            file_scanner::sniff_file(&root.join("notes.txt")).unwrap(),
+            None
+        );
+        // Text in other encodings is decoded rather than skipped
+        assert_eq!(
+            file_scanner::sniff_file(&root.join("latin1.txt")).unwrap(),
+            None
+        );
+
+        let engine = SearchEngine::new(root).unwrap();
+        engine.rebuild_index().unwrap();
+        let skipped = metadata::SkippedFiles::load(root.join(".probe/skipped.json")).unwrap();
+        let skipped: Vec<_> = skipped.iter().collect();
+        assert_eq!(
+            skipped,
+            vec![(&std::path::PathBuf::from("tool"), &SkipReason::Binary)]
+        );
+    }
+
+    #[test]
+    fn test_legacy_encodings_are_decoded() {
+        use code_chunker::decode_source;
+
+        let utf16 = |text: &str, little_endian: bool| -> Vec<u8> {
+            text.encode_utf16()
+                .flat_map(|unit| match little_endian {
+                    true => unit.to_le_bytes(),
+                    false => unit.to_be_bytes(),
+                })
+                .collect()
+        };
+        assert_eq!(decode_source(b"caf\xe9 = 1;\r\n"), "café = 1;\n");
+        assert_eq!(decode_source(b"\xef\xbb\xbfcaf\xc3\xa9"), "café");
+        assert_eq!(
+            decode_source(&utf16("\u{feff}int café;", true)),
+            "int café;"
+        );
+        assert_eq!(
+            decode_source(&utf16("\u{feff}int café;", false)),
+            "int café;"
+        );
+        assert_eq!(decode_source(&utf16("int café;\r\n", true)), "int café;\n");
+        assert_eq!(decode_source(&utf16("int café;", false)), "int café;");
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(
+            root.join("Legacy.java"),
+            b"class Legacy {\n    // Caf\xe9 needle\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("notes.txt"),
+            utf16("\u{feff}a needle in UTF-16", true),
+        )
+        .unwrap();
+        assert_eq!(
+            file_scanner::sniff_file(&root.join("notes.txt")).unwrap(),
+            None
+        );
+
+        let engine = SearchEngine::new(root).unwrap();
+        engine.rebuild_index().unwrap();
+        let results = engine
+            .search_with_reranker(
+                "needle",
+                Some(10),
+                None,
+                RerankerConfig {
+                    enabled: false,
+                    ..Default::default()
+                },
+                1,
+            )
+            .unwrap();
+        let mut paths: Vec<_> = results.iter().map(|result| result.path.clone()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                std::path::PathBuf::from("Legacy.java"),
+                std::path::PathBuf::from("notes.txt")
+            ]
+        );
+        assert!(results.iter().any(|result| result.snippet.contains("Café")));
+    }
+
+    #[test]
+    fn test_file_scanner_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new().unwrap();
+        let src_dir = temp_dir.path().join("src");
+        let real_file = src_dir.join("lib.rs");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::write(&real_file, "fn lib() {}").unwrap();
+        // A second path to the same directory and a cycle back to the root
+        symlink(&src_dir, temp_dir.path().join("src_link")).unwrap();
+        symlink(temp_dir.path(), src_dir.join("root_link")).unwrap();
+
+        let files = file_scanner::FileScanner::new(temp_dir.path())
+            .scan_files()
+            .unwrap();
+        assert_eq!(files, vec![real_file.clone()]);
+
+        let files = file_scanner::FileScanner::new(temp_dir.path())
+            .with_follow_symlinks(true)
+            .scan_files()
+            .unwrap();
+        assert_eq!(files.len(), 1);
+
+        let iterated: Vec<_> = file_scanner::FileScanner::new(temp_dir.path())
+            .with_follow_symlinks(true)
+            .iter_files()
+            .collect();
+        assert_eq!(iterated.len(), 1);
+    }
+
+    #[test]
+    fn test_crlf_and_bom_sources() {
+        assert_eq!(
+            code_chunker::normalize_source("\u{feff}class A {\r\n}\r\n"),
+            "class A {\n}\n"
+        );
+
+        let temp_dir = TempDir::new().unwrap();
+        let content = (1..=10)
+            .map(|i| {
+                if i == 7 {
+                    "needle here".to_string()
+                } else {
+                    format!("line {i}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        fs::write(
+            temp_dir.path().join("notes.txt"),
+            format!("\u{feff}{content}"),
+        )
+        .unwrap();
+
+        let engine = SearchEngine::new(temp_dir.path()).unwrap();
+        engine.rebuild_index().unwrap();
+        let results = engine
+            .search_with_reranker(
+                "needle",
+                Some(1),
+                None,
+                RerankerConfig {
+                    enabled: false,
+                    ..Default::default()
+                },
+                1,
+            )
+            .unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].snippet, "line 6\nneedle here\nline 8");
+        assert_eq!(results[0].snippet_line, Some(5));
+    }
+
+    #[test]
+    fn test_interrupted_rebuild_resumes() {
+        use std::sync::atomic::AtomicBool;
+        use std::sync::Arc;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "needle in a haystack").unwrap();
+
+        // Interrupted before any file was picked up
+        let interrupted = SearchEngine::new(temp_dir.path())
+            .unwrap()
+            .with_interrupt_flag(Arc::new(AtomicBool::new(true)));
+        let error = interrupted.rebuild_index().unwrap_err();
+        assert!(error.to_string().contains("probe rebuild --resume"));
+        assert!(temp_dir.path().join(".probe.rebuild/resume.json").exists());
+        assert!(!temp_dir.path().join(".probe").exists());
+
+        let engine = SearchEngine::new(temp_dir.path()).unwrap();
+        engine.resume_rebuild().unwrap();
+        assert!(!temp_dir.path().join(".probe.rebuild").exists());
+
+        let results = engine
+            .search_with_reranker(
+                "needle",
+                Some(1),
+                None,
+                RerankerConfig {
+                    enabled: false,
+                    ..Default::default()
+                },
+                0,
+            )
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(engine.resume_rebuild().is_err());
+    }
+
+    #[test]
+    fn test_searches_during_another_rebuild() {
+        let temp_dir = TempDir::new().unwrap();
+        let lock_dir = temp_dir.path().join(".probe.lock");
+        fs::write(temp_dir.path().join("notes.txt"), "needle in a haystack").unwrap();
+        let engine = SearchEngine::new(temp_dir.path()).unwrap();
+
+        // Nothing to search until the first build finishes
+        let rebuild = index_lock::IndexLock::for_rebuild(&lock_dir)
+            .unwrap()
+            .unwrap();
+        let error = engine.ensure_index_updated().unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("being built by another probe process"));
+        drop(rebuild);
+        engine.rebuild_index().unwrap();
+
+        // Later rebuilds leave the last complete index to search, without updating it
+        fs::write(temp_dir.path().join("more.txt"), "another needle").unwrap();
+        let _rebuild = index_lock::IndexLock::for_rebuild(&lock_dir)
+            .unwrap()
+            .unwrap();
+        let error = engine.rebuild_index().unwrap_err();
+        assert!(error.to_string().contains(&format!(
+            "being rebuilt by another probe process (pid {})",
+            std::process::id()
+        )));
+        let results = engine.search("needle", &SearchOptions::default()).unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_search_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        for count in 1..=5 {
+            let content = vec!["needle"; count].join(" ");
+            fs::write(temp_dir.path().join(format!("file{count}.txt")), content).unwrap();
+        }
+        let engine = SearchEngine::new(temp_dir.path()).unwrap();
+        engine.rebuild_index().unwrap();
+
+        let no_rerank = RerankerConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        let search = |limit, offset| {
+            let options = SearchOptions {
+                limit,
+                offset,
+                ..SearchOptions::default()
+            };
+            let paths = |results: Vec<SearchResult>| -> Vec<_> {
+                results.into_iter().map(|result| result.path).collect()
+            };
+            let indexed = engine
+                .search_with_options("needle", &options, no_rerank.clone())
+                .unwrap();
+            let scanned = engine.search_without_index("needle", &options).unwrap();
+            (paths(indexed), paths(scanned))
+        };
+
+        let (all, all_scanned) = search(10, 0);
+        assert_eq!(all.len(), 5);
+        for (page, offset) in [0, 2, 4].into_iter().enumerate() {
+            let (indexed, scanned) = search(2, offset);
+            assert_eq!(indexed, all.chunks(2).nth(page).unwrap());
+            assert_eq!(scanned, all_scanned.chunks(2).nth(page).unwrap());
+        }
+        // Paging past the end isn't mistaken for a typo
+        assert!(search(2, 6).0.is_empty());
+    }
+
+    #[test]
+    fn test_reindex_removes_stale_documents() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("edited.txt"), "needle in a haystack").unwrap();
+        fs::write(temp_dir.path().join("deleted.txt"), "another needle").unwrap();
+
+        let engine = SearchEngine::new(temp_dir.path()).unwrap();
+        engine.rebuild_index().unwrap();
+        let search = |query: &str| {
+            engine
+                .search_with_reranker(
+                    query,
+                    Some(10),
+                    None,
+                    RerankerConfig {
+                        enabled: false,
+                        ..Default::default()
+                    },
+                    0,
+                )
+                .unwrap()
+        };
+        assert_eq!(search("needle").len(), 2);
+
+        fs::write(temp_dir.path().join("edited.txt"), "just the haystack now").unwrap();
+        fs::remove_file(temp_dir.path().join("deleted.txt")).unwrap();
+        engine.ensure_index_updated().unwrap();
+        assert!(search("needle").is_empty());
+        assert_eq!(search("haystack").len(), 1);
+    }
+
+    #[test]
+    fn test_optimize_merges_segments() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("edited.txt"), "needle in a haystack").unwrap();
+        fs::write(temp_dir.path().join("other.txt"), "another needle").unwrap();
+
+        let engine = SearchEngine::builder(temp_dir.path())
+            .with_reranker_config(RerankerConfig {
+                enabled: false,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        engine.rebuild_index().unwrap();
+        let search = |query: &str| {
+            let options = SearchOptions::default();
+            engine.search(query, &options).unwrap().len()
+        };
+        fs::write(temp_dir.path().join("edited.txt"), "needle without hay").unwrap();
+        engine.ensure_index_updated().unwrap();
+        assert_eq!(search("needle"), 2);
+
+        let report = engine.optimize().unwrap();
+        assert!(report.before.segments > 1);
+        assert_eq!(report.after.segments, 1);
+        assert_eq!(report.after.deleted_docs, 0);
+        assert_eq!(report.after.docs, 2);
+        assert_eq!(search("needle"), 2);
+    }
+
+    #[test]
+    fn test_index_health() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Greeter.java"),
+            "class Greeter {\n    void greet() {\n    }\n}\n",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "plain notes").unwrap();
+        fs::write(temp_dir.path().join("huge.txt"), "x ".repeat(400_000)).unwrap();
+
+        let engine = SearchEngine::new(temp_dir.path()).unwrap();
+        engine.rebuild_index().unwrap();
+        let health = engine.index_health().unwrap();
+
+        assert_eq!(health.chunks.total, 3);
+        assert_eq!(health.chunks.by_extension["java"], 2);
+        assert_eq!(health.chunks.by_extension["txt"], 1);
+        assert_eq!(health.chunks.by_type["Class"], 1);
+        assert_eq!(health.chunks.by_type["Method"], 1);
+        assert_eq!(health.chunks.by_type["Other"], 1); // The paragraph of notes.txt
+        assert_eq!(health.segments.docs, 3);
+        assert!(health.index_bytes > 0);
+        assert!(health.indexed_at.is_some());
+        assert_eq!(
+            health.largest_skipped,
+            vec![(std::path::PathBuf::from("huge.txt"), 800_000)]
+        );
+    }
+
+    #[test]
+    fn test_engine_builder_overrides() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("project");
+        fs::create_dir_all(&root).unwrap();
+        // An invalid probe.yml is never read when the config is passed in
+        fs::write(root.join("probe.yml"), "not: [valid").unwrap();
+        fs::write(root.join("notes.txt"), "needle in a haystack").unwrap();
+        fs::write(root.join("build.log"), "needle in the logs").unwrap();
+
+        let index_dir = temp_dir.path().join("index");
+        let engine = SearchEngine::builder(&root)
+            .with_config(Config::default())
+            .with_index_dir(&index_dir)
+            .with_ignore_globs(["*.log"])
+            .with_threads(2)
+            .with_reranker_config(RerankerConfig {
+                enabled: false,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        engine.rebuild_index().unwrap();
+        assert!(index_dir.join("metadata.bin").exists());
+        assert!(!root.join(".probe").exists());
+
+        let results = engine.search("needle", &SearchOptions::default()).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, std::path::PathBuf::from("notes.txt"));
+    }
+
+    #[test]
+    fn test_near_duplicate_chunks_are_collapsed() {
+        let temp_dir = TempDir::new().unwrap();
+        let mapper = "class OrderMapper {\n    OrderDto toDto(Order order) {\n        OrderDto dto = new OrderDto();\n        dto.setId(order.getId());\n        dto.setTotal(order.getTotal());\n        dto.setCustomer(order.getCustomer());\n        return dto;\n    }\n}\n";
+        for dir in ["src", "gen/a", "gen/b"] {
+            fs::create_dir_all(temp_dir.path().join(dir)).unwrap();
+            fs::write(temp_dir.path().join(dir).join("OrderMapper.java"), mapper).unwrap();
+        }
+
+        let search = |config: Config| {
+            let engine = SearchEngine::builder(temp_dir.path())
+                .with_config(config)
+                .with_reranker_config(RerankerConfig {
+                    enabled: false,
+                    ..Default::default()
+                })
+                .build()
+                .unwrap();
+            engine.rebuild_index().unwrap();
+            engine
+                .search("toDto customer", &SearchOptions::default())
+                .unwrap()
+                .into_iter()
+                .filter(|result| result.chunk_type.as_deref() == Some("Method"))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(search(Config::default()).len(), 3);
+
+        let mut config = Config::default();
+        config.duplicates.enabled = true;
+        let methods = search(config);
+        assert_eq!(methods.len(), 1);
+        let mut paths: Vec<_> = methods[0]
+            .duplicates
+            .iter()
+            .chain([&methods[0].path])
+            .map(|path| path.to_str().unwrap())
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                "gen/a/OrderMapper.java",
+                "gen/b/OrderMapper.java",
+                "src/OrderMapper.java"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stem_language_keeps_score_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("tests")).unwrap();
+        fs::write(temp_dir.path().join("main.txt"), "needle needle needle").unwrap();
+        fs::write(temp_dir.path().join("tests/main_test.txt"), "needle").unwrap();
+
+        // Test files are up-ranked, so the one with fewer matches comes first
+        let mut config = Config::default();
+        config.scoring.test_penalty = 100.0;
+        let engine = SearchEngine::builder(temp_dir.path())
+            .with_config(config)
+            .with_reranker_config(RerankerConfig {
+                enabled: false,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        engine.rebuild_index().unwrap();
+
+        for stem_language in [None, Some(tantivy::tokenizer::Language::English)] {
+            let options = SearchOptions {
+                stem_language,
+                ..SearchOptions::default()
+            };
+            let results = engine.search("needle", &options).unwrap();
+            assert_eq!(
+                results[0].path,
+                std::path::Path::new("tests/main_test.txt"),
+                "{stem_language:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_chunk_neighbors() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Cart.java"),
+            "class Cart {\n    void add(Item item) {\n        items.add(item);\n    }\n\n    void remove(Item item) {\n        items.remove(item);\n    }\n\n    void checkout() {\n        payments.charge(total());\n    }\n}\n",
+        )
+        .unwrap();
+        let engine = SearchEngine::builder(temp_dir.path())
+            .with_reranker_config(RerankerConfig {
+                enabled: false,
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        engine.rebuild_index().unwrap();
+
+        let options = SearchOptions::default();
+        let results: Vec<_> = ["remove", "add"]
+            .iter()
+            .map(|name| {
+                let results = engine.search(name, &options).unwrap();
+                results
+                    .into_iter()
+                    .find(|result| result.chunk_name.as_deref() == Some(*name))
+                    .unwrap()
+            })
+            .collect();
+        let neighbors = engine
+            .chunk_neighbors("remove", &options, &results, 1)
+            .unwrap();
+        let names = |neighbors: &[SearchResult]| {
+            neighbors
+                .iter()
+                .map(|neighbor| neighbor.chunk_name.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // A method's siblings, or the class above the first method
+        assert_eq!(names(&neighbors[0]), vec!["add", "checkout"]);
+        assert_eq!(names(&neighbors[1]), vec!["Cart", "remove"]);
+        assert_eq!(neighbors[0][0].score, 0.0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_daemon_answers_searches() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), "needle in a haystack").unwrap();
+        let no_rerank = RerankerConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        let engine = |interrupt: Arc<AtomicBool>| {
+            SearchEngine::builder(temp_dir.path())
+                .with_reranker_config(no_rerank.clone())
+                .with_verbosity(Verbosity::Quiet)
+                .with_interrupt_flag(interrupt)
+                .build()
+                .unwrap()
+        };
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let daemon = engine(interrupt.clone());
+        let client = engine(Arc::default());
+        let options = SearchOptions::default();
+
+        // Searched in-process while no daemon is running
+        assert!(client
+            .search_with_daemon("needle", &options, &no_rerank)
+            .unwrap()
+            .is_none());
+
+        std::thread::scope(|scope| {
+            let served = scope.spawn(|| daemon.serve_daemon());
+            let socket = client.daemon_socket_path();
+            let started = std::time::Instant::now();
+            while std::os::unix::net::UnixStream::connect(&socket).is_err() {
+                assert!(started.elapsed().as_secs() < 10, "the daemon didn't start");
+                std::thread::sleep(std::time::Duration::from_millis(20));
+            }
+
+            let results = client
+                .search_with_daemon("needle", &options, &no_rerank)
+                .unwrap()
+                .unwrap();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].path, std::path::PathBuf::from("notes.txt"));
+            // A daemon started with other reranker settings leaves the search to the client
+            let reranked = client
+                .search_with_daemon("needle", &options, &RerankerConfig::default())
+                .unwrap();
+            assert!(reranked.is_none());
+
+            interrupt.store(true, Ordering::Relaxed);
+            served.join().unwrap().unwrap();
+        });
+        assert!(!client.daemon_socket_path().exists());
+    }
+
     #[test]
     fn test_metadata_tracking() {
         let temp_dir = TempDir::new().unwrap();
@@ -62,4 +844,86 @@ mod tests {
         let loaded_metadata = metadata::IndexMetadata::load(&metadata_file).unwrap();
         assert_eq!(loaded_metadata.file_count(), 1);
     }
+
+    #[test]
+    fn test_metadata_hashes_touched_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("Main.java");
+        fs::write(&test_file, "class Main {}").unwrap();
+        let files = vec![file_scanner::IndexedFile {
+            disk_path: test_file.clone(),
+            relative_path: "Main.java".into(),
+        }];
+        let touch = || {
+            let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+            fs::File::options()
+                .write(true)
+                .open(&test_file)
+                .unwrap()
+                .set_modified(an_hour_ago)
+                .unwrap();
+        };
+
+        let mut metadata = metadata::IndexMetadata::new();
+        metadata.update_file(&files[0]).unwrap();
+        touch();
+        assert!(metadata.needs_reindex(&files).unwrap().is_empty());
+        assert!(metadata.is_dirty());
+
+        // Same size, different content
+        fs::write(&test_file, "class Niam {}").unwrap();
+        assert_eq!(metadata.needs_reindex(&files).unwrap().len(), 1);
+
+        let mut metadata =
+            metadata::IndexMetadata::new().with_change_detection(config::ChangeDetection::Mtime);
+        metadata.update_file(&files[0]).unwrap();
+        touch();
+        assert_eq!(metadata.needs_reindex(&files).unwrap().len(), 1);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_metadata_paths_are_case_insensitive() {
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("Main.java");
+        fs::write(&test_file, "class Main {}").unwrap();
+
+        let mut metadata = metadata::IndexMetadata::new();
+        metadata
+            .update_file(&file_scanner::IndexedFile {
+                disk_path: test_file.clone(),
+                relative_path: "Main.java".into(),
+            })
+            .unwrap();
+
+        let renamed_case = vec![file_scanner::IndexedFile {
+            disk_path: test_file,
+            relative_path: "main.JAVA".into(),
+        }];
+        assert!(metadata.needs_reindex(&renamed_case).unwrap().is_empty());
+        assert!(metadata.prune(&renamed_case).is_empty());
+        assert_eq!(metadata.file_count(), 1);
+    }
+
+    #[test]
+    fn test_metadata_prune_removes_deleted_files() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("kept.txt"), "kept").unwrap();
+        fs::write(temp_dir.path().join("deleted.txt"), "deleted").unwrap();
+
+        let engine = SearchEngine::new(temp_dir.path()).unwrap();
+        engine.rebuild_index().unwrap();
+
+        fs::remove_file(temp_dir.path().join("deleted.txt")).unwrap();
+        engine.ensure_index_updated().unwrap();
+
+        let metadata =
+            metadata::IndexMetadata::load(temp_dir.path().join(".probe").join("metadata.bin"))
+                .unwrap();
+        assert_eq!(metadata.file_count(), 1);
+        assert_eq!(
+            metadata.list_files().collect::<Vec<_>>(),
+            vec![&std::path::PathBuf::from("kept.txt")]
+        );
+    }
 }
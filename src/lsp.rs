@@ -0,0 +1,500 @@
+use crate::code_chunker::read_source;
+use crate::search_engine::SearchEngine;
+use crate::search_index::{SearchOptions, SymbolMatch};
+use crate::server::{percent_decode, result_json};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Most symbols returned for one `workspace/symbol` or `textDocument/definition` request
+const MAX_SYMBOLS: usize = 200;
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Error answered to a request
+#[derive(Debug, PartialEq)]
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+impl ResponseError {
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self {
+            code: INVALID_PARAMS,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ResponseError {
+    fn from(e: anyhow::Error) -> Self {
+        Self {
+            code: INTERNAL_ERROR,
+            message: format!("{e:#}"),
+        }
+    }
+}
+
+/// Reads the body of one message framed by a `Content-Length` header, `None` once the
+/// client closes the input
+pub(crate) fn read_message<R: BufRead>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            // Blank lines between messages are tolerated
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                let value = value.trim();
+                length = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| anyhow!("Invalid Content-Length {value:?}"))?,
+                );
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+pub(crate) fn write_message<W: Write>(writer: &mut W, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Answers the messages read from `reader` on `writer` until the client sends `exit` or
+/// closes the input. Paths in responses are `file://` URIs under `root_dir`.
+pub(crate) fn serve<R: BufRead, W: Write>(
+    engine: &SearchEngine,
+    root_dir: &Path,
+    mut reader: R,
+    mut writer: W,
+    defaults: &SearchOptions,
+) -> Result<()> {
+    let root_dir = root_dir
+        .canonicalize()
+        .unwrap_or_else(|_| root_dir.to_path_buf());
+    while let Some(body) = read_message(&mut reader)? {
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let error = ResponseError {
+                    code: PARSE_ERROR,
+                    message: e.to_string(),
+                };
+                write_message(&mut writer, &response(&Value::Null, Err(error)))?;
+                continue;
+            }
+        };
+        let method = message["method"].as_str().unwrap_or("");
+
+        // Notifications get no answer
+        let Some(id) = message.get("id") else {
+            match method {
+                "exit" => return Ok(()),
+                "textDocument/didSave" => {
+                    if let Err(e) = engine.ensure_index_updated() {
+                        eprintln!("Warning: failed to update the index: {e:#}");
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        };
+        let result = respond(engine, &root_dir, method, &message["params"], defaults);
+        write_message(&mut writer, &response(id, result))?;
+    }
+    Ok(())
+}
+
+fn response(id: &Value, result: std::result::Result<Value, ResponseError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code, "message": error.message },
+        }),
+    }
+}
+
+fn respond(
+    engine: &SearchEngine,
+    root_dir: &Path,
+    method: &str,
+    params: &Value,
+    defaults: &SearchOptions,
+) -> std::result::Result<Value, ResponseError> {
+    match method {
+        "initialize" => Ok(json!({
+            "capabilities": {
+                "workspaceSymbolProvider": true,
+                "definitionProvider": true,
+                // Saved files are reindexed, nothing else about open documents is needed
+                "textDocumentSync": { "openClose": false, "change": 0, "save": true },
+            },
+            "serverInfo": { "name": "probe", "version": env!("CARGO_PKG_VERSION") },
+        })),
+        "shutdown" => Ok(Value::Null),
+        "workspace/symbol" => {
+            let query = params["query"]
+                .as_str()
+                .ok_or_else(|| ResponseError::invalid_params("Missing query"))?;
+            let symbols = engine.find_symbols(query, MAX_SYMBOLS)?;
+            Ok(symbols
+                .iter()
+                .map(|symbol| symbol_information(root_dir, symbol))
+                .collect())
+        }
+        "textDocument/definition" => definition(engine, root_dir, params),
+        "probe/search" => search(engine, root_dir, params, defaults),
+        _ => Err(ResponseError {
+            code: METHOD_NOT_FOUND,
+            message: format!("Unsupported method {method}"),
+        }),
+    }
+}
+
+/// Definitions named like the identifier at the given position, matching either the whole
+/// name or its last part, as in `Order.Item`
+fn definition(
+    engine: &SearchEngine,
+    root_dir: &Path,
+    params: &Value,
+) -> std::result::Result<Value, ResponseError> {
+    let uri = params["textDocument"]["uri"]
+        .as_str()
+        .ok_or_else(|| ResponseError::invalid_params("Missing textDocument.uri"))?;
+    let (Some(line), Some(character)) = (
+        params["position"]["line"].as_u64(),
+        params["position"]["character"].as_u64(),
+    ) else {
+        return Err(ResponseError::invalid_params("Missing position"));
+    };
+    let path = uri_to_path(uri)
+        .ok_or_else(|| ResponseError::invalid_params(format!("Not a file URI: {uri}")))?;
+    let content =
+        read_source(&path).map_err(|e| anyhow!("Failed to read {}: {e}", path.display()))?;
+    let Some(word) = content
+        .lines()
+        .nth(line as usize)
+        .and_then(|text| word_at(text, character as usize))
+    else {
+        return Ok(Value::Null);
+    };
+
+    let qualified = format!(".{word}");
+    Ok(engine
+        .find_symbols(word, MAX_SYMBOLS)?
+        .iter()
+        .filter(|symbol| symbol.name == word || symbol.name.ends_with(&qualified))
+        .map(|symbol| location(root_dir, &symbol.path, symbol.start_line, symbol.end_line))
+        .collect())
+}
+
+/// `probe/search` with params `{query, limit?, offset?, filetype?, kinds?, paths?,
+/// exclude_paths?}`, answered like `GET /search` of `probe serve` plus a URI and LSP range
+/// for each result
+fn search(
+    engine: &SearchEngine,
+    root_dir: &Path,
+    params: &Value,
+    defaults: &SearchOptions,
+) -> std::result::Result<Value, ResponseError> {
+    let query = params["query"]
+        .as_str()
+        .ok_or_else(|| ResponseError::invalid_params("Missing query"))?;
+    let mut options = defaults.clone();
+    let number = |name: &str| match &params[name] {
+        Value::Null => Ok(None),
+        value => value
+            .as_u64()
+            .map(|n| Some(n as usize))
+            .ok_or_else(|| ResponseError::invalid_params(format!("Invalid {name} {value}"))),
+    };
+    if let Some(limit) = number("limit")? {
+        options.limit = limit;
+    }
+    if let Some(offset) = number("offset")? {
+        options.offset = offset;
+    }
+    if let Some(filetype) = params["filetype"].as_str() {
+        options.filetype = Some(filetype.to_string());
+    }
+    let strings = |name: &str| {
+        params[name]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    options
+        .kinds
+        .extend(strings("kinds").iter().map(|kind| kind.to_lowercase()));
+    options.paths.extend(strings("paths"));
+    options.exclude_paths.extend(strings("exclude_paths"));
+
+    let results = engine.search(query, &options)?;
+    let results: Vec<Value> = results
+        .iter()
+        .map(|result| {
+            let mut value = result_json(result);
+            let start_line = result.start_line.unwrap_or(0);
+            let end_line = result.end_line.unwrap_or(start_line);
+            let location = location(root_dir, &result.path, start_line, end_line);
+            value["uri"] = location["uri"].clone();
+            value["range"] = location["range"].clone();
+            value
+        })
+        .collect();
+    Ok(json!({ "query": query, "offset": options.offset, "results": results }))
+}
+
+fn symbol_information(root_dir: &Path, symbol: &SymbolMatch) -> Value {
+    json!({
+        "name": symbol.name,
+        "kind": symbol_kind(&symbol.kind),
+        "location": location(root_dir, &symbol.path, symbol.start_line, symbol.end_line),
+    })
+}
+
+/// LSP `SymbolKind` of a chunk kind
+fn symbol_kind(kind: &str) -> u8 {
+    match kind {
+        "module" => 2,
+        "class" => 5,
+        "method" => 6,
+        "interface" => 11,
+        "function" => 12,
+        "struct" => 23,
+        _ => 13, // Variable
+    }
+}
+
+/// Location of 0-based lines `start_line` to `end_line` of a file in the index
+fn location(root_dir: &Path, relative_path: &Path, start_line: usize, end_line: usize) -> Value {
+    json!({
+        "uri": path_to_uri(&root_dir.join(relative_path)),
+        "range": {
+            "start": { "line": start_line, "character": 0 },
+            "end": { "line": end_line + 1, "character": 0 },
+        },
+    })
+}
+
+/// `file://` URI of an absolute path
+fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        // Windows paths like C:/src
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(char::from(byte))
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+/// Path of a `file://` URI
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // An optional host, usually left out or `localhost`, comes before the path
+    let path = &rest[rest.find('/')?..];
+    // `+` is no space in URIs, unlike in query strings
+    let path = percent_decode(&path.replace('+', "%2B"));
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_string(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
+}
+
+/// Identifier around a position given in UTF-16 code units, as LSP counts characters
+fn word_at(line: &str, character: usize) -> Option<&str> {
+    let mut units = 0;
+    let offset = line
+        .char_indices()
+        .find(|(_, c)| {
+            units += c.len_utf16();
+            units > character
+        })
+        .map_or(line.len(), |(offset, _)| offset);
+
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let start = line[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_word(c))
+        .last()
+        .map_or(offset, |(start, _)| start);
+    let end = line[offset..]
+        .find(|c: char| !is_word(c))
+        .map_or(line.len(), |len| offset + len);
+    (start < end).then(|| &line[start..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn frame(message: &Value) -> Vec<u8> {
+        let mut framed = Vec::new();
+        write_message(&mut framed, message).unwrap();
+        framed
+    }
+
+    /// Messages written by the server, in order
+    fn read_all(mut output: &[u8]) -> Vec<Value> {
+        let mut messages = Vec::new();
+        while let Some(body) = read_message(&mut output).unwrap() {
+            messages.push(serde_json::from_slice(&body).unwrap());
+        }
+        messages
+    }
+
+    #[test]
+    fn test_read_message() {
+        let mut input = &b"Content-Type: application/vscode-jsonrpc\r\ncontent-length: 2\r\n\r\n{}\r\nContent-Length: 4\r\n\r\nnull"[..];
+        assert_eq!(read_message(&mut input).unwrap(), Some(b"{}".to_vec()));
+        assert_eq!(read_message(&mut input).unwrap(), Some(b"null".to_vec()));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        assert!(read_message(&mut &b"Content-Length: x\r\n\r\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_uris() {
+        let uri = path_to_uri(Path::new("/home/me/my project/a+b.rs"));
+        assert_eq!(uri, "file:///home/me/my%20project/a%2Bb.rs");
+        assert_eq!(
+            uri_to_path(&uri),
+            Some(PathBuf::from("/home/me/my project/a+b.rs"))
+        );
+        assert_eq!(
+            uri_to_path("file://localhost/tmp/x.rs"),
+            Some(PathBuf::from("/tmp/x.rs"))
+        );
+        assert_eq!(
+            uri_to_path("file:///c%3A/src/Main.java"),
+            Some(PathBuf::from("c:/src/Main.java"))
+        );
+        assert_eq!(uri_to_path("https://example.com/x.rs"), None);
+    }
+
+    #[test]
+    fn test_word_at() {
+        let line = "    let total = order.totalPrice();";
+        assert_eq!(word_at(line, 24), Some("totalPrice"));
+        assert_eq!(word_at(line, 22), Some("totalPrice"));
+        assert_eq!(word_at(line, 21), Some("order"));
+        assert_eq!(word_at(line, 2), None);
+        // Characters outside the basic plane count as two UTF-16 code units
+        assert_eq!(word_at("\u{1F600} run_it()", 4), Some("run_it"));
+    }
+
+    #[test]
+    fn test_serve_symbols_definitions_and_search() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Orders.java"),
+            "class Orders {\n    int totalPrice() {\n        return 0;\n    }\n\n    int total() {\n        return totalPrice();\n    }\n}\n",
+        )
+        .unwrap();
+        let engine = SearchEngine::new(temp_dir.path()).unwrap();
+        engine.ensure_index_updated().unwrap();
+        let root_dir = temp_dir.path().canonicalize().unwrap();
+        let uri = path_to_uri(&root_dir.join("Orders.java"));
+
+        let mut input = Vec::new();
+        for message in [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "workspace/symbol", "params": { "query": "TOTAL" } }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "textDocument/definition",
+                "params": { "textDocument": { "uri": uri }, "position": { "line": 6, "character": 18 } },
+            }),
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "probe/search", "params": { "query": "totalPrice", "limit": 1 } }),
+            json!({ "jsonrpc": "2.0", "id": 5, "method": "textDocument/hover", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 6, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+            json!({ "jsonrpc": "2.0", "id": 7, "method": "shutdown" }),
+        ] {
+            input.extend(frame(&message));
+        }
+        let mut output = Vec::new();
+        serve(
+            &engine,
+            temp_dir.path(),
+            &input[..],
+            &mut output,
+            &SearchOptions::default(),
+        )
+        .unwrap();
+
+        // Nothing is answered to notifications or after exit
+        let responses = read_all(&output);
+        let ids: Vec<&Value> = responses.iter().map(|response| &response["id"]).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            responses[0]["result"]["capabilities"]["workspaceSymbolProvider"],
+            true
+        );
+
+        // The exact name comes first
+        let symbols = responses[1]["result"].as_array().unwrap();
+        let names: Vec<&str> = symbols
+            .iter()
+            .map(|symbol| symbol["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["total", "totalPrice"]);
+        assert_eq!(symbols[0]["kind"], 6);
+        assert_eq!(symbols[0]["location"]["uri"], uri.as_str());
+        assert_eq!(symbols[0]["location"]["range"]["start"]["line"], 5);
+
+        let definitions = responses[2]["result"].as_array().unwrap();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0]["range"]["start"]["line"], 1);
+
+        let results = responses[3]["result"]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["chunk_name"], "totalPrice");
+        assert_eq!(results[0]["uri"], uri.as_str());
+
+        assert_eq!(responses[4]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[5]["result"], Value::Null);
+    }
+}
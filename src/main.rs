@@ -1,8 +1,24 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
 use fastembed::RerankerModel;
-use probe::{available_models, parse_reranker_model, ProbeConfig, RerankerConfig, SearchEngine};
+use probe::dupes::DupesOptions;
+use probe::embeddings::{parse_embedding_model, Embedder};
+use probe::history::{self, SearchHistory};
+use probe::index_archive::PrefixRewrite;
+use probe::search_engine::arrange_results;
+use probe::syntax;
+use probe::terminal::{format_age, format_size, ColorChoice};
+use probe::{
+    available_models, config::parse_language, models, parse_reranker_model, paths,
+    ChunkExplanation, ChunkRange, Config, IndexStatus, ProbeConfig, QueryMode, Reranker,
+    RerankerConfig, SearchEngine, SearchOptions, SearchResult, Verbosity, WhyReport,
+};
+use std::collections::HashMap;
+use std::io::Read;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
 
 mod show_chunks;
 
@@ -13,12 +29,99 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    #[arg(short, long, help = "Directory to search")]
-    directory: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Directory to search; repeat to search several directories at once"
+    )]
+    directory: Vec<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "directory",
+        help = "Search a repository registered with `probe repos add` instead of --directory"
+    )]
+    repo: Option<String>,
+
+    #[arg(
+        long = "all-repos",
+        conflicts_with_all = ["directory", "repo", "index_dir"],
+        help = "Search every repository registered with `probe repos add`"
+    )]
+    all_repos: bool,
+
+    #[arg(
+        long = "index-dir",
+        help = "Directory to store the index in (default: <directory>/.probe)"
+    )]
+    index_dir: Option<PathBuf>,
+
+    #[arg(
+        short = 'j',
+        long,
+        global = true,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Threads chunking files while indexing (default: index.threads from probe.yml, or the number of CPUs)"
+    )]
+    threads: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "all_repos",
+        help = "Use the index of a profile from probe.yml, with its own file filters and stemming"
+    )]
+    profile: Option<String>,
+
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        action = ArgAction::Count,
+        global = true,
+        help = "Print diagnostics to stderr (-v for changed files and timings, -vv to also trace chunking)"
+    )]
+    verbose: u8,
+
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        global = true,
+        conflicts_with = "verbose",
+        help = "Suppress status and progress messages, printing only results"
+    )]
+    quiet: bool,
+
+    #[arg(
+        long,
+        value_name = "WHEN",
+        default_value = "auto",
+        value_parser = ["auto", "always", "never"],
+        global = true,
+        help = "Color snippets: auto (when printing to a terminal), always or never"
+    )]
+    color: String,
 
     #[arg(short = 't', long, help = "Filter by file type (extension)")]
     filetype: Option<String>,
 
+    #[arg(
+        long,
+        help = "Only return chunks of this kind (function, method, class, struct, interface, module, other); repeatable"
+    )]
+    kind: Vec<String>,
+
+    #[arg(
+        long = "path",
+        help = "Only return results from files matching this glob (e.g. \"src/**\"); repeatable"
+    )]
+    paths: Vec<String>,
+
+    #[arg(
+        long = "exclude-path",
+        help = "Leave out results from files matching this glob (e.g. \"**/generated/**\"); repeatable"
+    )]
+    exclude_paths: Vec<String>,
+
     #[arg(
         short = 'n',
         long = "num-results",
@@ -27,9 +130,54 @@ struct Cli {
     )]
     num_results: usize,
 
+    #[arg(long, help = "Skip this many of the best results")]
+    offset: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with = "offset",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Show this page of results, --num-results per page"
+    )]
+    page: Option<u64>,
+
     #[arg(long = "no-rerank", help = "Disable reranking of search results")]
     no_rerank: bool,
 
+    #[arg(
+        long = "no-index",
+        help = "Search the files directly without building or using an index (slower, no reranking or stemming)"
+    )]
+    no_index: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "no_index",
+        help = "Also rank chunks by embedding similarity to the query and fuse both rankings (needs embeddings.enabled in probe.yml)"
+    )]
+    hybrid: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["no_index", "all_repos"],
+        help = "Show how each result's score was derived: matched terms, full-text score, penalties and reranker score"
+    )]
+    explain: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["regex", "no_index"],
+        help = "Match the query as one literal string instead of independent words"
+    )]
+    exact: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "no_index",
+        help = "Match the query as a regular expression against single words (identifiers are split at camelCase, e.g. 'user.*' finds getUserName)"
+    )]
+    regex: bool,
+
     #[arg(
         long = "rerank-model",
         help = "Reranking model to use (built-in: bge-reranker-base, bge-reranker-v2-m3, etc. or custom model name from config)"
@@ -43,12 +191,96 @@ struct Cli {
     )]
     rerank_candidates: usize,
 
+    #[arg(
+        long = "candidate-multiplier",
+        value_name = "N",
+        help = "Candidates to fetch for reranking per wanted result",
+        default_value = "2"
+    )]
+    candidate_multiplier: usize,
+
+    #[arg(
+        long = "score-cutoff",
+        value_name = "FRACTION",
+        help = "Only keep candidates whose full-text score is at least this fraction (0-1) of the best match's"
+    )]
+    score_cutoff: Option<f32>,
+
+    #[arg(
+        long = "min-score",
+        value_name = "SCORE",
+        help = "Leave out results scoring below this (reranker scores, or full-text scores with --no-rerank)"
+    )]
+    min_score: Option<f32>,
+
+    #[arg(
+        long = "group-by-file",
+        help = "Show the results file by file, leaving out chunks that overlap better ones"
+    )]
+    group_by_file: bool,
+
+    #[arg(
+        long,
+        help = "Only search the chunks the previous search of the project found, to narrow its results down"
+    )]
+    refine: bool,
+
+    #[arg(
+        long = "expand",
+        value_name = "N",
+        default_value = "0",
+        help = "Also show the N chunks before and after each result in its file, like the methods around a matching method or the class above the first one (text and ndjson output of single-repository searches)"
+    )]
+    neighbors: usize,
+
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "text",
+        value_parser = ["text", "vimgrep", "csv", "tsv", "ndjson"],
+        conflicts_with = "explain",
+        help = "Result output: text; vimgrep for uncolored `path:line:col: text` lines, e.g. for Vim's :cexpr or fzf; or csv, tsv and ndjson records with path, lines, score, chunk name and type and the snippet on one line"
+    )]
+    format: String,
+
     #[arg(
         long = "config",
-        help = "Path to configuration file (default: ~/.probe/config.yaml)"
+        help = "Path to configuration file (default: config.yaml in the platform config directory, e.g. ~/.config/probe)"
     )]
     config_path: Option<PathBuf>,
 
+    #[arg(
+        long = "no-stem",
+        help = "Match exact word forms only, ignoring the stemming configured in probe.yml"
+    )]
+    no_stem: bool,
+
+    #[arg(
+        long = "stem",
+        conflicts_with = "no_stem",
+        help = "Match other forms of the query's words even when probe.yml turns stemming off"
+    )]
+    stem: bool,
+
+    #[arg(
+        long,
+        help = "Search for the query's words as written, without also matching the parts of identifiers or what abbreviations stand for"
+    )]
+    no_query_expansion: bool,
+
+    #[arg(
+        long = "no-fuzzy",
+        help = "Don't retry a query that matches nothing with words spelled similarly to its own"
+    )]
+    no_fuzzy: bool,
+
+    #[arg(
+        long = "stem-language",
+        conflicts_with = "no_stem",
+        help = "Stem the query with this language instead of the one from probe.yml (e.g. german, fr)"
+    )]
+    stem_language: Option<String>,
+
     #[arg(
         short = 'C',
         long = "context",
@@ -57,43 +289,1546 @@ struct Cli {
     )]
     context_lines: usize,
 
-    #[arg(help = "Search query")]
-    query: Option<String>,
+    #[arg(
+        help = "Search query (multiple words are joined with spaces, no quoting needed; use - to read it from stdin)"
+    )]
+    query: Vec<String>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     #[command(about = "Rebuild search index")]
-    Rebuild,
+    Rebuild {
+        #[arg(long, help = "Continue a rebuild that was interrupted with Ctrl-C")]
+        resume: bool,
+    },
     #[command(about = "Show index statistics")]
     Stats {
         #[arg(long, help = "List all indexed files")]
         ls_files: bool,
         #[arg(long, help = "Show files that would be indexed")]
         status: bool,
+        #[arg(long, help = "List files left out of the index and why")]
+        skipped: bool,
+    },
+    #[command(about = "Merge index segments and drop deleted documents to speed up searches")]
+    Optimize,
+    #[command(
+        about = "Compare the index with the working tree and report stale, missing and orphaned files"
+    )]
+    Verify,
+    #[command(
+        about = "Time a rebuild, an update, queries and reranking, and print the timings as JSON"
+    )]
+    Bench {
+        #[arg(
+            long = "query",
+            value_name = "QUERY",
+            help = "Query to time; repeatable (default: a few generic queries)"
+        )]
+        queries: Vec<String>,
+        #[arg(long, default_value = "10", help = "How many times each query is run")]
+        runs: usize,
+    },
+    #[command(about = "Keep the index up to date by reindexing files as they change")]
+    Watch,
+    #[command(
+        about = "Serve /search, /stats and /reindex as JSON over HTTP, keeping the index and reranker loaded"
+    )]
+    Serve {
+        #[arg(
+            long,
+            value_name = "ADDR",
+            default_value = "127.0.0.1:7878",
+            help = "Address to listen on"
+        )]
+        http: String,
+    },
+    #[command(
+        about = "Speak the Language Server Protocol over stdin/stdout, for editors to find symbols and search"
+    )]
+    Lsp,
+    #[command(
+        about = "Keep the index and reranker loaded and answer this project's searches from other probe invocations over a Unix socket"
+    )]
+    Daemon,
+    #[command(
+        about = "Explain how a file or chunk scores for a query, e.g. why it doesn't show up"
+    )]
+    Why {
+        #[arg(help = "Search query (quote queries of several words)")]
+        query: String,
+        #[arg(help = "File to explain, optionally with a line to pick its chunk: path[:line]")]
+        target: String,
+    },
+    #[command(about = "Find clusters of near-duplicate functions and methods")]
+    Dupes {
+        #[arg(
+            long,
+            default_value = "0.85",
+            help = "Minimum similarity (0-1) of two chunks' tokens to report them as duplicates"
+        )]
+        threshold: f64,
+        #[arg(
+            long = "min-lines",
+            default_value = "5",
+            help = "Ignore chunks shorter than this many lines"
+        )]
+        min_lines: usize,
+    },
+    #[command(about = "Export the graph of calls between methods defined in the repository")]
+    Graph {
+        #[arg(
+            long,
+            default_value = "dot",
+            value_parser = ["dot", "json"],
+            help = "Output format: dot (Graphviz) or json"
+        )]
+        format: String,
+    },
+    #[command(about = "Export or import the index, e.g. to cache it in CI")]
+    Index {
+        #[command(subcommand)]
+        command: IndexCommand,
     },
     #[command(about = "List available reranking models")]
     ListModels,
+    #[command(about = "Download, list and remove the models cached for reranking and embeddings")]
+    Models {
+        #[command(subcommand)]
+        command: ModelsCommand,
+    },
+    #[command(about = "Manage the repositories searchable with --repo and --all-repos")]
+    Repos {
+        #[command(subcommand)]
+        command: ReposCommand,
+    },
+    #[command(about = "Print the classes, methods and functions of a file as a tree")]
+    Outline {
+        #[arg(help = "File to outline")]
+        file: PathBuf,
+        #[arg(long, help = "Print the outline as JSON")]
+        json: bool,
+    },
+    #[command(about = "List recent searches, numbered for `probe last N` and `probe !N`")]
+    History {
+        #[arg(long, help = "Forget all recorded searches")]
+        clear: bool,
+    },
+    #[command(about = "Show the results of a previous search again without searching")]
+    Last {
+        #[arg(help = "Number of the search, as listed by `probe history` (default: the last one)")]
+        id: Option<u64>,
+    },
+    #[command(about = "Run a search recipe saved in probe.yml")]
+    Run {
+        #[arg(help = "Recipe name")]
+        name: String,
+        // Consumed by `expand_recipe` before the command line is parsed
+        #[allow(dead_code)]
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            help = "Values for the recipe's {0}, {1}, ... placeholders, followed by any extra flags or query words"
+        )]
+        args: Vec<String>,
+    },
+    #[command(about = "List the classes, methods and functions of files with their locations")]
+    Symbols {
+        #[arg(
+            default_value = ".",
+            help = "File or directory to list symbols of (default: current directory)"
+        )]
+        path: PathBuf,
+        #[arg(
+            long = "type",
+            help = "Only list symbols of this kind (function, method, class, struct, interface, module, other); repeatable"
+        )]
+        kinds: Vec<String>,
+        #[arg(
+            long,
+            help = "Only list symbols whose name starts with this (case-insensitive)"
+        )]
+        prefix: Option<String>,
+    },
     #[command(about = "Show how files are chunked for indexing")]
     ShowChunks {
         #[arg(help = "Files or directories to show chunks for (default: current directory)")]
         paths: Vec<String>,
+        #[arg(
+            long,
+            help = "Only show chunks of this kind (function, method, class, struct, interface, module, other); repeatable"
+        )]
+        kind: Vec<String>,
+        #[arg(
+            long,
+            help = "Only show chunks whose name matches this glob (e.g. 'get*')"
+        )]
+        name: Option<String>,
+        #[arg(long, help = "Print chunk kinds, names and line ranges as JSON")]
+        json: bool,
+        #[arg(
+            long,
+            help = "Print chunk counts and sizes per file and per language, and list files without structural chunking"
+        )]
+        summary: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum IndexCommand {
+    #[command(about = "Update the index and package it with a manifest into a .tar.zst file")]
+    Export {
+        #[arg(help = "Archive to write, e.g. probe-index.tar.zst")]
+        file: PathBuf,
     },
+    #[command(about = "Replace the index with one written by `probe index export`")]
+    Import {
+        #[arg(help = "Archive to read")]
+        file: PathBuf,
+        #[arg(
+            long = "rewrite-prefix",
+            value_name = "FROM=TO",
+            help = "Move indexed paths under FROM to TO, e.g. `services/api=` when the index was built from the repository root (repeatable)"
+        )]
+        rewrite_prefix: Vec<PrefixRewrite>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModelsCommand {
+    #[command(about = "Download models ahead of time, e.g. for offline use")]
+    Download {
+        #[arg(
+            help = "Reranking or embedding models (default: the reranker searches use, and the embedding model if probe.yml turns embeddings on)"
+        )]
+        names: Vec<String>,
+    },
+    #[command(about = "List downloaded models and the disk space they take")]
+    List,
+    #[command(about = "Delete downloaded models")]
+    Remove {
+        #[arg(help = "Models to delete, by name or Hugging Face repository")]
+        names: Vec<String>,
+        #[arg(long, conflicts_with = "names", help = "Delete every downloaded model")]
+        all: bool,
+    },
+    #[command(about = "Print the model cache directory, or where a downloaded model is")]
+    Path {
+        #[arg(help = "Model name or Hugging Face repository")]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReposCommand {
+    #[command(about = "Register a repository")]
+    Add {
+        #[arg(help = "Root directory of the repository")]
+        path: PathBuf,
+        #[arg(long, help = "Name to use with --repo (default: the directory name)")]
+        name: Option<String>,
+    },
+    #[command(about = "List registered repositories")]
+    List,
+    #[command(about = "Unregister a repository, keeping its index")]
+    Remove {
+        #[arg(help = "Name of the repository")]
+        name: String,
+    },
+}
+
+/// Queries `probe bench` times unless given `--query`
+const BENCH_QUERIES: &[&str] = &["error handling", "parse config", "test"];
+
+fn open_engine(
+    root_dir: &str,
+    index_dir: Option<&PathBuf>,
+    profile: Option<&str>,
+    threads: Option<usize>,
+    verbosity: Verbosity,
+) -> Result<SearchEngine> {
+    let mut builder = SearchEngine::builder(root_dir).with_verbosity(verbosity);
+    if let Some(index_dir) = index_dir {
+        builder = builder.with_index_dir(index_dir);
+    }
+    if let Some(threads) = threads {
+        builder = builder.with_threads(threads);
+    }
+    if let Some(profile) = profile {
+        builder = builder.with_profile(profile);
+    }
+    builder.build()
+}
+
+/// Flag set by the first Ctrl-C, or SIGTERM/SIGHUP as sent by CI runners cancelling a job,
+/// which lets indexing commit its progress before exiting. A second one exits immediately.
+/// Only the indexing commands install it, so that other commands stop on the first signal.
+fn interrupt_flag() -> Arc<AtomicBool> {
+    static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    FLAG.get_or_init(|| {
+        let flag = Arc::new(AtomicBool::new(false));
+        let handler_flag = flag.clone();
+        let installed = ctrlc::set_handler(move || {
+            if handler_flag.swap(true, Ordering::Relaxed) {
+                std::process::exit(130);
+            }
+            eprintln!("Interrupted, saving progress... (press Ctrl-C again to exit immediately)");
+        });
+        if let Err(e) = installed {
+            eprintln!("Warning: failed to install the Ctrl-C handler: {e}");
+        }
+        flag
+    })
+    .clone()
+}
+
+/// Returns whether the given flag (`-n`, `--num-results`) consumes the following argument
+fn flag_takes_value(command: &clap::Command, flag: &str) -> bool {
+    command.get_arguments().any(|arg| {
+        let matches = match flag.strip_prefix("--") {
+            Some(long) => arg.get_long() == Some(long),
+            None => flag.len() == 2 && arg.get_short() == flag.chars().nth(1),
+        };
+        matches && arg.get_action().takes_values()
+    })
+}
+
+/// Finds the first positional argument by skipping over the flags before it, returning its
+/// position and the directory given with `-d` or `--repo` (`.` if none)
+fn first_positional(command: &clap::Command, args: &[String]) -> (usize, String) {
+    let mut root_dir = None;
+    let mut repo = None;
+    let mut config_path = None;
+    let mut position = 1;
+
+    // Skip over flags (and their values) to find the first positional argument
+    while position < args.len() {
+        let arg = &args[position];
+        if arg == "--" || !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let value = if inline_value.is_none() && flag_takes_value(command, flag) {
+            position += 1;
+            args.get(position).cloned()
+        } else {
+            inline_value
+        };
+
+        // With several directories, aliases come from the first one's probe.yml
+        if flag == "-d" || flag == "--directory" {
+            if root_dir.is_none() {
+                root_dir = value;
+            }
+        } else if let Some(attached) = flag.strip_prefix("-d").filter(|v| !v.is_empty()) {
+            root_dir.get_or_insert_with(|| attached.to_string());
+        } else if flag == "--repo" {
+            repo = value;
+        } else if flag == "--config" {
+            config_path = value.map(PathBuf::from);
+        }
+        position += 1;
+    }
+
+    // An unknown repository is reported once the command actually runs
+    if let Some(repo) = repo {
+        if let Ok(config) = ProbeConfig::load_from_file(config_path.as_ref()) {
+            if let Ok(path) = config.repo_path(&repo) {
+                root_dir = Some(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+    (position, root_dir.unwrap_or_else(|| ".".to_string()))
+}
+
+/// Expands a `probe.yml` alias given in place of the first positional argument into its
+/// flags, e.g. `probe api parse` with `api: "-t java -n 10"` becomes `probe -t java -n 10 parse`.
+fn expand_aliases(args: Vec<String>) -> Result<Vec<String>> {
+    let command = Cli::command();
+    let (position, root_dir) = first_positional(&command, &args);
+    let Some(name) = args.get(position) else {
+        return Ok(args);
+    };
+    if command.get_subcommands().any(|sub| sub.get_name() == name) {
+        return Ok(args);
+    }
+
+    // Config errors are reported once the command actually runs
+    let Ok(config) = Config::load_from_dir(&root_dir) else {
+        return Ok(args);
+    };
+    let Some(expansion) = config.aliases.get(name) else {
+        return Ok(args);
+    };
+    let expanded = shlex::split(expansion)
+        .ok_or_else(|| anyhow::anyhow!("Invalid quoting in alias '{}': {}", name, expansion))?;
+
+    let mut result = args[..position].to_vec();
+    result.extend(expanded);
+    result.extend_from_slice(&args[position + 1..]);
+    Ok(result)
+}
+
+/// Expands `probe run <recipe> <args>...` into the search saved as `recipe` in `probe.yml`,
+/// e.g. `probe run handler-for Payment` with `handler-for: "name:{0}Handler --kind method"`
+/// becomes `probe name:PaymentHandler --kind method`. Arguments beyond the placeholders the
+/// recipe uses are appended, so `probe run handler-for Payment -n 10` also works.
+fn expand_recipe(args: Vec<String>) -> Result<Vec<String>> {
+    let (position, root_dir) = first_positional(&Cli::command(), &args);
+    if args.get(position).map(String::as_str) != Some("run") {
+        return Ok(args);
+    }
+    let Some(name) = args.get(position + 1) else {
+        return Ok(args);
+    };
+
+    // Unknown recipes and config errors are reported by the `run` command itself
+    let Ok(config) = Config::load_from_dir(&root_dir) else {
+        return Ok(args);
+    };
+    let Some(recipe) = config.recipes.get(name) else {
+        return Ok(args);
+    };
+    let template = shlex::split(recipe)
+        .ok_or_else(|| anyhow::anyhow!("Invalid quoting in recipe '{}': {}", name, recipe))?;
+
+    let params = &args[position + 2..];
+    let mut used = 0;
+    let mut result = args[..position].to_vec();
+    for token in &template {
+        let (expanded, placeholders) = substitute_params(token, params).map_err(|index| {
+            anyhow::anyhow!(
+                "Recipe '{}' uses {{{}}} but only {} argument(s) were given: {}",
+                name,
+                index,
+                params.len(),
+                recipe
+            )
+        })?;
+        used = used.max(placeholders);
+        result.push(expanded);
+    }
+    result.extend_from_slice(&params[used..]);
+    Ok(result)
+}
+
+/// Replaces the `{0}`, `{1}`, ... placeholders in `token` with the matching `params`.
+/// Returns the expanded token and the number of leading params it needs, or the index of
+/// the first placeholder without a param. Other braces, such as range queries, are kept.
+fn substitute_params(
+    token: &str,
+    params: &[String],
+) -> std::result::Result<(String, usize), usize> {
+    let mut expanded = String::with_capacity(token.len());
+    let mut needed = 0;
+    let mut rest = token;
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let placeholder = after
+            .find('}')
+            .and_then(|close| Some((after[..close].parse::<usize>().ok()?, close)));
+        match placeholder {
+            Some((index, close)) => {
+                expanded.push_str(params.get(index).ok_or(index)?);
+                needed = needed.max(index + 1);
+                rest = &after[close + 1..];
+            }
+            None => {
+                expanded.push('{');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok((expanded, needed))
+}
+
+/// Reranker settings from the command-line flags, falling back to the user config's default
+fn reranker_config(
+    cli: &Cli,
+    probe_config: ProbeConfig,
+    verbosity: Verbosity,
+) -> Result<RerankerConfig> {
+    // Determine which model to use - check if it's a built-in model or custom model
+    let (builtin_model, custom_model) = if let Some(model_name) = &cli.rerank_model {
+        if let Ok(builtin) = parse_reranker_model(model_name) {
+            // It's a built-in model
+            (builtin, None)
+        } else if probe_config.get_custom_model(model_name).is_some() {
+            // It's a custom model from config
+            (
+                RerankerModel::JINARerankerV1TurboEn,
+                Some(model_name.clone()),
+            )
+        // Use default built-in as fallback
+        } else {
+            return Err(anyhow::anyhow!("Unknown reranker model '{}'. Use a built-in model (bge-reranker-base, bge-reranker-v2-m3, etc.) or add it to your config file.", model_name));
+        }
+    } else if let Some(default_custom) = &probe_config.default_reranker {
+        // Use default custom model from config
+        (
+            RerankerModel::JINARerankerV1TurboEn,
+            Some(default_custom.clone()),
+        )
+    } else {
+        // Fall back to built-in default
+        (RerankerModel::JINARerankerV1TurboEn, None)
+    };
+
+    if let Some(cutoff) = cli.score_cutoff {
+        if !(0.0..=1.0).contains(&cutoff) {
+            return Err(anyhow::anyhow!(
+                "--score-cutoff must be between 0 and 1, got {cutoff}"
+            ));
+        }
+    }
+
+    Ok(RerankerConfig {
+        enabled: !cli.no_rerank,
+        model: builtin_model,
+        min_candidates: cli.rerank_candidates,
+        candidate_multiplier: cli.candidate_multiplier.max(1),
+        score_cutoff: cli.score_cutoff,
+        show_download_progress: !verbosity.is_quiet(),
+        custom_model,
+        probe_config: Some(probe_config),
+    })
+}
+
+/// Search options from the command-line flags
+fn search_options(cli: &Cli) -> Result<SearchOptions> {
+    let offset = match cli.page {
+        Some(page) => (page as usize - 1) * cli.num_results,
+        None => cli.offset.unwrap_or(0),
+    };
+    Ok(SearchOptions {
+        limit: cli.num_results,
+        offset,
+        filetype: cli.filetype.clone(),
+        context_lines: cli.context_lines,
+        no_stem: cli.no_stem,
+        stem: cli.stem,
+        no_query_expansion: cli.no_query_expansion,
+        stem_language: cli
+            .stem_language
+            .as_deref()
+            .map(parse_language)
+            .transpose()?,
+        kinds: cli
+            .kind
+            .iter()
+            .map(|kind| show_chunks::parse_chunk_kind(kind))
+            .collect::<Result<_>>()?,
+        hybrid: cli.hybrid,
+        paths: cli.paths.clone(),
+        exclude_paths: cli.exclude_paths.clone(),
+        mode: if cli.exact {
+            QueryMode::Exact
+        } else if cli.regex {
+            QueryMode::Regex
+        } else {
+            QueryMode::Terms
+        },
+        min_score: cli.min_score,
+        group_by_file: cli.group_by_file,
+        fuzzy_fallback: !cli.no_fuzzy,
+        ..SearchOptions::default()
+    })
+}
+
+/// Splits `path[:line]`; a suffix that isn't a line number stays part of the path
+fn parse_target(target: &str) -> (PathBuf, Option<usize>) {
+    match target.rsplit_once(':') {
+        Some((path, line)) if !path.is_empty() => match line.parse() {
+            Ok(line) => (PathBuf::from(path), Some(line)),
+            Err(_) => (PathBuf::from(target), None),
+        },
+        _ => (PathBuf::from(target), None),
+    }
+}
+
+fn print_why_report(report: &WhyReport, options: &SearchOptions) {
+    let path = report.relative_path.display();
+    match &report.status {
+        IndexStatus::NotScanned => {
+            println!("{path} is not indexed: it doesn't exist or is excluded by .gitignore or index.ignore.");
+            return;
+        }
+        IndexStatus::Skipped(reason) => {
+            println!("{path} is not indexed: {reason}.");
+            return;
+        }
+        IndexStatus::Indexed => {}
+    }
+    if report.chunks.is_empty() {
+        println!("{path} is indexed, but has no chunk at that line.");
+        return;
+    }
+
+    for (i, chunk) in report.chunks.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let result = &chunk.result;
+        let lines = match (result.start_line, result.end_line) {
+            (Some(start), Some(end)) => format!(":{}-{}", start + 1, end + 1),
+            _ => String::new(),
+        };
+        let heading = format!(
+            "{}{} {} {}",
+            path,
+            lines,
+            result.chunk_type.as_deref().unwrap_or("").to_lowercase(),
+            result.chunk_name.as_deref().unwrap_or("")
+        );
+        println!("{}", heading.trim_end());
+
+        if chunk.bm25_score.is_none() {
+            println!("  does not match the query");
+            continue;
+        }
+        print_score_breakdown(chunk);
+        for reason in &chunk.filtered_out {
+            println!("  filtered out: {reason}");
+        }
+        match chunk.final_rank {
+            Some(rank) => println!("  result: #{rank} of {}", options.limit),
+            None => println!("  result: not in the top {}", options.limit),
+        }
+    }
+}
+
+/// Downloads a reranking or embedding model by loading it once
+fn download_model(name: &str, probe_config: &ProbeConfig, verbosity: Verbosity) -> Result<()> {
+    if parse_embedding_model(name).is_ok() {
+        Embedder::new(name, !verbosity.is_quiet())?;
+        return Ok(());
+    }
+    let (model, custom_model) = match parse_reranker_model(name) {
+        Ok(model) => (model, None),
+        Err(_) if probe_config.get_custom_model(name).is_some() => {
+            (RerankerModel::JINARerankerV1TurboEn, Some(name.to_string()))
+        }
+        Err(_) => {
+            return Err(anyhow::anyhow!(
+                "Unknown model '{}'. See `probe list-models` for the built-in rerankers, or add it to your config file.",
+                name
+            ))
+        }
+    };
+    Reranker::new(RerankerConfig {
+        model,
+        custom_model,
+        show_download_progress: !verbosity.is_quiet(),
+        probe_config: Some(probe_config.clone()),
+        ..Default::default()
+    })?;
+    Ok(())
+}
+
+/// Prefixes the lines of a snippet that are lines of the result's chunk with their line
+/// number, like `grep -n`. Enclosing declarations shown above a method and the marker of a
+/// cut snippet are left as they are.
+fn number_lines(result: &SearchResult, snippet: &str) -> String {
+    let (Some(first), Some(start), Some(end)) =
+        (result.snippet_line, result.start_line, result.end_line)
+    else {
+        return snippet.to_string();
+    };
+    snippet
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            let line_number = first + i;
+            if (start..=end).contains(&line_number) && line != "…" {
+                format!("{}:{line}", line_number + 1)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats a result as `path:line:col: text` like `grep -n --column` and Vim's `:vimgrep`,
+/// pointing at the first match within the chunk, or at the chunk's first line without one
+fn vimgrep_line(result: &SearchResult) -> String {
+    let (text, matches) = syntax::strip_highlights(&result.snippet);
+    let first = result.snippet_line.unwrap_or(0);
+    let in_chunk = |line_number: usize| match (result.start_line, result.end_line) {
+        (Some(start), Some(end)) => (start..=end).contains(&line_number),
+        _ => true,
+    };
+
+    let mut fallback = None;
+    let mut offset = 0;
+    for (i, line) in text.split('\n').enumerate() {
+        let line_number = first + i;
+        if in_chunk(line_number) && line != "…" {
+            let line_match = matches
+                .iter()
+                .find(|range| (offset..offset + line.len()).contains(&range.start));
+            if let Some(range) = line_match {
+                let column = range.start - offset + 1;
+                return vimgrep_entry(result, line_number, column, line);
+            }
+            fallback.get_or_insert((line_number, line));
+        }
+        offset += line.len() + 1;
+    }
+    let (line_number, line) = fallback.unwrap_or((result.start_line.unwrap_or(0), ""));
+    let column = line.len() - line.trim_start().len() + 1;
+    vimgrep_entry(result, line_number, column, line)
+}
+
+fn vimgrep_entry(result: &SearchResult, line_number: usize, column: usize, line: &str) -> String {
+    format!(
+        "{}:{}:{column}: {}",
+        result.path.display(),
+        line_number + 1,
+        line.trim()
+    )
+}
+
+/// Prints the matched terms, the full-text score and what changed it afterwards
+fn print_score_breakdown(chunk: &ChunkExplanation) {
+    let terms: Vec<_> = chunk
+        .matched_terms
+        .iter()
+        .map(|term| format!("{}:{} ({:.2})", term.field, term.term, term.score))
+        .collect();
+    println!("  matched terms: {}", terms.join(", "));
+    println!(
+        "  full-text score: {:.3}",
+        chunk.bm25_score.unwrap_or_default()
+    );
+    for (reason, factor) in &chunk.penalties {
+        println!("  penalty: x{factor} ({reason})");
+    }
+    if !chunk.penalties.is_empty() {
+        println!("  score after penalties: {:.3}", chunk.result.score);
+    }
+    match chunk.fulltext_rank {
+        Some(rank) => println!("  full-text rank: {rank}"),
+        None => println!("  full-text rank: below the first 1000 candidates"),
+    }
+    if let Some(rerank_score) = chunk.rerank_score {
+        println!("  reranker score: {rerank_score:.3}");
+    }
+}
+
+/// Prints search results in the `--format` given. `repo_names` label the results of
+/// multi-repository searches and `explanations` are shown below the results they belong
+/// to with `--explain`; both only apply to text output. `neighbors` holds the chunks
+/// around each result for `--expand`, shown in text and ndjson output.
+fn print_results(
+    results: &[SearchResult],
+    repo_names: &[String],
+    explanations: &[ChunkExplanation],
+    neighbors: &[Vec<SearchResult>],
+    group_by_file: bool,
+    format: &str,
+) {
+    match format {
+        "vimgrep" => {
+            for result in results {
+                println!("{}", vimgrep_line(result));
+            }
+            return;
+        }
+        "csv" | "tsv" => {
+            let (separator, field): (&str, fn(&str) -> String) = if format == "csv" {
+                (",", csv_field)
+            } else {
+                ("\t", tsv_field)
+            };
+            println!("{}", RECORD_COLUMNS.join(separator));
+            for result in results {
+                let record = result_record(result);
+                let fields: Vec<_> = record.iter().map(|value| field(value)).collect();
+                println!("{}", fields.join(separator));
+            }
+            return;
+        }
+        "ndjson" => {
+            for (i, result) in results.iter().enumerate() {
+                let mut record = json_record(result);
+                if let Some(neighbors) = neighbors.get(i) {
+                    record["neighbors"] = neighbors.iter().map(json_record).collect();
+                }
+                println!("{record}");
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    for (i, result) in results.iter().enumerate() {
+        let line_info = line_info(result);
+
+        let repo = repo_names
+            .get(i)
+            .map(|name| format!("[{name}] "))
+            .unwrap_or_default();
+        let fuzzy = if result.fuzzy { " [fuzzy match]" } else { "" };
+        if !group_by_file {
+            let line_info = if line_info.is_empty() {
+                line_info
+            } else {
+                format!(" ({line_info})")
+            };
+            println!("{repo}{}{}{fuzzy}", result.path.display(), line_info);
+        } else {
+            if i == 0 || results[i - 1].path != result.path {
+                let chunks = results[i..]
+                    .iter()
+                    .take_while(|other| other.path == result.path)
+                    .count();
+                let plural = if chunks == 1 { "" } else { "es" };
+                println!("{repo}{} ({chunks} match{plural})", result.path.display());
+            }
+            println!("  {line_info}{fuzzy}");
+        }
+        if !result.duplicates.is_empty() {
+            println!("  {}", also_in(&result.duplicates));
+        }
+        if let Some(explanation) = explanations.get(i) {
+            print_score_breakdown(explanation);
+            println!("  final score: {:.3}\n", result.score);
+        }
+        // Chunks around the result are shown in file order, with the result in between
+        let (before, after) = neighbors.get(i).map_or((&[][..], &[][..]), |neighbors| {
+            let split = neighbors
+                .iter()
+                .take_while(|neighbor| neighbor.start_line < result.start_line)
+                .count();
+            neighbors.split_at(split)
+        });
+        print_neighbors(before);
+        if !result.snippet.is_empty() {
+            let snippet = syntax::highlight_snippet(&result.path, &result.snippet);
+            println!("{}\n", number_lines(result, &snippet));
+        }
+        print_neighbors(after);
+    }
+}
+
+/// The lines of a result, like `line 4` or `lines 4-9`, or nothing without line numbers
+fn line_info(result: &SearchResult) -> String {
+    match (result.start_line, result.end_line) {
+        (Some(start), Some(end)) if start == end => format!("line {}", start + 1),
+        (Some(start), Some(end)) => format!("lines {}-{}", start + 1, end + 1),
+        _ => String::new(),
+    }
+}
+
+/// Prints chunks shown around a result with `--expand`, each under a `~ lines 4-9: method
+/// save` line
+fn print_neighbors(neighbors: &[SearchResult]) {
+    for neighbor in neighbors {
+        let name = match (&neighbor.chunk_type, &neighbor.chunk_name) {
+            (Some(kind), Some(name)) if !name.is_empty() => {
+                format!(": {} {name}", kind.to_lowercase())
+            }
+            _ => String::new(),
+        };
+        println!("  ~ {}{name}", line_info(neighbor));
+        if !neighbor.snippet.is_empty() {
+            let snippet = syntax::highlight_snippet(&neighbor.path, &neighbor.snippet);
+            println!("{}\n", number_lines(neighbor, &snippet));
+        }
+    }
+}
+
+/// A result as an ndjson record, with 1-based line numbers and its snippet on one line
+fn json_record(result: &SearchResult) -> serde_json::Value {
+    serde_json::json!({
+        "path": result.path.display().to_string(),
+        "start_line": result.start_line.map(|line| line + 1),
+        "end_line": result.end_line.map(|line| line + 1),
+        "score": result.score,
+        "chunk_name": result.chunk_name,
+        "chunk_type": result.chunk_type,
+        "snippet": flat_snippet(result),
+    })
+}
+
+/// Columns of the csv and tsv output
+const RECORD_COLUMNS: [&str; 7] = [
+    "path",
+    "start_line",
+    "end_line",
+    "score",
+    "chunk_name",
+    "chunk_type",
+    "snippet",
+];
+
+/// The `RECORD_COLUMNS` of a result, with 1-based line numbers. Missing values are empty.
+fn result_record(result: &SearchResult) -> [String; 7] {
+    let line = |line: Option<usize>| line.map(|line| (line + 1).to_string());
+    [
+        result.path.display().to_string(),
+        line(result.start_line).unwrap_or_default(),
+        line(result.end_line).unwrap_or_default(),
+        result.score.to_string(),
+        result.chunk_name.clone().unwrap_or_default(),
+        result.chunk_type.clone().unwrap_or_default(),
+        flat_snippet(result),
+    ]
+}
+
+/// Where the near-duplicates collapsed into a result are, e.g.
+/// `also in gen/A.java, gen/B.java and 3 more files`
+fn also_in(duplicates: &[PathBuf]) -> String {
+    const SHOWN: usize = 3;
+    let shown: Vec<_> = duplicates
+        .iter()
+        .take(SHOWN)
+        .map(|path| path.display().to_string())
+        .collect();
+    match duplicates.len().saturating_sub(SHOWN) {
+        0 => format!("also in {}", shown.join(", ")),
+        1 => format!("also in {} and 1 more file", shown.join(", ")),
+        more => format!("also in {} and {more} more files", shown.join(", ")),
+    }
+}
+
+/// The snippet without highlights and with its lines trimmed and joined by spaces, so
+/// records stay on one line
+fn flat_snippet(result: &SearchResult) -> String {
+    let (text, _) = syntax::strip_highlights(&result.snippet);
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes a csv field if it contains a separator, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Tsv has no quoting, so tabs and line breaks in a field become spaces
+fn tsv_field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+/// The command line of a search run with `probe !N` or `probe !!`, taken from the history
+/// of the project being searched. Returns `None` for anything else.
+fn rerun_args(cli: &Cli, args: &[String]) -> Result<Option<Vec<String>>> {
+    let reference = match cli.query.as_slice() {
+        [arg] if cli.command.is_none() => history::parse_reference(arg),
+        _ => None,
+    };
+    let Some(id) = reference else {
+        return Ok(None);
+    };
+
+    let (_, root_dir) = first_positional(&Cli::command(), args);
+    let engine = open_engine(
+        &root_dir,
+        cli.index_dir.as_ref(),
+        cli.profile.as_deref(),
+        cli.threads.map(|threads| threads as usize),
+        Verbosity::Quiet,
+    )?;
+    let history = SearchHistory::load(engine.history_path())?;
+    let entry = find_history_entry(&history, id)?;
+    if !cli.quiet && cli.format != "vimgrep" {
+        // Like a shell, show what is run
+        eprintln!("probe {}", command_line(&entry.args));
+    }
+    let program = args.first().cloned().unwrap_or_else(|| "probe".to_string());
+    Ok(Some(
+        std::iter::once(program).chain(entry.args.clone()).collect(),
+    ))
+}
+
+/// The search numbered `id` in the history, or the last one
+fn find_history_entry(history: &SearchHistory, id: Option<u64>) -> Result<&history::HistoryEntry> {
+    match id {
+        Some(id) => history.get(id).ok_or_else(|| {
+            anyhow::anyhow!(
+                "No search {id} in the history, `probe history` lists the recorded ones"
+            )
+        }),
+        None => history
+            .last()
+            .ok_or_else(|| anyhow::anyhow!("No searches recorded yet")),
+    }
+}
+
+/// Arguments quoted the way a shell needs them to run the command again
+fn command_line(args: &[String]) -> String {
+    shlex::try_join(args.iter().map(String::as_str)).unwrap_or_else(|_| args.join(" "))
+}
+
+/// Command line to record for a search. A query read from stdin is written out, so running
+/// the search again doesn't wait for input.
+fn recorded_args(cli: &Cli, args: &[String], query: &str) -> Vec<String> {
+    let mut recorded = args.get(1..).unwrap_or_default().to_vec();
+    if cli.query.is_empty() {
+        recorded.push(query.to_string());
+    } else if cli.query == ["-"] {
+        if let Some(position) = recorded.iter().rposition(|arg| arg == "-") {
+            recorded[position] = query.to_string();
+        }
+    }
+    recorded
+}
+
+/// The chunks found by the last search in the history, for `--refine`
+fn refined_chunks(engine: &SearchEngine) -> Result<Vec<ChunkRange>> {
+    let history = SearchHistory::load(engine.history_path())?;
+    let entry = find_history_entry(&history, None)?;
+    match &entry.results {
+        Some(results) if !results.is_empty() => Ok(results.iter().map(ChunkRange::of).collect()),
+        Some(_) => Err(anyhow::anyhow!(
+            "Search {} found nothing to refine: `probe {}`",
+            entry.id,
+            command_line(&entry.args)
+        )),
+        None => Err(anyhow::anyhow!(
+            "The results of search {} are no longer kept, run it again with `probe !{}`",
+            entry.id,
+            entry.id
+        )),
+    }
+}
+
+/// Adds a search and its results to the history of the searched project
+fn record_search(
+    engine: &SearchEngine,
+    query: &str,
+    args: Vec<String>,
+    grouped: bool,
+    results: &[SearchResult],
+) -> Result<()> {
+    let path = engine.history_path();
+    let mut history = SearchHistory::load(&path)?;
+    history.record(query, args, grouped, results);
+    history.save(&path)
+}
+
+/// Searches one repository, updating its index first unless searching without one
+fn search_repo(
+    engine: &SearchEngine,
+    query: &str,
+    options: &SearchOptions,
+    reranker_config: RerankerConfig,
+    no_index: bool,
+) -> Result<Vec<SearchResult>> {
+    if no_index {
+        engine.search_without_index(query, options)
+    } else {
+        engine.ensure_index_updated()?;
+        engine.search_with_options(query, options, reranker_config)
+    }
+}
+
+/// Searches several repositories concurrently and keeps the best `options.limit` results
+/// overall, each with the name of its repository. Result paths are joined onto their
+/// repository's root so they can be opened from anywhere. The candidates of all
+/// repositories are reranked together, so the model is only loaded once.
+fn search_repos(
+    repos: &[(String, PathBuf)],
+    query: &str,
+    options: &SearchOptions,
+    reranker_config: RerankerConfig,
+    no_index: bool,
+    threads: Option<usize>,
+    verbosity: Verbosity,
+) -> Result<Vec<(String, SearchResult)>> {
+    if repos.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No repositories registered, add one with `probe repos add <path>`"
+        ));
+    }
+
+    // The minimum score applies to the merged results, which may be reranked, and pages
+    // are cut from them
+    let wanted = options.offset + options.limit;
+    let candidate_options = SearchOptions {
+        limit: reranker_config.candidate_limit(wanted),
+        offset: 0,
+        min_score: None,
+        ..options.clone()
+    };
+    let full_text_only = RerankerConfig {
+        enabled: false,
+        ..reranker_config.clone()
+    };
+    let searched: Vec<Result<Vec<SearchResult>>> = std::thread::scope(|scope| {
+        let searches: Vec<_> = repos
+            .iter()
+            .map(|(_, root)| {
+                let (candidate_options, full_text_only) = (&candidate_options, &full_text_only);
+                scope.spawn(move || {
+                    let engine =
+                        open_engine(&root.to_string_lossy(), None, None, threads, verbosity)?;
+                    search_repo(
+                        &engine,
+                        query,
+                        candidate_options,
+                        full_text_only.clone(),
+                        no_index,
+                    )
+                })
+            })
+            .collect();
+        searches
+            .into_iter()
+            .map(|search| {
+                search
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("the search panicked")))
+            })
+            .collect()
+    });
+
+    let mut candidates = Vec::new();
+    let mut names = HashMap::new();
+    for ((name, root), searched) in repos.iter().zip(searched) {
+        match searched {
+            Ok(found) => {
+                for mut result in found {
+                    result.path = root.join(&result.path);
+                    names.insert((result.path.clone(), result.start_line), name.clone());
+                    candidates.push(result);
+                }
+            }
+            // One missing or broken repository shouldn't hide the results of the others
+            Err(e) => eprintln!("Warning: failed to search repository '{name}': {e}"),
+        }
+    }
+    candidates.sort_by(SearchResult::rank_order);
+    candidates.truncate(candidate_options.limit);
+    reranker_config.cut_candidates(&mut candidates);
+
+    let mut results = if reranker_config.enabled && !no_index && candidates.len() >= 2 {
+        Reranker::new(reranker_config)?.rerank_results_or_keep_order(query, candidates, wanted)?
+    } else {
+        candidates.truncate(wanted);
+        candidates
+    };
+    results.drain(..options.offset.min(results.len()));
+    if let Some(min_score) = options.min_score {
+        results.retain(|result| result.score >= min_score);
+    }
+    Ok(arrange_results(results, options)
+        .into_iter()
+        .map(|result| {
+            let name = names
+                .get(&(result.path.clone(), result.start_line))
+                .cloned()
+                .unwrap_or_default();
+            (name, result)
+        })
+        .collect())
+}
+
+/// Resolves the query from the positional arguments, or from stdin for `probe -`
+/// and for `probe` invoked without a query while input is piped in.
+fn read_query(args: &[String]) -> Result<Option<String>> {
+    let from_stdin = args == ["-"] || (args.is_empty() && !atty::is(atty::Stream::Stdin));
+    if !from_stdin {
+        return Ok((!args.is_empty()).then(|| args.join(" ")));
+    }
+
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let query = sanitize_stdin_query(&input);
+    Ok((!query.is_empty()).then_some(query))
+}
+
+/// Piped input is usually free text (error messages, stack traces, code), so strip the
+/// characters that have a meaning in the query syntax instead of failing to parse it.
+fn sanitize_stdin_query(input: &str) -> String {
+    input
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let args = expand_recipe(expand_aliases(std::env::args().collect())?)?;
+    let mut cli = Cli::parse_from(&args);
+    let args = match rerun_args(&cli, &args)? {
+        Some(rerun) => {
+            cli = Cli::parse_from(&rerun);
+            rerun
+        }
+        None => args,
+    };
+    let command = cli.command.take();
+    probe::terminal::init();
+    let vimgrep = cli.format == "vimgrep";
+    if vimgrep {
+        // Matches are located by their highlights, which are stripped again when printing
+        probe::terminal::set_color_choice(ColorChoice::Always);
+    } else if matches!(command, Some(Commands::Daemon)) {
+        // Clients strip the highlights of the results the daemon sends if they don't show them
+        probe::terminal::set_color_choice(ColorChoice::Always);
+    } else {
+        probe::terminal::set_color_choice(ColorChoice::parse(&cli.color)?);
+    }
+
+    if let Err(e) = paths::migrate_legacy_locations() {
+        eprintln!("Warning: failed to migrate files from ~/.probe: {e}");
+    }
 
-    let root_dir = cli.directory.unwrap_or_else(|| ".".to_string());
+    let root_dir = match &cli.repo {
+        Some(repo) => ProbeConfig::load_from_file(cli.config_path.as_ref())?
+            .repo_path(repo)?
+            .to_string_lossy()
+            .into_owned(),
+        None => cli
+            .directory
+            .first()
+            .cloned()
+            .unwrap_or_else(|| ".".to_string()),
+    };
+    if cli.all_repos && command.is_some() {
+        return Err(anyhow::anyhow!(
+            "--all-repos only applies to searches, use --repo <name> to pick a repository"
+        ));
+    }
+    if cli.directory.len() > 1 {
+        if command.is_some() {
+            return Err(anyhow::anyhow!(
+                "Several --directory values only apply to searches"
+            ));
+        }
+        if cli.explain || cli.index_dir.is_some() || cli.profile.is_some() {
+            return Err(anyhow::anyhow!(
+                "--explain, --index-dir and --profile only apply to searches of a single directory"
+            ));
+        }
+    }
+    let index_dir = cli.index_dir.as_ref();
+    let profile = cli.profile.as_deref();
+    let threads = cli.threads.map(|threads| threads as usize);
+    // Editors read vimgrep output with stderr mixed in, so status messages stay off unless
+    // asked for with -v. LSP clients log the server's stderr, which shouldn't fill up.
+    let lsp = matches!(command, Some(Commands::Lsp));
+    let verbosity = if cli.quiet || lsp || (vimgrep && cli.verbose == 0) {
+        Verbosity::Quiet
+    } else {
+        Verbosity::from_occurrences(cli.verbose)
+    };
+
+    match command {
+        Some(Commands::Rebuild { resume }) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?
+                .with_interrupt_flag(interrupt_flag());
+            if resume {
+                engine.resume_rebuild()?;
+            } else {
+                engine.rebuild_index()?;
+            }
+        }
+        Some(Commands::Stats {
+            ls_files,
+            status,
+            skipped,
+        }) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            engine.stats(ls_files, status, skipped)?;
+        }
+        Some(Commands::Optimize) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            let report = engine.optimize()?;
+            if !verbosity.is_quiet() {
+                println!(
+                    "Merged {} segments into {}, removed {} deleted documents.",
+                    report.before.segments, report.after.segments, report.before.deleted_docs
+                );
+                println!(
+                    "Index size: {} -> {}",
+                    format_size(report.bytes_before),
+                    format_size(report.bytes_after)
+                );
+            }
+        }
+        Some(Commands::Bench { queries, runs }) => {
+            let probe_config = ProbeConfig::load_from_file(cli.config_path.as_ref())?;
+            let reranker_config = reranker_config(&cli, probe_config, Verbosity::Quiet)?;
+            let options = search_options(&cli)?;
+            // Status messages would end up in the middle of the report
+            let engine = open_engine(&root_dir, index_dir, profile, threads, Verbosity::Quiet)?
+                .with_reranker_config(reranker_config);
+            let queries = if queries.is_empty() {
+                BENCH_QUERIES
+                    .iter()
+                    .map(|query| query.to_string())
+                    .collect()
+            } else {
+                queries
+            };
+            let report = engine.bench(&queries, runs.max(1), &options)?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Some(Commands::Verify) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            let report = engine.verify()?;
+            for file in &report.stale {
+                println!(
+                    "stale: {} ({} chunks indexed, {} in source)",
+                    file.path.display(),
+                    file.indexed_chunks,
+                    file.source_chunks
+                );
+            }
+            for path in &report.missing {
+                println!("missing: {}", path.display());
+            }
+            for path in &report.orphaned {
+                println!("orphaned: {}", path.display());
+            }
 
-    match cli.command {
-        Some(Commands::Rebuild) => {
-            let engine = SearchEngine::new(&root_dir)?;
-            engine.rebuild_index()?;
+            if report.is_clean() {
+                if !verbosity.is_quiet() {
+                    println!("Index matches the working tree.");
+                }
+            } else {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Watch) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?
+                .with_interrupt_flag(interrupt_flag());
+            engine.watch()?;
         }
-        Some(Commands::Stats { ls_files, status }) => {
-            let engine = SearchEngine::new(&root_dir)?;
-            engine.stats(ls_files, status)?;
+        Some(Commands::Serve { http }) => {
+            let probe_config = ProbeConfig::load_from_file(cli.config_path.as_ref())?;
+            let reranker_config = reranker_config(&cli, probe_config, verbosity)?;
+            let options = search_options(&cli)?;
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?
+                .with_reranker_config(reranker_config)
+                .with_interrupt_flag(interrupt_flag());
+            engine.serve_http(&http, &options)?;
+        }
+        Some(Commands::Lsp) => {
+            let probe_config = ProbeConfig::load_from_file(cli.config_path.as_ref())?;
+            let reranker_config = reranker_config(&cli, probe_config, verbosity)?;
+            let options = search_options(&cli)?;
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?
+                .with_reranker_config(reranker_config);
+            engine.serve_lsp(&options)?;
+        }
+        Some(Commands::Daemon) => {
+            let probe_config = ProbeConfig::load_from_file(cli.config_path.as_ref())?;
+            let reranker_config = reranker_config(&cli, probe_config, verbosity)?;
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?
+                .with_reranker_config(reranker_config)
+                .with_interrupt_flag(interrupt_flag());
+            engine.serve_daemon()?;
+        }
+        Some(Commands::Why { query, target }) => {
+            let (path, line) = parse_target(&target);
+            let probe_config = ProbeConfig::load_from_file(cli.config_path.as_ref())?;
+            let reranker_config = reranker_config(&cli, probe_config, verbosity)?;
+            let options = search_options(&cli)?;
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            let report = engine.why(&query, &path, line, &options, reranker_config)?;
+            print_why_report(&report, &options);
+        }
+        Some(Commands::Dupes {
+            threshold,
+            min_lines,
+        }) => {
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(anyhow::anyhow!(
+                    "--threshold must be between 0 and 1, got {threshold}"
+                ));
+            }
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            let options = DupesOptions {
+                threshold,
+                min_lines,
+            };
+            let clusters = engine.find_duplicates(&options)?;
+
+            if clusters.is_empty() && !verbosity.is_quiet() {
+                println!("No duplicate functions found.");
+            }
+            for (i, cluster) in clusters.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                println!(
+                    "{} similar chunks (~{:.0}% similar):",
+                    cluster.chunks.len(),
+                    cluster.similarity * 100.0
+                );
+                for chunk in &cluster.chunks {
+                    println!(
+                        "  {}:{}-{} {}",
+                        chunk.path.display(),
+                        chunk.start_line + 1,
+                        chunk.end_line + 1,
+                        chunk.name
+                    );
+                }
+            }
+        }
+        Some(Commands::Graph { format }) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            let graph = engine.call_graph()?;
+            if format == "json" {
+                println!("{}", serde_json::to_string_pretty(&graph)?);
+            } else {
+                print!("{}", graph.to_dot());
+            }
+        }
+        Some(Commands::Index { command }) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            match command {
+                IndexCommand::Export { file } => {
+                    let manifest = engine.export_index(&file)?;
+                    if verbosity.is_verbose() {
+                        eprintln!("Root hash: {}", manifest.root_hash);
+                    }
+                }
+                IndexCommand::Import {
+                    file,
+                    rewrite_prefix,
+                } => engine.import_index(&file, &rewrite_prefix)?,
+            }
+        }
+        Some(Commands::Repos { command }) => {
+            let mut probe_config = ProbeConfig::load_from_file(cli.config_path.as_ref())?;
+            match command {
+                ReposCommand::Add { path, name } => {
+                    let name = probe_config.add_repo(&path, name.as_deref())?;
+                    probe_config.save_to_file(cli.config_path.as_ref())?;
+                    if !verbosity.is_quiet() {
+                        println!(
+                            "Registered '{}' at {}",
+                            name,
+                            probe_config.repo_path(&name)?.display()
+                        );
+                    }
+                }
+                ReposCommand::List => {
+                    if probe_config.repos.is_empty() && !verbosity.is_quiet() {
+                        println!(
+                            "No repositories registered, add one with `probe repos add <path>`."
+                        );
+                    }
+                    for (name, path) in &probe_config.repos {
+                        println!("{}\t{}", name, path.display());
+                    }
+                }
+                ReposCommand::Remove { name } => {
+                    let path = probe_config.remove_repo(&name)?;
+                    probe_config.save_to_file(cli.config_path.as_ref())?;
+                    if !verbosity.is_quiet() {
+                        println!("Unregistered '{}' ({})", name, path.display());
+                    }
+                }
+            }
+        }
+        Some(Commands::Models { command }) => {
+            let probe_config = ProbeConfig::load_from_file(cli.config_path.as_ref())?;
+            match command {
+                ModelsCommand::Download { names } => {
+                    if names.is_empty() {
+                        let mut reranker_config =
+                            reranker_config(&cli, probe_config.clone(), verbosity)?;
+                        reranker_config.enabled = true;
+                        Reranker::new(reranker_config)?;
+                        let config = Config::load_from_dir(&root_dir)?;
+                        if config.embeddings.enabled {
+                            Embedder::new(config.embeddings.model_name(), !verbosity.is_quiet())?;
+                        }
+                    }
+                    for name in &names {
+                        download_model(name, &probe_config, verbosity)?;
+                    }
+                    if !verbosity.is_quiet() {
+                        println!("Models are in {}", paths::model_cache_dir().display());
+                    }
+                }
+                ModelsCommand::List => {
+                    let cached = models::cached_models()?;
+                    if cached.is_empty() && !verbosity.is_quiet() {
+                        println!(
+                            "No models downloaded to {}",
+                            paths::model_cache_dir().display()
+                        );
+                    }
+                    for model in &cached {
+                        println!("{}\t{}", model.repo, format_size(model.bytes));
+                    }
+                    if !cached.is_empty() && !verbosity.is_quiet() {
+                        println!(
+                            "Total: {} in {}",
+                            format_size(cached.iter().map(|model| model.bytes).sum()),
+                            paths::model_cache_dir().display()
+                        );
+                    }
+                }
+                ModelsCommand::Remove { names, all } => {
+                    if names.is_empty() && !all {
+                        return Err(anyhow::anyhow!(
+                            "Name the models to delete, or use --all to delete every model"
+                        ));
+                    }
+                    let repos: Vec<String> = if all {
+                        models::cached_models()?
+                            .into_iter()
+                            .map(|model| model.repo)
+                            .collect()
+                    } else {
+                        names
+                            .iter()
+                            .map(|name| models::model_repo(name, &probe_config))
+                            .collect()
+                    };
+                    for repo in repos {
+                        let model = models::remove_model(&repo)?;
+                        if !verbosity.is_quiet() {
+                            println!("Removed {} ({})", model.repo, format_size(model.bytes));
+                        }
+                    }
+                }
+                ModelsCommand::Path { name: None } => {
+                    println!("{}", paths::model_cache_dir().display());
+                }
+                ModelsCommand::Path { name: Some(name) } => {
+                    let repo = models::model_repo(&name, &probe_config);
+                    let model = models::find_cached(&repo)?.ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Model '{}' is not downloaded, get it with `probe models download {}`",
+                            repo,
+                            name
+                        )
+                    })?;
+                    println!("{}", model.path.display());
+                }
+            }
         }
         Some(Commands::ListModels) => {
             println!("Available reranking models:");
@@ -101,84 +1836,250 @@ fn main() -> Result<()> {
                 println!("  {name}: {description}");
             }
         }
-        Some(Commands::ShowChunks { paths }) => {
-            show_chunks::show_chunks_command(paths)?;
+        Some(Commands::Outline { file, json }) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            let outline = engine.outline(&file)?;
+            if json {
+                // 1-based line numbers like the text output and `show-chunks --json`
+                let items: Vec<_> = outline
+                    .iter()
+                    .map(|item| {
+                        serde_json::json!({
+                            "kind": item.kind,
+                            "name": item.name,
+                            "start_line": item.start_line + 1,
+                            "end_line": item.end_line + 1,
+                            "depth": item.depth,
+                        })
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&items)?);
+            } else {
+                for item in &outline {
+                    let lines = if item.start_line == item.end_line {
+                        format!("line {}", item.start_line + 1)
+                    } else {
+                        format!("lines {}-{}", item.start_line + 1, item.end_line + 1)
+                    };
+                    println!(
+                        "{}{} {} ({})",
+                        "  ".repeat(item.depth),
+                        item.kind,
+                        item.name,
+                        lines
+                    );
+                }
+            }
+        }
+        Some(Commands::History { clear }) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            let path = engine.history_path();
+            let mut history = SearchHistory::load(&path)?;
+            if clear {
+                let forgotten = history.len();
+                history.clear();
+                history.save(&path)?;
+                if !verbosity.is_quiet() {
+                    eprintln!("Forgot {forgotten} searches.");
+                }
+            } else if history.is_empty() {
+                if !verbosity.is_quiet() {
+                    eprintln!("No searches recorded yet");
+                }
+            } else {
+                let now = SystemTime::now();
+                for entry in history.entries() {
+                    let age = now.duration_since(entry.time).unwrap_or_default();
+                    let plural = if entry.result_count == 1 { "" } else { "s" };
+                    println!(
+                        "{:>4}  {:<14}  {}  ({} result{plural})",
+                        entry.id,
+                        format_age(age),
+                        command_line(&entry.args),
+                        entry.result_count
+                    );
+                }
+            }
+        }
+        Some(Commands::Last { id }) => {
+            let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+            let history = SearchHistory::load(engine.history_path())?;
+            let entry = find_history_entry(&history, id)?;
+            let Some(results) = &entry.results else {
+                return Err(anyhow::anyhow!(
+                    "The results of search {} are no longer kept, run it again with `probe !{}`",
+                    entry.id,
+                    entry.id
+                ));
+            };
+            if results.is_empty() {
+                if !verbosity.is_quiet() {
+                    eprintln!("No results found for '{}'", entry.query);
+                }
+            } else if cli.format == "text" && !verbosity.is_quiet() {
+                let age = SystemTime::now()
+                    .duration_since(entry.time)
+                    .unwrap_or_default();
+                eprintln!(
+                    "Results of `probe {}`, {}:\n",
+                    command_line(&entry.args),
+                    format_age(age)
+                );
+            }
+            print_results(results, &[], &[], &[], entry.grouped, &cli.format);
+        }
+        Some(Commands::Run { name, .. }) => {
+            // Known recipes were already expanded into a search before parsing
+            let config = Config::load_from_dir(&root_dir)?;
+            let mut names: Vec<_> = config.recipes.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            return Err(if names.is_empty() {
+                anyhow::anyhow!("Unknown recipe '{name}', no recipes are defined in probe.yml")
+            } else {
+                anyhow::anyhow!(
+                    "Unknown recipe '{}', expected one of: {}",
+                    name,
+                    names.join(", ")
+                )
+            });
+        }
+        Some(Commands::Symbols {
+            path,
+            kinds,
+            prefix,
+        }) => {
+            let config = Config::load_from_dir(&root_dir)?;
+            let options = show_chunks::SymbolsOptions { kinds, prefix };
+            show_chunks::symbols_command(&path, &config.chunking, verbosity, &options)?;
+        }
+        Some(Commands::ShowChunks {
+            paths,
+            kind,
+            name,
+            json,
+            summary,
+        }) => {
+            let config = Config::load_from_dir(&root_dir)?;
+            let options = show_chunks::ShowChunksOptions {
+                kinds: kind,
+                name,
+                json,
+                summary,
+            };
+            show_chunks::show_chunks_command(paths, &config.chunking, verbosity, &options)?;
         }
         None => {
-            if let Some(query) = cli.query {
-                // Load configuration
+            if let Some(query) = read_query(&cli.query)? {
                 let probe_config = ProbeConfig::load_from_file(cli.config_path.as_ref())?;
-
-                // Determine which model to use - check if it's a built-in model or custom model
-                let (builtin_model, custom_model) = if let Some(model_name) = &cli.rerank_model {
-                    if let Ok(builtin) = parse_reranker_model(model_name) {
-                        // It's a built-in model
-                        (builtin, None)
-                    } else if probe_config.get_custom_model(model_name).is_some() {
-                        // It's a custom model from config
-                        (
-                            RerankerModel::JINARerankerV1TurboEn,
-                            Some(model_name.clone()),
-                        )
-                    // Use default built-in as fallback
-                    } else {
-                        return Err(anyhow::anyhow!("Unknown reranker model '{}'. Use a built-in model (bge-reranker-base, bge-reranker-v2-m3, etc.) or add it to your config file.", model_name));
-                    }
-                } else if let Some(default_custom) = &probe_config.default_reranker {
-                    // Use default custom model from config
-                    (
-                        RerankerModel::JINARerankerV1TurboEn,
-                        Some(default_custom.clone()),
-                    )
+                let repos = probe_config.repos.clone();
+                let reranker_config = reranker_config(&cli, probe_config, verbosity)?;
+                let mut options = search_options(&cli)?;
+                let workspace: Option<Vec<_>> = if cli.all_repos {
+                    Some(repos.into_iter().collect())
+                } else if cli.directory.len() > 1 {
+                    let dirs = cli.directory.iter();
+                    Some(dirs.map(|dir| (dir.clone(), PathBuf::from(dir))).collect())
                 } else {
-                    // Fall back to built-in default
-                    (RerankerModel::JINARerankerV1TurboEn, None)
+                    None
                 };
-
-                // Create reranker config
-                let reranker_config = RerankerConfig {
-                    enabled: !cli.no_rerank,
-                    model: builtin_model,
-                    min_candidates: cli.rerank_candidates,
-                    show_download_progress: true,
-                    custom_model,
-                    probe_config: Some(probe_config),
+                let mut explanations = Vec::new();
+                let mut neighbors = Vec::new();
+                let mut repo_names = Vec::new();
+                let results = if let Some(workspace) = workspace {
+                    if cli.refine {
+                        return Err(anyhow::anyhow!(
+                            "--refine narrows down the previous search of a single project"
+                        ));
+                    }
+                    let labeled = search_repos(
+                        &workspace,
+                        &query,
+                        &options,
+                        reranker_config,
+                        cli.no_index,
+                        threads,
+                        verbosity,
+                    )?;
+                    let (names, results) = labeled.into_iter().unzip();
+                    repo_names = names;
+                    results
+                } else {
+                    let engine = open_engine(&root_dir, index_dir, profile, threads, verbosity)?;
+                    if cli.refine {
+                        options.within = refined_chunks(&engine)?;
+                    }
+                    // A running `probe daemon` has the reranker loaded already. Explanations
+                    // look at the index in this process.
+                    let from_daemon = if cli.no_index || cli.explain {
+                        None
+                    } else {
+                        engine.search_with_daemon(&query, &options, &reranker_config)?
+                    };
+                    if from_daemon.is_some() && verbosity.is_verbose() {
+                        eprintln!(
+                            "Searched by the daemon on {}",
+                            engine.daemon_socket_path().display()
+                        );
+                    }
+                    let results = match from_daemon {
+                        Some(results) => results,
+                        None => search_repo(
+                            &engine,
+                            &query,
+                            &options,
+                            reranker_config.clone(),
+                            cli.no_index,
+                        )?,
+                    };
+                    if cli.explain {
+                        explanations =
+                            engine.explain_results(&query, &options, &results, &reranker_config)?;
+                    }
+                    if cli.neighbors > 0 && !cli.no_index {
+                        neighbors =
+                            engine.chunk_neighbors(&query, &options, &results, cli.neighbors)?;
+                    }
+                    let recorded = recorded_args(&cli, &args, &query);
+                    let grouped = cli.group_by_file;
+                    if let Err(e) = record_search(&engine, &query, recorded, grouped, &results) {
+                        eprintln!("Warning: failed to add the search to the history: {e}");
+                    }
+                    results
                 };
-
-                let engine = SearchEngine::new(&root_dir)?;
-                engine.ensure_index_updated()?;
-                let results = engine.search_with_reranker(
-                    &query,
-                    Some(cli.num_results),
-                    cli.filetype.as_deref(),
-                    reranker_config,
-                    cli.context_lines,
-                )?;
+                if cli.no_index && !verbosity.is_quiet() {
+                    eprintln!("Searched without an index. Run `probe rebuild` once to build one and get faster, reranked results.");
+                }
 
                 if results.is_empty() {
-                    eprintln!("No results found for '{query}'");
-                } else {
-                    eprintln!("Found {} results for '{}':\n", results.len(), query);
-                    for result in results.iter() {
-                        // Format line information
-                        let line_info = if let (Some(start), Some(end)) =
-                            (result.start_line, result.end_line)
-                        {
-                            if start == end {
-                                format!(" (line {})", start + 1)
-                            } else {
-                                format!(" (lines {}-{})", start + 1, end + 1)
-                            }
-                        } else {
-                            String::new()
-                        };
-
-                        println!("{}{}", result.path.display(), line_info);
-                        if !result.snippet.is_empty() {
-                            println!("{}\n", result.snippet);
-                        }
+                    if options.offset > 0 && !verbosity.is_quiet() {
+                        eprintln!(
+                            "No more results for '{query}' after the first {}",
+                            options.offset
+                        );
+                    } else if !verbosity.is_quiet() {
+                        eprintln!("No results found for '{query}'");
+                    }
+                } else if cli.format == "text" && !verbosity.is_quiet() {
+                    if options.offset > 0 {
+                        eprintln!(
+                            "Results {}-{} for '{}':\n",
+                            options.offset + 1,
+                            options.offset + results.len(),
+                            query
+                        );
+                    } else {
+                        eprintln!("Found {} results for '{}':\n", results.len(), query);
                     }
                 }
+                // Without results, csv and tsv output still has its header row
+                print_results(
+                    &results,
+                    &repo_names,
+                    &explanations,
+                    &neighbors,
+                    cli.group_by_file,
+                    &cli.format,
+                );
             } else {
                 println!("Usage: probe <query> or probe --help");
             }
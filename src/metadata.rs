@@ -1,7 +1,11 @@
+use crate::config::ChangeDetection;
 use crate::file_scanner::IndexedFile;
+use crate::paths::{path_key, write_atomic};
+use crate::search_index::{index_path, SkipReason, SkippedFile};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Component, Path, PathBuf};
 use std::time::SystemTime;
@@ -11,11 +15,76 @@ pub struct FileInfo {
     pub path: PathBuf,
     pub size: u64,
     pub modified: SystemTime,
+    /// `content_hash` of the file, `None` when recorded with mtime change detection
+    pub hash: Option<String>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct IndexMetadata {
+    /// Keyed by `path_key`, so lookups are case-insensitive where the filesystem is
     files: HashMap<PathBuf, FileInfo>,
+    #[serde(skip)]
+    change_detection: ChangeDetection,
+    /// Set when `needs_reindex` recorded new modification times that aren't saved yet
+    #[serde(skip)]
+    dirty: bool,
+    /// HEAD commit of the project's git repository when the index was last updated
+    commit: Option<String>,
+}
+
+/// `metadata.bin` as written before the HEAD commit was recorded
+#[derive(Deserialize)]
+struct UncommittedMetadata {
+    files: HashMap<PathBuf, FileInfo>,
+}
+
+impl From<UncommittedMetadata> for IndexMetadata {
+    fn from(metadata: UncommittedMetadata) -> Self {
+        Self {
+            files: metadata.files,
+            ..Self::default()
+        }
+    }
+}
+
+/// `metadata.bin` as written before content hashes were recorded
+#[derive(Deserialize)]
+struct LegacyMetadata {
+    files: HashMap<PathBuf, LegacyFileInfo>,
+}
+
+#[derive(Deserialize)]
+struct LegacyFileInfo {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl From<LegacyMetadata> for IndexMetadata {
+    fn from(legacy: LegacyMetadata) -> Self {
+        let files = legacy
+            .files
+            .into_iter()
+            .map(|(key, info)| {
+                let info = FileInfo {
+                    path: info.path,
+                    size: info.size,
+                    modified: info.modified,
+                    hash: None,
+                };
+                (key, info)
+            })
+            .collect();
+        Self {
+            files,
+            ..Self::default()
+        }
+    }
+}
+
+/// SHA-256 of a file's content as lowercase hex, the format index archive manifests use too
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
 }
 
 impl IndexMetadata {
@@ -25,21 +94,36 @@ impl IndexMetadata {
 
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         match fs::read(&path) {
-            Ok(data) => Ok(bincode::deserialize(&data)?),
+            Ok(data) => match bincode::deserialize(&data) {
+                Ok(metadata) => Ok(metadata),
+                // Entries of older versions have no hash; their files are compared by
+                // modification time until they are indexed again
+                Err(e) => bincode::deserialize::<UncommittedMetadata>(&data)
+                    .map(Self::from)
+                    .or_else(|_| bincode::deserialize::<LegacyMetadata>(&data).map(Self::from))
+                    .map_err(|_| e.into()),
+            },
             Err(_) => Ok(Self::new()), // Return empty metadata if file doesn't exist
         }
     }
 
+    /// Sets how `needs_reindex` compares files, content hashing by default
+    pub fn with_change_detection(mut self, change_detection: ChangeDetection) -> Self {
+        self.change_detection = change_detection;
+        self
+    }
+
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let data = bincode::serialize(self)?;
-        if let Some(parent) = path.as_ref().parent() {
-            fs::create_dir_all(parent)?;
-        }
-        fs::write(path, data)?;
-        Ok(())
+        // Written atomically, as a Ctrl-C or kill during the write must not leave
+        // metadata that disagrees with the committed index
+        write_atomic(path.as_ref(), bincode::serialize(self)?)
     }
 
-    pub fn needs_reindex(&self, files: &[IndexedFile]) -> Result<Vec<IndexedFile>> {
+    /// The files among `files` that changed since they were last indexed. With content
+    /// hashing, a file whose modification time changed but whose content didn't is not
+    /// reported; its new modification time is recorded instead, so it isn't hashed again
+    /// once the metadata is saved (see `is_dirty`).
+    pub fn needs_reindex(&mut self, files: &[IndexedFile]) -> Result<Vec<IndexedFile>> {
         let mut changed_files = Vec::new();
 
         for file in files {
@@ -57,29 +141,95 @@ impl IndexMetadata {
             Err(_) => return Ok(()), // Skip files that no longer exist
         };
 
+        let hash = match self.change_detection {
+            ChangeDetection::Hash => fs::read(&file.disk_path)
+                .ok()
+                .map(|content| content_hash(&content)),
+            ChangeDetection::Mtime => None,
+        };
         let file_info = FileInfo {
             path: file.relative_path.clone(),
             size: metadata.len(),
             modified: metadata.modified()?,
+            hash,
         };
 
-        self.files.insert(file.relative_path.clone(), file_info);
+        self.files.insert(path_key(&file.relative_path), file_info);
         Ok(())
     }
 
-    fn file_changed(&self, file: &IndexedFile) -> Result<bool> {
+    /// Whether `needs_reindex` recorded modification times, or `set_commit` a new commit,
+    /// that should be saved
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// The git commit checked out when the index was last updated
+    pub fn commit(&self) -> Option<&str> {
+        self.commit.as_deref()
+    }
+
+    pub fn set_commit(&mut self, commit: Option<String>) {
+        if self.commit != commit {
+            self.commit = commit;
+            self.dirty = true;
+        }
+    }
+
+    /// Whether `file` changed since it was last indexed, like `needs_reindex` for one file
+    pub fn file_changed(&mut self, file: &IndexedFile) -> Result<bool> {
         let current_metadata = match fs::metadata(&file.disk_path) {
             Ok(meta) => meta,
             Err(_) => return Ok(true), // File doesn't exist, consider it changed
         };
 
-        match self.files.get(&file.relative_path) {
-            Some(cached_info) => Ok(cached_info.size != current_metadata.len()
-                || cached_info.modified != current_metadata.modified()?),
-            None => Ok(true), // File not in cache, needs indexing
+        let Some(cached_info) = self.files.get_mut(&path_key(&file.relative_path)) else {
+            return Ok(true); // File not in cache, needs indexing
+        };
+        if cached_info.size != current_metadata.len() {
+            return Ok(true);
+        }
+        let modified = current_metadata.modified()?;
+        if cached_info.modified == modified {
+            return Ok(false);
+        }
+
+        match (self.change_detection, &cached_info.hash) {
+            (ChangeDetection::Hash, Some(hash)) => {
+                let unchanged =
+                    fs::read(&file.disk_path).is_ok_and(|content| content_hash(&content) == *hash);
+                if unchanged {
+                    cached_info.modified = modified;
+                    self.dirty = true;
+                }
+                Ok(!unchanged)
+            }
+            _ => Ok(true),
         }
     }
 
+    /// Removes entries for files that are no longer part of the scanned file set (deleted,
+    /// renamed or newly ignored) and returns their paths, sorted.
+    pub fn prune(&mut self, files: &[IndexedFile]) -> Vec<PathBuf> {
+        let current: HashSet<PathBuf> = files
+            .iter()
+            .map(|file| path_key(&file.relative_path))
+            .collect();
+        let removed_keys: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|key| !current.contains(*key))
+            .cloned()
+            .collect();
+        let mut removed: Vec<PathBuf> = removed_keys
+            .iter()
+            .filter_map(|key| self.files.remove(key))
+            .map(|info| info.path)
+            .collect();
+        removed.sort();
+        removed
+    }
+
     pub fn needs_relative_path_migration(&self) -> bool {
         self.files.keys().any(|path| {
             path.is_absolute() || matches!(path.components().next(), Some(Component::CurDir))
@@ -90,7 +240,88 @@ impl IndexMetadata {
         self.files.len()
     }
 
+    /// Modification time of every file when it was last indexed
+    pub fn modified_times(&self) -> impl Iterator<Item = (&PathBuf, SystemTime)> {
+        self.files.values().map(|info| (&info.path, info.modified))
+    }
+
     pub fn list_files(&self) -> impl Iterator<Item = &PathBuf> {
-        self.files.keys()
+        self.files.values().map(|info| &info.path)
+    }
+}
+
+/// Files the scanner found but indexing left out, with the reason. Stored as JSON next to
+/// the index metadata so `probe stats --skipped` can explain why a file isn't searchable.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SkippedFiles {
+    files: BTreeMap<PathBuf, SkipReason>,
+}
+
+impl SkippedFiles {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match fs::read_to_string(&path) {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_atomic(path.as_ref(), serde_json::to_string_pretty(self)?)
+    }
+
+    /// Replaces the entries of every file in `attempted` with the outcome of indexing it
+    pub fn record(&mut self, attempted: &[IndexedFile], skipped: &[SkippedFile]) {
+        for file in attempted {
+            self.files.remove(&file.relative_path);
+        }
+        for file in skipped {
+            self.files.insert(file.path.clone(), file.reason.clone());
+        }
+    }
+
+    /// Moves the entries of every file that `rewrite` maps to a new index path there
+    pub fn rewrite_paths(&mut self, rewrite: impl Fn(&str) -> Option<String>) {
+        self.files = std::mem::take(&mut self.files)
+            .into_iter()
+            .map(|(path, reason)| match rewrite(&index_path(&path)) {
+                Some(new_path) => (PathBuf::from(new_path), reason),
+                None => (path, reason),
+            })
+            .collect();
+    }
+
+    /// Drops entries of files that are no longer part of the scanned file set
+    pub fn prune(&mut self, files: &[IndexedFile]) {
+        let current: HashSet<PathBuf> = files
+            .iter()
+            .map(|file| path_key(&file.relative_path))
+            .collect();
+        self.files
+            .retain(|path, _| current.contains(&path_key(path)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PathBuf, &SkipReason)> {
+        self.files.iter()
+    }
+}
+
+/// Counts per reason, e.g. `2 too large, 1 not UTF-8`
+pub fn summarize_skipped<'a>(reasons: impl IntoIterator<Item = &'a SkipReason>) -> String {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for reason in reasons {
+        *counts.entry(reason.label()).or_default() += 1;
     }
+    counts
+        .iter()
+        .map(|(label, count)| format!("{count} {label}"))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
@@ -0,0 +1,143 @@
+use crate::embeddings::parse_embedding_model;
+use crate::paths;
+use crate::reranker::{parse_reranker_model, ProbeConfig};
+use anyhow::Result;
+use fastembed::{TextEmbedding, TextRerank};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prefix of the model directories in the Hugging Face cache layout
+const MODEL_DIR_PREFIX: &str = "models--";
+
+/// A model downloaded to the model cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedModel {
+    /// Hugging Face repository of the model, e.g. `BAAI/bge-reranker-base`
+    pub repo: String,
+    pub path: PathBuf,
+    /// Disk space taken by the model's files
+    pub bytes: u64,
+}
+
+/// Models in the model cache, sorted by repository
+pub fn cached_models() -> Result<Vec<CachedModel>> {
+    models_in(&paths::model_cache_dir())
+}
+
+fn models_in(cache_dir: &Path) -> Result<Vec<CachedModel>> {
+    if !cache_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut models = Vec::new();
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(repo) = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(MODEL_DIR_PREFIX))
+        else {
+            continue;
+        };
+        if entry.file_type()?.is_dir() {
+            models.push(CachedModel {
+                repo: repo.replace("--", "/"),
+                path: entry.path(),
+                bytes: paths::directory_size(&entry.path())?,
+            });
+        }
+    }
+    models.sort_by(|a, b| a.repo.cmp(&b.repo));
+    Ok(models)
+}
+
+/// Hugging Face repository of a model given by the name probe knows it by, e.g.
+/// `bge-reranker-base` or a custom reranker from the config. Other names are taken to be
+/// repositories already.
+pub fn model_repo(name: &str, probe_config: &ProbeConfig) -> String {
+    if let Ok(model) = parse_reranker_model(name) {
+        if let Some(info) = TextRerank::list_supported_models()
+            .into_iter()
+            .find(|info| info.model == model)
+        {
+            return info.model_code;
+        }
+    }
+    if let Ok(model) = parse_embedding_model(name) {
+        if let Some(info) = TextEmbedding::list_supported_models()
+            .into_iter()
+            .find(|info| info.model == model)
+        {
+            return info.model_code;
+        }
+    }
+    match probe_config.get_custom_model(name) {
+        Some(custom_model) => custom_model.model_code.clone(),
+        None => name.to_string(),
+    }
+}
+
+/// The cached model of the repository `repo`, compared case-insensitively
+pub fn find_cached(repo: &str) -> Result<Option<CachedModel>> {
+    Ok(cached_models()?
+        .into_iter()
+        .find(|model| model.repo.eq_ignore_ascii_case(repo)))
+}
+
+/// Deletes the cached files of the repository `repo`, returning what was removed
+pub fn remove_model(repo: &str) -> Result<CachedModel> {
+    let model =
+        find_cached(repo)?.ok_or_else(|| anyhow::anyhow!("Model '{}' is not downloaded", repo))?;
+    fs::remove_dir_all(&model.path)?;
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_models_in_cache_layout() {
+        let temp_dir = TempDir::new().unwrap();
+        let blobs = temp_dir
+            .path()
+            .join("models--BAAI--bge-reranker-base/blobs");
+        fs::create_dir_all(&blobs).unwrap();
+        fs::write(blobs.join("abc123"), vec![0u8; 1000]).unwrap();
+        fs::create_dir_all(temp_dir.path().join("models--org--small/snapshots")).unwrap();
+        // Lock files and other entries of the cache aren't models
+        fs::create_dir_all(temp_dir.path().join(".locks")).unwrap();
+
+        let models = models_in(temp_dir.path()).unwrap();
+        let summary: Vec<_> = models
+            .iter()
+            .map(|model| (model.repo.as_str(), model.bytes))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("BAAI/bge-reranker-base", 1000), ("org/small", 0)]
+        );
+        assert!(models_in(&temp_dir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_model_repo() {
+        let probe_config: ProbeConfig = serde_yaml::from_str(
+            "custom_rerankers:\n  \
+               small:\n    \
+                 description: Small\n    \
+                 model_code: org/small\n    \
+                 model_file: model.onnx\n    \
+                 additional_files: []\n",
+        )
+        .unwrap();
+        assert_eq!(
+            model_repo("bge-reranker-base", &probe_config),
+            "BAAI/bge-reranker-base"
+        );
+        assert_eq!(model_repo("small", &probe_config), "org/small");
+        assert_eq!(model_repo("org/other", &probe_config), "org/other");
+    }
+}
@@ -0,0 +1,91 @@
+use crate::language_processor::{ChunkType, CodeChunk};
+
+/// A structural chunk of a file as shown by `probe outline`, with 0-based inclusive lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutlineItem {
+    /// Lowercase chunk kind, e.g. `class` or `method`
+    pub kind: String,
+    pub name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// Number of enclosing items
+    pub depth: usize,
+}
+
+impl OutlineItem {
+    pub fn new(kind: &str, name: &str, start_line: usize, end_line: usize) -> Self {
+        Self {
+            kind: kind.to_lowercase(),
+            name: name.to_string(),
+            start_line,
+            end_line,
+            depth: 0,
+        }
+    }
+
+    pub fn from_chunk(chunk: &CodeChunk) -> Self {
+        Self::new(
+            &format!("{:?}", chunk.chunk_type),
+            &chunk.name,
+            chunk.start_line,
+            chunk.end_line,
+        )
+    }
+
+    /// Whole-file documents and the line windows used for code the parser couldn't make
+    /// sense of carry no structure worth outlining
+    fn is_structural(&self) -> bool {
+        self.kind != "file" && self.kind != format!("{:?}", ChunkType::Other).to_lowercase()
+    }
+}
+
+/// Orders `items` by position and sets each item's depth to the number of items whose
+/// line range contains it
+pub fn build_outline(items: Vec<OutlineItem>) -> Vec<OutlineItem> {
+    let mut items: Vec<_> = items
+        .into_iter()
+        .filter(|item| item.is_structural())
+        .collect();
+    items.sort_by(|a, b| {
+        a.start_line
+            .cmp(&b.start_line)
+            .then(b.end_line.cmp(&a.end_line))
+    });
+
+    // End lines of the items enclosing the current one
+    let mut open: Vec<usize> = Vec::new();
+    for item in &mut items {
+        while open.last().is_some_and(|&end| end < item.end_line) {
+            open.pop();
+        }
+        item.depth = open.len();
+        open.push(item.end_line);
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_outline_nests_by_line_range() {
+        let outline = build_outline(vec![
+            OutlineItem::new("Method", "run", 3, 5),
+            OutlineItem::new("Other", "", 20, 30),
+            OutlineItem::new("Class", "Outer", 0, 18),
+            OutlineItem::new("Class", "Inner", 7, 12),
+            OutlineItem::new("Method", "helper", 8, 11),
+            OutlineItem::new("Method", "close", 14, 16),
+        ]);
+
+        let rendered: Vec<_> = outline
+            .iter()
+            .map(|item| format!("{}{}", "  ".repeat(item.depth), item.name))
+            .collect();
+        assert_eq!(
+            rendered,
+            vec!["Outer", "  run", "  Inner", "    helper", "  close"]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory holding the global user configuration.
+///
+/// Resolves to `$XDG_CONFIG_HOME/probe` on Linux, `~/Library/Application Support/probe`
+/// on macOS and `%APPDATA%\probe` on Windows.
+pub fn config_dir() -> Result<PathBuf> {
+    let dir = dirs::config_dir().context("Failed to determine the user config directory")?;
+    Ok(dir.join("probe"))
+}
+
+/// Directory for downloaded and derived data that can be safely deleted.
+///
+/// Resolves to `$XDG_CACHE_HOME/probe` on Linux, `~/Library/Caches/probe` on macOS
+/// and `%LOCALAPPDATA%\probe` on Windows.
+pub fn cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("probe")
+}
+
+/// Directory where reranking models are cached, overridable with `FASTEMBED_CACHE_PATH`
+pub fn model_cache_dir() -> PathBuf {
+    std::env::var("FASTEMBED_CACHE_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| cache_dir().join("models"))
+}
+
+/// Total size of the files in `dir` and its subdirectories, not following symlinks
+pub fn directory_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            directory_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Pre-XDG location of the global config (`~/.probe`)
+fn legacy_config_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".probe"))
+}
+
+/// Pre-XDG location of the model cache
+fn legacy_model_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("probe-fastembed"))
+}
+
+/// Moves state from the locations used by older probe versions to the platform directories.
+/// Existing files at the new locations always win, so this is safe to call on every start.
+pub fn migrate_legacy_locations() -> Result<()> {
+    if let (Some(legacy), Ok(target)) = (legacy_config_dir(), config_dir()) {
+        let legacy_file = legacy.join("config.yaml");
+        let target_file = target.join("config.yaml");
+        if migrate_config_file(&legacy_file, &target_file)? {
+            eprintln!(
+                "Copied {} to {}; the old file is no longer read and can be removed.",
+                legacy_file.display(),
+                target_file.display()
+            );
+        }
+    }
+
+    if std::env::var("FASTEMBED_CACHE_PATH").is_err() {
+        if let Some(legacy) = legacy_model_cache_dir() {
+            migrate_model_cache(&legacy, &model_cache_dir())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies the legacy config file to its new location. Returns true if a copy happened.
+fn migrate_config_file(legacy_file: &Path, target_file: &Path) -> Result<bool> {
+    if !legacy_file.is_file() || target_file.exists() {
+        return Ok(false);
+    }
+
+    if let Some(parent) = target_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(legacy_file, target_file).with_context(|| {
+        format!(
+            "Failed to migrate config file {} to {}",
+            legacy_file.display(),
+            target_file.display()
+        )
+    })?;
+    Ok(true)
+}
+
+/// Moves the legacy model cache into place so models don't have to be downloaded again
+fn migrate_model_cache(legacy_dir: &Path, target_dir: &Path) -> Result<()> {
+    if !legacy_dir.is_dir() || target_dir.exists() {
+        return Ok(());
+    }
+
+    if let Some(parent) = target_dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // A failed rename (e.g. across filesystems) only costs a re-download, so don't fail the command
+    let _ = fs::rename(legacy_dir, target_dir);
+    Ok(())
+}
+
+/// Whether the platform's default filesystem treats paths that differ only in case as the same file
+pub const CASE_INSENSITIVE: bool = cfg!(windows);
+
+/// Removes the verbatim `\\?\` prefix that `canonicalize` puts on Windows paths, when the
+/// path can be expressed without it. Gitignore and glob matching don't understand verbatim
+/// paths, and the standard library adds the prefix by itself when a path exceeds `MAX_PATH`,
+/// so long paths keep working. Other platforms get the path unchanged.
+pub fn simplify_path(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        if let Some(simplified) = path.to_str().and_then(strip_verbatim_prefix) {
+            return PathBuf::from(simplified);
+        }
+    }
+    path.to_path_buf()
+}
+
+fn strip_verbatim_prefix(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(r"\\?\")?;
+    // Verbatim paths may legally contain characters and components that mean something
+    // else in the regular syntax, keep those as they are
+    if rest.contains('/') || rest.split('\\').any(|part| part == "." || part == "..") {
+        return None;
+    }
+    if let Some(unc) = rest.strip_prefix(r"UNC\") {
+        return Some(format!(r"\\{unc}"));
+    }
+    let bytes = rest.as_bytes();
+    let is_drive =
+        bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\';
+    is_drive.then(|| rest.to_string())
+}
+
+/// Whether `file_name` is one of the device names Windows reserves in every directory
+/// (`CON`, `NUL`, `COM1`, ...), with or without an extension. Such files can be created
+/// on other systems but can't be opened on Windows.
+pub fn is_reserved_name(file_name: &str) -> bool {
+    let stem = file_name.split('.').next().unwrap_or(file_name).trim_end();
+    let upper = stem.to_ascii_uppercase();
+    match upper.as_str() {
+        "CON" | "PRN" | "AUX" | "NUL" => true,
+        _ => match upper.as_bytes() {
+            [b'C', b'O', b'M', digit] | [b'L', b'P', b'T', digit] => {
+                matches!(digit, b'1'..=b'9')
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Key under which a relative path is tracked, so that paths differing only in case refer
+/// to the same file on case-insensitive filesystems
+pub fn path_key(path: &Path) -> PathBuf {
+    if CASE_INSENSITIVE {
+        PathBuf::from(path.to_string_lossy().to_lowercase())
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Replaces the file at `path` with `contents` by writing a temporary file next to it and
+/// renaming it into place, so a process killed halfway leaves the previous version intact
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    fs::write(&temp_path, contents)
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_migrate_config_file_copies_when_target_missing() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy_file = temp_dir.path().join("home/.probe/config.yaml");
+        let target_file = temp_dir.path().join("config/probe/config.yaml");
+        fs::create_dir_all(legacy_file.parent().unwrap()).unwrap();
+        fs::write(&legacy_file, "default_reranker: my-model\n").unwrap();
+
+        assert!(migrate_config_file(&legacy_file, &target_file).unwrap());
+        assert_eq!(
+            fs::read_to_string(&target_file).unwrap(),
+            "default_reranker: my-model\n"
+        );
+
+        // A second run must not overwrite the migrated file
+        fs::write(&target_file, "default_reranker: other\n").unwrap();
+        assert!(!migrate_config_file(&legacy_file, &target_file).unwrap());
+        assert_eq!(
+            fs::read_to_string(&target_file).unwrap(),
+            "default_reranker: other\n"
+        );
+    }
+
+    #[test]
+    fn test_migrate_model_cache_moves_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy_dir = temp_dir.path().join("probe-fastembed");
+        let target_dir = temp_dir.path().join("probe").join("models");
+        fs::create_dir_all(&legacy_dir).unwrap();
+        fs::write(legacy_dir.join("model.onnx"), "weights").unwrap();
+
+        migrate_model_cache(&legacy_dir, &target_dir).unwrap();
+
+        assert!(!legacy_dir.exists());
+        assert!(target_dir.join("model.onnx").exists());
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("index").join("metadata.bin");
+
+        write_atomic(&path, "first").unwrap();
+        write_atomic(&path, "second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert!(!path.with_file_name("metadata.bin.tmp").exists());
+    }
+
+    #[test]
+    fn test_is_reserved_name() {
+        for name in ["CON", "nul", "Aux.txt", "com1", "LPT9.log", "con.tar.gz"] {
+            assert!(is_reserved_name(name), "{name} should be reserved");
+        }
+        for name in ["console.rs", "COM0", "LPT10", "null", "CONFIG.md", "com"] {
+            assert!(!is_reserved_name(name), "{name} should not be reserved");
+        }
+    }
+
+    #[test]
+    fn test_strip_verbatim_prefix() {
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\C:\repo\src").as_deref(),
+            Some(r"C:\repo\src")
+        );
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\UNC\server\share\repo").as_deref(),
+            Some(r"\\server\share\repo")
+        );
+        assert_eq!(strip_verbatim_prefix(r"C:\repo"), None);
+        assert_eq!(strip_verbatim_prefix(r"\\?\Volume{1234}\repo"), None);
+        assert_eq!(strip_verbatim_prefix(r"\\?\C:\repo\..\src"), None);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::config::QueryConfig;
+use std::collections::HashMap;
+
+/// Common abbreviations in code and what they stand for, expanded with
+/// `query.builtin_abbreviations: true`
+const BUILTIN_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("arg", "argument"),
+    ("args", "arguments"),
+    ("auth", "authentication"),
+    ("btn", "button"),
+    ("buf", "buffer"),
+    ("cfg", "config"),
+    ("conf", "config"),
+    ("conn", "connection"),
+    ("ctx", "context"),
+    ("db", "database"),
+    ("dir", "directory"),
+    ("doc", "document"),
+    ("env", "environment"),
+    ("err", "error"),
+    ("func", "function"),
+    ("idx", "index"),
+    ("impl", "implementation"),
+    ("init", "initialize"),
+    ("len", "length"),
+    ("msg", "message"),
+    ("num", "number"),
+    ("obj", "object"),
+    ("param", "parameter"),
+    ("pkg", "package"),
+    ("ptr", "pointer"),
+    ("repo", "repository"),
+    ("req", "request"),
+    ("resp", "response"),
+    ("src", "source"),
+    ("str", "string"),
+    ("tmp", "temporary"),
+    ("val", "value"),
+    ("var", "variable"),
+];
+
+/// Query words that are part of the query syntax
+const OPERATORS: [&str; 4] = ["AND", "OR", "NOT", "TO"];
+
+/// Rewrites the words of a query before it's parsed, so that they also match the parts of
+/// identifiers and what abbreviations stand for. `parseCfg` becomes
+/// `(parseCfg parse cfg config)`: the word as written still matches the identifier, and
+/// ranks highest, while its parts match code that names things the other way around.
+#[derive(Debug, Clone)]
+pub struct QueryExpansion {
+    split_identifiers: bool,
+    /// Lowercase abbreviation to what it stands for
+    abbreviations: HashMap<String, String>,
+}
+
+impl Default for QueryExpansion {
+    fn default() -> Self {
+        Self::from_config(&QueryConfig::default())
+    }
+}
+
+impl QueryExpansion {
+    pub fn from_config(config: &QueryConfig) -> Self {
+        let mut abbreviations: HashMap<String, String> = HashMap::new();
+        if config.builtin_abbreviations {
+            abbreviations.extend(
+                BUILTIN_ABBREVIATIONS
+                    .iter()
+                    .map(|(short, long)| (short.to_string(), long.to_string())),
+            );
+        }
+        abbreviations.extend(
+            config
+                .abbreviations
+                .iter()
+                .map(|(short, long)| (short.to_lowercase(), long.clone())),
+        );
+        Self {
+            split_identifiers: config.split_identifiers,
+            abbreviations,
+        }
+    }
+
+    /// Expands the plain words of `query`. Phrases, `field:` terms, words with a `+` or `-`
+    /// prefix and anything else using the query syntax are left as written.
+    pub fn expand(&self, query: &str) -> String {
+        if !self.split_identifiers && self.abbreviations.is_empty() {
+            return query.to_string();
+        }
+
+        let mut expanded = String::with_capacity(query.len());
+        let mut rest = query;
+        while let Some(c) = rest.chars().next() {
+            let len = match c {
+                '"' => rest[1..].find('"').map_or(rest.len(), |end| end + 2),
+                _ if c.is_whitespace() || c == '(' || c == ')' => c.len_utf8(),
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '"'))
+                        .unwrap_or(rest.len());
+                    expanded.push_str(&self.expand_word(&rest[..end]));
+                    rest = &rest[end..];
+                    continue;
+                }
+            };
+            expanded.push_str(&rest[..len]);
+            rest = &rest[len..];
+        }
+        expanded
+    }
+
+    fn expand_word(&self, word: &str) -> String {
+        let plain = word
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !plain || word.starts_with(['-', '.']) || OPERATORS.contains(&word) {
+            return word.to_string();
+        }
+
+        let parts = if self.split_identifiers {
+            identifier_parts(word)
+        } else {
+            vec![word]
+        };
+        let mut terms: Vec<String> = Vec::new();
+        let mut add = |term: String| {
+            if !term.eq_ignore_ascii_case(word) && !terms.contains(&term) {
+                terms.push(term);
+            }
+        };
+        for part in parts {
+            // Single letters and digits match too much on their own
+            if part.chars().count() >= 2 {
+                add(part.to_string());
+            }
+            if let Some(long) = self.abbreviations.get(&part.to_lowercase()) {
+                add(query_term(long));
+            }
+        }
+
+        if terms.is_empty() {
+            word.to_string()
+        } else {
+            format!("({word} {})", terms.join(" "))
+        }
+    }
+}
+
+/// Splits an identifier into its words at `_`, `-` and `.`, between lowercase and uppercase
+/// letters, between letters and digits, and before the last capital of an acronym followed
+/// by a word: `parseHTTPResponse_v2` becomes `parse`, `HTTP`, `Response`, `v`, `2`.
+fn identifier_parts(word: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start = None;
+    for (n, &(i, c)) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if let Some(start) = start.take() {
+                parts.push(&word[start..i]);
+            }
+            continue;
+        }
+        let Some(part_start) = start else {
+            start = Some(i);
+            continue;
+        };
+        // The previous character is part of the same run of alphanumerics
+        let previous = chars[n - 1].1;
+        let next = chars.get(n + 1).map(|&(_, c)| c);
+        let boundary = (previous.is_lowercase() && c.is_uppercase())
+            || previous.is_alphabetic() != c.is_alphabetic()
+            || (previous.is_uppercase()
+                && c.is_uppercase()
+                && next.is_some_and(char::is_lowercase));
+        if boundary {
+            parts.push(&word[part_start..i]);
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        parts.push(&word[start..]);
+    }
+    parts
+}
+
+/// An expansion as a query term, quoted as a phrase unless it's a single word
+fn query_term(expansion: &str) -> String {
+    if expansion.chars().all(char::is_alphanumeric) {
+        expansion.to_string()
+    } else {
+        format!("\"{}\"", expansion.replace('"', ""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expansion(builtin_abbreviations: bool, abbreviations: &[(&str, &str)]) -> QueryExpansion {
+        QueryExpansion::from_config(&QueryConfig {
+            split_identifiers: true,
+            builtin_abbreviations,
+            abbreviations: abbreviations
+                .iter()
+                .map(|(short, long)| (short.to_string(), long.to_string()))
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_identifier_parts() {
+        assert_eq!(
+            identifier_parts("parseHTTPResponse_v2"),
+            ["parse", "HTTP", "Response", "v", "2"]
+        );
+        assert_eq!(identifier_parts("archive_lc"), ["archive", "lc"]);
+        assert_eq!(identifier_parts("os.path"), ["os", "path"]);
+        assert_eq!(identifier_parts("Größe"), ["Größe"]);
+    }
+
+    #[test]
+    fn test_split_identifiers() {
+        let expansion = expansion(false, &[]);
+        assert_eq!(
+            expansion.expand("parseConfig retry"),
+            "(parseConfig parse config) retry"
+        );
+        assert_eq!(
+            expansion.expand("(archive_lc OR x)"),
+            "((archive_lc archive lc) OR x)"
+        );
+        assert_eq!(expansion.expand("getX v2"), "(getX get) v2");
+    }
+
+    #[test]
+    fn test_query_syntax_is_kept() {
+        let expansion = expansion(true, &[]);
+        let query = "\"parseConfig cfg\" chunk_name:parseConfig +cfgLoader -db db^2 a*";
+        assert_eq!(expansion.expand(query), query);
+        assert_eq!(expansion.expand("x AND db"), "x AND (db database)");
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        assert_eq!(expansion(false, &[]).expand("cfg"), "cfg");
+        assert_eq!(expansion(true, &[]).expand("cfg"), "(cfg config)");
+        assert_eq!(
+            expansion(true, &[]).expand("loadCfg"),
+            "(loadCfg load cfg config)"
+        );
+
+        // Configured abbreviations replace built-in ones and may stand for several words
+        let expansion = expansion(true, &[("LC", "life cycle"), ("db", "datastore")]);
+        assert_eq!(
+            expansion.expand("archive lc"),
+            "archive (lc \"life cycle\")"
+        );
+        assert_eq!(expansion.expand("db"), "(db datastore)");
+    }
+}
@@ -1,11 +1,21 @@
+use crate::paths;
+use crate::search_index::{truncate_to_char_boundary, SearchResult};
 use anyhow::{Context, Result};
 use fastembed::{
     OnnxSource, RerankInitOptions, RerankInitOptionsUserDefined, RerankerModel, TextRerank,
     UserDefinedRerankingModel,
 };
+use ort::execution_providers::{
+    CPUExecutionProvider, CUDAExecutionProvider, CoreMLExecutionProvider,
+    DirectMLExecutionProvider, ExecutionProviderDispatch, TensorRTExecutionProvider,
+};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
 /// Custom reranker model configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,14 +24,41 @@ pub struct CustomRerankerModel {
     pub model_code: String,
     pub model_file: String,
     pub additional_files: Vec<String>,
+    /// Overrides the global `execution_providers` for this model
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub execution_providers: Vec<String>,
 }
 
 /// Configuration file structure
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ProbeConfig {
+    #[serde(default)]
     pub custom_rerankers: HashMap<String, CustomRerankerModel>,
     #[serde(default)]
     pub default_reranker: Option<String>,
+    /// Repositories registered with `probe repos add`, searchable with `--repo <name>`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub repos: BTreeMap<String, PathBuf>,
+    /// ONNX file to load for built-in models instead of their default one, e.g.
+    /// `onnx/model_quantized.onnx`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builtin_model_file: Option<String>,
+    /// Execution providers to try in order, e.g. `[cuda, cpu]`. Providers that aren't
+    /// available on this machine are skipped, ending up on the CPU.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub execution_providers: Vec<String>,
+    /// Documents scored per model call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rerank_batch_size: Option<usize>,
+    /// Tokens of each document the model sees; longer documents are truncated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rerank_max_length: Option<usize>,
+    /// Batches scored at the same time, each by its own copy of the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rerank_parallelism: Option<usize>,
+    /// Milliseconds to wait for the model before keeping the full-text order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rerank_timeout_ms: Option<u64>,
 }
 
 impl ProbeConfig {
@@ -45,17 +82,86 @@ impl ProbeConfig {
         Ok(config)
     }
 
-    /// Get the default configuration file path (~/.probe/config.yaml)
+    /// Get the default configuration file path (`config.yaml` in the platform config directory)
     pub fn default_config_path() -> Result<PathBuf> {
-        let home_dir = dirs::home_dir().context("Failed to get home directory")?;
+        Ok(paths::config_dir()?.join("config.yaml"))
+    }
 
-        Ok(home_dir.join(".probe").join("config.yaml"))
+    /// Write the configuration back to `config_path` (or the default location), creating
+    /// its directory if needed. Comments in an existing file are not preserved.
+    pub fn save_to_file(&self, config_path: Option<&PathBuf>) -> Result<()> {
+        let config_path = match config_path {
+            Some(path) => path.clone(),
+            None => Self::default_config_path()?,
+        };
+        if let Some(parent) = config_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&config_path, serde_yaml::to_string(self)?)
+            .with_context(|| format!("Failed to write config file: {}", config_path.display()))
     }
 
     /// Get a custom reranker model by name
     pub fn get_custom_model(&self, model_name: &str) -> Option<&CustomRerankerModel> {
         self.custom_rerankers.get(model_name)
     }
+
+    /// Registers the repository at `path` under `name`, defaulting to the directory name.
+    /// Returns the name it was registered under.
+    pub fn add_repo(&mut self, path: &Path, name: Option<&str>) -> Result<String> {
+        let path = paths::simplify_path(
+            &path
+                .canonicalize()
+                .with_context(|| format!("Repository not found: {}", path.display()))?,
+        );
+        if !path.is_dir() {
+            return Err(anyhow::anyhow!("Not a directory: {}", path.display()));
+        }
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Can't name the repository at {}, use --name",
+                        path.display()
+                    )
+                })?,
+        };
+        match self.repos.get(&name) {
+            Some(existing) if *existing != path => Err(anyhow::anyhow!(
+                "A repository named '{}' is already registered at {}, choose another --name",
+                name,
+                existing.display()
+            )),
+            _ => {
+                self.repos.insert(name.clone(), path);
+                Ok(name)
+            }
+        }
+    }
+
+    /// Unregisters a repository, returning where it was. Its index is left in place.
+    pub fn remove_repo(&mut self, name: &str) -> Result<PathBuf> {
+        self.repos.remove(name).ok_or_else(|| unknown_repo(name))
+    }
+
+    /// Get the root directory of a registered repository
+    pub fn repo_path(&self, name: &str) -> Result<&Path> {
+        self.repos
+            .get(name)
+            .map(PathBuf::as_path)
+            .ok_or_else(|| unknown_repo(name))
+    }
+}
+
+fn unknown_repo(name: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Unknown repository '{}', register it with `probe repos add <path> --name {}`",
+        name,
+        name
+    )
 }
 
 /// Configuration for the reranker
@@ -64,6 +170,10 @@ pub struct RerankerConfig {
     pub enabled: bool,
     pub model: RerankerModel,
     pub min_candidates: usize,
+    /// Candidates fetched per wanted result when reranking
+    pub candidate_multiplier: usize,
+    /// Drop candidates whose full-text score is below this fraction (0-1) of the best one's
+    pub score_cutoff: Option<f32>,
     pub show_download_progress: bool,
     pub custom_model: Option<String>,
     pub probe_config: Option<ProbeConfig>,
@@ -75,6 +185,8 @@ impl Default for RerankerConfig {
             enabled: true,
             model: RerankerModel::BGERerankerBase,
             min_candidates: 10,
+            candidate_multiplier: 2,
+            score_cutoff: None,
             show_download_progress: false,
             custom_model: None,
             probe_config: None,
@@ -82,6 +194,32 @@ impl Default for RerankerConfig {
     }
 }
 
+impl RerankerConfig {
+    /// How many full-text candidates to fetch for `limit` results: at least the minimum
+    /// candidates when reranking, but more if more results are wanted
+    pub fn candidate_limit(&self, limit: usize) -> usize {
+        if self.enabled {
+            std::cmp::max(self.min_candidates, limit * self.candidate_multiplier)
+        } else {
+            limit
+        }
+    }
+
+    /// Drops the candidates after the first one scoring below `score_cutoff` times the
+    /// score of the best candidate. Candidates must be sorted best first.
+    pub fn cut_candidates(&self, candidates: &mut Vec<SearchResult>) {
+        let (Some(cutoff), Some(best)) = (self.score_cutoff, candidates.first()) else {
+            return;
+        };
+        let min_score = best.score * cutoff;
+        let keep = candidates
+            .iter()
+            .position(|candidate| candidate.score < min_score)
+            .unwrap_or(candidates.len());
+        candidates.truncate(keep);
+    }
+}
+
 /// Represents a document to be reranked
 #[derive(Debug, Clone)]
 pub struct RerankDocument {
@@ -89,6 +227,18 @@ pub struct RerankDocument {
     pub metadata: HashMap<String, String>,
 }
 
+impl RerankDocument {
+    /// Path and start line from the metadata, used to order documents with equal scores
+    fn position(&self) -> (Option<&str>, Option<usize>) {
+        (
+            self.metadata.get("path").map(String::as_str),
+            self.metadata
+                .get("start_line")
+                .and_then(|line| line.parse().ok()),
+        )
+    }
+}
+
 /// Result after reranking
 #[derive(Debug, Clone)]
 pub struct RerankResult {
@@ -99,21 +249,26 @@ pub struct RerankResult {
 /// Downloads a custom HuggingFace model using configuration and returns the local file paths
 fn download_hf_model_sync(
     custom_model: &CustomRerankerModel,
-    _cache_dir: &std::path::Path,
+    cache_dir: &std::path::Path,
+    show_progress: bool,
 ) -> Result<(PathBuf, PathBuf, PathBuf)> {
-    use hf_hub::api::sync::Api;
+    use hf_hub::api::sync::ApiBuilder;
     use hf_hub::Repo;
 
     // Create API with cache directory
-    let api = Api::new()?;
+    let api = ApiBuilder::new()
+        .with_cache_dir(cache_dir.to_path_buf())
+        .build()?;
 
     let repo = api.repo(Repo::model(custom_model.model_code.clone()));
 
     // Download custom HuggingFace model
-    println!(
-        "Downloading custom reranking model: {} ({})",
-        custom_model.model_code, custom_model.description
-    );
+    if show_progress {
+        println!(
+            "Downloading custom reranking model: {} ({})",
+            custom_model.model_code, custom_model.description
+        );
+    }
 
     // Download the main model file
     let model_path = repo.get(&custom_model.model_file).with_context(|| {
@@ -199,89 +354,192 @@ fn create_user_defined_model(
     ))
 }
 
-/// Reranker wrapper that manages the fastembed reranking model
+/// A built-in model loaded from another ONNX file in its repository
+fn builtin_model_with_file(model: &RerankerModel, model_file: &str) -> Result<CustomRerankerModel> {
+    let info = TextRerank::list_supported_models()
+        .into_iter()
+        .find(|info| info.model == *model)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported reranker model: {:?}", model))?;
+    Ok(CustomRerankerModel {
+        description: info.description,
+        model_code: info.model_code,
+        model_file: model_file.to_string(),
+        // External weights belong to the default file
+        additional_files: Vec::new(),
+        execution_providers: Vec::new(),
+    })
+}
+
+/// Maps execution provider names from the config to ONNX Runtime providers, in order of
+/// preference. An empty list leaves the choice to fastembed.
+fn execution_providers(names: &[String]) -> Result<Vec<ExecutionProviderDispatch>> {
+    names
+        .iter()
+        .map(|name| match name.to_lowercase().as_str() {
+            "cpu" => Ok(CPUExecutionProvider::default().build()),
+            "cuda" => Ok(CUDAExecutionProvider::default().build()),
+            "tensorrt" => Ok(TensorRTExecutionProvider::default().build()),
+            "coreml" => Ok(CoreMLExecutionProvider::default().build()),
+            "directml" => Ok(DirectMLExecutionProvider::default().build()),
+            _ => Err(anyhow::anyhow!(
+                "Unknown execution provider '{}', expected cpu, cuda, tensorrt, coreml or directml",
+                name
+            )),
+        })
+        .collect()
+}
+
+const DEFAULT_BATCH_SIZE: usize = 32;
+const DEFAULT_MAX_LENGTH: usize = 512;
+/// No token is longer than this, so cutting documents at this many bytes per token
+/// before tokenizing them never loses text the model would have seen
+const MAX_BYTES_PER_TOKEN: usize = 16;
+
+/// Returned by `Reranker::rerank` when the model doesn't score the documents within
+/// `rerank_timeout_ms`
+#[derive(Debug)]
+pub struct RerankTimeout {
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for RerankTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "reranking took longer than {:?}", self.timeout)
+    }
+}
+
+impl std::error::Error for RerankTimeout {}
+
+/// Reranker wrapper that manages the fastembed reranking models
 pub struct Reranker {
-    model: TextRerank,
+    /// One copy of the model per batch scored in parallel. Shared with the thread scoring
+    /// the documents, which keeps them locked after a timeout until the model returns.
+    models: Arc<Mutex<Vec<TextRerank>>>,
     config: RerankerConfig,
+    batch_size: usize,
+    max_length: usize,
+    timeout: Option<Duration>,
 }
 
 impl Reranker {
     /// Create a new reranker with the specified configuration
     pub fn new(config: RerankerConfig) -> Result<Self> {
+        let probe_config = config.probe_config.clone().unwrap_or_default();
+        let batch_size = probe_config
+            .rerank_batch_size
+            .unwrap_or(DEFAULT_BATCH_SIZE)
+            .max(1);
+        let max_length = probe_config
+            .rerank_max_length
+            .unwrap_or(DEFAULT_MAX_LENGTH)
+            .max(1);
+        let timeout = probe_config.rerank_timeout_ms.map(Duration::from_millis);
+
         if !config.enabled {
             // Return a dummy reranker if disabled
             return Ok(Self {
-                model: Self::create_dummy_model()?,
+                models: Arc::new(Mutex::new(vec![Self::create_dummy_model()?])),
                 config,
+                batch_size,
+                max_length,
+                timeout,
             });
         }
 
-        // Set cache directory to a more appropriate location
-        let cache_dir = std::env::var("FASTEMBED_CACHE_PATH")
-            .map(std::path::PathBuf::from)
-            .unwrap_or_else(|_| {
-                // Default to a cache directory in the user's cache directory
-                dirs::cache_dir()
-                    .unwrap_or_else(std::env::temp_dir)
-                    .join("probe-fastembed")
-            });
+        let parallelism = probe_config.rerank_parallelism.unwrap_or(1).max(1);
+        let models = (0..parallelism)
+            .map(|_| Self::load_model(&config, max_length))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            models: Arc::new(Mutex::new(models)),
+            config,
+            batch_size,
+            max_length,
+            timeout,
+        })
+    }
 
-        let model = if let Some(custom_model_name) = &config.custom_model {
+    fn load_model(config: &RerankerConfig, max_length: usize) -> Result<TextRerank> {
+        let cache_dir = paths::model_cache_dir();
+
+        if let Some(custom_model_name) = &config.custom_model {
             // Use custom HuggingFace model
             let probe_config = config.probe_config.as_ref().ok_or_else(|| {
                 anyhow::anyhow!("Probe configuration is required when using custom models")
             })?;
-            Self::create_custom_model(custom_model_name, &cache_dir, probe_config)?
-        } else {
-            // Use built-in model
-            TextRerank::try_new(
-                RerankInitOptions::new(config.model.clone())
-                    .with_show_download_progress(config.show_download_progress)
-                    .with_cache_dir(cache_dir),
+            // Look up the custom model in the config
+            let custom_model = probe_config
+                .get_custom_model(custom_model_name)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Custom model '{}' not found in configuration. Please add it to your config file.",
+                        custom_model_name
+                    )
+                })?;
+            let providers = if custom_model.execution_providers.is_empty() {
+                &probe_config.execution_providers
+            } else {
+                &custom_model.execution_providers
+            };
+            Self::create_custom_model(
+                custom_model,
+                &cache_dir,
+                providers,
+                max_length,
+                config.show_download_progress,
             )
-            .context("Failed to initialize reranking model")?
-        };
-
-        Ok(Self { model, config })
+        } else {
+            let probe_config = config.probe_config.clone().unwrap_or_default();
+            match &probe_config.builtin_model_file {
+                // Another ONNX file of a built-in model, e.g. its quantized variant
+                Some(model_file) => Self::create_custom_model(
+                    &builtin_model_with_file(&config.model, model_file)?,
+                    &cache_dir,
+                    &probe_config.execution_providers,
+                    max_length,
+                    config.show_download_progress,
+                ),
+                // Use built-in model
+                None => TextRerank::try_new(
+                    RerankInitOptions::new(config.model.clone())
+                        .with_show_download_progress(config.show_download_progress)
+                        .with_cache_dir(cache_dir)
+                        .with_max_length(max_length)
+                        .with_execution_providers(execution_providers(
+                            &probe_config.execution_providers,
+                        )?),
+                )
+                .context("Failed to initialize reranking model"),
+            }
+        }
     }
 
     /// Create a custom model from HuggingFace using configuration
     fn create_custom_model(
-        model_name: &str,
+        custom_model: &CustomRerankerModel,
         cache_dir: &std::path::Path,
-        probe_config: &ProbeConfig,
+        providers: &[String],
+        max_length: usize,
+        show_progress: bool,
     ) -> Result<TextRerank> {
-        // Look up the custom model in the config
-        let custom_model = probe_config.get_custom_model(model_name).ok_or_else(|| {
-            anyhow::anyhow!(
-                "Custom model '{}' not found in configuration. Please add it to your config file.",
-                model_name
-            )
-        })?;
-
+        // Checked before downloading, so a typo doesn't cost a download
+        let mut options = RerankInitOptionsUserDefined::default();
+        options.execution_providers = execution_providers(providers)?;
+        options.max_length = max_length;
         let (model_path, tokenizer_path, config_path) =
-            download_hf_model_sync(custom_model, cache_dir)?;
+            download_hf_model_sync(custom_model, cache_dir, show_progress)?;
 
         let user_defined_model =
             create_user_defined_model(model_path, tokenizer_path, config_path)?;
 
-        let options = RerankInitOptionsUserDefined::default();
-
         TextRerank::try_new_from_user_defined(user_defined_model, options)
             .context("Failed to create custom model from user-defined model")
     }
 
     /// Create a dummy model for disabled reranker (won't be used)
     fn create_dummy_model() -> Result<TextRerank> {
-        // Set cache directory to a more appropriate location for dummy model too
-        let cache_dir = std::env::var("FASTEMBED_CACHE_PATH")
-            .map(std::path::PathBuf::from)
-            .unwrap_or_else(|_| {
-                // Default to a cache directory in the user's cache directory
-                dirs::cache_dir()
-                    .unwrap_or_else(std::env::temp_dir)
-                    .join("probe-fastembed")
-            });
+        let cache_dir = paths::model_cache_dir();
 
         TextRerank::try_new(
             RerankInitOptions::new(RerankerModel::BGERerankerBase).with_cache_dir(cache_dir),
@@ -289,6 +547,114 @@ impl Reranker {
         .context("Failed to create dummy model")
     }
 
+    /// Reorders search results by the model's relevance scores, which replace their
+    /// full-text scores, and keeps the best `limit`
+    pub fn rerank_results(
+        &mut self,
+        query: &str,
+        results: Vec<SearchResult>,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        // Convert SearchResults to RerankDocuments
+        let rerank_docs: Vec<RerankDocument> = results
+            .into_iter()
+            .map(|result| {
+                let mut metadata = HashMap::new();
+                metadata.insert(
+                    "path".to_string(),
+                    result.path.to_string_lossy().to_string(),
+                );
+                if let Some(chunk_type) = &result.chunk_type {
+                    metadata.insert("chunk_type".to_string(), chunk_type.clone());
+                }
+                if let Some(chunk_name) = &result.chunk_name {
+                    metadata.insert("chunk_name".to_string(), chunk_name.clone());
+                }
+                if let Some(start_line) = result.start_line {
+                    metadata.insert("start_line".to_string(), start_line.to_string());
+                }
+                if let Some(end_line) = result.end_line {
+                    metadata.insert("end_line".to_string(), end_line.to_string());
+                }
+                if let Some(snippet_line) = result.snippet_line {
+                    metadata.insert("snippet_line".to_string(), snippet_line.to_string());
+                }
+                if result.fuzzy {
+                    metadata.insert("fuzzy".to_string(), "true".to_string());
+                }
+                if !result.duplicates.is_empty() {
+                    let duplicates: Vec<_> = result
+                        .duplicates
+                        .iter()
+                        .map(|path| path.to_string_lossy())
+                        .collect();
+                    metadata.insert("duplicates".to_string(), duplicates.join("\n"));
+                }
+
+                RerankDocument {
+                    content: result.snippet,
+                    metadata,
+                }
+            })
+            .collect();
+
+        let rerank_result = self.rerank(query, rerank_docs, Some(limit))?;
+
+        // Convert back to SearchResults
+        Ok(rerank_result
+            .documents
+            .into_iter()
+            .enumerate()
+            .map(|(i, doc)| {
+                let rerank_score = rerank_result.rerank_scores.get(i).copied().unwrap_or(0.0);
+                SearchResult {
+                    path: PathBuf::from(doc.metadata.get("path").unwrap_or(&String::new())),
+                    score: rerank_score, // Use rerank score instead of original score
+                    snippet: doc.content,
+                    chunk_type: doc.metadata.get("chunk_type").cloned(),
+                    chunk_name: doc.metadata.get("chunk_name").cloned(),
+                    start_line: doc.metadata.get("start_line").and_then(|s| s.parse().ok()),
+                    end_line: doc.metadata.get("end_line").and_then(|s| s.parse().ok()),
+                    snippet_line: doc
+                        .metadata
+                        .get("snippet_line")
+                        .and_then(|s| s.parse().ok()),
+                    fuzzy: doc.metadata.contains_key("fuzzy"),
+                    duplicates: doc
+                        .metadata
+                        .get("duplicates")
+                        .map(|paths| paths.lines().map(PathBuf::from).collect())
+                        .unwrap_or_default(),
+                }
+            })
+            .collect())
+    }
+
+    /// Reranks like `rerank_results`, but if the model misses the `rerank_timeout_ms`
+    /// deadline, warns and keeps the full-text order and scores of the best `limit` results
+    pub fn rerank_results_or_keep_order(
+        &mut self,
+        query: &str,
+        results: Vec<SearchResult>,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let Some(timeout) = self.timeout else {
+            return self.rerank_results(query, results, limit);
+        };
+        let mut full_text_order = results.clone();
+        match self.rerank_results(query, results, limit) {
+            Err(e) if e.is::<RerankTimeout>() => {
+                eprintln!(
+                    "Warning: reranking took longer than {}ms, showing results in full-text order",
+                    timeout.as_millis()
+                );
+                full_text_order.truncate(limit);
+                Ok(full_text_order)
+            }
+            reranked => reranked,
+        }
+    }
+
     /// Rerank documents based on query relevance
     pub fn rerank(
         &mut self,
@@ -303,23 +669,53 @@ impl Reranker {
             });
         }
 
-        // Extract document content for reranking
-        let doc_contents: Vec<&str> = documents.iter().map(|doc| doc.content.as_str()).collect();
+        // Cut huge documents before tokenizing them, the model only sees their start
+        let max_bytes = self.max_length * MAX_BYTES_PER_TOKEN;
+        let doc_contents: Vec<String> = documents
+            .iter()
+            .map(|doc| truncate_to_char_boundary(&doc.content, max_bytes).to_string())
+            .collect();
 
-        // Perform reranking
-        let rerank_results = self
-            .model
-            .rerank(query, doc_contents, true, None)
-            .context("Failed to rerank documents")?;
+        // The model runs on its own thread, so a hung inference can be abandoned
+        let (sender, receiver) = mpsc::channel();
+        let models = Arc::clone(&self.models);
+        let query_text = query.to_string();
+        let batch_size = self.batch_size;
+        std::thread::spawn(move || {
+            let mut models = models.lock().unwrap_or_else(PoisonError::into_inner);
+            let _ = sender.send(score_documents(
+                &mut models,
+                &query_text,
+                &doc_contents,
+                batch_size,
+            ));
+        });
+        let received = match self.timeout {
+            Some(timeout) => receiver.recv_timeout(timeout),
+            None => receiver.recv().map_err(RecvTimeoutError::from),
+        };
+        let scores = match received {
+            Ok(scores) => scores?,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(RerankTimeout {
+                    timeout: self.timeout.unwrap_or_default(),
+                }
+                .into())
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow::anyhow!("The reranking model stopped unexpectedly"))
+            }
+        };
 
         // Sort documents by rerank score (higher is better)
-        let mut scored_docs: Vec<(RerankDocument, f32)> = documents
-            .into_iter()
-            .zip(rerank_results)
-            .map(|(doc, result)| (doc, result.score))
-            .collect();
+        let mut scored_docs: Vec<(RerankDocument, f32)> =
+            documents.into_iter().zip(scores).collect();
 
-        scored_docs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scored_docs.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.position().cmp(&b.0.position()))
+        });
 
         // Apply limit if specified
         if let Some(limit) = limit {
@@ -335,6 +731,42 @@ impl Reranker {
     }
 }
 
+/// Scores `documents` in batches of `batch_size`, each model scoring a run of
+/// consecutive batches
+fn score_documents(
+    models: &mut [TextRerank],
+    query: &str,
+    documents: &[String],
+    batch_size: usize,
+) -> Result<Vec<f32>> {
+    let doc_contents: Vec<&str> = documents.iter().map(String::as_str).collect();
+    let batches: Vec<&[&str]> = doc_contents.chunks(batch_size).collect();
+    let batches_per_model = batches.len().div_ceil(models.len());
+    Ok(models
+        .par_iter_mut()
+        .zip(batches.par_chunks(batches_per_model))
+        .map(|(model, batches)| score_batches(model, query, batches))
+        .collect::<Result<Vec<_>>>()?
+        .concat())
+}
+
+/// Scores the documents of `batches` with one model call each, in document order
+fn score_batches(model: &mut TextRerank, query: &str, batches: &[&[&str]]) -> Result<Vec<f32>> {
+    let mut scores = Vec::new();
+    for batch in batches {
+        let results = model
+            .rerank(query, batch.to_vec(), false, Some(batch.len()))
+            .context("Failed to rerank documents")?;
+        // Results come back best first
+        let mut batch_scores = vec![0.0; batch.len()];
+        for result in results {
+            batch_scores[result.index] = result.score;
+        }
+        scores.extend(batch_scores);
+    }
+    Ok(scores)
+}
+
 /// Parse reranker model from string
 pub fn parse_reranker_model(model_str: &str) -> Result<RerankerModel> {
     match model_str.to_lowercase().as_str() {
@@ -399,6 +831,103 @@ mod tests {
         assert_eq!(result.rerank_scores.len(), 0);
     }
 
+    #[test]
+    fn test_batched_rerank_matches_single_batch() {
+        let reranker = |probe_config: ProbeConfig| {
+            Reranker::new(RerankerConfig {
+                model: RerankerModel::JINARerankerV1TurboEn,
+                probe_config: Some(probe_config),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        let docs: Vec<RerankDocument> = [
+            "fn parse_config(path: &Path) -> Config",
+            "SELECT * FROM users WHERE id = ?",
+            "fn load_config_file() -> Result<Config>",
+            "body { margin: 0; }",
+            "x".repeat(100_000).as_str(),
+        ]
+        .iter()
+        .map(|content| RerankDocument {
+            content: content.to_string(),
+            metadata: HashMap::new(),
+        })
+        .collect();
+
+        let single = reranker(ProbeConfig::default())
+            .rerank("load config", docs.clone(), None)
+            .unwrap();
+        let batched = reranker(ProbeConfig {
+            rerank_batch_size: Some(2),
+            rerank_parallelism: Some(2),
+            ..Default::default()
+        })
+        .rerank("load config", docs, None)
+        .unwrap();
+
+        let contents = |result: &RerankResult| -> Vec<String> {
+            result
+                .documents
+                .iter()
+                .map(|doc| doc.content.clone())
+                .collect()
+        };
+        assert_eq!(contents(&batched), contents(&single));
+        assert!(contents(&single)[0].contains("config"));
+        for (batched, single) in batched.rerank_scores.iter().zip(&single.rerank_scores) {
+            assert!((batched - single).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_rerank_timeout_keeps_full_text_order() {
+        let mut reranker = Reranker::new(RerankerConfig {
+            model: RerankerModel::JINARerankerV1TurboEn,
+            probe_config: Some(ProbeConfig {
+                rerank_timeout_ms: Some(0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        let results: Vec<_> = [
+            ("Render.java", 3.0),
+            ("Config.java", 2.0),
+            ("Main.java", 1.0),
+        ]
+        .into_iter()
+        .map(|(path, score)| SearchResult {
+            path: PathBuf::from(path),
+            score,
+            snippet: format!("class {}", path.trim_end_matches(".java")),
+            chunk_type: None,
+            chunk_name: None,
+            start_line: Some(0),
+            end_line: Some(0),
+            snippet_line: None,
+            fuzzy: false,
+            duplicates: Vec::new(),
+        })
+        .collect();
+
+        let documents = vec![RerankDocument {
+            content: "fn load_config_file() -> Result<Config>".to_string(),
+            metadata: HashMap::new(),
+        }];
+        let error = reranker.rerank("load config", documents, None).unwrap_err();
+        assert!(error.is::<RerankTimeout>(), "{error}");
+
+        let kept = reranker
+            .rerank_results_or_keep_order("load config", results, 2)
+            .unwrap();
+        let kept: Vec<_> = kept
+            .iter()
+            .map(|result| (result.path.to_str().unwrap(), result.score))
+            .collect();
+        assert_eq!(kept, vec![("Render.java", 3.0), ("Config.java", 2.0)]);
+    }
+
     #[test]
     fn test_custom_model_config() {
         let config = RerankerConfig {
@@ -410,4 +939,142 @@ mod tests {
         assert!(config.custom_model.is_some());
         assert_eq!(config.custom_model.as_ref().unwrap(), "custom/model");
     }
+
+    #[test]
+    fn test_model_file_and_execution_providers() {
+        let config: ProbeConfig = serde_yaml::from_str(
+            "builtin_model_file: onnx/model_quantized.onnx\n\
+             execution_providers: [cuda, cpu]\n\
+             custom_rerankers:\n  \
+               small:\n    \
+                 description: Small\n    \
+                 model_code: org/small\n    \
+                 model_file: model.onnx\n    \
+                 additional_files: []\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.builtin_model_file.as_deref(),
+            Some("onnx/model_quantized.onnx")
+        );
+        assert_eq!(
+            execution_providers(&config.execution_providers)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(config.custom_rerankers["small"]
+            .execution_providers
+            .is_empty());
+        assert!(execution_providers(&["tpu".to_string()]).is_err());
+
+        let builtin = builtin_model_with_file(
+            &RerankerModel::JINARerankerV1TurboEn,
+            "onnx/model_quantized.onnx",
+        )
+        .unwrap();
+        assert_eq!(builtin.model_code, "jinaai/jina-reranker-v1-turbo-en");
+        assert_eq!(builtin.model_file, "onnx/model_quantized.onnx");
+    }
+
+    #[test]
+    fn test_unknown_execution_provider_fails_model_setup() {
+        let probe_config: ProbeConfig = serde_yaml::from_str(
+            "execution_providers: [cpu]\n\
+             custom_rerankers:\n  \
+               small:\n    \
+                 description: Small\n    \
+                 model_code: org/small\n    \
+                 model_file: model.onnx\n    \
+                 additional_files: []\n    \
+                 execution_providers: [tpu]\n",
+        )
+        .unwrap();
+        let mut builtin_config = probe_config.clone();
+        builtin_config.execution_providers = vec!["tpu".to_string()];
+        let mut quantized_config = builtin_config.clone();
+        quantized_config.builtin_model_file = Some("onnx/model_quantized.onnx".to_string());
+
+        // Each way of loading a model resolves its providers before downloading anything
+        for (custom_model, probe_config) in [
+            (Some("small"), probe_config),
+            (None, builtin_config),
+            (None, quantized_config),
+        ] {
+            let config = RerankerConfig {
+                enabled: true,
+                custom_model: custom_model.map(str::to_string),
+                probe_config: Some(probe_config),
+                ..Default::default()
+            };
+            let error = Reranker::new(config).err().unwrap();
+            assert!(
+                error
+                    .to_string()
+                    .contains("Unknown execution provider 'tpu'"),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_candidate_fetch_strategy() {
+        let config = RerankerConfig {
+            candidate_multiplier: 4,
+            score_cutoff: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(config.candidate_limit(2), 10);
+        assert_eq!(config.candidate_limit(5), 20);
+
+        let mut candidates: Vec<_> = [10.0, 6.0, 5.0, 4.9, 8.0]
+            .into_iter()
+            .map(|score| SearchResult {
+                path: PathBuf::from("Foo.java"),
+                score,
+                snippet: String::new(),
+                chunk_type: None,
+                chunk_name: None,
+                start_line: None,
+                end_line: None,
+                snippet_line: None,
+                fuzzy: false,
+                duplicates: Vec::new(),
+            })
+            .collect();
+        config.cut_candidates(&mut candidates);
+        let scores: Vec<_> = candidates.iter().map(|candidate| candidate.score).collect();
+        assert_eq!(scores, vec![10.0, 6.0, 5.0]);
+    }
+
+    #[test]
+    fn test_repo_registry() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path().join("shop");
+        std::fs::create_dir(&repo).unwrap();
+        let mut config = ProbeConfig::default();
+
+        assert_eq!(config.add_repo(&repo, None).unwrap(), "shop");
+        assert_eq!(config.add_repo(&repo, Some("work")).unwrap(), "work");
+        // Re-adding the same path is fine, reusing a name for another path is not
+        assert!(config.add_repo(&repo, None).is_ok());
+        assert!(config.add_repo(temp_dir.path(), Some("shop")).is_err());
+        assert!(config
+            .add_repo(&temp_dir.path().join("missing"), None)
+            .is_err());
+
+        // Survives a round trip through the config file
+        let config_path = temp_dir.path().join("config").join("config.yaml");
+        config.save_to_file(Some(&config_path)).unwrap();
+        let mut config = ProbeConfig::load_from_file(Some(&config_path)).unwrap();
+        assert_eq!(
+            config.repo_path("shop").unwrap(),
+            repo.canonicalize().unwrap()
+        );
+
+        config.remove_repo("shop").unwrap();
+        assert!(config.repo_path("shop").is_err());
+        assert!(config.remove_repo("shop").is_err());
+        assert_eq!(config.repos.len(), 1);
+    }
 }
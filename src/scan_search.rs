@@ -0,0 +1,219 @@
+use crate::code_chunker::{read_source, CodeChunker};
+use crate::config::ChunkingConfig;
+use crate::file_scanner::IndexedFile;
+use crate::language_processor::{ChunkType, CodeChunk};
+use crate::search_index::{
+    apply_highlights, camel_case_analyzer, extract_path_terms, index_path, skip_reason,
+    truncate_snippet, PathFilter, ScoreRules, SearchOptions, SearchResult,
+};
+use anyhow::Result;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use tantivy::tokenizer::TextAnalyzer;
+
+/// Searches `files` directly, chunking each one the way indexing would and scoring chunks by
+/// how often and how many of the query terms they contain. Terms are matched without
+/// stemming. Used when there is no index to search, so it's slower but needs no setup.
+pub fn scan_search(
+    files: &[IndexedFile],
+    chunking: &ChunkingConfig,
+    score_rules: &ScoreRules,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<SearchResult>> {
+    let (query, path_terms) = extract_path_terms(query);
+    let terms = tokenize(&mut camel_case_analyzer(None), &query)
+        .into_iter()
+        .map(|(term, _)| term)
+        .collect::<HashSet<_>>();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+    // Surface configuration errors instead of silently skipping every file
+    CodeChunker::with_config(chunking)?;
+
+    let max_snippet_bytes = options.max_candidate_bytes / options.limit.max(1);
+    let path_filter = PathFilter::new(options)?.with_terms(&path_terms)?;
+    let mut results: Vec<SearchResult> = files
+        .par_iter()
+        .filter(|file| {
+            options.filetype.as_deref().is_none_or(|filetype| {
+                file.relative_path
+                    .extension()
+                    .is_some_and(|ext| ext == filetype)
+            })
+        })
+        .filter(|file| path_filter.matches(&index_path(&file.relative_path)))
+        .map_init(
+            || {
+                (
+                    CodeChunker::with_config(chunking).ok(),
+                    camel_case_analyzer(None),
+                )
+            },
+            |(chunker, analyzer), file| match chunker {
+                Some(chunker) => search_file(chunker, analyzer, file, &terms, score_rules, options),
+                None => Vec::new(),
+            },
+        )
+        .flatten()
+        .filter(|result| {
+            path_filter.matches_lines(
+                &index_path(&result.path),
+                result.start_line.unwrap_or(0),
+                result.end_line.unwrap_or(0),
+            )
+        })
+        .collect();
+
+    results.sort_by(SearchResult::rank_order);
+    results.drain(..options.offset.min(results.len()));
+    results.truncate(options.limit);
+    for result in &mut results {
+        truncate_snippet(&mut result.snippet, max_snippet_bytes);
+    }
+    Ok(results)
+}
+
+fn search_file(
+    chunker: &mut CodeChunker,
+    analyzer: &mut TextAnalyzer,
+    file: &IndexedFile,
+    terms: &HashSet<String>,
+    score_rules: &ScoreRules,
+    options: &SearchOptions,
+) -> Vec<SearchResult> {
+    let Ok(content) = read_source(&file.disk_path) else {
+        return Vec::new();
+    };
+    if skip_reason(&content).is_some() {
+        return Vec::new();
+    }
+    let mut chunks = chunker
+        .chunk_code_for_indexing(&file.relative_path, &content)
+        .unwrap_or_default();
+    let whole_file = chunks.is_empty();
+    if whole_file {
+        chunks.push(CodeChunk {
+            start_line: 0,
+            end_line: content.lines().count().saturating_sub(1),
+            chunk_type: ChunkType::Other,
+            name: String::new(),
+            content: content.clone(),
+            declaration: String::new(),
+            doc: String::new(),
+        });
+    }
+
+    let path = file.relative_path.to_string_lossy();
+    chunks
+        .into_iter()
+        .filter_map(|chunk| {
+            let chunk_type = if whole_file {
+                "file".to_string()
+            } else {
+                format!("{:?}", chunk.chunk_type)
+            };
+            if !options.kinds.is_empty() && !options.kinds.contains(&chunk_type.to_lowercase()) {
+                return None;
+            }
+
+            let text = format!("{}{}", chunk.declaration, chunk.content);
+            let tokens = tokenize(analyzer, &text);
+            let mut frequencies: HashMap<&str, usize> = HashMap::new();
+            for (token, _) in &tokens {
+                if terms.contains(token) {
+                    *frequencies.entry(token.as_str()).or_default() += 1;
+                }
+            }
+            if frequencies.is_empty() {
+                return None;
+            }
+
+            // Every matched term counts with diminishing returns, and chunks matching more
+            // of the query rank above ones repeating a single term
+            let coverage = frequencies.len() as f32 / terms.len() as f32;
+            let score: f32 = frequencies
+                .values()
+                .map(|&count| 1.0 + (count as f32).ln())
+                .sum::<f32>()
+                * coverage;
+
+            let (snippet, snippet_line) =
+                if matches!(chunk.chunk_type, ChunkType::Function | ChunkType::Method) {
+                    // Enclosing declarations above the chunk's own lines
+                    let header_lines = (text.matches('\n').count() + 1)
+                        .saturating_sub(chunk.end_line + 1 - chunk.start_line);
+                    (
+                        highlight(&text, &tokens, terms),
+                        Some(chunk.start_line.saturating_sub(header_lines)),
+                    )
+                } else {
+                    let (snippet, line) =
+                        context_snippet(analyzer, &text, &tokens, terms, options.context_lines);
+                    let excerpt = matches!(chunk.chunk_type, ChunkType::Other);
+                    (snippet, excerpt.then_some(chunk.start_line + line))
+                };
+
+            Some(SearchResult {
+                path: file.relative_path.clone(),
+                score: score_rules.apply(score, &path, Some(&chunk_type)),
+                snippet,
+                chunk_name: Some(chunk.name),
+                chunk_type: Some(chunk_type),
+                start_line: Some(chunk.start_line),
+                end_line: Some(chunk.end_line),
+                snippet_line,
+                fuzzy: false,
+                duplicates: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Lowercased word tokens of `text` with their byte ranges, leaving out the whitespace and
+/// punctuation runs the analyzer also emits
+fn tokenize(analyzer: &mut TextAnalyzer, text: &str) -> Vec<(String, Range<usize>)> {
+    let mut tokens = Vec::new();
+    let mut stream = analyzer.token_stream(text);
+    while let Some(token) = stream.next() {
+        if token.text.chars().any(char::is_alphanumeric) {
+            tokens.push((token.text.clone(), token.offset_from..token.offset_to));
+        }
+    }
+    tokens
+}
+
+fn highlight(text: &str, tokens: &[(String, Range<usize>)], terms: &HashSet<String>) -> String {
+    apply_highlights(
+        text,
+        tokens
+            .iter()
+            .filter(|(token, _)| terms.contains(token))
+            .map(|(_, range)| range.clone()),
+    )
+}
+
+/// The lines around the first match, with `context_lines` lines before and after it, and the
+/// line of `text` they start on
+fn context_snippet(
+    analyzer: &mut TextAnalyzer,
+    text: &str,
+    tokens: &[(String, Range<usize>)],
+    terms: &HashSet<String>,
+    context_lines: usize,
+) -> (String, usize) {
+    let first_match = tokens
+        .iter()
+        .find(|(token, _)| terms.contains(token))
+        .map_or(0, |(_, range)| range.start);
+    let match_line = text[..first_match].matches('\n').count();
+
+    let lines: Vec<&str> = text.lines().collect();
+    let start = match_line.saturating_sub(context_lines);
+    let end = (match_line + context_lines + 1).min(lines.len());
+    let segment = lines[start..end].join("\n");
+    let segment_tokens = tokenize(analyzer, &segment);
+    (highlight(&segment, &segment_tokens, terms), start)
+}
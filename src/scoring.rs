@@ -0,0 +1,143 @@
+use crate::config::FusionStrategy;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Dampens the weight of the top ranks in reciprocal rank fusion, 60 as in the original paper
+const RRF_K: f32 = 60.0;
+
+/// Scales `scores` linearly to [0, 1], the best to 1 and the worst to 0, so scores on
+/// different scales (BM25, reranker logits, cosine similarities) can be compared and
+/// combined. When all scores are equal they become 1; scores that aren't finite become 0.
+pub fn normalize(scores: &[f32]) -> Vec<f32> {
+    let finite = || scores.iter().copied().filter(|score| score.is_finite());
+    let (Some(min), Some(max)) = (finite().reduce(f32::min), finite().reduce(f32::max)) else {
+        return vec![0.0; scores.len()];
+    };
+    scores
+        .iter()
+        .map(|&score| {
+            if !score.is_finite() {
+                0.0
+            } else if max > min {
+                (score - min) / (max - min)
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+/// Combines rankings of the same kind of items, each a list of items with their scores,
+/// best first, into one ranking with scores in [0, 1]:
+///
+/// - `Rrf` sums `(60 + 1) / (60 + rank)` over the rankings, so being first in all of them
+///   scores 1
+/// - `WeightedSum` sums the normalized scores
+/// - `Max` takes the best normalized score, ignoring the weights
+///
+/// The `weights` are the shares of the rankings, scaled to sum to 1, and missing weights
+/// count as 1. An item missing from a ranking scores 0 in it. Returns the items best first,
+/// ties in order of first appearance.
+pub fn fuse<K: Clone + Eq + Hash>(
+    rankings: &[Vec<(K, f32)>],
+    weights: &[f32],
+    strategy: FusionStrategy,
+) -> Vec<(K, f32)> {
+    let weights: Vec<f32> = (0..rankings.len())
+        .map(|i| weights.get(i).copied().unwrap_or(1.0).max(0.0))
+        .collect();
+    let total_weight: f32 = weights.iter().sum();
+
+    let mut scores: HashMap<K, (f32, usize)> = HashMap::new();
+    let mut order = 0;
+    for (ranking, weight) in rankings.iter().zip(&weights) {
+        let share = if total_weight > 0.0 {
+            weight / total_weight
+        } else {
+            1.0 / rankings.len() as f32
+        };
+        let normalized = normalize(&ranking.iter().map(|(_, score)| *score).collect::<Vec<_>>());
+        for (rank, ((item, _), normalized)) in ranking.iter().zip(normalized).enumerate() {
+            let entry = scores.entry(item.clone()).or_insert_with(|| {
+                order += 1;
+                (0.0, order)
+            });
+            let reciprocal_rank = (RRF_K + 1.0) / (RRF_K + rank as f32 + 1.0);
+            match strategy {
+                FusionStrategy::Rrf => entry.0 += share * reciprocal_rank,
+                FusionStrategy::WeightedSum => entry.0 += share * normalized,
+                FusionStrategy::Max => entry.0 = entry.0.max(normalized),
+            }
+        }
+    }
+
+    let mut fused: Vec<_> = scores.into_iter().collect();
+    fused.sort_by(|a, b| b.1 .0.total_cmp(&a.1 .0).then(a.1 .1.cmp(&b.1 .1)));
+    fused
+        .into_iter()
+        .map(|(item, (score, _))| (item, score.min(1.0)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(fused: &[(&'static str, f32)]) -> Vec<&'static str> {
+        fused.iter().map(|(item, _)| *item).collect()
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(&[12.0, 8.0, 4.0]), vec![1.0, 0.5, 0.0]);
+        // Reranker logits are often negative
+        assert_eq!(normalize(&[-1.0, -3.0]), vec![1.0, 0.0]);
+        assert_eq!(normalize(&[2.5, 2.5]), vec![1.0, 1.0]);
+        assert_eq!(normalize(&[f32::NAN, 3.0, 1.0]), vec![0.0, 1.0, 0.0]);
+        assert!(normalize(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_fuse() {
+        // BM25 scores and reranker logits on very different scales
+        let full_text = vec![("a", 20.0), ("b", 18.0), ("c", 2.0)];
+        let reranked = vec![("c", 4.0), ("a", -2.0), ("b", -6.0)];
+        let rankings = [full_text, reranked];
+
+        let fused = fuse(&rankings, &[0.5, 0.5], FusionStrategy::WeightedSum);
+        assert_eq!(items(&fused), vec!["a", "c", "b"]);
+        assert!((fused[0].1 - (0.5 + 0.5 * 0.4)).abs() < 1e-6);
+
+        // The reranker decides when it gets all the weight
+        let fused = fuse(&rankings, &[0.0, 1.0], FusionStrategy::WeightedSum);
+        assert_eq!(items(&fused), vec!["c", "a", "b"]);
+        assert_eq!(fused[0].1, 1.0);
+
+        let fused = fuse(&rankings, &[0.5, 0.5], FusionStrategy::Max);
+        assert_eq!(items(&fused), vec!["a", "c", "b"]);
+        assert!(fused.iter().take(2).all(|(_, score)| *score == 1.0));
+
+        // First and second place beats first and third
+        let fused = fuse(&rankings, &[], FusionStrategy::Rrf);
+        assert_eq!(items(&fused), vec!["a", "c", "b"]);
+        assert!(fused.iter().all(|(_, score)| (0.0..=1.0).contains(score)));
+        let first_everywhere = fuse(
+            &[vec![("a", 1.0)], vec![("a", 5.0)]],
+            &[],
+            FusionStrategy::Rrf,
+        );
+        assert!((first_everywhere[0].1 - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fuse_items_missing_from_a_ranking() {
+        let fused = fuse(
+            &[vec![("a", 3.0), ("b", 1.0)], vec![("c", 0.9)]],
+            &[0.7, 0.3],
+            FusionStrategy::WeightedSum,
+        );
+        assert_eq!(items(&fused), vec!["a", "c", "b"]);
+        assert!((fused[1].1 - 0.3).abs() < 1e-6);
+        assert_eq!(fused[2].1, 0.0);
+    }
+}
@@ -1,106 +1,1383 @@
-use crate::config::Config;
-use crate::file_scanner::FileScanner;
-use crate::metadata::IndexMetadata;
+use crate::code_chunker::{read_source, CodeChunker};
+use crate::config::{is_profile_name, Config, FusionConfig, FusionStrategy, StemmingConfig};
+#[cfg(unix)]
+use crate::daemon;
+use crate::dupes::{find_duplicates, ChunkLocation, DupesOptions, DuplicateCluster};
+use crate::embeddings::{parse_embedding_model, Embedder, VectorStore, VECTORS_FILE};
+use crate::file_scanner::{FileScanner, IndexedFile};
+use crate::git;
+use crate::graph::CallGraph;
+use crate::history::HISTORY_FILE;
+use crate::index_archive::{
+    fetch_archive, hash_files, read_archive, resolve_source, rewrite_path, write_archive,
+    write_checksum, Manifest, PrefixRewrite,
+};
+use crate::index_lock::IndexLock;
+use crate::language_processor::ChunkType;
+use crate::lsp;
+use crate::metadata::{summarize_skipped, IndexMetadata, SkippedFiles};
+use crate::outline::{build_outline, OutlineItem};
+use crate::paths::{directory_size, path_key, write_atomic};
+use crate::query_expansion::QueryExpansion;
 use crate::reranker::{RerankDocument, Reranker, RerankerConfig};
-use crate::search_index::{SearchIndex, SearchResult};
+use crate::scan_search::scan_search;
+use crate::scoring;
+use crate::search_index::{
+    group_by_file, index_path, is_indexable, ChunkCounts, ChunkExplanation, LanguageRules,
+    ScoreRules, SearchIndex, SearchOptions, SearchResult, SegmentStats, SkipReason, SkippedFile,
+    SymbolMatch,
+};
+use crate::server;
+use crate::terminal;
+use crate::verbosity::Verbosity;
+use crate::watch::{watcher_active, Heartbeat, WatchedTree, DEBOUNCE, HEARTBEAT_INTERVAL};
 use anyhow::Result;
-use std::collections::HashMap;
+use notify::{RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Instant, SystemTime};
+use tantivy::tokenizer::Language;
+
+/// Written into the staging directory when a rebuild is interrupted, so that
+/// `probe rebuild --resume` can pick up the files that weren't indexed yet
+const RESUME_CHECKPOINT: &str = "resume.json";
+/// Skipped files listed by `probe stats`
+const LARGEST_SKIPPED_SHOWN: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResumeCheckpoint {
+    root_dir: PathBuf,
+    indexed_files: usize,
+}
+
+/// Differences between the index and a fresh chunking pass over the working tree
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Files whose indexed chunks no longer match their current contents
+    pub stale: Vec<StaleFile>,
+    /// Files that would be indexed but have no documents in the index
+    pub missing: Vec<PathBuf>,
+    /// Indexed paths that are no longer part of the working tree
+    pub orphaned: Vec<PathBuf>,
+}
+
+/// File counts shown by `probe stats`
+#[derive(Debug)]
+pub struct IndexStats {
+    /// Files with documents in the index
+    pub files: usize,
+    /// Files left out of the index, e.g. because they are too large
+    pub skipped_files: usize,
+    /// Git commit checked out when the index was last updated
+    pub commit: Option<String>,
+    /// Commits made on top of `commit` since, `None` if unknown
+    pub commits_behind: Option<usize>,
+    pub index_dir: PathBuf,
+}
+
+/// What the index holds and how it is stored, shown by `probe stats`
+#[derive(Debug)]
+pub struct IndexHealth {
+    pub chunks: ChunkCounts,
+    pub segments: SegmentStats,
+    /// Total size of the index directory in bytes
+    pub index_bytes: u64,
+    /// When the index was last updated
+    pub indexed_at: Option<SystemTime>,
+    /// The largest skipped files still in the working tree with their size, largest first
+    pub largest_skipped: Vec<(PathBuf, u64)>,
+}
+
+/// Segments and size of the index before and after `probe optimize`
+#[derive(Debug)]
+pub struct OptimizeReport {
+    pub before: SegmentStats,
+    pub after: SegmentStats,
+    /// Total size of the index directory in bytes
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Timings measured by `probe bench`, in milliseconds, with the settings they were taken with
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub files: usize,
+    pub threads: usize,
+    /// Stemmer language, `None` with stemming disabled
+    pub stemming: Option<String>,
+    pub writer_heap_mb: usize,
+    pub commit_batch_size: usize,
+    pub rebuild_ms: f64,
+    /// Scanning the tree and finding nothing changed, which every search does first
+    pub update_ms: f64,
+    /// First run of the first query
+    pub cold_query_ms: f64,
+    /// Median and 95th percentile of the remaining runs of every query
+    pub warm_query_ms: f64,
+    pub warm_query_p95_ms: f64,
+    /// Loading the reranking model, `None` with reranking disabled
+    pub reranker_load_ms: Option<f64>,
+    /// Median time to rerank the candidates of a query
+    pub rerank_ms: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct StaleFile {
+    pub path: PathBuf,
+    pub indexed_chunks: usize,
+    pub source_chunks: usize,
+}
+
+/// Answer to `probe why`: whether a file is indexed and how its chunks score for a query
+#[derive(Debug)]
+pub struct WhyReport {
+    pub relative_path: PathBuf,
+    pub status: IndexStatus,
+    /// The file's chunks, or the ones containing the requested line
+    pub chunks: Vec<ChunkExplanation>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IndexStatus {
+    Indexed,
+    /// The scanner doesn't pick the file up, e.g. because it is ignored or doesn't exist
+    NotScanned,
+    Skipped(SkipReason),
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.stale.is_empty() && self.missing.is_empty() && self.orphaned.is_empty()
+    }
+}
 
 pub struct SearchEngine {
     root_dir: PathBuf,
     index_dir: PathBuf,
     metadata_path: PathBuf,
     config: Config,
+    /// Profile whose index this engine uses, `None` for the main index
+    profile: Option<String>,
+    verbosity: Verbosity,
+    interrupt: Arc<AtomicBool>,
+    threads: usize,
+    /// Used by `search`; the other search methods take the reranker config per call
+    reranker_config: RerankerConfig,
+    /// Model for `reranker_config`, loaded by the first reranked `search` and kept for
+    /// later ones
+    reranker: Mutex<Option<Reranker>>,
 }
 
-impl SearchEngine {
-    pub fn new<P: AsRef<Path>>(root_dir: P) -> Result<Self> {
-        let root_path = root_dir.as_ref().to_path_buf();
-        let index_dir = root_path.join(".probe");
+/// Configures a `SearchEngine` for use as a library. `SearchEngine::new` reads probe.yml
+/// from the root directory and keeps the index in `.probe`; the builder lets callers
+/// supply the configuration themselves and override where and how the index is built.
+pub struct SearchEngineBuilder {
+    root_dir: PathBuf,
+    config: Option<Config>,
+    index_dir: Option<PathBuf>,
+    profile: Option<String>,
+    stemming: Option<StemmingConfig>,
+    ignore_globs: Vec<String>,
+    threads: Option<usize>,
+    reranker_config: RerankerConfig,
+    verbosity: Verbosity,
+    interrupt: Arc<AtomicBool>,
+}
+
+impl SearchEngineBuilder {
+    pub fn new<P: AsRef<Path>>(root_dir: P) -> Self {
+        Self {
+            root_dir: root_dir.as_ref().to_path_buf(),
+            config: None,
+            index_dir: None,
+            profile: None,
+            stemming: None,
+            ignore_globs: Vec::new(),
+            threads: None,
+            reranker_config: RerankerConfig::default(),
+            verbosity: Verbosity::default(),
+            interrupt: Arc::default(),
+        }
+    }
+
+    /// Uses `config` instead of loading probe.yml from the root directory
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Stores the index (and its metadata) in `index_dir` instead of the configured one.
+    /// The index of a profile goes into a directory inside it.
+    pub fn with_index_dir<P: AsRef<Path>>(mut self, index_dir: P) -> Self {
+        self.index_dir = Some(index_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Uses the index of a profile from the configuration's `profiles`, with its file
+    /// filters and stemming, kept in a directory named after it inside the index directory
+    pub fn with_profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// Replaces the stemming settings of the configuration
+    pub fn with_stemming(mut self, stemming: StemmingConfig) -> Self {
+        self.stemming = Some(stemming);
+        self
+    }
+
+    /// Excludes files matching these gitignore-style globs, in addition to the ones in the
+    /// configuration's `index.ignore`
+    pub fn with_ignore_globs<I, S>(mut self, globs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.ignore_globs.extend(globs.into_iter().map(Into::into));
+        self
+    }
+
+    /// Number of threads chunking files while indexing, instead of the configuration's
+    /// `index.threads` (default: the number of CPUs)
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Reranker settings used by `SearchEngine::search`
+    pub fn with_reranker_config(mut self, reranker_config: RerankerConfig) -> Self {
+        self.reranker_config = reranker_config;
+        self
+    }
+
+    /// Sets how much diagnostic output indexing and searching print
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Stops indexing early once `interrupt` is set, keeping the files indexed so far
+    pub fn with_interrupt_flag(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    pub fn build(self) -> Result<SearchEngine> {
+        let root_path = self.root_dir;
+        let mut config = match self.config {
+            Some(config) => config,
+            None => Config::load_from_dir(&root_path)?,
+        };
+        if let Some(profile) = &self.profile {
+            config = config.for_profile(profile)?;
+        }
+        if let Some(stemming) = self.stemming {
+            config.stemming = stemming;
+        }
+        config.index.ignore.extend(self.ignore_globs);
+
+        let index_dir = match (self.index_dir, &config.index.dir) {
+            (Some(dir), _) => dir,
+            (None, Some(dir)) => root_path.join(dir),
+            (None, None) => root_path.join(".probe"),
+        };
+        let index_dir = match &self.profile {
+            Some(profile) => index_dir.join(profile),
+            None => index_dir,
+        };
         let metadata_path = index_dir.join("metadata.bin");
-        let config = Config::load_from_dir(&root_path)?;
+        let threads = self.threads.unwrap_or_else(|| config.index.threads());
+        // Validate the configuration up front instead of failing at index time
+        CodeChunker::with_config(&config.chunking)?;
+        LanguageRules::from_config(&config.stemming)?;
+        ScoreRules::from_config(&config.scoring)?;
+        parse_embedding_model(config.embeddings.model_name())?;
+        FileScanner::new(&root_path)
+            .with_ignore_globs(&config.index.ignore)?
+            .with_include_globs(&config.index.include)?;
 
-        Ok(Self {
+        Ok(SearchEngine {
             root_dir: root_path,
             index_dir,
             metadata_path,
             config,
+            profile: self.profile,
+            verbosity: self.verbosity,
+            interrupt: self.interrupt,
+            threads,
+            reranker_config: self.reranker_config,
+            reranker: Mutex::new(None),
+        })
+    }
+}
+
+impl SearchEngine {
+    /// An engine for the project in `root_dir`, configured by its probe.yml
+    pub fn new<P: AsRef<Path>>(root_dir: P) -> Result<Self> {
+        Self::builder(root_dir).build()
+    }
+
+    pub fn builder<P: AsRef<Path>>(root_dir: P) -> SearchEngineBuilder {
+        SearchEngineBuilder::new(root_dir)
+    }
+
+    /// Stores the index (and its metadata) in `index_dir` instead of `<root>/.probe`
+    pub fn with_index_dir<P: AsRef<Path>>(mut self, index_dir: P) -> Self {
+        self.index_dir = index_dir.as_ref().to_path_buf();
+        self.metadata_path = self.index_dir.join("metadata.bin");
+        self
+    }
+
+    /// Sets how much diagnostic output indexing and searching print
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Stops indexing early once `interrupt` is set (e.g. by a Ctrl-C handler), keeping
+    /// the files indexed so far
+    pub fn with_interrupt_flag(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    /// Sets the reranker config used by `search`
+    pub fn with_reranker_config(mut self, reranker_config: RerankerConfig) -> Self {
+        self.reranker_config = reranker_config;
+        self.reranker = Mutex::new(None);
+        self
+    }
+
+    fn interrupted(&self) -> bool {
+        self.interrupt.load(Ordering::Relaxed)
+    }
+
+    /// Prints a progress/status message to stderr unless running in quiet mode, so it never
+    /// mixes with results on stdout
+    fn status(&self, message: impl std::fmt::Display) {
+        if !self.verbosity.is_quiet() {
+            eprintln!("{message}");
+        }
+    }
+
+    /// Empty index metadata that compares files the way probe.yml asks for
+    fn new_metadata(&self) -> IndexMetadata {
+        IndexMetadata::new().with_change_detection(self.config.index.change_detection)
+    }
+
+    fn load_metadata(&self, path: &Path) -> Result<IndexMetadata> {
+        Ok(IndexMetadata::load(path)?.with_change_detection(self.config.index.change_detection))
+    }
+
+    fn open_index(&self) -> Result<SearchIndex> {
+        self.open_index_in(&self.index_dir)
+    }
+
+    /// The index, stemming queries for `--stem-language` when `options` ask for it
+    fn open_index_for(&self, options: &SearchOptions) -> Result<SearchIndex> {
+        match options.stem_language {
+            Some(language) => self.open_index_with_language(language),
+            None => self.open_index(),
+        }
+    }
+
+    /// The index set up like `open_index`, but with the query analyzer of `language`
+    fn open_index_with_language(&self, language: Language) -> Result<SearchIndex> {
+        let index = SearchIndex::open(&self.index_dir, language, true)?;
+        self.configure_index(index, LanguageRules::from_config(&self.config.stemming)?)
+    }
+
+    fn open_index_in(&self, dir: &Path) -> Result<SearchIndex> {
+        let language = self.config.get_language()?;
+        let index = SearchIndex::open(dir, language, self.config.stemming.enabled)?;
+        self.configure_index(index, LanguageRules::from_config(&self.config.stemming)?)
+    }
+
+    /// Applies the settings of probe.yml and this engine to an opened or created index
+    fn configure_index(
+        &self,
+        index: SearchIndex,
+        language_rules: LanguageRules,
+    ) -> Result<SearchIndex> {
+        Ok(index
+            .with_language_rules(language_rules)
+            .with_score_rules(self.score_rules()?)
+            .with_query_expansion(QueryExpansion::from_config(&self.config.query))
+            .with_chunking_config(self.config.chunking.clone())
+            .with_duplicates_config(self.config.duplicates.clone())
+            .with_writer_heap(self.config.index.writer_heap_bytes())
+            .with_commit_batch_size(self.config.index.commit_batch_size())
+            .with_memory_limit(self.config.index.memory_limit_bytes())
+            .with_verbosity(self.verbosity)
+            .with_interrupt_flag(self.interrupt.clone()))
+    }
+
+    /// Scoring from probe.yml, with the ages of recently changed files when the recency
+    /// boost is on
+    fn score_rules(&self) -> Result<ScoreRules> {
+        let rules = ScoreRules::from_config(&self.config.scoring)?;
+        let Some(half_life) = rules.recency_half_life_days() else {
+            return Ok(rules);
+        };
+        let now = SystemTime::now();
+        let days_since = |time: SystemTime| {
+            now.duration_since(time).unwrap_or_default().as_secs_f32() / 86_400.0
+        };
+
+        // Commit times survive clones and checkouts, which reset modification times. Files
+        // untouched for ten half-lives would get a negligible boost and are left out.
+        let changed = if git::head_commit(&self.root_dir).is_some() {
+            let window = (half_life * 10.0).ceil() as u64;
+            git::last_changed(&self.root_dir, window)?
+        } else {
+            self.load_metadata(&self.metadata_path)?
+                .modified_times()
+                .map(|(path, modified)| (path.clone(), modified))
+                .collect()
+        };
+        let ages = changed
+            .into_iter()
+            .map(|(path, time)| (index_path(&path), days_since(time)))
+            .collect();
+        Ok(rules.with_file_ages(ages))
+    }
+
+    fn create_index(&self) -> Result<SearchIndex> {
+        self.create_index_in(&self.index_dir)
+    }
+
+    fn create_index_in(&self, dir: &Path) -> Result<SearchIndex> {
+        let language = self.config.get_language()?;
+        let language_rules = LanguageRules::from_config(&self.config.stemming)?;
+        let index = SearchIndex::with_languages(
+            dir,
+            language,
+            self.config.stemming.enabled,
+            &language_rules.languages(),
+        )?;
+        self.configure_index(index, language_rules)
+    }
+
+    /// Whether probe.yml references stemmer languages the existing index has no fields for
+    fn index_missing_languages(&self) -> Result<bool> {
+        let Ok(index) = self.open_index() else {
+            return Ok(false);
+        };
+        let languages = LanguageRules::from_config(&self.config.stemming)?.languages();
+        Ok(!index.supports_languages(&languages))
+    }
+
+    /// Whether the existing index was built before documents could be deleted by path
+    fn index_lacks_deletes(&self) -> bool {
+        self.open_index()
+            .is_ok_and(|index| !index.supports_deletes())
+    }
+
+    /// The index directory, or for a profile the main index directory its index lives in
+    fn main_index_dir(&self) -> &Path {
+        match (&self.profile, self.index_dir.parent()) {
+            (Some(_), Some(parent)) => parent,
+            _ => &self.index_dir,
+        }
+    }
+
+    /// Directory next to the main index holding the lock shared by it and its profiles,
+    /// since rebuilding the main index moves theirs too
+    fn lock_dir(&self) -> PathBuf {
+        let main_index_dir = self.main_index_dir();
+        let mut name = main_index_dir
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| ".probe".into());
+        name.push(".lock");
+        main_index_dir.with_file_name(name)
+    }
+
+    /// Takes the index lock for replacing the index, failing if another process is already
+    /// replacing it
+    fn lock_for_rebuild(&self) -> Result<IndexLock> {
+        let lock_dir = self.lock_dir();
+        IndexLock::for_rebuild(&lock_dir)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "The index in {} is being rebuilt by another probe process{}, try again when it finishes",
+                self.index_dir.display(),
+                Self::lock_holder(&lock_dir)
+            )
         })
     }
 
-    pub fn ensure_index_updated(&self) -> Result<()> {
-        let scanner = FileScanner::new(&self.root_dir);
-        let files_iter = scanner.iter_indexed_files();
-        let files: Vec<_> = files_iter.collect::<Vec<_>>();
+    /// ` (pid N)` for the process holding the index lock in `lock_dir`, if known
+    fn lock_holder(lock_dir: &Path) -> String {
+        IndexLock::holder(lock_dir).map_or(String::new(), |pid| format!(" (pid {pid})"))
+    }
+
+    fn scanner(&self) -> Result<FileScanner> {
+        // A profile's index lives inside the main one, which must not be indexed either
+        Ok(FileScanner::new(&self.root_dir)
+            .with_ignore_globs(&self.config.index.ignore)?
+            .with_include_globs(&self.config.index.include)?
+            .with_excluded_dir(self.main_index_dir())
+            .with_excluded_dir(self.lock_dir())
+            .with_excluded_dir(self.sibling_dir("rebuild"))
+            .with_excluded_dir(self.sibling_dir("old"))
+            .with_excluded_dir(self.sibling_dir("corrupt"))
+            .with_excluded_dir(self.sibling_dir("import"))
+            .with_excluded_dir(self.sibling_dir("download"))
+            .with_follow_symlinks(self.config.index.follow_symlinks)
+            .with_git_files_only(self.config.index.git_files_only))
+    }
+
+    pub fn ensure_index_updated(&self) -> Result<()> {
+        if watcher_active(&self.index_dir) {
+            if self.verbosity.is_verbose() {
+                eprintln!("The index is kept up to date by `probe watch`, skipping the scan");
+            }
+            return Ok(());
+        }
+
+        let lock_dir = self.lock_dir();
+        if IndexLock::rebuild_in_progress(&lock_dir)? {
+            if !self.index_dir.join("meta.json").exists() {
+                return Err(anyhow::anyhow!(
+                    "The index in {} is being built by another probe process{}, try again when it finishes",
+                    self.index_dir.display(),
+                    Self::lock_holder(&lock_dir)
+                ));
+            }
+            self.status(
+                "Index is being rebuilt by another process, searching the last complete index.",
+            );
+            return Ok(());
+        }
+
+        if let Some(remote) = &self.config.index.remote {
+            let has_index =
+                self.index_dir.join("meta.json").exists() || self.metadata_path.exists();
+            if !has_index {
+                if let Err(e) = self.fetch_remote_index(remote) {
+                    eprintln!("Warning: failed to fetch the prebuilt index from {remote}: {e}. Indexing locally instead.");
+                }
+            }
+        }
+
+        if let Some(problem) = self.detect_corruption() {
+            let _lock = self.lock_for_rebuild()?;
+            self.quarantine_index(&problem)?;
+            return self.rebuild_holding_lock();
+        }
+
+        if IndexMetadata::load(&self.metadata_path)?.needs_relative_path_migration() {
+            return self.rebuild_index();
+        }
+        if self.index_missing_languages()? {
+            self.status("Stemming overrides added new languages, rebuilding index...");
+            return self.rebuild_index();
+        }
+        if self.index_lacks_deletes() {
+            self.status(
+                "Rebuilding index so that edited and deleted files can be removed from it...",
+            );
+            return self.rebuild_index();
+        }
+
+        // Rebuilds stream the files from the scanner, an update needs all of them to find the
+        // deleted ones
+        let started = Instant::now();
+        let files: Vec<_> = self.scanner()?.iter_indexed_files().collect();
+        if self.verbosity.is_verbose() {
+            eprintln!("Scanned {} files in {:.2?}", files.len(), started.elapsed());
+        }
+
+        self.apply_changes(&files, &files, started)?;
+        Ok(())
+    }
+
+    /// Indexes the files among `candidates` that changed since they were last indexed and
+    /// drops files that are no longer part of `files`, the complete list of files to index.
+    /// Returns false if another process holds the index lock or is rebuilding the index, so
+    /// nothing was indexed.
+    fn apply_changes(
+        &self,
+        files: &[IndexedFile],
+        candidates: &[IndexedFile],
+        started: Instant,
+    ) -> Result<bool> {
+        // Held until the update is saved, so a rebuild can't swap the index out meanwhile
+        let Some(_lock) = IndexLock::for_update(&self.lock_dir())? else {
+            self.status(
+                "Index is being rebuilt by another process, searching the last complete index.",
+            );
+            return Ok(false);
+        };
+        let mut metadata = self.load_metadata(&self.metadata_path)?;
+        metadata.set_commit(git::head_commit(&self.root_dir));
+        let removed_files = metadata.prune(files);
+        let changed_files = metadata.needs_reindex(candidates)?;
+        if removed_files.is_empty() && changed_files.is_empty() {
+            if metadata.is_dirty() {
+                // A new commit, or touched but unchanged files whose hashes shouldn't be
+                // checked again
+                metadata.save(&self.metadata_path)?;
+            }
+            return Ok(true);
+        }
+
+        let mut index = match self.open_index() {
+            Ok(index) => index,
+            Err(_) => self.create_index()?,
+        };
+
+        if !removed_files.is_empty() {
+            match index.delete_files(&removed_files) {
+                Ok(()) => {}
+                Err(e) if Self::is_lock_failure(&e) => {
+                    // Metadata isn't saved, so the files are removed again next time
+                    self.status("Index is being updated by another process, searching the last complete index.");
+                    return Ok(false);
+                }
+                Err(e) => return Err(e),
+            }
+            self.status(format_args!(
+                "Removed {} deleted files from the index.",
+                removed_files.len()
+            ));
+            if self.verbosity.is_verbose() {
+                for path in &removed_files {
+                    eprintln!("  removed: {}", path.display());
+                }
+            }
+        }
+
+        if changed_files.is_empty() {
+            metadata.save(&self.metadata_path)?;
+            self.record_skipped(&self.index_dir, Some(files), &[], &[])?;
+            self.update_embeddings(&index, &self.index_dir, Some(&removed_files))?;
+        } else {
+            self.status(format_args!(
+                "Indexing {} changed files...",
+                changed_files.len()
+            ));
+            if self.verbosity.is_verbose() {
+                for file in &changed_files {
+                    eprintln!("  changed: {}", file.relative_path.display());
+                }
+            }
+
+            let processed_files: Vec<IndexedFile> = match index
+                .index_files(changed_files, self.threads)
+            {
+                Ok(processed_files) => processed_files.collect(),
+                Err(e) if Self::is_lock_failure(&e) => {
+                    // Another probe is writing; its commit will pick these files up
+                    self.status("Index is being updated by another process, searching the last complete index.");
+                    return Ok(false);
+                }
+                Err(e) => return Err(e),
+            };
+
+            // Update metadata for indexed and skipped files
+            for file in &processed_files {
+                metadata.update_file(file)?;
+            }
+            self.record_skipped(
+                &self.index_dir,
+                Some(files),
+                &processed_files,
+                index.skipped_files(),
+            )?;
+            let touched: Vec<PathBuf> = removed_files
+                .into_iter()
+                .chain(processed_files.into_iter().map(|file| file.relative_path))
+                .collect();
+            self.update_embeddings(&index, &self.index_dir, Some(&touched))?;
+
+            if self.interrupted() {
+                // Files that weren't reached keep their old metadata and are indexed next time
+                metadata.save(&self.metadata_path)?;
+                return Err(anyhow::anyhow!(
+                    "Indexing interrupted, the remaining files will be indexed on the next run"
+                ));
+            }
+
+            // Update metadata for all candidates, so skipped files aren't retried every time
+            for file in candidates {
+                metadata.update_file(file)?;
+            }
+
+            metadata.save(&self.metadata_path)?;
+            self.status(format_args!(
+                "Index updated. {} files tracked.",
+                files.len()
+            ));
+            if self.verbosity.is_verbose() {
+                eprintln!("Incremental update took {:.2?}", started.elapsed());
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Brings the index up to date, then keeps it that way by reindexing files as they
+    /// change until interrupted. While this runs, searches skip scanning the tree.
+    pub fn watch(&self) -> Result<()> {
+        if watcher_active(&self.index_dir) {
+            return Err(anyhow::anyhow!(
+                "Another `probe watch` is already keeping {} up to date",
+                self.index_dir.display()
+            ));
+        }
+        self.ensure_index_updated()?;
+
+        let (event_tx, event_rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(event_tx)?;
+        watcher.watch(&self.root_dir, RecursiveMode::Recursive)?;
+        let root = self.root_dir.canonicalize()?;
+        let scanner = self.scanner()?;
+        let mut tree = WatchedTree::scan(&scanner, &self.root_dir);
+        let mut heartbeat = Heartbeat::start(&self.index_dir)?;
+        self.status(format_args!(
+            "Watching {} for changes, press Ctrl-C to stop.",
+            self.root_dir.display()
+        ));
+
+        let mut changed = HashSet::new();
+        let mut rescan = false;
+        // Files that couldn't be indexed because another process held the index lock
+        let mut pending = Vec::new();
+        while !self.interrupted() {
+            let quiet = changed.is_empty() && !rescan;
+            let idle = quiet && pending.is_empty();
+            let timeout = if quiet { HEARTBEAT_INTERVAL } else { DEBOUNCE };
+            match event_rx.recv_timeout(timeout) {
+                Ok(Ok(event)) => {
+                    // The backend dropped events, e.g. because its queue overflowed
+                    rescan |= event.need_rescan();
+                    changed.extend(
+                        event
+                            .paths
+                            .iter()
+                            .filter_map(|path| path.strip_prefix(&root).ok())
+                            .map(Path::to_path_buf),
+                    );
+                }
+                Ok(Err(e)) => eprintln!("Warning: file watcher error: {e}"),
+                Err(RecvTimeoutError::Timeout) if !idle => {
+                    let started = Instant::now();
+                    let mut candidates = if std::mem::take(&mut rescan) {
+                        changed.clear();
+                        tree = WatchedTree::scan(&scanner, &self.root_dir);
+                        tree.files()
+                    } else {
+                        tree.refresh(&scanner, &std::mem::take(&mut changed))
+                    };
+                    candidates.append(&mut pending);
+                    if !self.apply_changes(&tree.files(), &candidates, started)? {
+                        pending = candidates;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow::anyhow!("The file watcher stopped unexpectedly"));
+                }
+            }
+            heartbeat.beat_if_due();
+        }
+
+        self.status("Stopped watching.");
+        Ok(())
+    }
+
+    /// Answers `GET /search`, `GET /stats` and `POST /reindex` with JSON over HTTP on `addr`
+    /// until interrupted, keeping the index and the reranker model loaded between requests.
+    /// Searches use `defaults` for whatever the request's query parameters don't set.
+    pub fn serve_http(&self, addr: &str, defaults: &SearchOptions) -> Result<()> {
+        // Snippets go to HTTP clients, not to the terminal probe runs in
+        terminal::disable_colors();
+        self.ensure_index_updated()?;
+        let listener = TcpListener::bind(addr)?;
+        // Polled so that Ctrl-C stops the server between requests
+        listener.set_nonblocking(true)?;
+        self.status(format_args!(
+            "Serving {} on http://{}, press Ctrl-C to stop.",
+            self.root_dir.display(),
+            listener.local_addr()?
+        ));
+
+        while !self.interrupted() {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let started = Instant::now();
+                    match server::handle_connection(self, stream, defaults) {
+                        Ok(request) if self.verbosity.is_verbose() => {
+                            eprintln!("{request} in {:.2?}", started.elapsed());
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Warning: failed to answer a request: {e:#}"),
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(server::ACCEPT_POLL_INTERVAL);
+                }
+                Err(e) => eprintln!("Warning: failed to accept a connection: {e}"),
+            }
+        }
+
+        self.status("Stopped serving.");
+        Ok(())
+    }
+
+    /// Speaks the Language Server Protocol on stdin and stdout until the client exits,
+    /// answering `workspace/symbol`, `textDocument/definition` and `probe/search` requests.
+    /// Status messages would mix with the protocol, so engines for it should be quiet.
+    pub fn serve_lsp(&self, defaults: &SearchOptions) -> Result<()> {
+        // Snippets go to the editor, not to a terminal
+        terminal::disable_colors();
+        self.ensure_index_updated()?;
+        lsp::serve(
+            self,
+            &self.root_dir,
+            std::io::stdin().lock(),
+            std::io::stdout().lock(),
+            defaults,
+        )
+    }
+
+    /// Socket `probe daemon` listens on, in the lock directory shared by the main index
+    /// and its profiles
+    pub fn daemon_socket_path(&self) -> PathBuf {
+        let name = match &self.profile {
+            Some(profile) => format!("daemon-{profile}.sock"),
+            None => "daemon.sock".to_string(),
+        };
+        self.lock_dir().join(name)
+    }
+
+    /// Loads the reranker now rather than on the first reranked `search`
+    pub fn warm_up(&self) -> Result<()> {
+        if self.reranker_config.enabled {
+            let mut reranker = self.reranker.lock().unwrap_or_else(PoisonError::into_inner);
+            if reranker.is_none() {
+                *reranker = Some(Reranker::new(self.reranker_config.clone())?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the index and reranker loaded and answers the searches of other `probe`
+    /// invocations for this project on `daemon_socket_path` until Ctrl-C. Clients strip
+    /// highlights they don't show, so the process should have colors forced on.
+    #[cfg(unix)]
+    pub fn serve_daemon(&self) -> Result<()> {
+        let socket = self.daemon_socket_path();
+        if daemon::is_running(&socket) {
+            return Err(anyhow::anyhow!(
+                "A daemon is already serving {} on {}",
+                self.root_dir.display(),
+                socket.display()
+            ));
+        }
+        self.ensure_index_updated()?;
+        self.warm_up()?;
+
+        std::fs::create_dir_all(self.lock_dir())?;
+        // Left behind by a daemon that didn't shut down
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket)?;
+        // Polled so that Ctrl-C stops the daemon between requests
+        listener.set_nonblocking(true)?;
+        let settings = daemon::settings_key(&self.config, &self.reranker_config);
+        self.status(format_args!(
+            "Serving searches of {} on {}, press Ctrl-C to stop.",
+            self.root_dir.display(),
+            socket.display()
+        ));
+
+        while !self.interrupted() {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let started = Instant::now();
+                    match daemon::handle_connection(self, stream, &settings) {
+                        Ok(query) if self.verbosity.is_verbose() => {
+                            eprintln!("'{query}' in {:.2?}", started.elapsed());
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Warning: failed to answer a request: {e:#}"),
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(server::ACCEPT_POLL_INTERVAL);
+                }
+                Err(e) => eprintln!("Warning: failed to accept a connection: {e}"),
+            }
+        }
+
+        let _ = std::fs::remove_file(&socket);
+        self.status("Stopped serving.");
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn serve_daemon(&self) -> Result<()> {
+        Err(anyhow::anyhow!(
+            "probe daemon listens on a Unix socket, which this platform doesn't have"
+        ))
+    }
+
+    /// Searches with the `probe daemon` of this project, which has the reranker loaded
+    /// already. `None` when no daemon is running, it runs with other settings than this
+    /// engine and `reranker_config` or it fails to answer.
+    #[cfg(unix)]
+    pub fn search_with_daemon(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        reranker_config: &RerankerConfig,
+    ) -> Result<Option<Vec<SearchResult>>> {
+        let settings = daemon::settings_key(&self.config, reranker_config);
+        daemon::search(
+            &self.daemon_socket_path(),
+            query,
+            options,
+            settings,
+            self.verbosity,
+        )
+    }
+
+    /// Daemons listen on Unix sockets, so there is never one to search with here
+    #[cfg(not(unix))]
+    pub fn search_with_daemon(
+        &self,
+        _query: &str,
+        _options: &SearchOptions,
+        _reranker_config: &RerankerConfig,
+    ) -> Result<Option<Vec<SearchResult>>> {
+        Ok(None)
+    }
+
+    /// Definitions in the index whose name contains `query`, ignoring case, best matches
+    /// first
+    pub fn find_symbols(&self, query: &str, limit: usize) -> Result<Vec<SymbolMatch>> {
+        self.open_index()?.find_symbols(query, limit)
+    }
+
+    /// Times a full rebuild, an update with nothing to do, `runs` full-text searches for
+    /// every query and reranking their candidates. Rebuilds the index as a side effect.
+    pub fn bench(
+        &self,
+        queries: &[String],
+        runs: usize,
+        options: &SearchOptions,
+    ) -> Result<BenchReport> {
+        let started = Instant::now();
+        self.rebuild_index()?;
+        let rebuild_ms = millis(started);
+
+        let started = Instant::now();
+        self.ensure_index_updated()?;
+        let update_ms = millis(started);
+
+        let mut cold_query_ms = None;
+        let mut warm = Vec::new();
+        for query in queries {
+            for _ in 0..runs {
+                let started = Instant::now();
+                self.search_index(query, options)?;
+                match cold_query_ms {
+                    None => cold_query_ms = Some(millis(started)),
+                    Some(_) => warm.push(millis(started)),
+                }
+            }
+        }
+        warm.sort_by(f64::total_cmp);
+
+        let (reranker_load_ms, rerank_ms) = if self.reranker_config.enabled {
+            let started = Instant::now();
+            let mut reranker = Reranker::new(self.reranker_config.clone())?;
+            let load_ms = millis(started);
+            let mut rerank = Vec::new();
+            for query in queries {
+                let candidates = self.search_index(
+                    query,
+                    &SearchOptions {
+                        limit: self.reranker_config.candidate_limit(options.limit),
+                        ..options.clone()
+                    },
+                )?;
+                let started = Instant::now();
+                reranker.rerank_results(query, candidates, options.limit)?;
+                rerank.push(millis(started));
+            }
+            rerank.sort_by(f64::total_cmp);
+            (Some(load_ms), Some(percentile(&rerank, 0.5)))
+        } else {
+            (None, None)
+        };
+
+        Ok(BenchReport {
+            files: self.index_stats()?.files,
+            threads: self.threads,
+            stemming: self
+                .config
+                .stemming
+                .enabled
+                .then(|| self.config.stemming.language.clone()),
+            writer_heap_mb: self.config.index.writer_heap_bytes() / (1024 * 1024),
+            commit_batch_size: self.config.index.commit_batch_size(),
+            rebuild_ms,
+            update_ms,
+            cold_query_ms: cold_query_ms.unwrap_or_default(),
+            warm_query_ms: percentile(&warm, 0.5),
+            warm_query_p95_ms: percentile(&warm, 0.95),
+            reranker_load_ms,
+            rerank_ms,
+        })
+    }
+
+    /// Rebuilds the index in a staging directory next to the current one and swaps it in
+    /// once complete, so concurrent searches keep using the previous index until then.
+    /// Fails if another process is rebuilding the index.
+    pub fn rebuild_index(&self) -> Result<()> {
+        let _lock = self.lock_for_rebuild()?;
+        self.rebuild_holding_lock()
+    }
+
+    /// `rebuild_index` for a caller that already took the index lock
+    fn rebuild_holding_lock(&self) -> Result<()> {
+        self.status("Rebuilding index from scratch...");
+
+        if self.index_dir.exists() && !self.looks_like_index_dir(&self.index_dir)? {
+            return Err(anyhow::anyhow!(
+                "Refusing to rebuild into '{}': directory is not empty and does not contain a probe index",
+                self.index_dir.display()
+            ));
+        }
+
+        // Start from a fresh schema, discarding whatever an interrupted rebuild left behind
+        let staging_dir = self.sibling_dir("rebuild");
+        if staging_dir.exists() {
+            std::fs::remove_dir_all(&staging_dir)?;
+        }
+        let index = self.create_index_in(&staging_dir)?;
+        self.finish_rebuild(index, &staging_dir, self.new_metadata())
+    }
+
+    /// Continues a rebuild that was interrupted, indexing only the files the staging
+    /// index doesn't have yet
+    pub fn resume_rebuild(&self) -> Result<()> {
+        let _lock = self.lock_for_rebuild()?;
+        let staging_dir = self.sibling_dir("rebuild");
+        let checkpoint: ResumeCheckpoint =
+            match std::fs::read_to_string(staging_dir.join(RESUME_CHECKPOINT)) {
+                Ok(content) => serde_json::from_str(&content)?,
+                Err(_) => {
+                    return Err(anyhow::anyhow!(
+                        "No interrupted rebuild to resume, run `probe rebuild` instead"
+                    ))
+                }
+            };
+        if checkpoint.root_dir != self.root_dir {
+            return Err(anyhow::anyhow!(
+                "The interrupted rebuild in '{}' was started for '{}', run `probe rebuild` instead",
+                staging_dir.display(),
+                checkpoint.root_dir.display()
+            ));
+        }
+
+        self.status(format_args!(
+            "Resuming rebuild, {} files were already indexed...",
+            checkpoint.indexed_files
+        ));
+        let index = self.open_index_in(&staging_dir)?;
+        let metadata = self.load_metadata(&staging_dir.join("metadata.bin"))?;
+        self.finish_rebuild(index, &staging_dir, metadata)
+    }
+
+    /// Indexes every file `metadata` doesn't know about into the staging index, then swaps
+    /// it in. When interrupted, the progress is saved for `resume_rebuild` instead.
+    fn finish_rebuild(
+        &self,
+        mut index: SearchIndex,
+        staging_dir: &Path,
+        mut metadata: IndexMetadata,
+    ) -> Result<()> {
+        let started = Instant::now();
+        let checkpoint_path = staging_dir.join(RESUME_CHECKPOINT);
+        let scanner = self.scanner()?;
+        // Streamed from the scanner, leaving out the files a resumed rebuild already indexed
+        let pending = scanner
+            .iter_indexed_files()
+            .filter(|file| metadata.file_changed(file).unwrap_or(true));
+        let processed_files: Vec<IndexedFile> = index.index_files(pending, self.threads)?.collect();
+
+        // Skipped files are tracked too, so they're only retried once they change
+        for file in &processed_files {
+            metadata.update_file(file)?;
+        }
+        let skipped =
+            self.record_skipped(staging_dir, None, &processed_files, index.skipped_files())?;
+        let file_count = metadata.file_count().saturating_sub(skipped);
+        metadata.set_commit(git::head_commit(&self.root_dir));
+        metadata.save(staging_dir.join("metadata.bin"))?;
+        if !self.interrupted() {
+            self.update_embeddings(&index, staging_dir, None)?;
+        }
+        drop(index);
+
+        if self.interrupted() {
+            let checkpoint = ResumeCheckpoint {
+                root_dir: self.root_dir.clone(),
+                indexed_files: file_count,
+            };
+            write_atomic(&checkpoint_path, serde_json::to_string(&checkpoint)?)?;
+            return Err(anyhow::anyhow!(
+                "Rebuild interrupted after indexing {file_count} files. Run `probe rebuild --resume` to continue."
+            ));
+        }
+
+        if checkpoint_path.exists() {
+            std::fs::remove_file(&checkpoint_path)?;
+        }
+        self.swap_in_index(staging_dir)?;
+
+        self.status(format_args!("Index rebuilt. {file_count} files indexed."));
+        if self.verbosity.is_verbose() {
+            eprintln!("Rebuild took {:.2?}", started.elapsed());
+        }
+        Ok(())
+    }
+
+    /// Embeds the chunks of the files in `touched` (every file when `None`) and stores the
+    /// vectors in `dir`, if embeddings are enabled. Switching models re-embeds everything.
+    fn update_embeddings(
+        &self,
+        index: &SearchIndex,
+        dir: &Path,
+        touched: Option<&[PathBuf]>,
+    ) -> Result<()> {
+        if !self.config.embeddings.enabled {
+            return Ok(());
+        }
+        let vectors_path = dir.join(VECTORS_FILE);
+        let model = self.config.embeddings.model_name();
+        let mut store = VectorStore::load(&vectors_path)?;
+        let touched = if store.model() == model {
+            touched
+        } else {
+            store.reset(model);
+            None
+        };
+
+        let chunks = match touched {
+            Some(paths) => {
+                for path in paths {
+                    store.remove_file(&index_path(path));
+                }
+                index.chunk_texts(Some(paths))?
+            }
+            None => index.chunk_texts(None)?,
+        };
+        if !chunks.is_empty() {
+            let started = Instant::now();
+            self.status(format_args!("Embedding {} chunks...", chunks.len()));
+            let mut embedder = Embedder::new(model, !self.verbosity.is_quiet())?;
+            let vectors =
+                embedder.embed(chunks.iter().map(|chunk| chunk.text.clone()).collect())?;
+            store.insert(chunks, vectors);
+            if self.verbosity.is_verbose() {
+                eprintln!("Embedding took {:.2?}", started.elapsed());
+            }
+        }
+        store.save(&vectors_path)
+    }
+
+    /// Updates the skipped-files report in `dir` after indexing `attempted`, prints a summary
+    /// of the newly skipped files and returns how many files the report lists in total.
+    /// Entries of files missing from `files` are dropped; without a complete list (rebuilds
+    /// stream the files) the next update drops them.
+    fn record_skipped(
+        &self,
+        dir: &Path,
+        files: Option<&[IndexedFile]>,
+        attempted: &[IndexedFile],
+        skipped: &[SkippedFile],
+    ) -> Result<usize> {
+        let report_path = dir.join("skipped.json");
+        let mut report = SkippedFiles::load(&report_path)?;
+        report.record(attempted, skipped);
+        if let Some(files) = files {
+            report.prune(files);
+        }
+        report.save(&report_path)?;
 
-        let metadata = IndexMetadata::load(&self.metadata_path)?;
-        if metadata.needs_relative_path_migration() {
-            return self.rebuild_index();
+        if !skipped.is_empty() {
+            self.status(format_args!(
+                "Skipped {} files ({}), see `probe stats --skipped` for details.",
+                skipped.len(),
+                summarize_skipped(skipped.iter().map(|file| &file.reason))
+            ));
+            if self.verbosity.is_verbose() {
+                for file in skipped {
+                    eprintln!("  skipped: {} ({})", file.path.display(), file.reason);
+                }
+            }
         }
+        Ok(report.len())
+    }
 
-        let mut metadata = metadata;
-        let changed_files = metadata.needs_reindex(&files)?;
+    /// Checks whether an existing index can be read. Returns a description of the problem
+    /// for truncated files, a missing meta.json or an index written by an incompatible
+    /// tantivy version; a directory without any index is not considered corrupted.
+    fn detect_corruption(&self) -> Option<String> {
+        // A concurrent commit or rebuild can make a healthy index look broken for a moment
+        self.check_index()?;
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        self.check_index()
+    }
 
-        if !changed_files.is_empty() {
-            println!("Indexing {} changed files...", changed_files.len());
+    fn check_index(&self) -> Option<String> {
+        let has_meta = self.index_dir.join("meta.json").exists();
+        if !has_meta {
+            return self
+                .metadata_path
+                .exists()
+                .then(|| "meta.json is missing".to_string());
+        }
 
-            let language = self.config.get_language()?;
-            let mut index =
-                match SearchIndex::open(&self.index_dir, language, self.config.stemming.enabled) {
-                    Ok(index) => index,
-                    Err(_) => {
-                        SearchIndex::new(&self.index_dir, language, self.config.stemming.enabled)?
-                    }
-                };
+        if let Err(e) = self.open_index().and_then(|index| index.validate()) {
+            return Some(e.to_string());
+        }
+        if let Err(e) = IndexMetadata::load(&self.metadata_path) {
+            return Some(format!("unreadable metadata.bin: {e}"));
+        }
+        None
+    }
 
-            let indexed_files = index.index_files(changed_files.into_iter(), 8)?;
+    /// Moves a broken index aside (replacing any earlier quarantined one) so it can be
+    /// inspected, leaving the index directory free for a rebuild
+    fn quarantine_index(&self, problem: &str) -> Result<()> {
+        let quarantine_dir = self.sibling_dir("corrupt");
+        if quarantine_dir.exists() {
+            std::fs::remove_dir_all(&quarantine_dir)?;
+        }
+        std::fs::rename(&self.index_dir, &quarantine_dir)?;
+        self.carry_over_state(&quarantine_dir)?;
+        eprintln!(
+            "Warning: the index in {} could not be read ({}). It was moved to {} and will be rebuilt.",
+            self.index_dir.display(),
+            problem,
+            quarantine_dir.display()
+        );
+        Ok(())
+    }
 
-            // Update metadata for indexed files
-            for file in indexed_files {
-                metadata.update_file(&file)?;
-            }
+    /// Directory next to the index used while replacing it, e.g. `.probe.rebuild`
+    fn sibling_dir(&self, suffix: &str) -> PathBuf {
+        let mut name = self
+            .index_dir
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| ".probe".into());
+        name.push(format!(".{suffix}"));
+        self.index_dir.with_file_name(name)
+    }
 
-            // Update metadata for all files
-            for file in &files {
-                metadata.update_file(file)?;
+    /// Replaces the index directory with `staging_dir`. The old index is moved aside rather
+    /// than deleted first, so the directory is only missing between two renames.
+    fn swap_in_index(&self, staging_dir: &Path) -> Result<()> {
+        if !self.index_dir.exists() {
+            if let Some(parent) = self.index_dir.parent() {
+                std::fs::create_dir_all(parent)?;
             }
-
-            metadata.save(&self.metadata_path)?;
-            println!("Index updated. {} files tracked.", files.len());
+            std::fs::rename(staging_dir, &self.index_dir)?;
+            return Ok(());
         }
 
+        let old_dir = self.sibling_dir("old");
+        if old_dir.exists() {
+            std::fs::remove_dir_all(&old_dir)?;
+        }
+        std::fs::rename(&self.index_dir, &old_dir)?;
+        std::fs::rename(staging_dir, &self.index_dir)?;
+        self.carry_over_state(&old_dir)?;
+        // Searches still reading the old generation keep their open files
+        let _ = std::fs::remove_dir_all(&old_dir);
         Ok(())
     }
 
-    pub fn rebuild_index(&self) -> Result<()> {
-        println!("Rebuilding index from scratch...");
-
-        // Remove existing index directory if it exists to handle schema changes
-        if self.index_dir.exists() {
-            std::fs::remove_dir_all(&self.index_dir)?;
+    /// Moves the search history and the profile indexes kept inside `old_dir`, the index
+    /// directory before it was replaced, into the current one
+    fn carry_over_state(&self, old_dir: &Path) -> Result<()> {
+        let history = old_dir.join(HISTORY_FILE);
+        if history.is_file() {
+            std::fs::create_dir_all(&self.index_dir)?;
+            std::fs::rename(&history, self.history_path())?;
         }
+        if self.profile.is_some() {
+            return Ok(());
+        }
+        for name in self.config.profiles.keys() {
+            let profile_dir = old_dir.join(name);
+            if is_profile_name(name) && profile_dir.is_dir() {
+                std::fs::create_dir_all(&self.index_dir)?;
+                std::fs::rename(&profile_dir, self.index_dir.join(name))?;
+            }
+        }
+        Ok(())
+    }
 
-        let scanner = FileScanner::new(&self.root_dir);
-        let files_iter = scanner.iter_indexed_files();
-
-        let language = self.config.get_language()?;
-        let mut index = SearchIndex::new(&self.index_dir, language, self.config.stemming.enabled)?;
+    fn is_lock_failure(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<tantivy::TantivyError>(),
+            Some(tantivy::TantivyError::LockFailure(..))
+        )
+    }
 
-        // Index the files and get back an iterator of processed files
-        let indexed_files = index.index_files(files_iter, 8)?;
+    /// Errors caused by the index being swapped or garbage collected while it was opened
+    fn is_transient_index_error(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<tantivy::TantivyError>(),
+            Some(
+                tantivy::TantivyError::OpenDirectoryError(_)
+                    | tantivy::TantivyError::OpenReadError(_)
+                    | tantivy::TantivyError::IoError(_)
+                    | tantivy::TantivyError::DataCorruption(_)
+            )
+        )
+    }
 
-        // Update metadata for indexed files
-        let mut metadata = IndexMetadata::new();
-        let mut file_count = 0;
-        for file in indexed_files {
-            metadata.update_file(&file)?;
-            file_count += 1;
+    /// A custom index directory may point anywhere, so only wipe it when it is empty
+    /// (but for the search history and the indexes of profiles) or already holds a probe index.
+    fn looks_like_index_dir(&self, dir: &Path) -> Result<bool> {
+        if dir.join("meta.json").exists() || dir.join("metadata.bin").exists() {
+            return Ok(true);
+        }
+        for entry in std::fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            if name == HISTORY_FILE {
+                continue;
+            }
+            let is_profile = self.profile.is_none()
+                && name
+                    .to_str()
+                    .is_some_and(|name| self.config.profiles.contains_key(name));
+            if !is_profile {
+                return Ok(false);
+            }
         }
-        metadata.save(&self.metadata_path)?;
+        Ok(true)
+    }
 
-        println!("Index rebuilt. {file_count} files indexed.");
-        Ok(())
+    /// Searches with the reranker settings the engine was built with
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult>> {
+        let mut reranker = self.reranker.lock().unwrap_or_else(PoisonError::into_inner);
+        self.search_reranked(query, options, &self.reranker_config, &mut reranker)
     }
 
     pub fn search_with_reranker(
@@ -111,86 +1388,747 @@ impl SearchEngine {
         reranker_config: RerankerConfig,
         context_lines: usize,
     ) -> Result<Vec<SearchResult>> {
-        let language = self.config.get_language()?;
-        let mut index = SearchIndex::open(&self.index_dir, language, self.config.stemming.enabled)?;
+        let options = SearchOptions {
+            limit: limit.unwrap_or(5),
+            filetype: filetype.map(str::to_string),
+            context_lines,
+            ..SearchOptions::default()
+        };
+        self.search_with_options(query, &options, reranker_config)
+    }
 
-        // Determine how many candidates to fetch
-        let final_limit = limit.unwrap_or(5);
-        let fetch_limit = if reranker_config.enabled {
-            // Fetch at least the minimum candidates, but more if user wants more results
-            std::cmp::max(reranker_config.min_candidates, final_limit * 2)
+    pub fn search_with_options(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        reranker_config: RerankerConfig,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_reranked(query, options, &reranker_config, &mut None)
+    }
+
+    /// Searches and reranks the results with `reranker`, loading it first if it is `None`
+    fn search_reranked(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        reranker_config: &RerankerConfig,
+        reranker: &mut Option<Reranker>,
+    ) -> Result<Vec<SearchResult>> {
+        // Reranked and fused results are ordered after the full-text search, so their pages
+        // are cut from the top results; full-text pages come straight from the index
+        let page_in_index = !reranker_config.enabled && !options.hybrid;
+        let (fetch_offset, final_limit) = if page_in_index {
+            (options.offset, options.limit)
         } else {
-            final_limit
+            (0, options.offset + options.limit)
         };
+        // Determine how many candidates to fetch
+        let fetch_limit = reranker_config.candidate_limit(final_limit);
 
         // Get initial results from Tantivy
-        let mut results = index.search(query, fetch_limit, filetype, context_lines)?;
+        let started = Instant::now();
+        let index_options = SearchOptions {
+            limit: fetch_limit,
+            offset: fetch_offset,
+            ..options.clone()
+        };
+        let mut results = if options.hybrid {
+            self.hybrid_search(query, &index_options)?
+        } else {
+            self.search_index(query, &index_options)?
+        };
+        reranker_config.cut_candidates(&mut results);
+        if self.verbosity.is_verbose() {
+            eprintln!(
+                "Full-text search returned {} candidates in {:.2?}",
+                results.len(),
+                started.elapsed()
+            );
+        }
 
         // Apply reranking if enabled and we have enough results
         if reranker_config.enabled && results.len() >= 2 {
-            // Initialize reranker
-            let mut reranker = Reranker::new(reranker_config)?;
+            let reranker = match reranker {
+                Some(reranker) => reranker,
+                None => reranker.insert(Reranker::new(reranker_config.clone())?),
+            };
 
-            // Convert SearchResults to RerankDocuments
-            let rerank_docs: Vec<RerankDocument> = results
-                .into_iter()
-                .map(|result| {
-                    let mut metadata = HashMap::new();
-                    metadata.insert(
-                        "path".to_string(),
-                        result.path.to_string_lossy().to_string(),
-                    );
-                    if let Some(chunk_type) = &result.chunk_type {
-                        metadata.insert("chunk_type".to_string(), chunk_type.clone());
-                    }
-                    if let Some(chunk_name) = &result.chunk_name {
-                        metadata.insert("chunk_name".to_string(), chunk_name.clone());
-                    }
-                    if let Some(start_line) = result.start_line {
-                        metadata.insert("start_line".to_string(), start_line.to_string());
-                    }
-                    if let Some(end_line) = result.end_line {
-                        metadata.insert("end_line".to_string(), end_line.to_string());
-                    }
+            let started = Instant::now();
+            results = match &self.config.scoring.fusion {
+                Some(fusion) => {
+                    let full_text: Vec<_> = results
+                        .iter()
+                        .map(|result| (fusion_key(result), result.score))
+                        .collect();
+                    let limit = results.len();
+                    let reranked = reranker.rerank_results_or_keep_order(query, results, limit)?;
+                    fuse_reranked(fusion, full_text, reranked, final_limit)
+                }
+                None => reranker.rerank_results_or_keep_order(query, results, final_limit)?,
+            };
+            if self.verbosity.is_verbose() {
+                eprintln!("Reranking took {:.2?}", started.elapsed());
+            }
+        } else {
+            // No reranking, just limit results
+            results.truncate(final_limit);
+        }
+        if !page_in_index {
+            results.drain(..options.offset.min(results.len()));
+        }
+        if let Some(min_score) = options.min_score {
+            results.retain(|result| result.score >= min_score);
+        }
+
+        Ok(arrange_results(results, options))
+    }
+
+    /// Passes full-text results to `on_result` as soon as each one is ready, until the
+    /// top `options.limit` were passed or `on_result` returns false. Results are neither
+    /// reranked nor sorted by their final score; use `search_with_options` for that.
+    pub fn search_stream<F>(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        mut on_result: F,
+    ) -> Result<()>
+    where
+        F: FnMut(SearchResult) -> bool,
+    {
+        let index = self.open_index_for(options)?;
+        for result in index.search_iter(query, options)? {
+            if !on_result(result?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fuses the full-text candidates with the chunks whose embeddings are closest to the
+    /// query, by reciprocal rank fusion unless `scoring.fusion` says otherwise. Scores of the
+    /// results are the fused scores.
+    fn hybrid_search(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult>> {
+        if !self.config.embeddings.enabled {
+            return Err(anyhow::anyhow!(
+                "--hybrid needs chunk embeddings, set `embeddings.enabled: true` in probe.yml"
+            ));
+        }
+        let full_text = self.search_index(query, options)?;
+        let store = VectorStore::load(self.index_dir.join(VECTORS_FILE))?;
+        if store.is_empty() {
+            return Ok(full_text);
+        }
+
+        let started = Instant::now();
+        let mut embedder = Embedder::new(store.model(), !self.verbosity.is_quiet())?;
+        let query_vector = embedder
+            .embed(vec![query.to_string()])?
+            .pop()
+            .unwrap_or_default();
+        let nearest = store.nearest(&query_vector, options.limit);
+        if self.verbosity.is_verbose() {
+            eprintln!(
+                "Vector search returned {} candidates in {:.2?}",
+                nearest.len(),
+                started.elapsed()
+            );
+        }
+
+        let full_text_ranking = full_text
+            .iter()
+            .map(|result| (fusion_key(result), result.score))
+            .collect();
+        let nearest = nearest
+            .into_iter()
+            .map(|((path, start, end), similarity)| ((path, Some(start), Some(end)), similarity))
+            .collect();
+        let (weights, strategy) = match &self.config.scoring.fusion {
+            Some(fusion) => (fusion.weights(), fusion.strategy),
+            None => ([0.5, 0.5], FusionStrategy::Rrf),
+        };
+        let fused = scoring::fuse(&[full_text_ranking, nearest], &weights, strategy);
+
+        // Chunks only the vector search found still need their snippets
+        let mut candidates: HashMap<_, _> = full_text
+            .into_iter()
+            .map(|result| (fusion_key(&result), result))
+            .collect();
+        let missing: Vec<_> = fused
+            .iter()
+            .map(|(chunk, _)| chunk)
+            .filter(|chunk| !candidates.contains_key(*chunk))
+            .map(|(path, start, _)| (path.clone(), start.unwrap_or(0)))
+            .collect();
+        let index = self.open_index_for(options)?;
+        for result in index.chunk_results(query, options, &missing)? {
+            candidates.insert(fusion_key(&result), result);
+        }
+
+        let mut results: Vec<_> = fused
+            .into_iter()
+            .filter_map(|(chunk, score)| {
+                let mut result = candidates.remove(&chunk)?;
+                result.score = score;
+                Some(result)
+            })
+            .collect();
+        results.truncate(options.limit);
+        Ok(results)
+    }
 
-                    RerankDocument {
-                        content: result.snippet.clone(),
-                        metadata,
+    /// Searches the working tree directly without reading or updating the index, for
+    /// checkouts that have none yet. Results are not reranked.
+    pub fn search_without_index(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let files: Vec<_> = self.scanner()?.iter_indexed_files().collect();
+        let results = scan_search(
+            &files,
+            &self.config.chunking,
+            &self.score_rules()?,
+            query,
+            options,
+        )?;
+        if self.verbosity.is_verbose() {
+            eprintln!(
+                "Scanned {} files without an index in {:.2?}",
+                files.len(),
+                started.elapsed()
+            );
+        }
+        Ok(arrange_results(results, options))
+    }
+
+    /// Compares the documents stored in the index with what indexing the working tree
+    /// would produce right now, without modifying the index
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let index = self.open_index()?;
+        let mut indexed = index.stored_line_ranges()?;
+        let mut chunker = CodeChunker::with_config(&self.config.chunking)?;
+        let mut report = VerifyReport::default();
+
+        for file in self.scanner()?.iter_indexed_files() {
+            let content = match read_source(&file.disk_path) {
+                Ok(content) if is_indexable(&content) => content,
+                _ => continue, // Files the indexer skips
+            };
+
+            let chunks = chunker.chunk_code_for_indexing(&file.relative_path, &content)?;
+            let mut expected: Vec<_> = if chunks.is_empty() {
+                vec![(0, content.lines().count().saturating_sub(1))]
+            } else {
+                chunks
+                    .iter()
+                    .map(|chunk| (chunk.start_line, chunk.end_line))
+                    .collect()
+            };
+
+            match indexed.remove(&file.relative_path) {
+                None => report.missing.push(file.relative_path),
+                Some(mut ranges) => {
+                    ranges.sort_unstable();
+                    expected.sort_unstable();
+                    if ranges != expected {
+                        report.stale.push(StaleFile {
+                            path: file.relative_path,
+                            indexed_chunks: ranges.len(),
+                            source_chunks: expected.len(),
+                        });
                     }
+                }
+            }
+        }
+
+        report.orphaned = indexed.into_keys().collect();
+        report.stale.sort_by(|a, b| a.path.cmp(&b.path));
+        report.missing.sort();
+        report.orphaned.sort();
+        Ok(report)
+    }
+
+    /// Merges the index into a single segment and drops the documents of edited and deleted
+    /// files, which searches otherwise keep skipping over
+    pub fn optimize(&self) -> Result<OptimizeReport> {
+        if !self.index_dir.exists() {
+            anyhow::bail!("No index found, run a search or `probe rebuild` first");
+        }
+        let mut index = self.open_index()?;
+        let before = index.segment_stats()?;
+        let bytes_before = directory_size(&self.index_dir)?;
+        match index.optimize() {
+            Ok(()) => {}
+            Err(e) if Self::is_lock_failure(&e) => {
+                anyhow::bail!("Index is being updated by another process, try again later")
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(OptimizeReport {
+            before,
+            after: index.segment_stats()?,
+            bytes_before,
+            bytes_after: directory_size(&self.index_dir)?,
+        })
+    }
+
+    /// Structural outline of a file. Uses the chunks stored in the index when the file is
+    /// indexed and unchanged since, and chunks the file directly otherwise.
+    pub fn outline(&self, path: &Path) -> Result<Vec<OutlineItem>> {
+        let disk_path = if path.exists() {
+            path.to_path_buf()
+        } else {
+            self.root_dir.join(path)
+        };
+        if let Some(items) = self.indexed_outline(&disk_path)? {
+            return Ok(build_outline(items));
+        }
+
+        let content = read_source(&disk_path)?;
+        let mut chunker = CodeChunker::with_config(&self.config.chunking)?;
+        let chunks = chunker.chunk_code_for_indexing(&disk_path, &content)?;
+        Ok(build_outline(
+            chunks.iter().map(OutlineItem::from_chunk).collect(),
+        ))
+    }
+
+    /// Outline items from the index, `None` if the file isn't indexed or has changed
+    fn indexed_outline(&self, disk_path: &Path) -> Result<Option<Vec<OutlineItem>>> {
+        let (Ok(canonical), Ok(root)) = (disk_path.canonicalize(), self.root_dir.canonicalize())
+        else {
+            return Ok(None);
+        };
+        let Ok(relative_path) = canonical.strip_prefix(&root) else {
+            return Ok(None);
+        };
+        let file = IndexedFile {
+            disk_path: disk_path.to_path_buf(),
+            relative_path: relative_path.to_path_buf(),
+        };
+
+        let mut metadata = self.load_metadata(&self.metadata_path)?;
+        if metadata.file_count() == 0
+            || !metadata
+                .needs_reindex(std::slice::from_ref(&file))?
+                .is_empty()
+        {
+            return Ok(None);
+        }
+        let Ok(index) = self.open_index() else {
+            return Ok(None);
+        };
+        let items = index.stored_outline(&file.relative_path)?;
+        Ok((!items.is_empty()).then_some(items))
+    }
+
+    /// Extracts the methods defined in the working tree and the calls between them
+    pub fn call_graph(&self) -> Result<CallGraph> {
+        let mut chunker = CodeChunker::with_config(&self.config.chunking)?;
+        let mut files = Vec::new();
+
+        for file in self.scanner()?.iter_indexed_files() {
+            if chunker.language_for(&file.relative_path).is_none() {
+                continue;
+            }
+            let content = match read_source(&file.disk_path) {
+                Ok(content) if is_indexable(&content) => content,
+                _ => continue, // Files the indexer skips
+            };
+            let references = chunker.extract_references(&file.relative_path, &content)?;
+            files.push((file.relative_path, references));
+        }
+
+        Ok(CallGraph::build(files))
+    }
+
+    /// Chunks the working tree and groups functions and methods that are near-identical
+    pub fn find_duplicates(&self, options: &DupesOptions) -> Result<Vec<DuplicateCluster>> {
+        let mut chunker = CodeChunker::with_config(&self.config.chunking)?;
+        let mut chunks = Vec::new();
+
+        for file in self.scanner()?.iter_indexed_files() {
+            let content = match read_source(&file.disk_path) {
+                Ok(content) if is_indexable(&content) => content,
+                _ => continue, // Files the indexer skips
+            };
+
+            for chunk in chunker.chunk_code_for_indexing(&file.relative_path, &content)? {
+                if matches!(chunk.chunk_type, ChunkType::Function | ChunkType::Method) {
+                    let location = ChunkLocation {
+                        path: file.relative_path.clone(),
+                        name: chunk.name,
+                        start_line: chunk.start_line,
+                        end_line: chunk.end_line,
+                    };
+                    chunks.push((location, format!("{}{}", chunk.declaration, chunk.content)));
+                }
+            }
+        }
+
+        Ok(find_duplicates(chunks, options))
+    }
+
+    /// Runs the full-text search, retrying when a concurrent rebuild or commit replaced
+    /// index files between opening the index and reading from it
+    fn search_index(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult>> {
+        const ATTEMPTS: u32 = 5;
+
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .open_index_for(options)
+                .and_then(|mut index| index.search_with_options(query, options));
+
+            match result {
+                Err(e) if attempt < ATTEMPTS && Self::is_transient_index_error(&e) => {
+                    std::thread::sleep(std::time::Duration::from_millis(50 * u64::from(attempt)));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Brings the index up to date and packages it with a manifest of the indexed files'
+    /// hashes into `archive_path`, for `import_index` on another machine
+    pub fn export_index(&self, archive_path: &Path) -> Result<Manifest> {
+        self.ensure_index_updated()?;
+        if !self.index_dir.join("meta.json").exists() {
+            return Err(anyhow::anyhow!(
+                "No index to export in {}, run `probe rebuild` first",
+                self.index_dir.display()
+            ));
+        }
+
+        let files: Vec<_> = self.scanner()?.iter_indexed_files().collect();
+        let manifest = Manifest::new(&files)?;
+        write_archive(&self.index_dir, &manifest, archive_path)?;
+        let checksum_path = write_checksum(archive_path)?;
+        self.status(format_args!(
+            "Exported the index of {} files to {} (checksum in {}).",
+            manifest.files.len(),
+            archive_path.display(),
+            checksum_path.display()
+        ));
+        Ok(manifest)
+    }
+
+    /// Downloads the archive configured as `index.remote` and imports it
+    fn fetch_remote_index(&self, remote: &str) -> Result<()> {
+        let source = resolve_source(remote, &self.root_dir);
+        self.status(format_args!("Downloading prebuilt index from {source}..."));
+        let download_dir = self.sibling_dir("download");
+        std::fs::create_dir_all(&download_dir)?;
+        let archive_path = download_dir.join("index.tar.zst");
+        let imported = fetch_archive(&source, &archive_path)
+            .and_then(|()| self.import_index(&archive_path, &[]));
+        let _ = std::fs::remove_dir_all(&download_dir);
+        imported
+    }
+
+    /// Replaces the index with one exported by `export_index`, moving the paths under each
+    /// of `rewrites` first. Files whose contents match the manifest are considered indexed;
+    /// the rest are reindexed on the next search.
+    pub fn import_index(&self, archive_path: &Path, rewrites: &[PrefixRewrite]) -> Result<()> {
+        let _lock = self.lock_for_rebuild()?;
+        if self.index_dir.exists() && !self.looks_like_index_dir(&self.index_dir)? {
+            return Err(anyhow::anyhow!(
+                "Refusing to import into '{}': directory is not empty and does not contain a probe index",
+                self.index_dir.display()
+            ));
+        }
+
+        let staging_dir = self.sibling_dir("import");
+        if staging_dir.exists() {
+            std::fs::remove_dir_all(&staging_dir)?;
+        }
+        let imported = read_archive(archive_path, &staging_dir).and_then(|mut manifest| {
+            let mut index = self.open_index_in(&staging_dir)?;
+            index.validate()?;
+            if !rewrites.is_empty() {
+                self.rewrite_imported_paths(&mut index, &staging_dir, rewrites)?;
+                manifest.rewrite_paths(rewrites);
+            }
+            Ok(manifest)
+        });
+        let manifest = match imported {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&staging_dir);
+                return Err(e);
+            }
+        };
+
+        // The exported metadata holds modification times from the machine that built the
+        // index, so record the local ones for the files that are unchanged here
+        let files: Vec<_> = self.scanner()?.iter_indexed_files().collect();
+        let hashes = hash_files(&files);
+        let mut metadata = self.new_metadata();
+        let mut unchanged = 0;
+        for (file, (path, hash)) in files.iter().zip(&hashes) {
+            if hash.is_some() && manifest.files.get(path) == hash.as_ref() {
+                metadata.update_file(file)?;
+                unchanged += 1;
+            }
+        }
+
+        // Files missing here, or outside the rewritten prefixes, would never be pruned since
+        // the metadata doesn't know them
+        let local_paths: HashSet<&str> = hashes.iter().map(|(path, _)| path.as_str()).collect();
+        let missing: Vec<&String> = manifest
+            .files
+            .keys()
+            .filter(|path| !local_paths.contains(path.as_str()))
+            .collect();
+        if !missing.is_empty() {
+            let missing_paths: Vec<PathBuf> = missing.iter().map(PathBuf::from).collect();
+            self.open_index_in(&staging_dir)?
+                .delete_files(&missing_paths)?;
+            let vectors_path = staging_dir.join(VECTORS_FILE);
+            if vectors_path.exists() {
+                let mut store = VectorStore::load(&vectors_path)?;
+                for path in &missing {
+                    store.remove_file(path);
+                }
+                store.save(&vectors_path)?;
+            }
+            self.status(format_args!(
+                "Removed {} files missing from the working tree from the imported index.",
+                missing.len()
+            ));
+        }
+        metadata.save(staging_dir.join("metadata.bin"))?;
+        self.swap_in_index(&staging_dir)?;
+
+        let changed = files.len() - unchanged;
+        if changed == 0 {
+            self.status(format_args!(
+                "Imported index of {unchanged} files, it matches the working tree."
+            ));
+        } else {
+            self.status(format_args!(
+                "Imported index of {unchanged} files. {changed} changed files will be indexed on the next search."
+            ));
+        }
+        Ok(())
+    }
+
+    /// Moves the documents, vectors and skipped-file entries of an unpacked archive in `dir`
+    /// to the paths `rewrites` give them
+    fn rewrite_imported_paths(
+        &self,
+        index: &mut SearchIndex,
+        dir: &Path,
+        rewrites: &[PrefixRewrite],
+    ) -> Result<()> {
+        let rewrite = |path: &str| rewrite_path(rewrites, path);
+        let moved = index.rewrite_paths(rewrite)?;
+        self.status(format_args!("Rewrote the paths of {moved} chunks."));
+
+        let vectors_path = dir.join(VECTORS_FILE);
+        if vectors_path.exists() {
+            let mut store = VectorStore::load(&vectors_path)?;
+            store.rewrite_paths(rewrite);
+            store.save(&vectors_path)?;
+        }
+        let skipped_path = dir.join("skipped.json");
+        if skipped_path.exists() {
+            let mut skipped = SkippedFiles::load(&skipped_path)?;
+            skipped.rewrite_paths(rewrite);
+            skipped.save(&skipped_path)?;
+        }
+        Ok(())
+    }
+
+    /// Explains how the file at `path` (in the working tree, or relative to the project root)
+    /// scores for `query`: whether it is indexed, which terms match, the full-text score and
+    /// penalties, the reranker score and where it ends up. `line` is 1-based and narrows the
+    /// report down to the innermost chunk containing it.
+    pub fn why(
+        &self,
+        query: &str,
+        path: &Path,
+        line: Option<usize>,
+        options: &SearchOptions,
+        reranker_config: RerankerConfig,
+    ) -> Result<WhyReport> {
+        self.ensure_index_updated()?;
+        let relative_path = self.relative_path(path);
+
+        let scanned = self
+            .scanner()?
+            .iter_indexed_files()
+            .any(|file| path_key(&file.relative_path) == path_key(&relative_path));
+        let skipped = SkippedFiles::load(self.index_dir.join("skipped.json"))?
+            .iter()
+            .find(|(skipped, _)| path_key(skipped) == path_key(&relative_path))
+            .map(|(_, reason)| reason.clone());
+        let status = match skipped {
+            Some(reason) => IndexStatus::Skipped(reason),
+            None if scanned => IndexStatus::Indexed,
+            None => IndexStatus::NotScanned,
+        };
+        if status != IndexStatus::Indexed {
+            return Ok(WhyReport {
+                relative_path,
+                status,
+                chunks: Vec::new(),
+            });
+        }
+
+        let index = self.open_index_for(options)?;
+        let mut chunks = index.explain(
+            query,
+            options,
+            &relative_path,
+            line.map(|line| line.saturating_sub(1)),
+        )?;
+        drop(index);
+        Self::add_rerank_scores(query, &mut chunks, &reranker_config)?;
+
+        let results = self.search_with_options(query, options, reranker_config)?;
+        for chunk in &mut chunks {
+            chunk.final_rank = results
+                .iter()
+                .position(|result| {
+                    result.path == chunk.result.path && result.start_line == chunk.result.start_line
                 })
-                .collect();
+                .map(|position| position + 1);
+        }
 
-            // Perform reranking
-            let rerank_result = reranker.rerank(query, rerank_docs, Some(final_limit))?;
+        Ok(WhyReport {
+            relative_path,
+            status,
+            chunks,
+        })
+    }
 
-            // Convert back to SearchResults
-            results = rerank_result
-                .documents
-                .into_iter()
-                .enumerate()
-                .map(|(i, doc)| {
-                    let rerank_score = rerank_result.rerank_scores.get(i).copied().unwrap_or(0.0);
-                    SearchResult {
-                        path: PathBuf::from(doc.metadata.get("path").unwrap_or(&String::new())),
-                        score: rerank_score, // Use rerank score instead of original score
-                        snippet: doc.content,
-                        chunk_type: doc.metadata.get("chunk_type").cloned(),
-                        chunk_name: doc.metadata.get("chunk_name").cloned(),
-                        start_line: doc.metadata.get("start_line").and_then(|s| s.parse().ok()),
-                        end_line: doc.metadata.get("end_line").and_then(|s| s.parse().ok()),
-                    }
+    /// Explains how each of `results`, as returned by `search_with_options` for the same
+    /// query, options and reranker config, got its score and rank
+    pub fn explain_results(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        results: &[SearchResult],
+        reranker_config: &RerankerConfig,
+    ) -> Result<Vec<ChunkExplanation>> {
+        let index = self.open_index_for(options)?;
+        let mut chunks = results
+            .iter()
+            .enumerate()
+            .map(|(i, result)| {
+                let mut chunk = index
+                    .explain(query, options, &result.path, result.start_line)?
+                    .into_iter()
+                    .find(|chunk| chunk.result.start_line == result.start_line)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("{} is no longer in the index", result.path.display())
+                    })?;
+                chunk.final_rank = Some(i + 1);
+                Ok(chunk)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        drop(index);
+        Self::add_rerank_scores(query, &mut chunks, reranker_config)?;
+        Ok(chunks)
+    }
+
+    /// The `count` chunks before and after each of `results` in its file, for showing a
+    /// result among its siblings. See `SearchIndex::chunk_neighbors`.
+    pub fn chunk_neighbors(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        results: &[SearchResult],
+        count: usize,
+    ) -> Result<Vec<Vec<SearchResult>>> {
+        let index = self.open_index_for(options)?;
+        index.chunk_neighbors(query, options, results, count)
+    }
+
+    fn add_rerank_scores(
+        query: &str,
+        chunks: &mut [ChunkExplanation],
+        reranker_config: &RerankerConfig,
+    ) -> Result<()> {
+        if !reranker_config.enabled || chunks.is_empty() {
+            return Ok(());
+        }
+        // The model scores each query/chunk pair on its own, like in a real search
+        let mut reranker = Reranker::new(reranker_config.clone())?;
+        for chunk in chunks {
+            let document = RerankDocument {
+                content: chunk.result.snippet.clone(),
+                metadata: HashMap::new(),
+            };
+            let reranked = reranker.rerank(query, vec![document], None)?;
+            chunk.rerank_score = reranked.rerank_scores.first().copied();
+        }
+        Ok(())
+    }
+
+    /// Path of `path` relative to the project root. Paths that exist are resolved from the
+    /// current directory, anything else is taken to be relative to the root already.
+    fn relative_path(&self, path: &Path) -> PathBuf {
+        let (Ok(canonical), Ok(root)) = (path.canonicalize(), self.root_dir.canonicalize()) else {
+            return path.to_path_buf();
+        };
+        canonical
+            .strip_prefix(&root)
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    /// File keeping the searches run from the command line, next to the index
+    pub fn history_path(&self) -> PathBuf {
+        self.index_dir.join(HISTORY_FILE)
+    }
+
+    pub fn index_stats(&self) -> Result<IndexStats> {
+        let metadata = self.load_metadata(&self.metadata_path)?;
+        let skipped_files = SkippedFiles::load(self.index_dir.join("skipped.json"))?.len();
+        let commit = metadata.commit().map(str::to_string);
+        Ok(IndexStats {
+            files: metadata.file_count().saturating_sub(skipped_files),
+            skipped_files,
+            commits_behind: commit
+                .as_deref()
+                .and_then(|commit| git::commits_since(&self.root_dir, commit)),
+            commit,
+            index_dir: self.index_dir.clone(),
+        })
+    }
+
+    /// Chunk counts, segments and size of the index, which unlike `index_stats` reads
+    /// every stored document
+    pub fn index_health(&self) -> Result<IndexHealth> {
+        let index = self.open_index()?;
+        let mut largest_skipped: Vec<(PathBuf, u64)> =
+            SkippedFiles::load(self.index_dir.join("skipped.json"))?
+                .iter()
+                .filter_map(|(path, _)| {
+                    let size = std::fs::metadata(self.root_dir.join(path)).ok()?.len();
+                    Some((path.clone(), size))
                 })
                 .collect();
-        } else {
-            // No reranking, just limit results
-            results.truncate(final_limit);
-        }
+        largest_skipped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        largest_skipped.truncate(LARGEST_SKIPPED_SHOWN);
 
-        Ok(results)
+        Ok(IndexHealth {
+            chunks: index.chunk_counts()?,
+            segments: index.segment_stats()?,
+            index_bytes: directory_size(&self.index_dir)?,
+            indexed_at: std::fs::metadata(&self.metadata_path)
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+            largest_skipped,
+        })
     }
 
-    pub fn stats(&self, ls_files: bool, status: bool) -> Result<()> {
-        let metadata = IndexMetadata::load(&self.metadata_path)?;
+    pub fn stats(&self, ls_files: bool, status: bool, skipped: bool) -> Result<()> {
+        let mut metadata = self.load_metadata(&self.metadata_path)?;
+        let skipped_files = SkippedFiles::load(self.index_dir.join("skipped.json"))?;
 
         if ls_files {
             for file in metadata.list_files() {
@@ -199,7 +2137,7 @@ impl SearchEngine {
         }
 
         if status {
-            let scanner = FileScanner::new(&self.root_dir);
+            let scanner = self.scanner()?;
             let files: Vec<_> = scanner.iter_indexed_files().collect();
             let changed_files = metadata.needs_reindex(&files)?;
 
@@ -213,11 +2151,146 @@ impl SearchEngine {
             }
         }
 
-        if !ls_files && !status {
-            println!("Files in index: {}", metadata.file_count());
+        if skipped {
+            for (path, reason) in skipped_files.iter() {
+                println!("{}: {}", path.display(), reason);
+            }
+        }
+
+        if !ls_files && !status && !skipped {
+            let stats = self.index_stats()?;
+            // Without an index there are no chunks or segments to report
+            let health = if self.index_dir.join("meta.json").exists() {
+                Some(self.index_health()?)
+            } else {
+                None
+            };
+            println!("Files in index: {}", stats.files);
+            if let Some(chunks) = health.as_ref().map(|health| &health.chunks) {
+                println!("Chunks in index: {}", chunks.total);
+                if chunks.total > 0 {
+                    println!("  by extension: {}", format_counts(&chunks.by_extension));
+                    println!("  by type: {}", format_counts(&chunks.by_type));
+                }
+            }
+            if !skipped_files.is_empty() {
+                println!(
+                    "Skipped files: {} ({})",
+                    skipped_files.len(),
+                    summarize_skipped(skipped_files.iter().map(|(_, reason)| reason))
+                );
+                let largest: Vec<_> = health
+                    .iter()
+                    .flat_map(|health| &health.largest_skipped)
+                    .map(|(path, size)| {
+                        format!("{} ({})", path.display(), terminal::format_size(*size))
+                    })
+                    .collect();
+                if !largest.is_empty() {
+                    println!("  largest: {}", largest.join(", "));
+                }
+            }
+            if let Some(commit) = &stats.commit {
+                let commit = &commit[..commit.len().min(12)];
+                match stats.commits_behind {
+                    Some(0) => println!("Indexed at commit: {commit} (HEAD)"),
+                    Some(1) => println!("Indexed at commit: {commit} (1 commit behind HEAD)"),
+                    Some(n) => println!("Indexed at commit: {commit} ({n} commits behind HEAD)"),
+                    None => println!("Indexed at commit: {commit}"),
+                }
+            }
+            if let Some(health) = &health {
+                if let Some(age) = health
+                    .indexed_at
+                    .and_then(|indexed_at| indexed_at.elapsed().ok())
+                {
+                    println!("Last indexed: {}", terminal::format_age(age));
+                }
+                println!(
+                    "Index size: {} in {} segments ({} deleted documents)",
+                    terminal::format_size(health.index_bytes),
+                    health.segments.segments,
+                    health.segments.deleted_docs
+                );
+            }
             println!("Index directory: {}", self.index_dir.display());
         }
 
         Ok(())
     }
 }
+
+/// Orders results file by file when `options.group_by_file` asks for it
+pub fn arrange_results(results: Vec<SearchResult>, options: &SearchOptions) -> Vec<SearchResult> {
+    if !options.group_by_file {
+        return results;
+    }
+    group_by_file(results)
+        .into_iter()
+        .flat_map(|group| group.results)
+        .collect()
+}
+
+/// Identifies a result across the full-text and reranked rankings
+type FusionKey = (String, Option<usize>, Option<usize>);
+
+fn fusion_key(result: &SearchResult) -> FusionKey {
+    (index_path(&result.path), result.start_line, result.end_line)
+}
+
+/// Scores `reranked` by fusing their reranker scores with the `full_text` scores they had
+/// before, keeping the best `limit`
+fn fuse_reranked(
+    fusion: &FusionConfig,
+    full_text: Vec<(FusionKey, f32)>,
+    reranked: Vec<SearchResult>,
+    limit: usize,
+) -> Vec<SearchResult> {
+    let reranked_ranking = reranked
+        .iter()
+        .map(|result| (fusion_key(result), result.score))
+        .collect();
+    let fused = scoring::fuse(
+        &[full_text, reranked_ranking],
+        &fusion.weights(),
+        fusion.strategy,
+    );
+    let mut results: HashMap<_, _> = reranked
+        .into_iter()
+        .map(|result| (fusion_key(&result), result))
+        .collect();
+    fused
+        .into_iter()
+        .filter_map(|(key, score)| {
+            let mut result = results.remove(&key)?;
+            result.score = score;
+            Some(result)
+        })
+        .take(limit)
+        .collect()
+}
+
+/// Counts largest first, e.g. `java 120, sh 8`
+fn format_counts(counts: &BTreeMap<String, usize>) -> String {
+    let mut counts: Vec<_> = counts.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    counts
+        .iter()
+        .map(|(name, count)| format!("{name} {count}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Milliseconds since `started`
+fn millis(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1000.0
+}
+
+/// The value below which `fraction` of the sorted `values` fall, 0 if there are none
+fn percentile(values: &[f64], fraction: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let rank = (fraction * (values.len() - 1) as f64).round() as usize;
+    values[rank]
+}
@@ -1,15 +1,38 @@
-use crate::code_chunker::CodeChunker;
-use crate::file_scanner::IndexedFile;
+use crate::code_chunker::{read_source, CodeChunker};
+use crate::config::{
+    parse_language, ChunkingConfig, DuplicatesConfig, ScoringConfig, StemmingConfig,
+};
+use crate::dupes::{fingerprint, parse_fingerprint, resolve_duplicates};
+use crate::file_scanner::{sniff, sniff_file, IndexedFile, SNIFF_BYTES};
+use crate::outline::OutlineItem;
+use crate::query_expansion::QueryExpansion;
+use crate::terminal;
+use crate::verbosity::Verbosity;
 use anyhow::Result;
-use atty::Stream;
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tantivy::{
-    collector::TopDocs,
-    query::{BooleanQuery, Occur, QueryParser, TermQuery},
-    schema::{IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, STORED, TEXT},
+    collector::{DocSetCollector, TopDocs},
+    query::{
+        AllQuery, BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, QueryParser,
+        RegexQuery, TermQuery, TermSetQuery,
+    },
+    schema::{
+        Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, STORED, STRING,
+        TEXT,
+    },
     tokenizer::{Language, LowerCaser, RegexTokenizer, RemoveLongFilter, Stemmer, TextAnalyzer},
-    Index, IndexWriter, Snippet, SnippetGenerator, TantivyDocument, Term,
+    DocAddress, Index, IndexReader, IndexWriter, Searcher, Snippet, SnippetGenerator,
+    TantivyDocument, Term,
 };
 
 pub struct SearchIndex {
@@ -22,9 +45,202 @@ pub struct SearchIndex {
     chunk_name_field: tantivy::schema::Field,
     start_line_field: tantivy::schema::Field,
     end_line_field: tantivy::schema::Field,
+    /// Unstemmed copies of declaration/body, missing in indexes built by older versions
+    raw_fields: Option<(Field, Field)>,
+    /// Text of the doc comments above each chunk, missing in indexes built by older versions
+    doc_field: Option<Field>,
+    /// MinHash fingerprints of chunks for finding near-duplicates, missing in indexes built
+    /// by older versions
+    fingerprint_field: Option<Field>,
+    /// Position of each chunk among the chunks of its file, missing in indexes built by
+    /// older versions
+    ordinal_field: Option<Field>,
+    /// Name of the stemmer language a document was indexed with, if not the default one
+    language_field: Option<Field>,
+    /// Declaration/body fields for documents stemmed with a non-default language
+    language_fields: Vec<LanguageFields>,
+    language: Language,
+    /// Whether queries match stemmed word forms unless `SearchOptions` say otherwise
+    stemming_enabled: bool,
+    language_rules: LanguageRules,
+    score_rules: ScoreRules,
+    query_expansion: QueryExpansion,
+    chunking: ChunkingConfig,
+    duplicates: DuplicatesConfig,
+    verbosity: Verbosity,
+    /// Set when indexing should stop early, e.g. after Ctrl-C
+    interrupt: Arc<AtomicBool>,
+    /// Memory budget of the index writer in bytes
+    writer_heap: usize,
+    /// Documents added between commits by `index_files`
+    commit_batch_size: usize,
+    /// Rough cap on the memory `index_files` uses in bytes, see `with_memory_limit`
+    memory_limit: Option<usize>,
+    /// Files the last `index_files` call left out, with the reason
+    skipped: Vec<SkippedFile>,
 }
 
-#[derive(Debug)]
+/// Stored and indexed declaration/body fields analyzed with one extra stemmer language
+#[derive(Debug, Clone)]
+struct LanguageFields {
+    language: Language,
+    name: String,
+    declaration: Field,
+    body: Field,
+}
+
+/// Assigns stemmer languages to files by path, the first matching rule wins
+#[derive(Debug, Clone, Default)]
+pub struct LanguageRules {
+    rules: Vec<(Override, Language)>,
+}
+
+impl LanguageRules {
+    pub fn from_config(stemming: &StemmingConfig) -> Result<Self> {
+        let rules = stemming
+            .overrides
+            .iter()
+            .map(|rule| -> Result<(Override, Language)> {
+                let mut builder = OverrideBuilder::new(".");
+                builder.add(&rule.pattern)?;
+                Ok((builder.build()?, parse_language(&rule.language)?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Distinct languages referenced by the rules
+    pub fn languages(&self) -> Vec<Language> {
+        let mut languages = Vec::new();
+        for (_, language) in &self.rules {
+            if !languages.contains(language) {
+                languages.push(*language);
+            }
+        }
+        languages
+    }
+
+    pub fn language_for(&self, relative_path: &Path) -> Option<Language> {
+        self.rules
+            .iter()
+            .find(|(matcher, _)| matcher.matched(relative_path, false).is_whitelist())
+            .map(|(_, language)| *language)
+    }
+}
+
+/// Field boosts and score multipliers applied to full-text matches
+#[derive(Debug, Clone)]
+pub struct ScoreRules {
+    declaration_boost: f32,
+    name_boost: f32,
+    doc_boost: f32,
+    body_boost: f32,
+    test_penalty: f32,
+    type_penalty: f32,
+    downrank_paths: Vec<(Override, String, f32)>,
+    recency_half_life_days: Option<f32>,
+    /// Days since each file last changed, by index path, see `with_file_ages`
+    file_ages: HashMap<String, f32>,
+}
+
+impl Default for ScoreRules {
+    fn default() -> Self {
+        Self::from_config(&ScoringConfig::default()).expect("default scoring config is valid")
+    }
+}
+
+impl ScoreRules {
+    pub fn from_config(scoring: &ScoringConfig) -> Result<Self> {
+        let downrank_paths = scoring
+            .downrank_paths
+            .iter()
+            .map(|rule| -> Result<(Override, String, f32)> {
+                let mut builder = OverrideBuilder::new(".");
+                builder.add(&rule.pattern)?;
+                Ok((builder.build()?, rule.pattern.clone(), rule.factor))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            declaration_boost: scoring.declaration_boost,
+            name_boost: scoring.name_boost,
+            doc_boost: scoring.doc_boost,
+            body_boost: scoring.body_boost,
+            test_penalty: scoring.test_penalty,
+            type_penalty: scoring.type_penalty,
+            downrank_paths,
+            recency_half_life_days: scoring.recency_half_life_days.filter(|days| *days > 0.0),
+            file_ages: HashMap::new(),
+        })
+    }
+
+    /// Days after which the recency boost halves, `None` when it's off
+    pub fn recency_half_life_days(&self) -> Option<f32> {
+        self.recency_half_life_days
+    }
+
+    /// Sets how many days ago each file (by index path) last changed, for the recency
+    /// boost. Files without an age aren't boosted.
+    pub fn with_file_ages(mut self, file_ages: HashMap<String, f32>) -> Self {
+        self.file_ages = file_ages;
+        self
+    }
+
+    pub(crate) fn apply(&self, score: f32, path: &str, chunk_type: Option<&str>) -> f32 {
+        self.penalties(path, chunk_type)
+            .iter()
+            .fold(score, |score, (_, factor)| score * factor)
+    }
+
+    /// The multipliers `apply` applies to a chunk, each with its reason. Factors of 1
+    /// change nothing and are left out.
+    pub(crate) fn penalties(&self, path: &str, chunk_type: Option<&str>) -> Vec<(String, f32)> {
+        let mut penalties = Vec::new();
+
+        // Test files are usually not what a search is after
+        if path.to_lowercase().contains("test") {
+            penalties.push(("path contains \"test\"".to_string(), self.test_penalty));
+        }
+
+        // Down-rank whole types compared to the methods in them
+        if matches!(
+            chunk_type,
+            Some("Class") | Some("Interface") | Some("Struct")
+        ) {
+            penalties.push((
+                "class, interface or struct chunk".to_string(),
+                self.type_penalty,
+            ));
+        }
+
+        for (matcher, pattern, factor) in &self.downrank_paths {
+            if matcher.matched(path, false).is_whitelist() {
+                penalties.push((format!("path matches \"{pattern}\""), *factor));
+            }
+        }
+
+        // Up to double the score of a file changed just now, fading out with age
+        if let Some(half_life) = self.recency_half_life_days {
+            if let Some(&age) = self.file_ages.get(path) {
+                let factor = 1.0 + 0.5f32.powf(age / half_life);
+                let age = Duration::from_secs_f32(age.max(0.0) * 86_400.0);
+                penalties.push((
+                    format!("changed {}", terminal::format_age(age)),
+                    (factor * 100.0).round() / 100.0,
+                ));
+            }
+        }
+
+        penalties.retain(|(_, factor)| *factor != 1.0);
+        penalties
+    }
+}
+
+/// Lowercase language name used in field and tokenizer names, e.g. `body_german`
+fn language_field_name(language: Language) -> String {
+    format!("{language:?}").to_lowercase()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub path: PathBuf,
     pub score: f32,
@@ -33,6 +249,745 @@ pub struct SearchResult {
     pub chunk_name: Option<String>,
     pub start_line: Option<usize>,
     pub end_line: Option<usize>,
+    /// Line of the file the snippet's first line is on. Declarations of enclosing types
+    /// shown above a method are counted as if they were right above it, so only lines
+    /// from `start_line` on are real.
+    pub snippet_line: Option<usize>,
+    /// Found by the fuzzy fallback for misspelled queries rather than the query as written
+    pub fuzzy: bool,
+    /// Paths of the near-duplicates of this chunk left out of the results, see
+    /// `DuplicatesConfig`
+    #[serde(default)]
+    pub duplicates: Vec<PathBuf>,
+}
+
+impl SearchResult {
+    /// Orders results by descending score, breaking ties by path and then start line so
+    /// that equal-score results come back in the same order on every run
+    pub fn rank_order(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .score
+            .partial_cmp(&self.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| self.path.cmp(&other.path))
+            .then_with(|| self.start_line.cmp(&other.start_line))
+    }
+}
+
+/// A definition found by name with `SearchIndex::find_symbols`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolMatch {
+    pub path: PathBuf,
+    /// Lowercase chunk kind, e.g. `class` or `method`
+    pub kind: String,
+    pub name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// The results of a search in one file
+#[derive(Debug)]
+pub struct FileGroup {
+    pub path: PathBuf,
+    /// Log-sum-exp of the chunk scores: at least the best chunk's score, and higher the
+    /// more chunks of the file match. Works for the negative scores of rerankers too.
+    pub score: f32,
+    /// In line order
+    pub results: Vec<SearchResult>,
+}
+
+/// Groups results by file, best file first. Results whose lines overlap a better-ranked
+/// result of the same file, like a class around a matching method, are left out.
+pub fn group_by_file(mut results: Vec<SearchResult>) -> Vec<FileGroup> {
+    results.sort_by(SearchResult::rank_order);
+    let mut groups: Vec<FileGroup> = Vec::new();
+    for result in results {
+        let group = match groups.iter().position(|group| group.path == result.path) {
+            Some(position) => &mut groups[position],
+            None => {
+                groups.push(FileGroup {
+                    path: result.path.clone(),
+                    score: result.score,
+                    results: Vec::new(),
+                });
+                groups.last_mut().unwrap()
+            }
+        };
+        let overlaps = |other: &SearchResult| match (
+            result.start_line.zip(result.end_line),
+            other.start_line.zip(other.end_line),
+        ) {
+            (Some((start, end)), Some((other_start, other_end))) => {
+                start <= other_end && other_start <= end
+            }
+            _ => false,
+        };
+        if !group.results.iter().any(overlaps) {
+            group.results.push(result);
+        }
+    }
+
+    for group in &mut groups {
+        // The first result is the best one, so every exponent is at most 0
+        let best = group.score;
+        let sum: f32 = group
+            .results
+            .iter()
+            .map(|result| (result.score - best).exp())
+            .sum();
+        group.score = best + sum.ln();
+        group.results.sort_by_key(|result| result.start_line);
+    }
+    groups.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    groups
+}
+
+/// Full-text candidates `SearchIndex::explain` ranks a chunk among
+const EXPLAIN_CANDIDATES: usize = 1000;
+
+/// How many times more candidates than requested are fetched when near-duplicates are
+/// collapsed, so the ones left out make room for other results
+const DUPLICATE_CANDIDATES_FACTOR: usize = 3;
+
+/// Words shorter than this are left out of fuzzy queries
+const MIN_FUZZY_WORD_CHARS: usize = 4;
+
+/// How one indexed chunk scores against a query, as reported by `probe why`
+#[derive(Debug)]
+pub struct ChunkExplanation {
+    /// The chunk as a search result; its score is the full-text score after penalties
+    pub result: SearchResult,
+    /// Full-text (BM25) score before penalties, `None` if the chunk doesn't match the query
+    pub bm25_score: Option<f32>,
+    pub matched_terms: Vec<TermMatch>,
+    /// Multipliers applied to the full-text score, each with its reason
+    pub penalties: Vec<(String, f32)>,
+    /// Why the `--filetype`, `--kind` and path filters leave the chunk out
+    pub filtered_out: Vec<String>,
+    /// 1-based position among the full-text candidates, `None` past the first 1000
+    pub fulltext_rank: Option<usize>,
+    /// Score from the reranking model, filled in by `SearchEngine::why`
+    pub rerank_score: Option<f32>,
+    /// 1-based position in the final results, filled in by `SearchEngine::why`
+    pub final_rank: Option<usize>,
+}
+
+/// A query term found in a chunk
+#[derive(Debug)]
+pub struct TermMatch {
+    pub field: String,
+    /// The term as indexed, i.e. lowercased and possibly stemmed
+    pub term: String,
+    /// BM25 contribution of the term before field boosts
+    pub score: f32,
+}
+
+/// The text of a stored chunk, as given to the embedding model
+#[derive(Debug, Clone)]
+pub struct ChunkText {
+    /// Path in the index, with forward slashes
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+}
+
+/// A parsed search query with the fields it runs against
+struct PreparedQuery {
+    searched_fields: Vec<(Field, Field)>,
+    /// The query terms alone, used for snippets and explanations
+    content_query: Box<dyn tantivy::query::Query>,
+    /// `content_query` narrowed down by the filetype, chunk kind and path filters
+    filtered_query: Box<dyn tantivy::query::Query>,
+    /// The `--path`/`--exclude-path` globs together with the query's `path:` terms
+    path_filter: PathFilter,
+}
+
+/// Query-time options shared by `SearchIndex` and `SearchEngine` searches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchOptions {
+    pub limit: usize,
+    /// Skip this many of the best results, to page through them `limit` at a time
+    pub offset: usize,
+    pub filetype: Option<String>,
+    pub context_lines: usize,
+    /// Match unstemmed tokens only, regardless of the stemming configured for the index
+    pub no_stem: bool,
+    /// Match stemmed tokens even if stemming is turned off for the index
+    pub stem: bool,
+    /// Stem the query with this language instead of the configured one
+    pub stem_language: Option<Language>,
+    /// Search for the query's words as written, without splitting identifiers or expanding
+    /// abbreviations
+    pub no_query_expansion: bool,
+    /// Upper bound on the combined size of all candidate snippets; each of the `limit`
+    /// candidates gets an equal share and longer snippets are truncated
+    pub max_candidate_bytes: usize,
+    /// Only return chunks of these lowercase kinds (e.g. `method`); empty matches every kind
+    pub kinds: Vec<String>,
+    /// Fuse the full-text ranking with the chunks closest to the query by embedding
+    pub hybrid: bool,
+    /// Only return chunks of files matching one of these globs; empty matches every file
+    pub paths: Vec<String>,
+    /// Leave out chunks of files matching any of these globs
+    pub exclude_paths: Vec<String>,
+    /// How the query string is interpreted
+    pub mode: QueryMode,
+    /// Leave out results scoring below this; reranker scores when reranking, full-text
+    /// scores otherwise
+    pub min_score: Option<f32>,
+    /// Return the results file by file as `group_by_file` orders them, leaving out results
+    /// overlapping better ones of the same file
+    pub group_by_file: bool,
+    /// When a `QueryMode::Terms` query matches nothing, retry with chunk names and
+    /// declarations containing words within an edit distance of 1-2 of the query's
+    pub fuzzy_fallback: bool,
+    /// Only return chunks overlapping one of these, to narrow down the results of an earlier
+    /// search; empty matches every chunk
+    pub within: Vec<ChunkRange>,
+}
+
+/// Lines of a file a search is narrowed down to, see `SearchOptions::within`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRange {
+    /// Path in the index, with forward slashes
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl ChunkRange {
+    /// The lines of a result; a result without lines covers its whole file
+    pub fn of(result: &SearchResult) -> Self {
+        Self {
+            path: index_path(&result.path),
+            start_line: result.start_line.unwrap_or(0),
+            end_line: result.end_line.unwrap_or(usize::MAX),
+        }
+    }
+}
+
+/// How the query string of a search is interpreted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueryMode {
+    /// Query syntax: words match independently (OR), with `field:`, quotes, `+`/`-` and boosts
+    #[default]
+    Terms,
+    /// The query is a literal string, matched as the exact sequence of its tokens
+    Exact,
+    /// The query is a regular expression matched against single indexed words. Identifiers
+    /// are indexed split at camelCase and punctuation, so `user.*` finds `getUserName` but
+    /// `getUser.*` does not.
+    Regex,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            limit: 5,
+            offset: 0,
+            filetype: None,
+            context_lines: 3,
+            no_stem: false,
+            stem: false,
+            stem_language: None,
+            no_query_expansion: false,
+            max_candidate_bytes: 8 * 1024 * 1024,
+            kinds: Vec::new(),
+            hybrid: false,
+            paths: Vec::new(),
+            exclude_paths: Vec::new(),
+            mode: QueryMode::default(),
+            min_score: None,
+            group_by_file: false,
+            fuzzy_fallback: true,
+            within: Vec::new(),
+        }
+    }
+}
+
+/// The `--path` and `--exclude-path` globs of a search, plus the `path:` terms of its
+/// query. Globs are matched against paths relative to the project root; a glob without a
+/// `/` matches file names in any directory.
+pub(crate) struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    /// From `path:` terms, a file has to match all of them
+    required: Vec<PathTermMatcher>,
+    /// From `-path:` terms, a file must match none of them
+    forbidden: Vec<PathTermMatcher>,
+    /// Line ranges of `SearchOptions::within` by path
+    within: HashMap<String, Vec<(usize, usize)>>,
+}
+
+/// `path:` terms taken out of a query, lowercased
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PathTerms {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl PathTerms {
+    fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+/// A `path:` term matches paths containing it, ignoring case, or is a glob like `--path`
+/// when it has glob characters (`path:*.js`)
+enum PathTermMatcher {
+    Contains(String),
+    Glob(GlobMatcher),
+}
+
+impl PathTermMatcher {
+    fn new(term: &str) -> Result<Self> {
+        Ok(if term.contains(['*', '?', '[', '{']) {
+            Self::Glob(path_glob(term, true)?.compile_matcher())
+        } else {
+            Self::Contains(term.to_lowercase())
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        match self {
+            Self::Contains(term) => path.to_lowercase().contains(term.as_str()),
+            Self::Glob(glob) => glob.is_match(path),
+        }
+    }
+}
+
+/// Compiles a `--path` style glob: relative to the project root, or matching file names in
+/// any directory when it has no `/`
+fn path_glob(glob: &str, case_insensitive: bool) -> Result<Glob> {
+    let glob = glob.trim_start_matches("./");
+    let pattern = if glob.contains('/') {
+        glob.to_string()
+    } else {
+        format!("**/{glob}")
+    };
+    Ok(GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .case_insensitive(case_insensitive)
+        .build()?)
+}
+
+/// Splits the top-level `path:` terms off `query` (`path:handlers`, `-path:test`,
+/// `path:"my dir"`), returning the rest of the query and the terms. Terms inside
+/// parentheses or quotes are left to the query parser.
+pub(crate) fn extract_path_terms(query: &str) -> (String, PathTerms) {
+    const FIELD: &str = "path:";
+    let mut rest = String::with_capacity(query.len());
+    let mut terms = PathTerms::default();
+    let mut depth = 0usize;
+    let mut in_quotes = false;
+    let mut word_start = true;
+    let mut i = 0;
+    while let Some(c) = query[i..].chars().next() {
+        if word_start && depth == 0 && !in_quotes {
+            let word = &query[i..];
+            let (excluded, unsigned) = match word.strip_prefix('-') {
+                Some(unsigned) => (true, unsigned),
+                None => (false, word.strip_prefix('+').unwrap_or(word)),
+            };
+            if let Some(value) = unsigned.strip_prefix(FIELD) {
+                let prefix_len = word.len() - value.len();
+                let (value, len) = match value.strip_prefix('"') {
+                    Some(quoted) => match quoted.find('"') {
+                        Some(end) => (&quoted[..end], prefix_len + end + 2),
+                        None => (quoted, word.len()),
+                    },
+                    None => {
+                        let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                        // Boosts mean nothing for a filter
+                        (
+                            value[..end].split('^').next().unwrap_or(""),
+                            prefix_len + end,
+                        )
+                    }
+                };
+                if !value.is_empty() {
+                    let value = value.to_lowercase();
+                    if excluded {
+                        terms.exclude.push(value);
+                    } else {
+                        terms.include.push(value);
+                    }
+                    i += len;
+                    continue;
+                }
+            }
+        }
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        rest.push(c);
+        word_start = c.is_whitespace() || matches!(c, '(' | '+' | '-');
+        i += c.len_utf8();
+    }
+
+    if !terms.is_empty() {
+        // `path:x AND retry` leaves an operator without a left operand
+        let mut words: Vec<&str> = rest.split_whitespace().collect();
+        let is_operator = |word: &&str| matches!(*word, "AND" | "OR" | "&&" | "||");
+        while words.first().is_some_and(is_operator) {
+            words.remove(0);
+        }
+        while words.last().is_some_and(is_operator) {
+            words.pop();
+        }
+        if words.len() < rest.split_whitespace().count() {
+            rest = words.join(" ");
+        }
+    }
+    (rest, terms)
+}
+
+impl PathFilter {
+    pub(crate) fn new(options: &SearchOptions) -> Result<Self> {
+        let build = |globs: &[String]| -> Result<Option<GlobSet>> {
+            if globs.is_empty() {
+                return Ok(None);
+            }
+            let mut builder = GlobSetBuilder::new();
+            for glob in globs {
+                builder.add(path_glob(glob, false)?);
+            }
+            Ok(Some(builder.build()?))
+        };
+        let mut within: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        for range in &options.within {
+            within
+                .entry(range.path.clone())
+                .or_default()
+                .push((range.start_line, range.end_line));
+        }
+        Ok(Self {
+            include: build(&options.paths)?,
+            exclude: build(&options.exclude_paths)?,
+            required: Vec::new(),
+            forbidden: Vec::new(),
+            within,
+        })
+    }
+
+    /// Also applies the `path:` terms of a query
+    pub(crate) fn with_terms(mut self, terms: &PathTerms) -> Result<Self> {
+        let compile = |terms: &[String]| -> Result<Vec<PathTermMatcher>> {
+            terms
+                .iter()
+                .map(|term| PathTermMatcher::new(term))
+                .collect()
+        };
+        self.required = compile(&terms.include)?;
+        self.forbidden = compile(&terms.exclude)?;
+        Ok(self)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.include.is_none()
+            && self.exclude.is_none()
+            && self.required.is_empty()
+            && self.forbidden.is_empty()
+            && self.within.is_empty()
+    }
+
+    /// Whether only some lines of the files passing the filter are searched
+    pub(crate) fn has_lines(&self) -> bool {
+        !self.within.is_empty()
+    }
+
+    /// Whether the file at `path` (relative, with forward slashes) passes the filter
+    pub(crate) fn matches(&self, path: &str) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|globs| globs.is_match(path))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|globs| globs.is_match(path))
+            && self.required.iter().all(|term| term.matches(path))
+            && !self.forbidden.iter().any(|term| term.matches(path))
+            && (self.within.is_empty() || self.within.contains_key(path))
+    }
+
+    /// Whether the chunk of `path` from `start_line` to `end_line` passes the filter
+    pub(crate) fn matches_lines(&self, path: &str, start_line: usize, end_line: usize) -> bool {
+        self.matches(path)
+            && (self.within.is_empty()
+                || self.within[path]
+                    .iter()
+                    .any(|&(start, end)| start <= end_line && start_line <= end))
+    }
+}
+
+/// Rewrites `name:` and `type:` to the `chunk_name` and `chunk_type` fields they stand
+/// for, so `name:PaymentHandler` only matches chunks called that and `type:class` only
+/// classes. Only whole words are rewritten, not e.g. `filename:`.
+fn expand_field_shorthands(query: &str) -> String {
+    const SHORTHANDS: [&str; 2] = ["name:", "type:"];
+    let mut expanded = String::with_capacity(query.len());
+    let mut word_start = true;
+    for (i, c) in query.char_indices() {
+        if word_start
+            && SHORTHANDS
+                .iter()
+                .any(|shorthand| query[i..].starts_with(shorthand))
+        {
+            expanded.push_str("chunk_");
+        }
+        expanded.push(c);
+        word_start = c.is_whitespace() || matches!(c, '(' | '+' | '-');
+    }
+    expanded
+}
+
+/// Returns the longest prefix of `text` that fits in `max_bytes` and ends on a char boundary
+pub(crate) fn truncate_to_char_boundary(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Cuts a rendered snippet to at most `max_bytes` (plus a short marker), preferring the last
+/// line break that fits and never splitting a highlight escape sequence
+pub(crate) fn truncate_snippet(snippet: &mut String, max_bytes: usize) {
+    if snippet.len() <= max_bytes {
+        return;
+    }
+
+    let mut end = truncate_to_char_boundary(snippet, max_bytes).len();
+    if let Some(line_end) = snippet[..end].rfind('\n').filter(|&i| i > 0) {
+        end = line_end;
+    }
+    if let Some(escape) = snippet[..end].rfind('\x1b') {
+        if !snippet[escape..end].contains('m') {
+            end = escape;
+        }
+    }
+    let highlighted = snippet[..end].contains('\x1b');
+
+    snippet.truncate(end);
+    if highlighted {
+        snippet.push_str("\x1b[0m"); // Close a highlight the cut may have left open
+    }
+    snippet.push_str("\n…");
+}
+
+/// Stores relative paths with `/` separators on every platform, so indexes and results
+/// don't depend on the OS that built them
+pub(crate) fn index_path(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Version of the fields and document layout below. Bump it when an index built by an older
+/// probe can no longer be searched correctly, so imported indexes are rejected instead.
+pub const INDEX_SCHEMA_VERSION: u32 = 2;
+
+/// Memory budget of the index writer, unless probe.yml sets `index.writer_heap_mb`
+pub const DEFAULT_WRITER_HEAP_MB: usize = 50;
+/// Documents indexed between commits, unless probe.yml sets `index.commit_batch_size`
+pub const DEFAULT_COMMIT_BATCH_SIZE: usize = 10_000;
+/// Smallest memory budget tantivy accepts for an index writer
+const MIN_WRITER_HEAP: usize = 15_000_000;
+/// Chunked files waiting for the writer, per indexing thread
+const QUEUED_FILES_PER_THREAD: usize = 4;
+
+const MAX_FILE_SIZE: usize = 512 * 1024; // 512KB
+const MAX_LINE_LENGTH: usize = 8096;
+
+/// Why a file that was picked up by the scanner has no documents in the index
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SkipReason {
+    /// Larger than 512KB
+    TooLarge,
+    /// Has a line longer than 8096 bytes, typically minified or generated code
+    LongLines,
+    /// Not valid UTF-8, left out by versions that didn't decode other encodings
+    Encoding,
+    /// Has a NUL byte in its first 8KB, like executables and other binary files
+    Binary,
+    ReadError(String),
+    ChunkingFailed(String),
+}
+
+impl SkipReason {
+    /// Short label used to group skipped files in summaries
+    pub fn label(&self) -> &'static str {
+        match self {
+            SkipReason::TooLarge => "too large",
+            SkipReason::LongLines => "long lines",
+            SkipReason::Encoding => "not UTF-8",
+            SkipReason::Binary => "binary",
+            SkipReason::ReadError(_) => "read error",
+            SkipReason::ChunkingFailed(_) => "chunking failed",
+        }
+    }
+
+    fn from_read_error(error: &std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::InvalidData => SkipReason::Encoding,
+            _ => SkipReason::ReadError(error.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::TooLarge => write!(f, "larger than {}KB", MAX_FILE_SIZE / 1024),
+            SkipReason::LongLines => write!(f, "has lines longer than {MAX_LINE_LENGTH} bytes"),
+            SkipReason::Encoding => write!(f, "not valid UTF-8"),
+            SkipReason::Binary => write!(f, "binary, contains NUL bytes"),
+            SkipReason::ReadError(error) => write!(f, "could not be read: {error}"),
+            SkipReason::ChunkingFailed(error) => write!(f, "could not be chunked: {error}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// What an indexing worker made of one file
+enum ProcessedFile {
+    /// The file's documents and the size of its text
+    Indexed(IndexedFile, Vec<TantivyDocument>, usize),
+    Skipped(IndexedFile, SkipReason),
+}
+
+/// How the documents of an index are spread over its segments
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SegmentStats {
+    pub segments: usize,
+    pub docs: u64,
+    pub deleted_docs: u64,
+}
+
+/// Numbers of chunks in the index per file extension and per chunk type
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkCounts {
+    pub total: usize,
+    /// Keyed by lowercase extension, with files without one under `(none)`
+    pub by_extension: BTreeMap<String, usize>,
+    pub by_type: BTreeMap<String, usize>,
+}
+
+/// Returns why `content` is not indexed: binary files and files larger than 512KB or with
+/// lines longer than 8096 bytes are left out
+pub(crate) fn skip_reason(content: &str) -> Option<SkipReason> {
+    let head = &content.as_bytes()[..content.len().min(SNIFF_BYTES)];
+    if let Some(reason) = sniff(head) {
+        Some(reason)
+    } else if content.len() > MAX_FILE_SIZE {
+        Some(SkipReason::TooLarge)
+    } else if content.lines().any(|line| line.len() > MAX_LINE_LENGTH) {
+        Some(SkipReason::LongLines)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn is_indexable(content: &str) -> bool {
+    skip_reason(content).is_none()
+}
+
+/// Splits camelCase/snake_case identifiers into separate lowercased tokens. Letters are
+/// matched by Unicode category so accented identifiers split like ASCII ones (`getGröße`
+/// becomes `get`, `größe`), CJK ideographs and kana become one token per character since
+/// they aren't separated by spaces, and emoji are dropped.
+pub(crate) fn camel_case_analyzer(stemmer: Option<Language>) -> TextAnalyzer {
+    const PATTERN: &str = concat!(
+        r"\p{Lu}[\p{Ll}\p{M}]*",               // Capitalized word part: Foo, Größe
+        r"|\p{Ll}[\p{Ll}\p{M}]*",              // Lowercase run: foo, größe
+        r"|[\p{Han}\p{Hiragana}\p{Katakana}]", // Single CJK character
+        r"|\p{L}[\p{L}\p{M}]*",                // Other scripts without case: Hangul, Arabic
+        r"|\p{N}+",                            // Digits
+        r"|[^\p{L}\p{M}\p{N}\p{Extended_Pictographic}]+", // Punctuation and whitespace runs
+    );
+    let builder = TextAnalyzer::builder(RegexTokenizer::new(PATTERN).unwrap())
+        .filter(RemoveLongFilter::limit(40))
+        .filter(LowerCaser);
+
+    match stemmer {
+        Some(language) => builder.filter(Stemmer::new(language)).build(),
+        None => builder.build(),
+    }
+}
+
+/// Wraps the given byte ranges of `text` in terminal highlight codes (none when stdout is not
+/// a terminal). Ranges are sorted, overlaps merged, and ranges that fall outside `text` or
+/// off a UTF-8 char boundary are skipped rather than risking a panic on multi-byte text.
+pub(crate) fn apply_highlights(
+    text: &str,
+    ranges: impl IntoIterator<Item = Range<usize>>,
+) -> String {
+    let mut ranges: Vec<_> = ranges
+        .into_iter()
+        .filter(|range| {
+            range.start < range.end
+                && range.end <= text.len()
+                && text.is_char_boundary(range.start)
+                && text.is_char_boundary(range.end)
+        })
+        .collect();
+
+    // If no highlighting needed, return plain text
+    if ranges.is_empty() {
+        return text.to_string();
+    }
+
+    // Check if we should use colors
+    let use_colors = terminal::use_colors();
+    let (highlight_start, highlight_end) = if use_colors {
+        (terminal::HIGHLIGHT, terminal::RESET)
+    } else {
+        ("", "") // No highlighting when not in terminal
+    };
+
+    // Sort ranges by start position to handle overlapping ranges
+    ranges.sort_by_key(|range| range.start);
+
+    let mut result = String::new();
+    let mut last_end = 0;
+    for range in ranges {
+        // Only highlight the part not already covered by the previous range
+        let start = range.start.max(last_end);
+        if start >= range.end {
+            continue;
+        }
+
+        // Add text before highlight, then the highlighted text
+        result.push_str(&text[last_end..start]);
+        result.push_str(highlight_start);
+        result.push_str(&text[start..range.end]);
+        result.push_str(highlight_end);
+
+        last_end = range.end;
+    }
+
+    // Add remaining text after last highlight
+    result.push_str(&text[last_end..]);
+    result
 }
 
 impl SearchIndex {
@@ -40,27 +995,20 @@ impl SearchIndex {
         index_dir: P,
         language: Language,
         stemming_enabled: bool,
+    ) -> Result<Self> {
+        Self::with_languages(index_dir, language, stemming_enabled, &[])
+    }
+
+    /// Creates an index that can additionally hold documents stemmed with `extra_languages`
+    pub fn with_languages<P: AsRef<Path>>(
+        index_dir: P,
+        language: Language,
+        stemming_enabled: bool,
+        extra_languages: &[Language],
     ) -> Result<Self> {
         let mut schema_builder = Schema::builder();
-        let path_field = schema_builder.add_text_field("path", STORED);
-
-        // Create custom tokenizer for camel case splitting with optional stemming
-        let camel_case_tokenizer = if stemming_enabled {
-            TextAnalyzer::builder(
-                RegexTokenizer::new(r"[a-z]+|[A-Z][a-z]*|[0-9]+|[^a-zA-Z0-9]+").unwrap(),
-            )
-            .filter(RemoveLongFilter::limit(40))
-            .filter(LowerCaser)
-            .filter(Stemmer::new(language))
-            .build()
-        } else {
-            TextAnalyzer::builder(
-                RegexTokenizer::new(r"[a-z]+|[A-Z][a-z]*|[0-9]+|[^a-zA-Z0-9]+").unwrap(),
-            )
-            .filter(RemoveLongFilter::limit(40))
-            .filter(LowerCaser)
-            .build()
-        };
+        // Indexed as a single term so a file's documents can be deleted when it is reindexed
+        let path_field = schema_builder.add_text_field("path", STRING | STORED);
 
         // Configure declaration and body fields with custom tokenizer
         let field_indexing = TextFieldIndexing::default()
@@ -75,20 +1023,55 @@ impl SearchIndex {
         let declaration_field = schema_builder.add_text_field("declaration", field_options.clone());
         let body_field = schema_builder.add_text_field("body", field_options.clone());
 
+        // Unstemmed, index-only copies so stemming can be turned off per query
+        let raw_field_options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer("camel_case_raw")
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+        let declaration_raw_field =
+            schema_builder.add_text_field("declaration_raw", raw_field_options.clone());
+        let body_raw_field = schema_builder.add_text_field("body_raw", raw_field_options);
+
+        let language_field = schema_builder.add_text_field("language", STRING | STORED);
+        let language_fields = extra_languages
+            .iter()
+            .filter(|extra| **extra != language)
+            .map(|&extra| {
+                let name = language_field_name(extra);
+                let options = TextOptions::default()
+                    .set_indexing_options(
+                        TextFieldIndexing::default()
+                            .set_tokenizer(&format!("camel_case_{name}"))
+                            .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+                    )
+                    .set_stored();
+                LanguageFields {
+                    language: extra,
+                    declaration: schema_builder
+                        .add_text_field(&format!("declaration_{name}"), options.clone()),
+                    body: schema_builder.add_text_field(&format!("body_{name}"), options),
+                    name,
+                }
+            })
+            .collect::<Vec<_>>();
+
         let filetype_field = schema_builder.add_text_field("filetype", TEXT | STORED);
         let chunk_type_field = schema_builder.add_text_field("chunk_type", TEXT | STORED);
         let chunk_name_field = schema_builder.add_text_field("chunk_name", TEXT | STORED);
         let start_line_field = schema_builder.add_u64_field("start_line", STORED);
         let end_line_field = schema_builder.add_u64_field("end_line", STORED);
+        let doc_field = schema_builder.add_text_field("doc", field_options);
+        let fingerprint_field = schema_builder.add_bytes_field("fingerprint", STORED);
+        let ordinal_field = schema_builder.add_u64_field("ordinal", STORED);
         let schema = schema_builder.build();
 
         fs::create_dir_all(&index_dir)?;
         let index = Index::create_in_dir(&index_dir, schema.clone())?;
 
-        // Register the custom tokenizer
-        index
-            .tokenizers()
-            .register("camel_case", camel_case_tokenizer);
+        // The main fields are stemmed even with stemming turned off, which only makes queries
+        // use the unstemmed copies by default
+        Self::register_tokenizers(&index, language, true, &language_fields);
 
         Ok(Self {
             index,
@@ -100,6 +1083,25 @@ impl SearchIndex {
             chunk_name_field,
             start_line_field,
             end_line_field,
+            raw_fields: Some((declaration_raw_field, body_raw_field)),
+            doc_field: Some(doc_field),
+            fingerprint_field: Some(fingerprint_field),
+            ordinal_field: Some(ordinal_field),
+            language_field: Some(language_field),
+            language_fields,
+            language,
+            stemming_enabled,
+            language_rules: LanguageRules::default(),
+            score_rules: ScoreRules::default(),
+            query_expansion: QueryExpansion::default(),
+            chunking: ChunkingConfig::default(),
+            duplicates: DuplicatesConfig::default(),
+            verbosity: Verbosity::default(),
+            interrupt: Arc::default(),
+            writer_heap: DEFAULT_WRITER_HEAP_MB * 1024 * 1024,
+            commit_batch_size: DEFAULT_COMMIT_BATCH_SIZE,
+            memory_limit: None,
+            skipped: Vec::new(),
         })
     }
 
@@ -127,28 +1129,23 @@ impl SearchIndex {
         let end_line_field = schema
             .get_field("end_line")
             .unwrap_or_else(|_| schema.get_field("filetype").unwrap());
-
-        // Register the custom tokenizer for existing indexes
-        let camel_case_tokenizer = if stemming_enabled {
-            TextAnalyzer::builder(
-                RegexTokenizer::new(r"[a-z]+|[A-Z][a-z]*|[0-9]+|[^a-zA-Z0-9]+").unwrap(),
-            )
-            .filter(RemoveLongFilter::limit(40))
-            .filter(LowerCaser)
-            .filter(Stemmer::new(language))
-            .build()
-        } else {
-            TextAnalyzer::builder(
-                RegexTokenizer::new(r"[a-z]+|[A-Z][a-z]*|[0-9]+|[^a-zA-Z0-9]+").unwrap(),
-            )
-            .filter(RemoveLongFilter::limit(40))
-            .filter(LowerCaser)
-            .build()
+        let raw_fields = match (
+            schema.get_field("declaration_raw"),
+            schema.get_field("body_raw"),
+        ) {
+            (Ok(declaration), Ok(body)) => Some((declaration, body)),
+            _ => None,
         };
+        let doc_field = schema.get_field("doc").ok();
+        let fingerprint_field = schema.get_field("fingerprint").ok();
+        let ordinal_field = schema.get_field("ordinal").ok();
+        let language_field = schema.get_field("language").ok();
+        let language_fields = Self::language_fields_in(&schema);
 
-        index
-            .tokenizers()
-            .register("camel_case", camel_case_tokenizer);
+        // Register the custom tokenizer for existing indexes. Ones with unstemmed copies
+        // stem the main fields even with stemming turned off, for queries that turn it on.
+        let stem_fields = stemming_enabled || raw_fields.is_some();
+        Self::register_tokenizers(&index, language, stem_fields, &language_fields);
 
         Ok(Self {
             index,
@@ -160,508 +1157,1967 @@ impl SearchIndex {
             chunk_name_field,
             start_line_field,
             end_line_field,
+            raw_fields,
+            doc_field,
+            fingerprint_field,
+            ordinal_field,
+            language_field,
+            language_fields,
+            language,
+            stemming_enabled,
+            language_rules: LanguageRules::default(),
+            score_rules: ScoreRules::default(),
+            query_expansion: QueryExpansion::default(),
+            chunking: ChunkingConfig::default(),
+            duplicates: DuplicatesConfig::default(),
+            verbosity: Verbosity::default(),
+            interrupt: Arc::default(),
+            writer_heap: DEFAULT_WRITER_HEAP_MB * 1024 * 1024,
+            commit_batch_size: DEFAULT_COMMIT_BATCH_SIZE,
+            memory_limit: None,
+            skipped: Vec::new(),
         })
     }
 
-    pub fn index_files<I>(
-        &mut self,
-        files: I,
-        num_threads: usize,
-    ) -> Result<impl Iterator<Item = IndexedFile>>
-    where
-        I: IntoIterator<Item = IndexedFile>,
-    {
-        use rayon::ThreadPoolBuilder;
-        use std::sync::mpsc;
+    /// Finds the `declaration_<language>`/`body_<language>` pairs of an existing index
+    fn language_fields_in(schema: &Schema) -> Vec<LanguageFields> {
+        schema
+            .fields()
+            .filter_map(|(body, entry)| {
+                let name = entry.name().strip_prefix("body_")?;
+                let language = parse_language(name).ok()?;
+                let declaration = schema.get_field(&format!("declaration_{name}")).ok()?;
+                Some(LanguageFields {
+                    language,
+                    name: name.to_string(),
+                    declaration,
+                    body,
+                })
+            })
+            .collect()
+    }
 
-        let mut index_writer: IndexWriter<tantivy::TantivyDocument> =
-            self.index.writer(50_000_000)?; // 50MB heap
+    fn register_tokenizers(
+        index: &Index,
+        language: Language,
+        stem_fields: bool,
+        language_fields: &[LanguageFields],
+    ) {
+        let stemmer = stem_fields.then_some(language);
+        index
+            .tokenizers()
+            .register("camel_case", camel_case_analyzer(stemmer));
+        index
+            .tokenizers()
+            .register("camel_case_raw", camel_case_analyzer(None));
+        for fields in language_fields {
+            index.tokenizers().register(
+                &format!("camel_case_{}", fields.name),
+                camel_case_analyzer(stem_fields.then_some(fields.language)),
+            );
+        }
+    }
 
-        let (doc_tx, doc_rx) = mpsc::channel();
-        let (path_tx, path_rx) = mpsc::channel();
+    /// Sets which files are stemmed with a language other than the default one
+    pub fn with_language_rules(mut self, language_rules: LanguageRules) -> Self {
+        self.language_rules = language_rules;
+        self
+    }
 
-        let files_vec: Vec<_> = files.into_iter().collect();
+    /// Sets the field boosts and score multipliers used to rank matches
+    pub fn with_score_rules(mut self, score_rules: ScoreRules) -> Self {
+        self.score_rules = score_rules;
+        self
+    }
 
-        // Only build global thread pool if it doesn't exist yet
-        if ThreadPoolBuilder::new()
-            .num_threads(num_threads)
-            .build_global()
-            .is_err()
-        {
-            // Global thread pool already exists, which is fine
-        }
-
-        rayon::scope(|s| {
-            // Spawn worker threads to process files
-            for indexed_file in &files_vec {
-                let doc_tx = doc_tx.clone();
-                let path_tx = path_tx.clone();
-                let path_field = self.path_field;
-                let declaration_field = self.declaration_field;
-                let body_field = self.body_field;
-                let filetype_field = self.filetype_field;
-                let chunk_type_field = self.chunk_type_field;
-                let chunk_name_field = self.chunk_name_field;
-                let start_line_field = self.start_line_field;
-                let end_line_field = self.end_line_field;
-                let indexed_file = indexed_file.clone();
-                s.spawn(move |_| {
-                    // Create a new CodeChunker instance for this thread
-                    let mut code_chunker = match CodeChunker::new() {
-                        Ok(chunker) => chunker,
-                        Err(_) => return,
-                    };
+    /// Sets how the words of term queries are expanded before parsing
+    pub fn with_query_expansion(mut self, query_expansion: QueryExpansion) -> Self {
+        self.query_expansion = query_expansion;
+        self
+    }
 
-                    let content = match fs::read_to_string(&indexed_file.disk_path) {
-                        Ok(content) => content,
-                        Err(_) => return, // Skip files we can't read as text
-                    };
+    /// Whether the index has fields for all of the given stemmer languages
+    pub fn supports_languages(&self, languages: &[Language]) -> bool {
+        languages.iter().all(|language| {
+            *language == self.language
+                || self
+                    .language_fields
+                    .iter()
+                    .any(|fields| fields.language == *language)
+        })
+    }
+
+    /// Sets the per-language chunking overrides used when indexing files
+    pub fn with_chunking_config(mut self, chunking: ChunkingConfig) -> Self {
+        self.chunking = chunking;
+        self
+    }
 
-                    // Skip files larger than 512KB or with lines longer than 8096 bytes
-                    const MAX_FILE_SIZE: usize = 512 * 1024; // 512KB
-                    const MAX_LINE_LENGTH: usize = 8096;
+    /// Sets whether chunks are fingerprinted while indexing and what searches do with
+    /// near-duplicates of better results
+    pub fn with_duplicates_config(mut self, duplicates: DuplicatesConfig) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
 
-                    if content.len() > MAX_FILE_SIZE {
-                        return; // Skip large files silently
-                    }
+    /// Sets how much diagnostic output indexing prints (chunk traces at debug level)
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Makes `index_files` stop picking up new files once `interrupt` is set. Files already
+    /// being processed are finished and everything indexed so far is committed.
+    pub fn with_interrupt_flag(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    /// Sets the memory budget of the index writer in bytes
+    pub fn with_writer_heap(mut self, bytes: usize) -> Self {
+        self.writer_heap = bytes;
+        self
+    }
+
+    /// Makes `index_files` commit every `docs` documents, so memory stays bounded and an
+    /// interrupted run keeps what it indexed
+    pub fn with_commit_batch_size(mut self, docs: usize) -> Self {
+        self.commit_batch_size = docs.max(1);
+        self
+    }
+
+    /// Keeps the memory `index_files` uses under roughly `bytes`: the writer's budget is
+    /// lowered to fit and the documents are committed more often than every
+    /// `commit_batch_size` when their text would take more than the rest
+    pub fn with_memory_limit(mut self, bytes: Option<usize>) -> Self {
+        self.memory_limit = bytes;
+        self
+    }
+
+    /// Indexes `files` on `num_threads` threads, committing in batches and showing a progress
+    /// bar on stderr unless quiet. Files are taken from the iterator as workers get to them,
+    /// so it can stream them from the scanner. Returns the files that were indexed or skipped
+    /// (see `skipped_files`), leaving out those an interrupt kept it from reaching.
+    pub fn index_files<I>(
+        &mut self,
+        files: I,
+        num_threads: usize,
+    ) -> Result<impl Iterator<Item = IndexedFile>>
+    where
+        I: IntoIterator<Item = IndexedFile>,
+        I::IntoIter: Send,
+    {
+        use indicatif::{ProgressBar, ProgressStyle};
+        use rayon::ThreadPoolBuilder;
+        use std::sync::mpsc;
+
+        let (writer_heap, uncommitted_bytes_limit) = self.memory_budget();
+        let mut index_writer: IndexWriter<tantivy::TantivyDocument> =
+            self.index.writer(writer_heap)?;
+
+        // Workers wait for the writer once this many chunked files are queued, rather than
+        // holding the documents of the whole repository in memory
+        let num_threads = num_threads.max(1);
+        let (file_tx, file_rx) = mpsc::sync_channel(num_threads * QUEUED_FILES_PER_THREAD);
+
+        let files = files.into_iter();
+        let (min_len, max_len) = files.size_hint();
+        let files = Mutex::new(files);
+
+        // A pool of our own, so that indexing neither depends on nor takes over the global
+        // pool of an application embedding probe
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("probe-index-{i}"))
+            .build()?;
 
-                    if content.lines().any(|line| line.len() > MAX_LINE_LENGTH) {
-                        return; // Skip files with very long lines silently
+        let progress = if self.verbosity.is_quiet() {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(max_len.unwrap_or(min_len) as u64)
+        };
+        progress.set_style(ProgressStyle::with_template(
+            "{bar:30} {pos}/{len} files ({per_sec}) {msg}",
+        )?);
+
+        let chunking = &self.chunking;
+        let debug_trace = self.verbosity.is_debug();
+        let interrupt = &self.interrupt;
+
+        let this = &*self;
+        let batch_size = self.commit_batch_size;
+        let mut processed_files = Vec::new();
+        let mut skipped = Vec::new();
+        let mut chunk_count = 0;
+        let mut uncommitted = 0;
+        let mut uncommitted_bytes = 0;
+        let written = std::thread::scope(|threads| -> Result<()> {
+            // Files are processed on the pool while this thread writes their documents
+            let pool = &pool;
+            let files = &files;
+            threads.spawn(move || {
+                pool.scope(|s| {
+                    for _ in 0..num_threads {
+                        let file_tx = file_tx.clone();
+                        s.spawn(move |_| {
+                            let mut code_chunker = match CodeChunker::with_config(chunking) {
+                                Ok(chunker) => chunker.with_debug_trace(debug_trace),
+                                Err(_) => return,
+                            };
+                            while !interrupt.load(Ordering::Relaxed) {
+                                let next = files.lock().ok().and_then(|mut files| files.next());
+                                let Some(indexed_file) = next else {
+                                    return;
+                                };
+                                let processed = this.process_file(&mut code_chunker, indexed_file);
+                                // The writer stopped after an error
+                                if file_tx.send(processed).is_err() {
+                                    return;
+                                }
+                            }
+                        });
                     }
-                    let extension = indexed_file
-                        .relative_path
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .unwrap_or("");
-                    let chunks = match code_chunker
-                        .chunk_code_for_indexing(&indexed_file.relative_path, &content)
-                    {
-                        Ok(chunks) => chunks,
-                        Err(_) => return,
-                    };
+                });
+            });
 
-                    // Send the file path to the caller
-                    let _ = path_tx.send(indexed_file.clone());
-
-                    if chunks.is_empty() {
-                        let mut doc = tantivy::TantivyDocument::new();
-                        doc.add_text(
-                            path_field,
-                            indexed_file.relative_path.to_string_lossy().as_ref(),
-                        );
-                        doc.add_text(declaration_field, "");
-                        doc.add_text(body_field, &content);
-                        doc.add_text(filetype_field, extension);
-                        doc.add_text(chunk_type_field, "file");
-                        doc.add_text(chunk_name_field, "");
-                        doc.add_u64(start_line_field, 0);
-                        doc.add_u64(
-                            end_line_field,
-                            content.lines().count().saturating_sub(1) as u64,
-                        );
-                        let _ = doc_tx.send(doc);
-                    } else {
-                        for chunk in chunks {
-                            let mut doc = tantivy::TantivyDocument::new();
-                            doc.add_text(
-                                path_field,
-                                indexed_file.relative_path.to_string_lossy().as_ref(),
-                            );
-                            doc.add_text(declaration_field, &chunk.declaration);
-                            doc.add_text(body_field, &chunk.content);
-                            doc.add_text(filetype_field, extension);
-                            doc.add_text(chunk_type_field, format!("{:?}", chunk.chunk_type));
-                            doc.add_text(chunk_name_field, &chunk.name);
-                            doc.add_u64(start_line_field, chunk.start_line as u64);
-                            doc.add_u64(end_line_field, chunk.end_line as u64);
-                            let _ = doc_tx.send(doc);
+            // The documents of the previous version of every file that was reached are
+            // deleted, including ones that are skipped now. Files an interrupt kept us from
+            // reaching keep their old documents until they are indexed.
+            for processed in file_rx {
+                match processed {
+                    ProcessedFile::Indexed(file, docs, bytes) => {
+                        this.delete_path(&index_writer, &file.relative_path);
+                        chunk_count += docs.len();
+                        uncommitted += docs.len();
+                        uncommitted_bytes += bytes;
+                        for doc in docs {
+                            index_writer.add_document(doc)?;
                         }
+                        processed_files.push(file);
                     }
-                });
+                    ProcessedFile::Skipped(file, reason) => {
+                        this.delete_path(&index_writer, &file.relative_path);
+                        skipped.push(SkippedFile {
+                            path: file.relative_path.clone(),
+                            reason,
+                        });
+                        processed_files.push(file);
+                    }
+                }
+                if progress.position() >= progress.length().unwrap_or(0) {
+                    // More files than the iterator said, e.g. streamed from the scanner
+                    progress.inc_length(1);
+                }
+                progress.inc(1);
+                progress.set_message(format!("{chunk_count} chunks"));
+                if uncommitted >= batch_size
+                    || uncommitted_bytes_limit.is_some_and(|limit| uncommitted_bytes >= limit)
+                {
+                    index_writer.commit()?;
+                    uncommitted = 0;
+                    uncommitted_bytes = 0;
+                }
             }
+            Ok(())
         });
-        drop(doc_tx); // Close the channel
-        drop(path_tx); // Close the path channel
+        if let Err(e) = written {
+            // Drop the documents of the batch that failed, keeping the committed ones
+            progress.finish_and_clear();
+            index_writer.rollback()?;
+            return Err(e);
+        }
+        index_writer.commit()?;
+        progress.finish_and_clear();
 
-        // Process all documents from the channel
-        for doc in doc_rx {
-            index_writer.add_document(doc)?;
+        skipped.sort_by(|a, b| a.path.cmp(&b.path));
+        self.skipped = skipped;
+
+        // Return an iterator over the processed file paths
+        Ok(processed_files.into_iter())
+    }
+
+    /// Reads and chunks one file into its documents, or the reason it is skipped
+    fn process_file(
+        &self,
+        code_chunker: &mut CodeChunker,
+        indexed_file: IndexedFile,
+    ) -> ProcessedFile {
+        let skip = |reason| ProcessedFile::Skipped(indexed_file.clone(), reason);
+
+        // Binaries are told apart by their first bytes rather than read
+        match sniff_file(&indexed_file.disk_path) {
+            Ok(Some(reason)) => return skip(reason),
+            Ok(None) => {}
+            Err(e) => return skip(SkipReason::from_read_error(&e)),
         }
+        let content = match read_source(&indexed_file.disk_path) {
+            Ok(content) => content,
+            Err(e) => return skip(SkipReason::from_read_error(&e)),
+        };
 
+        if let Some(reason) = skip_reason(&content) {
+            return skip(reason); // Large or minified files
+        }
+        let extension = indexed_file
+            .relative_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+        let chunks =
+            match code_chunker.chunk_code_for_indexing(&indexed_file.relative_path, &content) {
+                Ok(chunks) => chunks,
+                Err(e) => return skip(SkipReason::ChunkingFailed(e.to_string())),
+            };
+
+        // Documents in files with a stemming override go to that language's fields
+        let override_fields = self
+            .language_rules
+            .language_for(&indexed_file.relative_path)
+            .and_then(|language| {
+                self.language_fields
+                    .iter()
+                    .find(|fields| fields.language == language)
+            });
+        let (declaration_field, body_field) = match override_fields {
+            Some(fields) => (fields.declaration, fields.body),
+            None => (self.declaration_field, self.body_field),
+        };
+        let language_name = override_fields.map(|fields| fields.name.as_str());
+        let fingerprint_field = self.fingerprint_field.filter(|_| self.duplicates.enabled);
+
+        let mut docs = Vec::new();
+        if chunks.is_empty() {
+            let mut doc = tantivy::TantivyDocument::new();
+            doc.add_text(self.path_field, index_path(&indexed_file.relative_path));
+            doc.add_text(declaration_field, "");
+            doc.add_text(body_field, &content);
+            if let Some((_, body_raw_field)) = self.raw_fields {
+                doc.add_text(body_raw_field, &content);
+            }
+            if let (Some(field), Some(name)) = (self.language_field, language_name) {
+                doc.add_text(field, name);
+            }
+            doc.add_text(self.filetype_field, extension);
+            if let Some(field) = fingerprint_field {
+                if let Some(fingerprint) = fingerprint(&content) {
+                    doc.add_bytes(field, fingerprint);
+                }
+            }
+            doc.add_text(self.chunk_type_field, "file");
+            doc.add_text(self.chunk_name_field, "");
+            doc.add_u64(self.start_line_field, 0);
+            doc.add_u64(
+                self.end_line_field,
+                content.lines().count().saturating_sub(1) as u64,
+            );
+            if let Some(field) = self.ordinal_field {
+                doc.add_u64(field, 0);
+            }
+            docs.push(doc);
+        } else {
+            for (ordinal, chunk) in chunks.into_iter().enumerate() {
+                let mut doc = tantivy::TantivyDocument::new();
+                doc.add_text(self.path_field, index_path(&indexed_file.relative_path));
+                doc.add_text(declaration_field, &chunk.declaration);
+                doc.add_text(body_field, &chunk.content);
+                if let Some((declaration_raw_field, body_raw_field)) = self.raw_fields {
+                    doc.add_text(declaration_raw_field, &chunk.declaration);
+                    doc.add_text(body_raw_field, &chunk.content);
+                }
+                if let Some(field) = self.doc_field.filter(|_| !chunk.doc.is_empty()) {
+                    doc.add_text(field, &chunk.doc);
+                }
+                if let Some(field) = fingerprint_field {
+                    let text = format!("{}{}", chunk.declaration, chunk.content);
+                    if let Some(fingerprint) = fingerprint(&text) {
+                        doc.add_bytes(field, fingerprint);
+                    }
+                }
+                if let (Some(field), Some(name)) = (self.language_field, language_name) {
+                    doc.add_text(field, name);
+                }
+                doc.add_text(self.filetype_field, extension);
+                doc.add_text(self.chunk_type_field, format!("{:?}", chunk.chunk_type));
+                doc.add_text(self.chunk_name_field, &chunk.name);
+                doc.add_u64(self.start_line_field, chunk.start_line as u64);
+                doc.add_u64(self.end_line_field, chunk.end_line as u64);
+                if let Some(field) = self.ordinal_field {
+                    doc.add_u64(field, ordinal as u64);
+                }
+                docs.push(doc);
+            }
+        }
+        ProcessedFile::Indexed(indexed_file, docs, content.len())
+    }
+
+    /// The index writer's memory budget and, with a memory limit set, how many bytes of
+    /// file text `index_files` adds before committing. The writer gets at most half the
+    /// limit, the documents waiting to be committed the rest.
+    fn memory_budget(&self) -> (usize, Option<usize>) {
+        match self.memory_limit {
+            Some(limit) => {
+                let writer_heap = self.writer_heap.min(limit / 2).max(MIN_WRITER_HEAP);
+                let uncommitted = limit.saturating_sub(writer_heap).max(MAX_FILE_SIZE);
+                (writer_heap, Some(uncommitted))
+            }
+            None => (self.writer_heap, None),
+        }
+    }
+
+    /// Removes all documents of the given files, e.g. because they were deleted from disk
+    pub fn delete_files(&mut self, relative_paths: &[PathBuf]) -> Result<()> {
+        let mut index_writer: IndexWriter<tantivy::TantivyDocument> =
+            self.index.writer(self.writer_heap)?;
+        for relative_path in relative_paths {
+            self.delete_path(&index_writer, relative_path);
+        }
         index_writer.commit()?;
+        Ok(())
+    }
 
-        // Return an iterator over the processed file paths
-        Ok(path_rx.into_iter())
+    /// Whether documents can be deleted by path. Indexes built before the path was indexed
+    /// can't, and keep the documents of edited and deleted files until they are rebuilt.
+    pub fn supports_deletes(&self) -> bool {
+        self.index
+            .schema()
+            .get_field_entry(self.path_field)
+            .is_indexed()
     }
 
-    pub fn search(
-        &mut self,
-        query_str: &str,
-        limit: usize,
-        filetype: Option<&str>,
-        context_lines: usize,
-    ) -> Result<Vec<SearchResult>> {
-        let reader = self.index.reader_builder().try_into()?;
+    /// Number of segments and of live and deleted documents in the index
+    pub fn segment_stats(&self) -> Result<SegmentStats> {
+        let metas = self.index.searchable_segment_metas()?;
+        Ok(SegmentStats {
+            segments: metas.len(),
+            docs: metas.iter().map(|meta| u64::from(meta.num_docs())).sum(),
+            deleted_docs: metas
+                .iter()
+                .map(|meta| u64::from(meta.num_deleted_docs()))
+                .sum(),
+        })
+    }
 
-        let searcher = reader.searcher();
+    /// Merges all segments into one, which drops the documents of edited and deleted files,
+    /// and removes the files of the merged segments from the index directory
+    pub fn optimize(&mut self) -> Result<()> {
+        let mut index_writer: IndexWriter<tantivy::TantivyDocument> =
+            self.index.writer(self.writer_heap)?;
+        let stats = self.segment_stats()?;
+        if stats.segments > 1 || stats.deleted_docs > 0 {
+            let segment_ids = self.index.searchable_segment_ids()?;
+            index_writer.merge(&segment_ids).wait()?;
+        }
+        index_writer.garbage_collect_files().wait()?;
+        index_writer.wait_merging_threads()?;
+        Ok(())
+    }
 
-        // Create query parser with boosted fields - declaration gets higher boost than body
-        let mut query_parser = QueryParser::for_index(
-            &self.index,
-            vec![
-                self.declaration_field,
-                self.body_field,
-                self.chunk_name_field,
-            ],
-        );
+    /// Moves the documents of every file that `rewrite` maps to a new index path there,
+    /// returning how many documents moved
+    pub fn rewrite_paths(&mut self, rewrite: impl Fn(&str) -> Option<String>) -> Result<usize> {
+        if !self.supports_deletes() {
+            return Err(anyhow::anyhow!(
+                "Paths can't be rewritten in an index built by an older probe, run `probe rebuild` instead"
+            ));
+        }
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        let mut index_writer: IndexWriter<tantivy::TantivyDocument> =
+            self.index.writer(self.writer_heap)?;
+        let mut rewritten_paths = HashSet::new();
+        let mut moved = 0;
+
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader.get_store_reader(64)?;
+            for doc in store_reader.iter::<TantivyDocument>(segment_reader.alive_bitset()) {
+                let doc = doc?;
+                let path = doc
+                    .get_first(self.path_field)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("");
+                let Some(new_path) = rewrite(path).filter(|new_path| new_path != path) else {
+                    continue;
+                };
+                if rewritten_paths.insert(path.to_string()) {
+                    index_writer.delete_term(Term::from_field_text(self.path_field, path));
+                }
 
-        // Set field boosts: declaration > chunk_name > body
-        query_parser.set_field_boost(self.declaration_field, 3.0); // Highest boost for method declarations
-        query_parser.set_field_boost(self.chunk_name_field, 2.5); // High boost for function/class names
-        query_parser.set_field_boost(self.body_field, 1.0); // Baseline boost for method bodies
+                // Stored values carry over as they are; the unstemmed copies are index-only
+                // and get rebuilt from the stored declaration and body
+                let mut new_doc = TantivyDocument::new();
+                new_doc.add_text(self.path_field, &new_path);
+                for field_value in doc.field_values() {
+                    if field_value.field() != self.path_field {
+                        new_doc.add_field_value(field_value.field(), field_value.value().clone());
+                    }
+                }
+                if let Some((declaration_raw_field, body_raw_field)) = self.raw_fields {
+                    let (declaration_field, body_field) = self.stored_fields(&doc);
+                    let text = |field| doc.get_first(field).and_then(|v| v.as_str()).unwrap_or("");
+                    new_doc.add_text(declaration_raw_field, text(declaration_field));
+                    new_doc.add_text(body_raw_field, text(body_field));
+                }
+                index_writer.add_document(new_doc)?;
+                moved += 1;
+            }
+        }
 
-        let content_query = query_parser.parse_query(query_str)?;
+        index_writer.commit()?;
+        Ok(moved)
+    }
 
-        // Build the final query with optional filetype filter
-        let final_query: Box<dyn tantivy::query::Query> = if let Some(filetype) = filetype {
-            let filetype_term = Term::from_field_text(self.filetype_field, filetype);
-            let filetype_query =
-                TermQuery::new(filetype_term, tantivy::schema::IndexRecordOption::Basic);
+    fn delete_path(&self, index_writer: &IndexWriter, relative_path: &Path) {
+        if self.supports_deletes() {
+            index_writer.delete_term(Term::from_field_text(
+                self.path_field,
+                &index_path(relative_path),
+            ));
+        }
+    }
 
-            Box::new(BooleanQuery::new(vec![
-                (Occur::Must, content_query),
-                (Occur::Must, Box::new(filetype_query)),
-            ]))
-        } else {
-            content_query
-        };
+    /// Files the last `index_files` call didn't index, sorted by path
+    pub fn skipped_files(&self) -> &[SkippedFile] {
+        &self.skipped
+    }
 
-        let top_docs = searcher.search(&final_query, &TopDocs::with_limit(limit))?;
-        let mut results = Vec::new();
+    /// Opens every segment of the index, surfacing truncated or unreadable files that
+    /// opening the index alone (which only reads meta.json) doesn't notice
+    pub fn validate(&self) -> Result<()> {
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        for segment_reader in searcher.segment_readers() {
+            segment_reader.get_store_reader(0)?;
+            for field in [self.declaration_field, self.body_field] {
+                segment_reader.inverted_index(field)?;
+            }
+        }
+        Ok(())
+    }
 
-        // Create snippet generators for both body and declaration fields
-        let snippet_query = query_parser.parse_query(query_str)?;
-        let snippet_generator =
-            SnippetGenerator::create(&searcher, &*snippet_query, self.body_field)?;
-        let declaration_snippet_generator =
-            SnippetGenerator::create(&searcher, &*snippet_query, self.declaration_field)?;
+    /// Line ranges of all documents currently in the index, grouped by path
+    pub fn stored_line_ranges(&self) -> Result<HashMap<PathBuf, Vec<(usize, usize)>>> {
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        let mut ranges: HashMap<PathBuf, Vec<(usize, usize)>> = HashMap::new();
+
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader.get_store_reader(64)?;
+            for doc in store_reader.iter::<TantivyDocument>(segment_reader.alive_bitset()) {
+                let doc = doc?;
+                let path = doc
+                    .get_first(self.path_field)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("");
+                let line =
+                    |field| doc.get_first(field).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                ranges
+                    .entry(PathBuf::from(path))
+                    .or_default()
+                    .push((line(self.start_line_field), line(self.end_line_field)));
+            }
+        }
 
-        for (score, doc_address) in top_docs {
-            let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
+        Ok(ranges)
+    }
 
-            let path_text = retrieved_doc
-                .get_first(self.path_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
+    /// Counts the chunks currently in the index by file extension and chunk type
+    pub fn chunk_counts(&self) -> Result<ChunkCounts> {
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        let mut counts = ChunkCounts::default();
+
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader.get_store_reader(64)?;
+            for doc in store_reader.iter::<TantivyDocument>(segment_reader.alive_bitset()) {
+                let doc = doc?;
+                let text = |field| doc.get_first(field).and_then(|v| v.as_str()).unwrap_or("");
+                let extension = Path::new(text(self.path_field))
+                    .extension()
+                    .map_or("(none)".to_string(), |extension| {
+                        extension.to_string_lossy().to_lowercase()
+                    });
+                counts.total += 1;
+                *counts.by_extension.entry(extension).or_default() += 1;
+                *counts
+                    .by_type
+                    .entry(text(self.chunk_type_field).to_string())
+                    .or_default() += 1;
+            }
+        }
 
-            // Extract chunk metadata first
-            let chunk_type = retrieved_doc
-                .get_first(self.chunk_type_field)
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
+        Ok(counts)
+    }
 
-            // Apply path-based and chunk-type score penalties
-            let adjusted_score =
-                Self::apply_score_penalties(score, path_text, chunk_type.as_deref());
+    /// Declaration and body of the chunks stored for the given files, or for every file
+    /// when `relative_paths` is `None`
+    pub fn chunk_texts(&self, relative_paths: Option<&[PathBuf]>) -> Result<Vec<ChunkText>> {
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        let docs = match relative_paths {
+            Some(relative_paths) => {
+                let mut docs = Vec::new();
+                for relative_path in relative_paths {
+                    docs.extend(self.docs_for_path(&searcher, &index_path(relative_path))?);
+                }
+                docs
+            }
+            None => {
+                let mut docs = Vec::new();
+                for segment_reader in searcher.segment_readers() {
+                    let store_reader = segment_reader.get_store_reader(64)?;
+                    for doc in store_reader.iter::<TantivyDocument>(segment_reader.alive_bitset()) {
+                        docs.push(doc?);
+                    }
+                }
+                docs
+            }
+        };
 
-            let chunk_name = retrieved_doc
-                .get_first(self.chunk_name_field)
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
+        Ok(docs
+            .iter()
+            .map(|doc| {
+                let (declaration_field, body_field) = self.stored_fields(doc);
+                let text = |field| doc.get_first(field).and_then(|v| v.as_str()).unwrap_or("");
+                let line =
+                    |field| doc.get_first(field).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                ChunkText {
+                    path: text(self.path_field).to_string(),
+                    start_line: line(self.start_line_field),
+                    end_line: line(self.end_line_field),
+                    text: format!("{}\n{}", text(declaration_field), text(body_field)),
+                }
+            })
+            .collect())
+    }
 
-            let start_line = retrieved_doc
-                .get_first(self.start_line_field)
-                .and_then(|v| v.as_u64())
-                .map(|n| n as usize);
+    /// Builds results for the chunks starting at the given index path and 0-based line, e.g.
+    /// the nearest neighbours of a vector search, with snippets highlighted for `query_str`.
+    /// Chunks that no longer exist or don't pass the filetype and kind filters in `options`
+    /// are left out. Scores are 0, the caller decides the order.
+    pub fn chunk_results(
+        &self,
+        query_str: &str,
+        options: &SearchOptions,
+        chunks: &[(String, usize)],
+    ) -> Result<Vec<SearchResult>> {
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        let prepared = self.prepare_query(&searcher, query_str, options, false)?;
+        let snippet_generators = self.snippet_generators(&searcher, &prepared)?;
+        let max_snippet_bytes = options.max_candidate_bytes / options.limit.max(1);
+        let path_filter = &prepared.path_filter;
 
-            let end_line = retrieved_doc
+        let mut results = Vec::new();
+        for (path, start_line) in chunks {
+            let docs = self.docs_for_path(&searcher, path)?;
+            let Some(doc) = docs.iter().find(|doc| {
+                doc.get_first(self.start_line_field)
+                    .and_then(|v| v.as_u64())
+                    == Some(*start_line as u64)
+            }) else {
+                continue;
+            };
+            let text = |field| doc.get_first(field).and_then(|v| v.as_str()).unwrap_or("");
+            if options
+                .filetype
+                .as_deref()
+                .is_some_and(|filetype| text(self.filetype_field) != filetype)
+            {
+                continue;
+            }
+            let kind = text(self.chunk_type_field).to_lowercase();
+            if !options.kinds.is_empty() && !options.kinds.contains(&kind) {
+                continue;
+            }
+            let end_line = doc
                 .get_first(self.end_line_field)
                 .and_then(|v| v.as_u64())
-                .map(|n| n as usize);
-
-            // Generate snippet with highlighting - for methods/functions, show full content
-            let body_content = retrieved_doc
-                .get_first(self.body_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
+                .map_or(*start_line, |line| line as usize);
+            if !path_filter.matches_lines(path, *start_line, end_line) {
+                continue;
+            }
+            results.push(self.to_search_result(
+                doc,
+                0.0,
+                &snippet_generators,
+                options.context_lines,
+                max_snippet_bytes,
+            )?);
+        }
+        Ok(results)
+    }
 
-            let declaration_content = retrieved_doc
-                .get_first(self.declaration_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
+    /// The `count` chunks stored right before and after each of `results` in its file, in
+    /// file order: the methods around a method, or the class above the first of them.
+    /// Snippets are highlighted for `query_str` and scores are 0. Chunks of indexes built
+    /// by older versions have no ordinals and are ordered by line instead.
+    pub fn chunk_neighbors(
+        &self,
+        query_str: &str,
+        options: &SearchOptions,
+        results: &[SearchResult],
+        count: usize,
+    ) -> Result<Vec<Vec<SearchResult>>> {
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        let prepared = self.prepare_query(&searcher, query_str, options, false)?;
+        let snippet_generators = self.snippet_generators(&searcher, &prepared)?;
+        let max_snippet_bytes = options.max_candidate_bytes / options.limit.max(1);
+        let line = |doc: &TantivyDocument, field| {
+            doc.get_first(field)
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize)
+        };
 
-            let snippet_text = if matches!(chunk_type.as_deref(), Some("Function") | Some("Method"))
-            {
-                // For methods and functions, show the full content with highlighting
-                if body_content.trim().is_empty() {
-                    // For methods without bodies (e.g., interface methods), use declaration content
-                    self.highlight_content(declaration_content, &declaration_snippet_generator)?
-                } else {
-                    // For methods with bodies, combine declaration and body for complete context
-                    let declaration_highlighted = self
-                        .highlight_content(declaration_content, &declaration_snippet_generator)?;
-                    let body_highlighted =
-                        self.highlight_content(body_content, &snippet_generator)?;
-                    format!("{declaration_highlighted}{body_highlighted}")
+        let mut file_docs: HashMap<&Path, Vec<TantivyDocument>> = HashMap::new();
+        let mut neighbors = Vec::new();
+        for result in results {
+            let docs = match file_docs.entry(result.path.as_path()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let mut docs = self.docs_for_path(&searcher, &index_path(&result.path))?;
+                    docs.sort_by_key(|doc| {
+                        let ordinal = self.ordinal_field.and_then(|field| line(doc, field));
+                        (ordinal, line(doc, self.start_line_field))
+                    });
+                    entry.insert(docs)
                 }
-            } else if matches!(chunk_type.as_deref(), Some("Other") | Some("file")) {
-                // For unsupported languages (entire files indexed), show relevant segments with context
-                self.extract_relevant_segment_with_context(
-                    body_content,
-                    &snippet_generator,
-                    context_lines,
-                )?
-            } else {
-                // For other chunk types, use the default snippet behavior
-                let snippet = snippet_generator.snippet_from_doc(&retrieved_doc);
-                self.render_snippet_with_terminal_colors(&snippet)
             };
-
-            results.push(SearchResult {
-                path: PathBuf::from(path_text),
-                score: adjusted_score,
-                snippet: snippet_text,
-                chunk_type,
-                chunk_name,
-                start_line,
-                end_line,
+            let position = docs.iter().position(|doc| {
+                line(doc, self.start_line_field) == result.start_line
+                    && line(doc, self.end_line_field) == result.end_line
             });
+            let Some(position) = position else {
+                neighbors.push(Vec::new());
+                continue;
+            };
+            let around = position.saturating_sub(count)..(position + count + 1).min(docs.len());
+            neighbors.push(
+                around
+                    .filter(|&i| i != position)
+                    .map(|i| {
+                        self.to_search_result(
+                            &docs[i],
+                            0.0,
+                            &snippet_generators,
+                            options.context_lines,
+                            max_snippet_bytes,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            );
         }
+        Ok(neighbors)
+    }
 
-        // Sort results by adjusted score in descending order (highest score first)
-        results.sort_by(|a, b| {
-            b.score
-                .partial_cmp(&a.score)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+    /// Documents stored for the file with the given index path, in index order
+    fn docs_for_path(&self, searcher: &Searcher, path: &str) -> Result<Vec<TantivyDocument>> {
+        let query = TermQuery::new(
+            Term::from_field_text(self.path_field, path),
+            IndexRecordOption::Basic,
+        );
+        let mut doc_addresses: Vec<_> = searcher
+            .search(&query, &DocSetCollector)?
+            .into_iter()
+            .collect();
+        doc_addresses.sort();
+        doc_addresses
+            .into_iter()
+            .map(|doc_address| Ok(searcher.doc(doc_address)?))
+            .collect()
+    }
 
-        Ok(results)
+    /// Chunks stored for the file at `relative_path`, in index order
+    pub fn stored_outline(&self, relative_path: &Path) -> Result<Vec<OutlineItem>> {
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        let docs = self.docs_for_path(&searcher, &index_path(relative_path))?;
+        let items = docs
+            .iter()
+            .map(|doc| {
+                let text = |field| doc.get_first(field).and_then(|v| v.as_str()).unwrap_or("");
+                let line =
+                    |field| doc.get_first(field).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                OutlineItem::new(
+                    text(self.chunk_type_field),
+                    text(self.chunk_name_field),
+                    line(self.start_line_field),
+                    line(self.end_line_field),
+                )
+            })
+            .collect();
+        Ok(items)
     }
 
-    fn apply_score_penalties(score: f32, path: &str, chunk_type: Option<&str>) -> f32 {
-        let mut adjusted_score = score;
+    /// Definitions whose name contains `query`, ignoring case, up to `limit` of them. The
+    /// candidates come from the terms of the chunk name field, so only names sharing a word
+    /// with the query are read. Exact matches come first, then names starting with the
+    /// query, then shorter names. Blocks of lines that aren't definitions are left out.
+    pub fn find_symbols(&self, query: &str, limit: usize) -> Result<Vec<SymbolMatch>> {
+        let wanted = query.trim().to_lowercase();
+        // Names are indexed as lowercase words, the longest word of the query narrows them down
+        let Some(word) = wanted
+            .split(|c: char| !c.is_alphanumeric())
+            .max_by_key(|word| word.len())
+            .filter(|word| !word.is_empty())
+        else {
+            return Ok(Vec::new());
+        };
 
-        // Apply penalty for test files - reduce score by 50% if path contains "test"
-        if path.to_lowercase().contains("test") {
-            adjusted_score *= 0.5;
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        let mut names = HashSet::new();
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(self.chunk_name_field)?;
+            let mut terms = inverted_index.terms().stream()?;
+            while terms.advance() {
+                match std::str::from_utf8(terms.key()) {
+                    Ok(term) if term.contains(word) => {
+                        names.insert(term.to_string());
+                    }
+                    _ => {}
+                }
+            }
         }
+        let query = TermSetQuery::new(
+            names
+                .iter()
+                .map(|name| Term::from_field_text(self.chunk_name_field, name)),
+        );
 
-        // Apply penalty for Class chunks - down-rank them compared to Method chunks
-        // Classes get 60% of their original score to prioritize methods in search results
-        if matches!(
-            chunk_type,
-            Some("Class") | Some("Interface") | Some("Struct")
-        ) {
-            adjusted_score *= 0.6;
+        let mut symbols = Vec::new();
+        for doc_address in searcher.search(&query, &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            let text = |field| doc.get_first(field).and_then(|v| v.as_str()).unwrap_or("");
+            let kind = text(self.chunk_type_field).to_lowercase();
+            let name = text(self.chunk_name_field);
+            if matches!(kind.as_str(), "other" | "file") || !name.to_lowercase().contains(&wanted) {
+                continue;
+            }
+            let line = |field| doc.get_first(field).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+            symbols.push(SymbolMatch {
+                path: PathBuf::from(text(self.path_field)),
+                kind,
+                name: name.to_string(),
+                start_line: line(self.start_line_field),
+                end_line: line(self.end_line_field),
+            });
         }
 
-        adjusted_score
+        let rank = |symbol: &SymbolMatch| {
+            let name = symbol.name.to_lowercase();
+            if name == wanted {
+                0
+            } else if name.starts_with(&wanted) {
+                1
+            } else {
+                2
+            }
+        };
+        symbols.sort_by(|a, b| {
+            rank(a)
+                .cmp(&rank(b))
+                .then_with(|| a.name.len().cmp(&b.name.len()))
+                .then_with(|| a.path.cmp(&b.path))
+                .then_with(|| a.start_line.cmp(&b.start_line))
+        });
+        symbols.truncate(limit);
+        Ok(symbols)
     }
 
-    fn render_snippet_with_terminal_colors(&self, snippet: &Snippet) -> String {
-        let text = snippet.fragment();
-        let highlighted_ranges = snippet.highlighted();
+    pub fn search(
+        &mut self,
+        query_str: &str,
+        limit: usize,
+        filetype: Option<&str>,
+        context_lines: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let options = SearchOptions {
+            limit,
+            filetype: filetype.map(str::to_string),
+            context_lines,
+            ..SearchOptions::default()
+        };
+        self.search_with_options(query_str, &options)
+    }
 
-        // If no highlighting needed, return plain text
-        if highlighted_ranges.is_empty() {
-            return text.to_string();
-        }
+    /// Searches for `text` as a literal string, see `QueryMode::Exact`
+    pub fn search_exact(
+        &mut self,
+        text: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let options = SearchOptions {
+            mode: QueryMode::Exact,
+            ..options.clone()
+        };
+        self.search_with_options(text, &options)
+    }
+
+    /// Searches for words matching the regular expression `pattern`, see `QueryMode::Regex`
+    pub fn search_regex(
+        &mut self,
+        pattern: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let options = SearchOptions {
+            mode: QueryMode::Regex,
+            ..options.clone()
+        };
+        self.search_with_options(pattern, &options)
+    }
 
-        // Check if we should use colors
-        let use_colors = atty::is(Stream::Stdout);
-        let (highlight_start, highlight_end) = if use_colors {
-            ("\x1b[1;33m", "\x1b[0m") // Bold yellow
+    /// Parses `query_str` against the fields searched with `options` and adds the
+    /// filetype and chunk kind filters. `fuzzy` turns a terms query into `fuzzy_query`.
+    fn prepare_query(
+        &self,
+        searcher: &Searcher,
+        query_str: &str,
+        options: &SearchOptions,
+        fuzzy: bool,
+    ) -> Result<PreparedQuery> {
+        // Declaration/body fields to search for the default language, followed by one pair
+        // per extra stemmer language. Unstemmed queries go against the raw copies, which
+        // hold the documents of every language. Regexes match words as written, not their
+        // stems, unless the index predates the raw copies, whose main fields are only
+        // stemmed with stemming turned on.
+        let unstemmed = !self.stemming_enabled && !options.stem;
+        let on_raw = (unstemmed || options.mode == QueryMode::Regex) && self.raw_fields.is_some();
+        let searched_fields: Vec<(Field, Field)> = if options.no_stem || on_raw {
+            let raw_fields = self.raw_fields.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "This index was built without unstemmed fields, run `probe rebuild` to use --no-stem"
+                )
+            })?;
+            vec![raw_fields; self.language_fields.len() + 1]
         } else {
-            ("", "") // No highlighting when not in terminal
+            std::iter::once((self.declaration_field, self.body_field))
+                .chain(
+                    self.language_fields
+                        .iter()
+                        .map(|fields| (fields.declaration, fields.body)),
+                )
+                .collect()
+        };
+        // Doc comments are only indexed stemmed with the default language
+        let doc_field = self.doc_field.filter(|_| !options.no_stem && !on_raw);
+
+        let mut path_filter = PathFilter::new(options)?;
+        let content_query = match options.mode {
+            QueryMode::Terms => {
+                let (query_str, path_terms) = extract_path_terms(query_str);
+                path_filter = path_filter.with_terms(&path_terms)?;
+                if query_str.trim().is_empty() && !path_filter.is_empty() {
+                    // Only `path:` terms, e.g. to list the chunks of matching files
+                    Box::new(AllQuery)
+                } else if fuzzy {
+                    self.fuzzy_query(&searched_fields, &query_str)?
+                } else {
+                    let query_str = if options.no_query_expansion {
+                        query_str
+                    } else {
+                        self.query_expansion.expand(&query_str)
+                    };
+                    self.parse_terms_query(&searched_fields, doc_field, &query_str)?
+                }
+            }
+            QueryMode::Exact => self.exact_query(&searched_fields, query_str)?,
+            QueryMode::Regex => self.regex_query(&searched_fields, query_str)?,
         };
 
-        let mut result = String::new();
-        let mut last_end = 0;
+        // Build the final query with optional filetype and chunk kind filters
+        let mut filters: Vec<(Occur, Box<dyn tantivy::query::Query>)> = Vec::new();
+        if let Some(filetype) = options.filetype.as_deref() {
+            let filetype_term = Term::from_field_text(self.filetype_field, filetype);
+            filters.push((
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    filetype_term,
+                    tantivy::schema::IndexRecordOption::Basic,
+                )),
+            ));
+        }
+        if !options.kinds.is_empty() {
+            // chunk_type is tokenized, so `Method` is indexed as `method`
+            let kind_queries = options
+                .kinds
+                .iter()
+                .map(|kind| {
+                    let kind_term = Term::from_field_text(self.chunk_type_field, kind);
+                    let kind_query: Box<dyn tantivy::query::Query> = Box::new(TermQuery::new(
+                        kind_term,
+                        tantivy::schema::IndexRecordOption::Basic,
+                    ));
+                    (Occur::Should, kind_query)
+                })
+                .collect();
+            filters.push((Occur::Must, Box::new(BooleanQuery::new(kind_queries))));
+        }
+        if !path_filter.is_empty() {
+            let paths = self.indexed_paths(searcher)?;
+            let terms = paths
+                .iter()
+                .filter(|path| path_filter.matches(path))
+                .map(|path| Term::from_field_text(self.path_field, path));
+            filters.push((Occur::Must, Box::new(TermSetQuery::new(terms))));
+        }
+        let filtered_query: Box<dyn tantivy::query::Query> = if filters.is_empty() {
+            content_query.box_clone()
+        } else {
+            filters.insert(0, (Occur::Must, content_query.box_clone()));
+            Box::new(BooleanQuery::new(filters))
+        };
 
-        // Sort ranges by start position to handle overlapping ranges
-        let mut ranges: Vec<_> = highlighted_ranges.to_vec();
-        ranges.sort_by_key(|r| r.start);
+        Ok(PreparedQuery {
+            searched_fields,
+            content_query,
+            filtered_query,
+            path_filter,
+        })
+    }
 
-        for range in ranges {
-            // Add text before highlight
-            if range.start > last_end {
-                result.push_str(&text[last_end..range.start]);
+    /// Parses `query_str` with the query syntax against the chunk name, `searched_fields`
+    /// and the doc comments in `doc_field`
+    fn parse_terms_query(
+        &self,
+        searched_fields: &[(Field, Field)],
+        doc_field: Option<Field>,
+        query_str: &str,
+    ) -> Result<Box<dyn Query>> {
+        // Create query parser with boosted fields - declaration gets higher boost than body
+        let mut query_fields = vec![self.chunk_name_field];
+        for (declaration_field, body_field) in searched_fields {
+            if !query_fields.contains(declaration_field) {
+                query_fields.extend([*declaration_field, *body_field]);
             }
+        }
+        query_fields.extend(doc_field);
+        let mut query_parser = QueryParser::for_index(&self.index, query_fields);
+
+        // Set field boosts, by default declaration > chunk_name > doc > body
+        for (declaration_field, body_field) in searched_fields {
+            query_parser.set_field_boost(*declaration_field, self.score_rules.declaration_boost);
+            query_parser.set_field_boost(*body_field, self.score_rules.body_boost);
+        }
+        query_parser.set_field_boost(self.chunk_name_field, self.score_rules.name_boost);
+        if let Some(doc_field) = doc_field {
+            query_parser.set_field_boost(doc_field, self.score_rules.doc_boost);
+        }
 
-            // Add highlighted text
-            result.push_str(highlight_start);
-            result.push_str(&text[range.start..range.end]);
-            result.push_str(highlight_end);
+        Ok(query_parser.parse_query(&expand_field_shorthands(query_str))?)
+    }
 
-            last_end = range.end;
+    /// Matches chunk names and declarations containing words close to the words of
+    /// `query_str`, for queries with typos. Words are compared as each field's analyzer
+    /// indexes them, allowing one edit for words of up to 5 characters and two for longer
+    /// ones; shorter words than `MIN_FUZZY_WORD_CHARS` are left out, as too much is close
+    /// to them.
+    fn fuzzy_query(
+        &self,
+        searched_fields: &[(Field, Field)],
+        query_str: &str,
+    ) -> Result<Box<dyn Query>> {
+        let mut fields = vec![(self.chunk_name_field, self.score_rules.name_boost)];
+        for (declaration_field, _) in searched_fields {
+            if !fields.iter().any(|(field, _)| field == declaration_field) {
+                fields.push((*declaration_field, self.score_rules.declaration_boost));
+            }
+        }
+        // Operators and field names of the query syntax aren't words to correct
+        let words = query_str
+            .split(|c: char| c.is_whitespace() || "+-()\"^".contains(c))
+            .filter(|word| !matches!(*word, "AND" | "OR" | "NOT"))
+            .map(|word| word.rsplit(':').next().unwrap_or(word))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut word_queries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for (field, boost) in fields {
+            let mut analyzer = self.index.tokenizer_for_field(field)?;
+            let mut stream = analyzer.token_stream(&words);
+            while let Some(token) = stream.next() {
+                let chars = token.text.chars().count();
+                if chars < MIN_FUZZY_WORD_CHARS {
+                    continue;
+                }
+                let distance = if chars <= 5 { 1 } else { 2 };
+                let term = Term::from_field_text(field, &token.text);
+                let query: Box<dyn Query> = Box::new(FuzzyTermQuery::new(term, distance, true));
+                word_queries.push((Occur::Should, Box::new(BoostQuery::new(query, boost))));
+            }
         }
+        Ok(Box::new(BooleanQuery::new(word_queries)))
+    }
 
-        // Add remaining text after last highlight
-        if last_end < text.len() {
-            result.push_str(&text[last_end..]);
+    /// The declaration and body fields of `searched_fields` with their boosts, each once
+    fn boosted_fields(&self, searched_fields: &[(Field, Field)]) -> Vec<(Field, f32)> {
+        let mut fields: Vec<(Field, f32)> = Vec::new();
+        for (declaration_field, body_field) in searched_fields {
+            if !fields.iter().any(|(field, _)| field == declaration_field) {
+                fields.push((*declaration_field, self.score_rules.declaration_boost));
+                fields.push((*body_field, self.score_rules.body_boost));
+            }
         }
+        fields
+    }
 
-        result
+    /// Matches `text` as a phrase in any of the searched fields. The text is tokenized by
+    /// each field's analyzer, so it matches where indexing produced the same sequence of
+    /// words, punctuation and whitespace.
+    fn exact_query(
+        &self,
+        searched_fields: &[(Field, Field)],
+        text: &str,
+    ) -> Result<Box<dyn Query>> {
+        let text = text.trim();
+        let mut field_queries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for (field, boost) in self.boosted_fields(searched_fields) {
+            let mut analyzer = self.index.tokenizer_for_field(field)?;
+            let mut terms = Vec::new();
+            let mut stream = analyzer.token_stream(text);
+            while let Some(token) = stream.next() {
+                terms.push((token.position, Term::from_field_text(field, &token.text)));
+            }
+            let query: Box<dyn Query> = match terms.len() {
+                0 => continue,
+                1 => Box::new(TermQuery::new(
+                    terms.remove(0).1,
+                    IndexRecordOption::WithFreqs,
+                )),
+                // Positions keep the gaps of overly long tokens dropped by the analyzer
+                _ => Box::new(PhraseQuery::new_with_offset(terms)),
+            };
+            let boosted: Box<dyn Query> = Box::new(BoostQuery::new(query, boost));
+            field_queries.push((Occur::Should, boosted));
+        }
+        if field_queries.is_empty() {
+            return Err(anyhow::anyhow!("Nothing to search for in \"{text}\""));
+        }
+        Ok(Box::new(BooleanQuery::new(field_queries)))
     }
 
-    fn highlight_content(
+    /// Matches words of the searched fields against the regular expression `pattern`,
+    /// ignoring case since indexed words are lowercase
+    fn regex_query(
         &self,
-        content: &str,
-        snippet_generator: &SnippetGenerator,
-    ) -> Result<String> {
-        // Generate snippet to get highlight ranges
-        let snippet = snippet_generator.snippet(content);
-        let highlighted_ranges = snippet.highlighted();
-        let highlight_fragment_offset = content.find(snippet.fragment()).unwrap_or(0);
+        searched_fields: &[(Field, Field)],
+        pattern: &str,
+    ) -> Result<Box<dyn Query>> {
+        let pattern = format!("(?i){pattern}");
+        let field_queries = self
+            .boosted_fields(searched_fields)
+            .into_iter()
+            .map(|(field, boost)| -> Result<(Occur, Box<dyn Query>)> {
+                let query = RegexQuery::from_pattern(&pattern, field)
+                    .map_err(|e| anyhow::anyhow!("Invalid regex {pattern:?}: {e}"))?;
+                let boosted: Box<dyn Query> = Box::new(BoostQuery::new(Box::new(query), boost));
+                Ok((Occur::Should, boosted))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Box::new(BooleanQuery::new(field_queries)))
+    }
 
-        // If no highlighting needed, return the full content
-        if highlighted_ranges.is_empty() {
-            return Ok(content.to_string());
+    /// Every path in the index, read from the path field's term dictionary
+    fn indexed_paths(&self, searcher: &Searcher) -> Result<HashSet<String>> {
+        if !self.supports_deletes() {
+            return Err(anyhow::anyhow!(
+                "This index was built without an indexed path field, run `probe rebuild` to filter by path"
+            ));
+        }
+        let mut paths = HashSet::new();
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(self.path_field)?;
+            let mut terms = inverted_index.terms().stream()?;
+            while terms.advance() {
+                if let Ok(path) = std::str::from_utf8(terms.key()) {
+                    paths.insert(path.to_string());
+                }
+            }
         }
+        Ok(paths)
+    }
 
-        // Check if we should use colors
-        let use_colors = atty::is(Stream::Stdout);
-        let (highlight_start, highlight_end) = if use_colors {
-            ("\x1b[1;33m", "\x1b[0m") // Bold yellow
-        } else {
-            ("", "")
+    /// Snippet generators for both body and declaration fields of every language
+    fn snippet_generators(
+        &self,
+        searcher: &Searcher,
+        prepared: &PreparedQuery,
+    ) -> Result<Vec<(SnippetGenerator, SnippetGenerator)>> {
+        prepared
+            .searched_fields
+            .iter()
+            .map(|(declaration_field, body_field)| {
+                Ok((
+                    SnippetGenerator::create(
+                        searcher,
+                        &*prepared.content_query,
+                        *declaration_field,
+                    )?,
+                    SnippetGenerator::create(searcher, &*prepared.content_query, *body_field)?,
+                ))
+            })
+            .collect()
+    }
+
+    pub fn search_with_options(
+        &mut self,
+        query_str: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        if self.duplicates.enabled && self.fingerprint_field.is_some() {
+            return self.search_without_duplicates(query_str, options);
+        }
+        let mut results = self
+            .search_iter(query_str, options)?
+            .collect::<Result<Vec<_>>>()?;
+
+        // Sort results by adjusted score in descending order (highest score first)
+        results.sort_by(SearchResult::rank_order);
+
+        Ok(results)
+    }
+
+    /// Searches extra candidates from the top, collapses or down-ranks the near-duplicates
+    /// among them and cuts the requested page out of what is left
+    fn search_without_duplicates(
+        &self,
+        query_str: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let candidate_options = SearchOptions {
+            offset: 0,
+            limit: (options.offset + options.limit).saturating_mul(DUPLICATE_CANDIDATES_FACTOR),
+            max_candidate_bytes: options
+                .max_candidate_bytes
+                .saturating_mul(DUPLICATE_CANDIDATES_FACTOR),
+            // Like paging past the last match in the index, later pages don't go fuzzy
+            fuzzy_fallback: options.fuzzy_fallback && options.offset == 0,
+            ..options.clone()
         };
+        let mut candidates = self
+            .search_fingerprinted(query_str, &candidate_options)?
+            .collect::<Result<Vec<_>>>()?;
+        candidates.sort_by(|a, b| a.0.rank_order(&b.0));
+
+        let mut results = resolve_duplicates(candidates, &self.duplicates);
+        results.drain(..options.offset.min(results.len()));
+        results.truncate(options.limit);
+        Ok(results)
+    }
 
-        let mut result = String::new();
-        let mut last_end = 0;
+    /// Like `search_with_options`, but loads the documents and builds the snippets of the
+    /// top `options.limit` matches after the first `options.offset` one at a time as the
+    /// iterator is advanced, so callers can
+    /// show the first results right away and stop early. Results come in order of their
+    /// full-text score, before the path and chunk type penalties are applied, and
+    /// near-duplicates are not collapsed.
+    pub fn search_iter<'a>(
+        &'a self,
+        query_str: &str,
+        options: &SearchOptions,
+    ) -> Result<impl Iterator<Item = Result<SearchResult>> + 'a> {
+        Ok(self
+            .search_fingerprinted(query_str, options)?
+            .map(|result| result.map(|(result, _)| result)))
+    }
 
-        // Sort ranges by start position to handle overlapping ranges
-        let mut ranges: Vec<_> = highlighted_ranges.to_vec();
-        ranges.sort_by_key(|r| r.start);
+    /// Like `search_iter`, with the near-duplicate fingerprint stored for each result
+    fn search_fingerprinted<'a>(
+        &'a self,
+        query_str: &str,
+        options: &SearchOptions,
+    ) -> Result<impl Iterator<Item = Result<(SearchResult, Option<Vec<u64>>)>> + 'a> {
+        let limit = options.limit;
+        let max_snippet_bytes = options.max_candidate_bytes / limit.max(1);
+        let context_lines = options.context_lines;
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
 
-        for range in ranges {
-            // Add text before highlight
-            if (range.start + highlight_fragment_offset) > last_end {
-                result.push_str(&content[last_end..range.start + highlight_fragment_offset]);
+        let searcher = reader.searcher();
+        let mut prepared = self.prepare_query(&searcher, query_str, options, false)?;
+
+        let mut top_docs = self.top_docs(&searcher, &prepared, options)?;
+        // Nothing matched, maybe because of a typo, rather than paging past the last match
+        let fuzzy = top_docs.is_empty()
+            && options.offset == 0
+            && options.fuzzy_fallback
+            && options.mode == QueryMode::Terms
+            && !query_str.trim().is_empty();
+        if fuzzy {
+            prepared = self.prepare_query(&searcher, query_str, options, true)?;
+            top_docs = self.top_docs(&searcher, &prepared, options)?;
+        }
+        let snippet_generators = self.snippet_generators(&searcher, &prepared)?;
+
+        Ok(top_docs.into_iter().map(move |(score, doc_address)| {
+            let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
+            let mut result = self.to_search_result(
+                &retrieved_doc,
+                score,
+                &snippet_generators,
+                context_lines,
+                max_snippet_bytes,
+            )?;
+            result.fuzzy = fuzzy;
+            let fingerprint = self
+                .fingerprint_field
+                .and_then(|field| retrieved_doc.get_first(field))
+                .and_then(|value| value.as_bytes())
+                .and_then(parse_fingerprint);
+            Ok((result, fingerprint))
+        }))
+    }
+
+    /// The best `options.limit` matches of `prepared` after the first `options.offset`, with
+    /// their full-text scores. Chunk lines aren't indexed, so when only some lines of the
+    /// files are searched every match in those files is loaded to check its lines.
+    fn top_docs(
+        &self,
+        searcher: &Searcher,
+        prepared: &PreparedQuery,
+        options: &SearchOptions,
+    ) -> Result<Vec<(f32, DocAddress)>> {
+        let path_filter = &prepared.path_filter;
+        if !path_filter.has_lines() {
+            let collector = TopDocs::with_limit(options.limit).and_offset(options.offset);
+            return Ok(searcher.search(&prepared.filtered_query, &collector)?);
+        }
+
+        let every_match = TopDocs::with_limit(searcher.num_docs().max(1) as usize);
+        let mut top_docs = Vec::new();
+        for (score, address) in searcher.search(&prepared.filtered_query, &every_match)? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let path = doc.get_first(self.path_field).and_then(|v| v.as_str());
+            let line = |field| doc.get_first(field).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+            let (start_line, end_line) = (line(self.start_line_field), line(self.end_line_field));
+            if path_filter.matches_lines(path.unwrap_or(""), start_line, end_line) {
+                top_docs.push((score, address));
             }
+        }
+        Ok(top_docs
+            .into_iter()
+            .skip(options.offset)
+            .take(options.limit)
+            .collect())
+    }
 
-            // Add highlighted text
-            result.push_str(highlight_start);
-            result.push_str(
-                &content[range.start + highlight_fragment_offset
-                    ..range.end + highlight_fragment_offset],
-            );
-            result.push_str(highlight_end);
+    /// Explains how the chunks stored for `relative_path` score against `query_str`, or only
+    /// the innermost chunk containing the 0-based `line`. Empty when the file has no documents.
+    pub fn explain(
+        &self,
+        query_str: &str,
+        options: &SearchOptions,
+        relative_path: &Path,
+        line: Option<usize>,
+    ) -> Result<Vec<ChunkExplanation>> {
+        let reader: IndexReader = self.index.reader_builder().try_into()?;
+        let searcher = reader.searcher();
+        let prepared = self.prepare_query(&searcher, query_str, options, false)?;
+        let path_filter = &prepared.path_filter;
+        let wanted = index_path(relative_path);
+
+        let mut targets = Vec::new();
+        for (segment_ord, segment_reader) in searcher.segment_readers().iter().enumerate() {
+            let store_reader = segment_reader.get_store_reader(64)?;
+            for doc_id in segment_reader.doc_ids_alive() {
+                let doc: TantivyDocument = store_reader.get(doc_id)?;
+                if doc.get_first(self.path_field).and_then(|v| v.as_str()) != Some(wanted.as_str())
+                {
+                    continue;
+                }
+                let stored_line = |field| doc.get_first(field).and_then(|v| v.as_u64());
+                let lines = (
+                    stored_line(self.start_line_field).unwrap_or(0),
+                    stored_line(self.end_line_field).unwrap_or(u64::MAX),
+                );
+                let contains_line =
+                    line.is_none_or(|line| lines.0 <= line as u64 && line as u64 <= lines.1);
+                if contains_line {
+                    let span = lines.1 - lines.0;
+                    targets.push((span, DocAddress::new(segment_ord as u32, doc_id), doc));
+                }
+            }
+        }
+        // A line picks the innermost chunk around it, e.g. the method rather than its class
+        if line.is_some() {
+            let innermost = targets.iter().map(|(span, _, _)| *span).min();
+            targets.retain(|(span, _, _)| Some(*span) == innermost);
+        }
+        if targets.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut terms: Vec<Term> = Vec::new();
+        prepared.content_query.query_terms(&mut |term, _| {
+            if !terms.contains(term) {
+                terms.push(term.clone());
+            }
+        });
+
+        // Where the chunk lands among the full-text candidates once penalties are applied
+        let mut candidates = searcher
+            .search(
+                &prepared.filtered_query,
+                &TopDocs::with_limit(EXPLAIN_CANDIDATES),
+            )?
+            .into_iter()
+            .map(|(score, address)| {
+                let doc: TantivyDocument = searcher.doc(address)?;
+                let text = |field| doc.get_first(field).and_then(|v| v.as_str());
+                let score = self.score_rules.apply(
+                    score,
+                    text(self.path_field).unwrap_or(""),
+                    text(self.chunk_type_field),
+                );
+                Ok((score, address))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let max_snippet_bytes = options.max_candidate_bytes / options.limit.max(1);
+        let snippet_generators = self.snippet_generators(&searcher, &prepared)?;
+        let schema = self.index.schema();
+        let mut explanations = Vec::new();
+        for (_, address, doc) in targets {
+            let bm25_score = prepared
+                .content_query
+                .explain(&searcher, address)
+                .ok()
+                .map(|explanation| explanation.value());
+            let matched_terms = terms
+                .iter()
+                .filter_map(|term| {
+                    let query = TermQuery::new(term.clone(), IndexRecordOption::WithFreqs);
+                    let explanation = query.explain(&searcher, address).ok()?;
+                    Some(TermMatch {
+                        field: schema.get_field_name(term.field()).to_string(),
+                        term: term.value().as_str()?.to_string(),
+                        score: explanation.value(),
+                    })
+                })
+                .collect();
+
+            let result = self.to_search_result(
+                &doc,
+                bm25_score.unwrap_or(0.0),
+                &snippet_generators,
+                options.context_lines,
+                max_snippet_bytes,
+            )?;
+            let text = |field| doc.get_first(field).and_then(|v| v.as_str());
+            let mut filtered_out = Vec::new();
+            if let Some(filetype) = options.filetype.as_deref() {
+                if text(self.filetype_field) != Some(filetype) {
+                    filtered_out.push(format!("file type is not {filetype}"));
+                }
+            }
+            let kind = text(self.chunk_type_field).unwrap_or("").to_lowercase();
+            if !options.kinds.is_empty() && !options.kinds.contains(&kind) {
+                filtered_out.push(format!("{kind} chunks are not among --kind"));
+            }
+            let path = text(self.path_field).unwrap_or("");
+            if !path_filter.matches(path) {
+                filtered_out
+                    .push("path is excluded by --path, --exclude-path or path: terms".to_string());
+            } else if !path_filter.matches_lines(
+                path,
+                result.start_line.unwrap_or(0),
+                result.end_line.unwrap_or(usize::MAX),
+            ) {
+                filtered_out.push("lines are outside the results being refined".to_string());
+            }
 
-            last_end = range.end + highlight_fragment_offset;
+            explanations.push(ChunkExplanation {
+                penalties: self.score_rules.penalties(
+                    text(self.path_field).unwrap_or(""),
+                    result.chunk_type.as_deref(),
+                ),
+                fulltext_rank: candidates
+                    .iter()
+                    .position(|(_, candidate)| *candidate == address)
+                    .map(|position| position + 1),
+                bm25_score,
+                matched_terms,
+                filtered_out,
+                result,
+                rerank_score: None,
+                final_rank: None,
+            });
         }
+        explanations.sort_by_key(|explanation| explanation.result.start_line);
+        Ok(explanations)
+    }
+
+    /// Builds the result for a matching document, applying the score penalties to its
+    /// full-text `score` and highlighting the query terms in its snippet
+    fn to_search_result(
+        &self,
+        retrieved_doc: &TantivyDocument,
+        score: f32,
+        snippet_generators: &[(SnippetGenerator, SnippetGenerator)],
+        context_lines: usize,
+        max_snippet_bytes: usize,
+    ) -> Result<SearchResult> {
+        let path_text = retrieved_doc
+            .get_first(self.path_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+
+        // Extract chunk metadata first
+        let chunk_type = retrieved_doc
+            .get_first(self.chunk_type_field)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        // Apply path-based and chunk-type score penalties
+        let adjusted_score = self
+            .score_rules
+            .apply(score, path_text, chunk_type.as_deref());
+
+        let chunk_name = retrieved_doc
+            .get_first(self.chunk_name_field)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        let start_line = retrieved_doc
+            .get_first(self.start_line_field)
+            .and_then(|v| v.as_u64())
+            .map(|n| n as usize);
+
+        let end_line = retrieved_doc
+            .get_first(self.end_line_field)
+            .and_then(|v| v.as_u64())
+            .map(|n| n as usize);
+
+        // Content is stored in the fields of the language the document was stemmed with
+        let language_index = self.language_index(retrieved_doc);
+        let (stored_declaration_field, stored_body_field) = self.stored_fields(retrieved_doc);
+        let (declaration_snippet_generator, snippet_generator) =
+            &snippet_generators[language_index];
+
+        // Generate snippet with highlighting - for methods/functions, show full content
+        let body_content = retrieved_doc
+            .get_first(stored_body_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+
+        let declaration_content = retrieved_doc
+            .get_first(stored_declaration_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+
+        let mut snippet_line = None;
+        let mut snippet_text = if matches!(chunk_type.as_deref(), Some("Function") | Some("Method"))
+        {
+            // Lines of the snippet that aren't part of the chunk are the enclosing
+            // declarations above it
+            let shown_body = if body_content.trim().is_empty() {
+                ""
+            } else {
+                body_content
+            };
+            let snippet_lines =
+                declaration_content.matches('\n').count() + shown_body.matches('\n').count() + 1;
+            snippet_line = start_line.zip(end_line).map(|(start, end)| {
+                start.saturating_sub(snippet_lines.saturating_sub(end + 1 - start))
+            });
+
+            // Full bodies can be large, only highlight what fits in this candidate's share
+            let declaration_content =
+                truncate_to_char_boundary(declaration_content, max_snippet_bytes);
+            let body_content = truncate_to_char_boundary(
+                body_content,
+                max_snippet_bytes.saturating_sub(declaration_content.len()),
+            );
 
-        // Add remaining text after last highlight
-        if last_end < content.len() {
-            result.push_str(&content[last_end..]);
+            // For methods and functions, show the full content with highlighting
+            if body_content.trim().is_empty() {
+                // For methods without bodies (e.g., interface methods), use declaration content
+                self.highlight_content(declaration_content, declaration_snippet_generator)?
+            } else {
+                // For methods with bodies, combine declaration and body for complete context
+                let declaration_highlighted =
+                    self.highlight_content(declaration_content, declaration_snippet_generator)?;
+                let body_highlighted = self.highlight_content(body_content, snippet_generator)?;
+                format!("{declaration_highlighted}{body_highlighted}")
+            }
+        } else if matches!(chunk_type.as_deref(), Some("Other") | Some("file")) {
+            // For unsupported languages (entire files indexed), show relevant segments with context
+            let (segment, segment_line) = self.extract_relevant_segment_with_context(
+                body_content,
+                snippet_generator,
+                context_lines,
+            )?;
+            snippet_line = start_line.map(|start| start + segment_line);
+            segment
+        } else {
+            // For other chunk types, use the default snippet behavior
+            let snippet = snippet_generator.snippet(body_content);
+            self.render_snippet_with_terminal_colors(&snippet)
+        };
+        truncate_snippet(&mut snippet_text, max_snippet_bytes);
+
+        Ok(SearchResult {
+            path: PathBuf::from(path_text),
+            score: adjusted_score,
+            snippet: snippet_text,
+            chunk_type,
+            chunk_name,
+            start_line,
+            end_line,
+            snippet_line,
+            fuzzy: false,
+            duplicates: Vec::new(),
+        })
+    }
+
+    /// Position of the document's stemmer language in `language_fields`, offset by one
+    /// so that 0 stands for the default language
+    /// Declaration and body fields holding the content of `doc`
+    fn stored_fields(&self, doc: &TantivyDocument) -> (Field, Field) {
+        match self.language_index(doc) {
+            0 => (self.declaration_field, self.body_field),
+            i => (
+                self.language_fields[i - 1].declaration,
+                self.language_fields[i - 1].body,
+            ),
         }
+    }
+
+    fn language_index(&self, doc: &TantivyDocument) -> usize {
+        let name = self
+            .language_field
+            .and_then(|field| doc.get_first(field))
+            .and_then(|v| v.as_str());
+        name.and_then(|name| {
+            self.language_fields
+                .iter()
+                .position(|fields| fields.name == name)
+        })
+        .map_or(0, |position| position + 1)
+    }
 
-        Ok(result)
+    fn render_snippet_with_terminal_colors(&self, snippet: &Snippet) -> String {
+        apply_highlights(snippet.fragment(), snippet.highlighted().iter().cloned())
     }
 
-    /// Extract the most relevant segment from a file with context lines around it.
+    fn highlight_content(
+        &self,
+        content: &str,
+        snippet_generator: &SnippetGenerator,
+    ) -> Result<String> {
+        // Generate snippet to get highlight ranges
+        let snippet = snippet_generator.snippet(content);
+        let highlight_fragment_offset = content.find(snippet.fragment()).unwrap_or(0);
+
+        // Highlight ranges are relative to the fragment, shift them onto the full content
+        let ranges = snippet.highlighted().iter().map(|range| {
+            range.start + highlight_fragment_offset..range.end + highlight_fragment_offset
+        });
+        Ok(apply_highlights(content, ranges))
+    }
+
+    /// Extract the most relevant segment from a file with context lines around it, along
+    /// with the line of `content` it starts on.
     /// This is used for unsupported languages where entire files are indexed.
     fn extract_relevant_segment_with_context(
         &self,
         content: &str,
         snippet_generator: &SnippetGenerator,
         context_lines: usize,
-    ) -> Result<String> {
+    ) -> Result<(String, usize)> {
         // Generate snippet to find the most relevant fragment
         let snippet = snippet_generator.snippet(content);
         let fragment = snippet.fragment();
         let highlighted_ranges = snippet.highlighted();
 
+        // Find where the fragment appears in the full content
+        let fragment_offset = content.find(fragment).unwrap_or(0);
+
         // If no highlighting, just return the default snippet
         if highlighted_ranges.is_empty() {
-            return Ok(self.render_snippet_with_terminal_colors(&snippet));
+            return Ok((
+                self.render_snippet_with_terminal_colors(&snippet),
+                content[..fragment_offset].matches('\n').count(),
+            ));
         }
 
-        // Find where the fragment appears in the full content
-        let fragment_offset = content.find(fragment).unwrap_or(0);
+        // Byte offset where each line starts, measured on the content itself so that
+        // line endings of any length (\n or \r\n) are accounted for
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+            .filter(|&start| start < content.len())
+            .collect();
 
-        // Convert content to lines for easier line-based extraction
-        let lines: Vec<&str> = content.lines().collect();
+        // Find which line contains the first match; the fragment can start lines earlier
+        let first_match_offset = fragment_offset + highlighted_ranges[0].start;
+        let match_line_idx = line_starts
+            .partition_point(|&start| start <= first_match_offset)
+            .saturating_sub(1);
 
-        // Find which line contains the fragment
-        let mut byte_count = 0;
-        let mut fragment_line_idx = 0;
-        for (idx, line) in lines.iter().enumerate() {
-            let line_end = byte_count + line.len();
-            if fragment_offset >= byte_count && fragment_offset < line_end {
-                fragment_line_idx = idx;
-                break;
+        // Calculate the range of lines to extract (with context)
+        let start_line = match_line_idx.saturating_sub(context_lines);
+        let end_line = (match_line_idx + context_lines + 1).min(line_starts.len());
+
+        // Extract the segment as a slice of the content so highlight offsets stay valid
+        let segment_start_offset = line_starts.get(start_line).copied().unwrap_or(0);
+        let segment_end_offset = line_starts.get(end_line).copied().unwrap_or(content.len());
+        let segment =
+            content[segment_start_offset..segment_end_offset].trim_end_matches(['\r', '\n']);
+
+        // Move the highlight ranges from fragment to segment coordinates, clipping any
+        // that extend past the segment
+        let ranges = highlighted_ranges.iter().filter_map(|range| {
+            let start = (fragment_offset + range.start).max(segment_start_offset);
+            let end = (fragment_offset + range.end).min(segment_start_offset + segment.len());
+            if start < end {
+                Some(start - segment_start_offset..end - segment_start_offset)
+            } else {
+                None
             }
-            byte_count = line_end + 1; // +1 for newline
-        }
+        });
 
-        // Calculate the range of lines to extract (with context)
-        let start_line = fragment_line_idx.saturating_sub(context_lines);
-        let end_line = (fragment_line_idx + context_lines + 1).min(lines.len());
+        Ok((apply_highlights(segment, ranges), start_line))
+    }
+}
 
-        // Extract the segment
-        let segment_lines = &lines[start_line..end_line];
-        let segment = segment_lines.join("\n");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_highlights_skips_ranges_off_char_boundaries() {
+        let text = "größe = 1";
+        // 'ö' and 'ß' are two bytes each, so byte 3 is inside 'ö'
+        assert_eq!(apply_highlights(text, [1..3, 2..4]), text);
+        assert_eq!(apply_highlights(text, std::iter::once(0..100)), text);
+        // Overlapping ranges are merged instead of duplicating text
+        assert_eq!(apply_highlights(text, [0..7, 4..9]), text);
+    }
 
-        // Calculate the offset adjustment for highlighting
-        let mut segment_start_offset = 0;
-        for line in &lines[0..start_line] {
-            segment_start_offset += line.len() + 1; // +1 for newline
-        }
+    #[test]
+    fn test_index_path_uses_forward_slashes() {
+        let path: PathBuf = ["src", "nested", "lib.rs"].iter().collect();
+        assert_eq!(index_path(&path), "src/nested/lib.rs");
+    }
 
-        // Highlight the segment using the original highlight ranges
-        let use_colors = atty::is(Stream::Stdout);
-        let (highlight_start, highlight_end) = if use_colors {
-            ("\x1b[1;33m", "\x1b[0m") // Bold yellow
-        } else {
-            ("", "")
+    #[test]
+    fn test_equal_scores_are_ordered_by_path_and_line() {
+        let result = |path: &str, score: f32, start_line: usize| SearchResult {
+            path: PathBuf::from(path),
+            score,
+            snippet: String::new(),
+            chunk_type: None,
+            chunk_name: None,
+            start_line: Some(start_line),
+            end_line: None,
+            snippet_line: None,
+            fuzzy: false,
+            duplicates: Vec::new(),
         };
+        let mut results = [
+            result("b.rs", 1.0, 3),
+            result("a.rs", 1.0, 9),
+            result("c.rs", 2.0, 0),
+            result("a.rs", 1.0, 2),
+        ];
+        results.sort_by(SearchResult::rank_order);
+
+        let order: Vec<_> = results
+            .iter()
+            .map(|r| (r.path.to_str().unwrap(), r.start_line.unwrap()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![("c.rs", 0), ("a.rs", 2), ("a.rs", 9), ("b.rs", 3)]
+        );
+    }
 
-        let mut result = String::new();
-        let mut last_end = 0;
+    #[test]
+    fn test_group_by_file_collapses_overlapping_chunks() {
+        let result = |path: &str, score: f32, lines: (usize, usize)| SearchResult {
+            path: PathBuf::from(path),
+            score,
+            snippet: String::new(),
+            chunk_type: None,
+            chunk_name: None,
+            start_line: Some(lines.0),
+            end_line: Some(lines.1),
+            snippet_line: None,
+            fuzzy: false,
+            duplicates: Vec::new(),
+        };
+        let groups = group_by_file(vec![
+            result("a.rs", 2.0, (0, 40)),
+            result("a.rs", 3.0, (10, 20)),
+            result("a.rs", 1.0, (50, 60)),
+            result("b.rs", 3.0, (0, 5)),
+        ]);
+
+        let summary: Vec<_> = groups
+            .iter()
+            .map(|group| {
+                let lines: Vec<_> = group
+                    .results
+                    .iter()
+                    .map(|r| r.start_line.unwrap())
+                    .collect();
+                (group.path.to_str().unwrap(), lines)
+            })
+            .collect();
+        // The class around the better method is dropped, and two matches outrank one
+        assert_eq!(summary, vec![("a.rs", vec![10, 50]), ("b.rs", vec![0])]);
+        assert!(groups[0].score > 3.0 && groups[0].score < 4.0);
+        assert_eq!(groups[1].score, 3.0);
+    }
 
-        // Sort ranges by start position
-        let mut ranges: Vec<_> = highlighted_ranges.to_vec();
-        ranges.sort_by_key(|r| r.start);
+    #[test]
+    fn test_expand_field_shorthands() {
+        assert_eq!(
+            expand_field_shorthands("name:PaymentHandler"),
+            "chunk_name:PaymentHandler"
+        );
+        assert_eq!(
+            expand_field_shorthands("retry (name:send OR -name:log) filename:x"),
+            "retry (chunk_name:send OR -chunk_name:log) filename:x"
+        );
+        assert_eq!(
+            expand_field_shorthands("+type:class subtype:x"),
+            "+chunk_type:class subtype:x"
+        );
+    }
 
-        for range in ranges {
-            let abs_start = fragment_offset + range.start;
-            let abs_end = fragment_offset + range.end;
+    #[test]
+    fn test_extract_path_terms() {
+        let (rest, terms) =
+            extract_path_terms("retry path:Handlers -path:test +path:\"my dir\" path:*.js^2");
+        assert_eq!(rest.trim(), "retry");
+        assert_eq!(terms.include, vec!["handlers", "my dir", "*.js"]);
+        assert_eq!(terms.exclude, vec!["test"]);
+
+        // Terms inside groups and phrases stay part of the query
+        let query = "(path:a OR b) \"see path:b\" classpath:c";
+        assert_eq!(
+            extract_path_terms(query),
+            (query.to_string(), PathTerms::default())
+        );
 
-            // Skip ranges outside our segment
-            if abs_end < segment_start_offset {
-                continue;
-            }
-            if abs_start >= segment_start_offset + segment.len() {
-                break;
-            }
+        let (rest, terms) = extract_path_terms("path:handlers AND retry OR -path:Test -path:*.md");
+        assert_eq!(rest, "retry");
+        let filter = PathFilter::new(&SearchOptions::default())
+            .unwrap()
+            .with_terms(&terms)
+            .unwrap();
+        assert!(filter.matches("src/Handlers/Pay.java"));
+        assert!(!filter.matches("src/handlers/PayTest.java"));
+        assert!(!filter.matches("docs/handlers/README.MD"));
+        assert!(!filter.matches("src/Pay.java"));
+    }
 
-            // Adjust the range to be relative to our segment
-            let seg_start = abs_start.saturating_sub(segment_start_offset);
-            let seg_end = if abs_end > segment_start_offset {
-                (abs_end - segment_start_offset).min(segment.len())
-            } else {
-                continue;
-            };
+    #[test]
+    fn test_path_filter() {
+        let filter = PathFilter::new(&SearchOptions {
+            paths: vec!["src/**".to_string(), "*.md".to_string()],
+            exclude_paths: vec!["**/generated/**".to_string()],
+            ..SearchOptions::default()
+        })
+        .unwrap();
+        assert!(filter.matches("src/main.rs"));
+        assert!(filter.matches("src/nested/lib.rs"));
+        assert!(filter.matches("docs/guide.md"));
+        assert!(!filter.matches("tests/main.rs"));
+        assert!(!filter.matches("src/generated/api.rs"));
+        assert!(PathFilter::new(&SearchOptions::default())
+            .unwrap()
+            .is_empty());
+    }
 
-            // Add text before highlight
-            if seg_start > last_end {
-                result.push_str(&segment[last_end..seg_start]);
-            }
+    #[test]
+    fn test_path_filter_within_lines() {
+        let range = |path: &str, start_line, end_line| ChunkRange {
+            path: path.to_string(),
+            start_line,
+            end_line,
+        };
+        let filter = PathFilter::new(&SearchOptions {
+            within: vec![range("src/cart.rs", 10, 20), range("src/cart.rs", 40, 45)],
+            ..SearchOptions::default()
+        })
+        .unwrap();
+        assert!(!filter.is_empty());
+        assert!(filter.matches("src/cart.rs"));
+        assert!(!filter.matches("src/order.rs"));
+        assert!(filter.matches_lines("src/cart.rs", 15, 18));
+        assert!(filter.matches_lines("src/cart.rs", 0, 10));
+        assert!(filter.matches_lines("src/cart.rs", 45, 60));
+        assert!(!filter.matches_lines("src/cart.rs", 21, 39));
+        assert!(!filter.matches_lines("src/order.rs", 15, 18));
+    }
 
-            // Add highlighted text
-            result.push_str(highlight_start);
-            result.push_str(&segment[seg_start..seg_end]);
-            result.push_str(highlight_end);
+    #[test]
+    fn test_score_rules() {
+        let defaults = ScoreRules::default();
+        assert_eq!(defaults.apply(1.0, "src/FooTest.java", Some("Class")), 0.3);
+        assert_eq!(defaults.apply(1.0, "vendor/lib.rs", Some("Method")), 1.0);
+
+        let rules = ScoreRules::from_config(&ScoringConfig {
+            test_penalty: 1.0,
+            downrank_paths: vec![crate::config::PathWeight {
+                pattern: "vendor/**".to_string(),
+                factor: 0.25,
+            }],
+            ..ScoringConfig::default()
+        })
+        .unwrap();
+        assert!(rules
+            .penalties("src/FooTest.java", Some("Method"))
+            .is_empty());
+        assert_eq!(
+            rules.penalties("vendor/lib.rs", Some("Class")),
+            vec![
+                ("class, interface or struct chunk".to_string(), 0.6),
+                ("path matches \"vendor/**\"".to_string(), 0.25),
+            ]
+        );
+        assert_eq!(rules.apply(2.0, "src/vendor/lib.rs", None), 2.0);
+    }
 
-            last_end = seg_end;
-        }
+    #[test]
+    fn test_recency_boost() {
+        let ages = HashMap::from([
+            ("src/New.java".to_string(), 0.0),
+            ("src/Month.java".to_string(), 30.0),
+        ]);
+        let rules = ScoreRules::from_config(&ScoringConfig {
+            recency_half_life_days: Some(30.0),
+            ..ScoringConfig::default()
+        })
+        .unwrap()
+        .with_file_ages(ages.clone());
+        assert_eq!(rules.apply(1.0, "src/New.java", None), 2.0);
+        assert_eq!(
+            rules.penalties("src/Month.java", None),
+            vec![("changed 30 days ago".to_string(), 1.5)]
+        );
+        assert_eq!(rules.apply(1.0, "src/Old.java", None), 1.0);
 
-        // Add remaining text after last highlight
-        if last_end < segment.len() {
-            result.push_str(&segment[last_end..]);
-        }
+        // Without a half-life the ages change nothing
+        let rules = ScoreRules::default().with_file_ages(ages);
+        assert_eq!(rules.apply(1.0, "src/New.java", None), 1.0);
+    }
+
+    #[test]
+    fn test_truncate_snippet() {
+        let mut snippet = "first line\nsecond line\nthird".to_string();
+        truncate_snippet(&mut snippet, 15);
+        assert_eq!(snippet, "first line\n…");
+
+        // Never leaves a highlight open or cuts a multi-byte char
+        let mut snippet = "\x1b[1;33mgrößer\x1b[0m und größer".to_string();
+        truncate_snippet(&mut snippet, 12);
+        assert_eq!(snippet, "\x1b[1;33mgrö\x1b[0m\n…");
 
-        Ok(result)
+        let mut snippet = "short".to_string();
+        truncate_snippet(&mut snippet, 100);
+        assert_eq!(snippet, "short");
     }
 }
@@ -0,0 +1,269 @@
+use crate::search_engine::SearchEngine;
+use crate::search_index::{index_path, SearchOptions, SearchResult};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// How long a client may take to send its request before the connection is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often `probe serve` checks for Ctrl-C while waiting for connections
+pub(crate) const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Request line of an HTTP request. Headers and bodies are read past but not used.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl Request {
+    pub(crate) fn read<R: BufRead>(mut reader: R) -> Result<Self> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(anyhow!("Malformed request line: {:?}", line.trim_end()));
+        };
+
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+            header.clear();
+        }
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let params = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(name), percent_decode(value))
+            })
+            .collect();
+        Ok(Self {
+            method: method.to_string(),
+            path: percent_decode(path),
+            params,
+        })
+    }
+
+    /// First value of the query parameter `name`
+    pub(crate) fn param<'a>(&'a self, name: &str) -> Option<&'a str> {
+        self.values(name).next()
+    }
+
+    /// Every value of a query parameter that may be repeated, e.g. `kind=method&kind=class`
+    pub(crate) fn values<'a, 'n>(
+        &'a self,
+        name: &'n str,
+    ) -> impl Iterator<Item = &'a str> + use<'a, 'n> {
+        self.params
+            .iter()
+            .filter(move |(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Decodes `%XX` escapes and `+` as used in URLs and form-encoded query strings
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match (hex_digit(bytes[i + 1]), hex_digit(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        decoded.push((high << 4) | low);
+                        i += 2;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    char::from(byte).to_digit(16).map(|digit| digit as u8)
+}
+
+/// Answers one request on `stream` and returns its method, path and status for logging
+pub(crate) fn handle_connection(
+    engine: &SearchEngine,
+    mut stream: TcpStream,
+    defaults: &SearchOptions,
+) -> Result<String> {
+    // Accepted sockets inherit non-blocking mode from the listener on some platforms
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let request = match Request::read(BufReader::new(&stream)) {
+        Ok(request) => request,
+        Err(e) => {
+            write_json(&mut stream, 400, &json!({ "error": format!("{e:#}") }))?;
+            return Err(e);
+        }
+    };
+
+    let (status, body) = respond(engine, &request, defaults)
+        .unwrap_or_else(|e| (500, json!({ "error": format!("{e:#}") })));
+    write_json(&mut stream, status, &body)?;
+    Ok(format!("{} {} {}", request.method, request.path, status))
+}
+
+fn respond(
+    engine: &SearchEngine,
+    request: &Request,
+    defaults: &SearchOptions,
+) -> Result<(u16, Value)> {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/search") => search(engine, request, defaults),
+        ("GET", "/stats") => {
+            let stats = engine.index_stats()?;
+            Ok((
+                200,
+                json!({
+                    "files": stats.files,
+                    "skipped_files": stats.skipped_files,
+                    "commit": stats.commit,
+                    "commits_behind": stats.commits_behind,
+                    "index_dir": stats.index_dir,
+                }),
+            ))
+        }
+        ("POST", "/reindex") => {
+            engine.ensure_index_updated()?;
+            Ok((200, json!({ "files": engine.index_stats()?.files })))
+        }
+        (method, path @ ("/search" | "/stats" | "/reindex")) => Ok((
+            405,
+            json!({ "error": format!("{method} is not supported for {path}") }),
+        )),
+        (_, path) => Ok((404, json!({ "error": format!("No endpoint {path}") }))),
+    }
+}
+
+/// `GET /search?q=<query>[&limit=N][&offset=N][&filetype=ext][&kind=..][&path=..][&exclude_path=..]`
+fn search(
+    engine: &SearchEngine,
+    request: &Request,
+    defaults: &SearchOptions,
+) -> Result<(u16, Value)> {
+    let Some(query) = request.param("q") else {
+        return Ok((400, json!({ "error": "Missing query parameter q" })));
+    };
+    let mut options = defaults.clone();
+    if let Some(limit) = request.param("limit") {
+        match limit.parse() {
+            Ok(limit) => options.limit = limit,
+            Err(_) => return Ok((400, json!({ "error": format!("Invalid limit {limit:?}") }))),
+        }
+    }
+    if let Some(offset) = request.param("offset") {
+        match offset.parse() {
+            Ok(offset) => options.offset = offset,
+            Err(_) => {
+                return Ok((
+                    400,
+                    json!({ "error": format!("Invalid offset {offset:?}") }),
+                ))
+            }
+        }
+    }
+    if let Some(filetype) = request.param("filetype") {
+        options.filetype = Some(filetype.to_string());
+    }
+    options
+        .kinds
+        .extend(request.values("kind").map(str::to_lowercase));
+    options
+        .paths
+        .extend(request.values("path").map(str::to_string));
+    options
+        .exclude_paths
+        .extend(request.values("exclude_path").map(str::to_string));
+
+    let results = engine.search(query, &options)?;
+    Ok((
+        200,
+        json!({
+            "query": query,
+            "offset": options.offset,
+            "results": results.iter().map(result_json).collect::<Vec<_>>(),
+        }),
+    ))
+}
+
+/// A search result with 1-based line numbers, like the text output
+pub(crate) fn result_json(result: &SearchResult) -> Value {
+    json!({
+        "path": index_path(&result.path),
+        "score": result.score,
+        "start_line": result.start_line.map(|line| line + 1),
+        "end_line": result.end_line.map(|line| line + 1),
+        "chunk_type": result.chunk_type,
+        "chunk_name": result.chunk_name,
+        "snippet": result.snippet,
+        "snippet_line": result.snippet_line.map(|line| line + 1),
+        "fuzzy": result.fuzzy,
+        "duplicates": result.duplicates.iter().map(|path| index_path(path)).collect::<Vec<_>>(),
+    })
+}
+
+fn write_json(stream: &mut TcpStream, status: u16, body: &Value) -> Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_request() {
+        let request = Request::read(
+            &b"GET /search?q=retry+policy%3F&kind=method&kind=Class&limit=2 HTTP/1.1\r\nHost: localhost\r\n\r\n"[..],
+        )
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/search");
+        assert_eq!(request.param("q"), Some("retry policy?"));
+        assert_eq!(request.param("limit"), Some("2"));
+        assert_eq!(request.param("filetype"), None);
+        assert_eq!(
+            request.values("kind").collect::<Vec<_>>(),
+            vec!["method", "Class"]
+        );
+
+        assert!(Request::read(&b"\r\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b+c"), "a b c");
+        assert_eq!(percent_decode("%E2%9C%93"), "\u{2713}");
+        // Incomplete and invalid escapes are kept as they are
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+}
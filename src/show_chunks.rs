@@ -1,62 +1,375 @@
 use anyhow::Result;
-use std::fs;
+use globset::{Glob, GlobMatcher};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::path::Path;
 
-use probe::{CodeChunker, FileScanner};
+use probe::code_chunker::read_source;
+use probe::config::ChunkingConfig;
+use probe::{CodeChunk, CodeChunker, FileScanner, Verbosity};
 
-pub fn show_chunks_command(paths: Vec<String>) -> Result<()> {
-    let mut chunker = CodeChunker::new()?;
+const CHUNK_KINDS: &[&str] = &[
+    "function",
+    "method",
+    "class",
+    "struct",
+    "interface",
+    "module",
+    "other",
+];
+
+/// Language reported for files without a language processor
+const PLAIN_TEXT: &str = "plain text";
+
+/// Lowercases `kind` and checks that it names a chunk kind, for `--kind` filters
+pub fn parse_chunk_kind(kind: &str) -> Result<String> {
+    let kind = kind.to_lowercase();
+    if CHUNK_KINDS.contains(&kind.as_str()) {
+        Ok(kind)
+    } else {
+        Err(anyhow::anyhow!(
+            "Unknown chunk kind '{}', expected one of: {}",
+            kind,
+            CHUNK_KINDS.join(", ")
+        ))
+    }
+}
+
+/// Filters and output format for `probe show-chunks`
+#[derive(Debug, Default)]
+pub struct ShowChunksOptions {
+    /// Only show chunks of these kinds (e.g. `method`), case-insensitive
+    pub kinds: Vec<String>,
+    /// Only show chunks whose name matches this glob (e.g. `get*`)
+    pub name: Option<String>,
+    pub json: bool,
+    /// Print chunk statistics per file and per language instead of the chunks themselves
+    pub summary: bool,
+}
+
+/// Filters for `probe symbols`
+#[derive(Debug, Default)]
+pub struct SymbolsOptions {
+    /// Only list symbols of these kinds (e.g. `method`), case-insensitive
+    pub kinds: Vec<String>,
+    /// Only list symbols whose name starts with this, case-insensitive
+    pub prefix: Option<String>,
+}
+
+struct ChunkFilter {
+    kinds: Vec<String>,
+    name: Option<GlobMatcher>,
+}
+
+impl ChunkFilter {
+    fn new(options: &ShowChunksOptions) -> Result<Self> {
+        let kinds = options
+            .kinds
+            .iter()
+            .map(|kind| parse_chunk_kind(kind))
+            .collect::<Result<_>>()?;
+        let name = match &options.name {
+            Some(pattern) => Some(Glob::new(pattern)?.compile_matcher()),
+            None => None,
+        };
+        Ok(Self { kinds, name })
+    }
+
+    fn matches(&self, chunk: &CodeChunk) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&chunk_kind(chunk)))
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|matcher| matcher.is_match(&chunk.name))
+    }
+}
+
+#[derive(Serialize)]
+struct JsonFile {
+    path: String,
+    chunks: Vec<JsonChunk>,
+}
+
+/// Chunk counts and sizes for a file or a whole language
+#[derive(Debug, Default, Serialize)]
+struct ChunkStats {
+    files: usize,
+    chunks: usize,
+    average_lines: f64,
+    kinds: BTreeMap<String, usize>,
+    #[serde(skip)]
+    total_lines: usize,
+}
+
+impl ChunkStats {
+    fn add_file(&mut self, chunks: &[CodeChunk]) {
+        self.files += 1;
+        for chunk in chunks {
+            self.chunks += 1;
+            self.total_lines += chunk.end_line - chunk.start_line + 1;
+            *self.kinds.entry(chunk_kind(chunk)).or_default() += 1;
+        }
+        if self.chunks > 0 {
+            self.average_lines = self.total_lines as f64 / self.chunks as f64;
+        }
+    }
+
+    fn describe(&self) -> String {
+        let kinds = self
+            .kinds
+            .iter()
+            .map(|(kind, count)| format!("{count} {kind}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "{} chunks ({}), avg {:.1} lines",
+            self.chunks, kinds, self.average_lines
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct FileSummary {
+    path: String,
+    language: String,
+    #[serde(flatten)]
+    stats: ChunkStats,
+}
+
+#[derive(Default, Serialize)]
+struct Summary {
+    files: Vec<FileSummary>,
+    languages: BTreeMap<String, ChunkStats>,
+    /// Files indexed as a single whole-file chunk because no processor handles them
+    /// or the processor found nothing to extract
+    fallback_files: Vec<String>,
+}
+
+impl Summary {
+    fn add_file(
+        &mut self,
+        path: &Path,
+        language: Option<&str>,
+        all_chunks: &[CodeChunk],
+        chunks: &[CodeChunk],
+    ) {
+        let language = language.unwrap_or(PLAIN_TEXT).to_string();
+        if language == PLAIN_TEXT || all_chunks.is_empty() {
+            self.fallback_files.push(path.display().to_string());
+        }
+
+        let mut stats = ChunkStats::default();
+        stats.add_file(chunks);
+        self.languages
+            .entry(language.clone())
+            .or_default()
+            .add_file(chunks);
+        self.files.push(FileSummary {
+            path: path.display().to_string(),
+            language,
+            stats,
+        });
+    }
+
+    fn print(&self) {
+        for file in &self.files {
+            println!(
+                "{} [{}]: {}",
+                file.path,
+                file.language,
+                file.stats.describe()
+            );
+        }
+
+        println!();
+        println!("By language:");
+        for (language, stats) in &self.languages {
+            println!(
+                "  {}: {} files, {}",
+                language,
+                stats.files,
+                stats.describe()
+            );
+        }
+
+        if !self.fallback_files.is_empty() {
+            println!();
+            println!(
+                "Files without structural chunking ({}):",
+                self.fallback_files.len()
+            );
+            for path in &self.fallback_files {
+                println!("  {path}");
+            }
+        }
+    }
+}
+
+fn chunk_kind(chunk: &CodeChunk) -> String {
+    format!("{:?}", chunk.chunk_type).to_lowercase()
+}
+
+/// Chunk boundaries as reported by `--json`, with 1-based inclusive line numbers
+#[derive(Serialize)]
+struct JsonChunk {
+    kind: String,
+    name: String,
+    start_line: usize,
+    end_line: usize,
+}
+
+pub fn show_chunks_command(
+    paths: Vec<String>,
+    chunking: &ChunkingConfig,
+    verbosity: Verbosity,
+    options: &ShowChunksOptions,
+) -> Result<()> {
+    let mut chunker = CodeChunker::with_config(chunking)?.with_debug_trace(verbosity.is_debug());
+    let filter = ChunkFilter::new(options)?;
+    let mut json_files = Vec::new();
+    let mut summary = Summary::default();
+
+    let mut show_file = |path: &Path| -> Result<()> {
+        let content = read_source(path)?;
+        let all_chunks = chunker.chunk_code_for_indexing(path, &content)?;
+        let chunks: Vec<_> = all_chunks
+            .iter()
+            .filter(|chunk| filter.matches(chunk))
+            .cloned()
+            .collect();
+
+        if options.summary {
+            if !content.trim().is_empty() {
+                summary.add_file(path, chunker.language_for(path), &all_chunks, &chunks);
+            }
+            return Ok(());
+        }
+        if chunks.is_empty() {
+            return Ok(());
+        }
+        if options.json {
+            json_files.push(JsonFile {
+                path: path.display().to_string(),
+                chunks: chunks
+                    .iter()
+                    .map(|chunk| JsonChunk {
+                        kind: chunk_kind(chunk),
+                        name: chunk.name.clone(),
+                        start_line: chunk.start_line + 1,
+                        end_line: chunk.end_line + 1,
+                    })
+                    .collect(),
+            });
+        } else {
+            print_chunks(path, &chunks);
+        }
+        Ok(())
+    };
 
     if paths.is_empty() {
         // No paths provided, scan current directory
-        show_chunks_for_directory(Path::new("."), &mut chunker)?;
+        show_chunks_for_directory(Path::new("."), &mut show_file)?;
     } else {
         for path_str in paths {
             let path = Path::new(&path_str);
 
             if path.is_file() {
-                show_chunks_for_file(path, &mut chunker)?;
+                show_file(path)?;
             } else if path.is_dir() {
-                show_chunks_for_directory(path, &mut chunker)?;
+                show_chunks_for_directory(path, &mut show_file)?;
             } else {
                 eprintln!("Warning: '{path_str}' is not a valid file or directory");
             }
         }
     }
 
+    if options.summary {
+        if options.json {
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        } else {
+            summary.print();
+        }
+    } else if options.json {
+        println!("{}", serde_json::to_string_pretty(&json_files)?);
+    }
+
     Ok(())
 }
 
-fn show_chunks_for_file(file_path: &Path, chunker: &mut CodeChunker) -> Result<()> {
-    let content = fs::read_to_string(file_path)?;
-    let chunks = chunker.chunk_code_for_indexing(file_path, &content)?;
-
-    if !chunks.is_empty() {
-        println!("{}", file_path.display());
+/// Lists the named chunks of the files under `path` as `file:line kind name`, one per line
+/// with 1-based line numbers, like a tags file
+pub fn symbols_command(
+    path: &Path,
+    chunking: &ChunkingConfig,
+    verbosity: Verbosity,
+    options: &SymbolsOptions,
+) -> Result<()> {
+    let mut chunker = CodeChunker::with_config(chunking)?.with_debug_trace(verbosity.is_debug());
+    let filter = ChunkFilter::new(&ShowChunksOptions {
+        kinds: options.kinds.clone(),
+        ..ShowChunksOptions::default()
+    })?;
+    let prefix = options.prefix.as_deref().map(str::to_lowercase);
 
-        for (i, chunk) in chunks.iter().enumerate() {
-            if !chunk.declaration.is_empty() {
-                print!("{}", chunk.declaration);
+    let mut list_file = |file_path: &Path| -> Result<()> {
+        let content = read_source(file_path)?;
+        // Paths under the current directory are printed without the leading `./`
+        let display_path = file_path.strip_prefix(".").unwrap_or(file_path);
+        for chunk in chunker.chunk_code_for_indexing(file_path, &content)? {
+            let matches_prefix = prefix
+                .as_deref()
+                .is_none_or(|prefix| chunk.name.to_lowercase().starts_with(prefix));
+            if !chunk.name.is_empty() && matches_prefix && filter.matches(&chunk) {
+                println!(
+                    "{}:{} {} {}",
+                    display_path.display(),
+                    chunk.start_line + 1,
+                    chunk_kind(&chunk),
+                    chunk.name
+                );
             }
-            print!("{}", chunk.content);
+        }
+        Ok(())
+    };
 
-            if i < chunks.len() - 1 {
-                println!();
-                println!("-----");
-            }
+    if path.is_file() {
+        list_file(path)
+    } else if path.is_dir() {
+        show_chunks_for_directory(path, &mut list_file)
+    } else {
+        Err(anyhow::anyhow!(
+            "'{}' is not a valid file or directory",
+            path.display()
+        ))
+    }
+}
+
+fn print_chunks(file_path: &Path, chunks: &[CodeChunk]) {
+    println!("{}", file_path.display());
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        if !chunk.declaration.is_empty() {
+            print!("{}", chunk.declaration);
         }
+        print!("{}", chunk.content);
 
-        println!();
+        if i < chunks.len() - 1 {
+            println!();
+            println!("-----");
+        }
     }
 
-    Ok(())
+    println!();
 }
 
-fn show_chunks_for_directory(dir_path: &Path, chunker: &mut CodeChunker) -> Result<()> {
+fn show_chunks_for_directory(
+    dir_path: &Path,
+    show_file: &mut impl FnMut(&Path) -> Result<()>,
+) -> Result<()> {
     let scanner = FileScanner::new(dir_path);
 
     for file_path in scanner.iter_files() {
-        match show_chunks_for_file(file_path.as_path(), chunker) {
+        match show_file(file_path.as_path()) {
             Ok(()) => {}
             Err(e) => {
                 eprintln!("Error processing file '{}': {}", file_path.display(), e);
@@ -0,0 +1,246 @@
+use crate::terminal::{self, HIGHLIGHT, RESET};
+use std::ops::Range;
+use std::path::Path;
+
+const COMMENT: &str = "\x1b[90m"; // Gray
+const STRING: &str = "\x1b[32m"; // Green
+const KEYWORD: &str = "\x1b[35m"; // Magenta
+const NUMBER: &str = "\x1b[36m"; // Cyan
+
+/// Just enough of a language's lexical structure to color snippets, which are fragments of
+/// files that a parser can't make sense of on their own
+struct Syntax {
+    line_comment: &'static str,
+    block_comment: Option<(&'static str, &'static str)>,
+    /// Space-separated
+    keywords: &'static str,
+}
+
+const JAVA: Syntax = Syntax {
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    keywords: "abstract boolean break byte case catch char class continue default do double \
+         else enum extends false final finally float for if implements import instanceof \
+         int interface long new null package private protected public record return short \
+         static super switch synchronized this throw throws true try var void volatile \
+         while",
+};
+
+/// Languages with C-style comments and strings, colored with their most common keywords
+const C_FAMILY: Syntax = Syntax {
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    keywords: "async await break case catch class const continue default else enum export false \
+         fn for func function if impl import interface let match mut new null package pub \
+         return static struct switch this throw trait true try type use var void while",
+};
+
+const SHELL: Syntax = Syntax {
+    line_comment: "#",
+    block_comment: None,
+    keywords: "case do done elif else esac export fi for function if in local return then until \
+         while",
+};
+
+fn syntax_for(path: &Path) -> Option<&'static Syntax> {
+    match path.extension()?.to_str()? {
+        "java" => Some(&JAVA),
+        "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "go" | "js" | "jsx" | "ts" | "tsx" | "kt"
+        | "rs" | "scala" | "swift" => Some(&C_FAMILY),
+        "sh" | "bash" => Some(&SHELL),
+        _ => None,
+    }
+}
+
+/// Colors comments, strings, numbers and keywords of a snippet of the file at `path`, keeping
+/// its search match highlights on top. Returns the snippet unchanged when colors are off or
+/// the language isn't known.
+pub fn highlight_snippet(path: &Path, snippet: &str) -> String {
+    match syntax_for(path) {
+        Some(syntax) if terminal::use_colors() => {
+            let (text, matches) = strip_highlights(snippet);
+            render(&text, &matches, &tokens(syntax, &text))
+        }
+        _ => snippet.to_string(),
+    }
+}
+
+/// Removes escape sequences from `snippet`, returning the plain text and the byte ranges
+/// of it that were highlighted as matches
+pub fn strip_highlights(snippet: &str) -> (String, Vec<Range<usize>>) {
+    let mut text = String::with_capacity(snippet.len());
+    let mut matches = Vec::new();
+    let mut match_start = None;
+    let mut rest = snippet;
+    while let Some(escape) = rest.find('\x1b') {
+        text.push_str(&rest[..escape]);
+        let sequence_end = rest[escape..]
+            .find('m')
+            .map_or(rest.len(), |end| escape + end + 1);
+        match &rest[escape..sequence_end] {
+            HIGHLIGHT => match_start = Some(text.len()),
+            _ => {
+                if let Some(start) = match_start.take() {
+                    matches.push(start..text.len());
+                }
+            }
+        }
+        rest = &rest[sequence_end..];
+    }
+    text.push_str(rest);
+    if let Some(start) = match_start {
+        matches.push(start..text.len());
+    }
+    (text, matches)
+}
+
+/// Colored ranges of `text`, in order
+fn tokens(syntax: &Syntax, text: &str) -> Vec<(Range<usize>, &'static str)> {
+    let bytes = text.as_bytes();
+    let line_end = |from: usize| text[from..].find('\n').map_or(text.len(), |i| from + i);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &text[i..];
+        let word_start = i == 0 || !is_word_byte(bytes[i - 1]);
+        let end = if rest.starts_with(syntax.line_comment)
+            // `#` starts a shell comment only at the start of a word, unlike in `$#`
+            && (syntax.line_comment != "#" || i == 0 || bytes[i - 1].is_ascii_whitespace())
+        {
+            tokens.push((i..line_end(i), COMMENT));
+            line_end(i)
+        } else if let Some((open, close)) = syntax
+            .block_comment
+            .filter(|(open, _)| rest.starts_with(open))
+        {
+            let end = rest[open.len()..]
+                .find(close)
+                .map_or(text.len(), |end| i + open.len() + end + close.len());
+            tokens.push((i..end, COMMENT));
+            end
+        } else if bytes[i] == b'"' || bytes[i] == b'\'' {
+            let end = string_end(text, i);
+            tokens.push((i..end, STRING));
+            end
+        } else if word_start && is_word_byte(bytes[i]) {
+            let end = rest
+                .find(|c: char| !c.is_ascii() || !is_word_byte(c as u8))
+                .map_or(text.len(), |end| i + end);
+            let word = &text[i..end];
+            if bytes[i].is_ascii_digit() {
+                tokens.push((i..end, NUMBER));
+            } else if syntax
+                .keywords
+                .split_whitespace()
+                .any(|keyword| keyword == word)
+            {
+                tokens.push((i..end, KEYWORD));
+            }
+            end
+        } else {
+            i + rest.chars().next().map_or(1, char::len_utf8)
+        };
+        i = end;
+    }
+    tokens
+}
+
+fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// End of the string literal starting with the quote at `start`: after the closing quote,
+/// or at the end of the line for strings the snippet cuts off
+fn string_end(text: &str, start: usize) -> usize {
+    let quote = text.as_bytes()[start];
+    let mut escaped = false;
+    for (offset, byte) in text.as_bytes()[start + 1..].iter().enumerate() {
+        match byte {
+            b'\n' => return start + 1 + offset,
+            b'\\' if !escaped => escaped = true,
+            _ if *byte == quote && !escaped => return start + 2 + offset,
+            _ => escaped = false,
+        }
+    }
+    text.len()
+}
+
+/// Writes `text` with `tokens` colored and `matches` highlighted over them
+fn render(text: &str, matches: &[Range<usize>], tokens: &[(Range<usize>, &str)]) -> String {
+    let style_at = |offset: usize| {
+        if matches.iter().any(|range| range.contains(&offset)) {
+            Some(HIGHLIGHT)
+        } else {
+            tokens
+                .iter()
+                .find(|(range, _)| range.contains(&offset))
+                .map(|(_, color)| *color)
+        }
+    };
+
+    let mut result = String::with_capacity(text.len() * 2);
+    let mut current = None;
+    for (offset, c) in text.char_indices() {
+        let style = style_at(offset);
+        if style != current {
+            if current.is_some() {
+                result.push_str(RESET);
+            }
+            if let Some(style) = style {
+                result.push_str(style);
+            }
+            current = style;
+        }
+        result.push(c);
+    }
+    if current.is_some() {
+        result.push_str(RESET);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        let text = "return count + 42; // \"done\"\nString s = \"a\\\"b\";";
+        let colored: Vec<_> = tokens(&JAVA, text)
+            .into_iter()
+            .map(|(range, color)| (&text[range], color))
+            .collect();
+        assert_eq!(
+            colored,
+            vec![
+                ("return", KEYWORD),
+                ("42", NUMBER),
+                ("// \"done\"", COMMENT),
+                ("\"a\\\"b\"", STRING),
+            ]
+        );
+
+        // `$#` is the argument count, not a comment
+        let text = "echo $# # args";
+        let colored: Vec<_> = tokens(&SHELL, text)
+            .into_iter()
+            .map(|(range, color)| (&text[range], color))
+            .collect();
+        assert_eq!(colored, vec![("# args", COMMENT)]);
+    }
+
+    #[test]
+    fn test_match_highlights_layered_over_syntax() {
+        let snippet = format!("// find {HIGHLIGHT}needle{RESET} here\nint x;");
+        let (text, matches) = strip_highlights(&snippet);
+        assert_eq!(text, "// find needle here\nint x;");
+        assert_eq!(matches, vec![8..14]);
+
+        assert_eq!(
+            render(&text, &matches, &tokens(&JAVA, &text)),
+            format!(
+                "{COMMENT}// find {RESET}{HIGHLIGHT}needle{RESET}{COMMENT} here{RESET}\n{KEYWORD}int{RESET} x;"
+            )
+        );
+    }
+}
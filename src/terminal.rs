@@ -0,0 +1,93 @@
+use atty::Stream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Starts a search match highlight
+pub(crate) const HIGHLIGHT: &str = "\x1b[1;33m"; // Bold yellow
+/// Ends a highlight or color
+pub(crate) const RESET: &str = "\x1b[0m";
+
+static ANSI_SUPPORTED: AtomicBool = AtomicBool::new(true);
+static FORCE_COLORS: AtomicBool = AtomicBool::new(false);
+
+/// When output is colored, as chosen with `--color`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only when stdout is a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(choice: &str) -> anyhow::Result<Self> {
+        match choice {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            other => Err(anyhow::anyhow!(
+                "Unknown color choice '{other}', expected auto, always or never"
+            )),
+        }
+    }
+}
+
+/// Prepares the console for ANSI escape sequences. On Windows this turns on virtual terminal
+/// processing, and if the console doesn't support it colors are disabled for the process.
+pub fn init() {
+    #[cfg(windows)]
+    if enable_ansi_support::enable_ansi_support().is_err() {
+        ANSI_SUPPORTED.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Applies `--color` for the rest of the process
+pub fn set_color_choice(choice: ColorChoice) {
+    match choice {
+        ColorChoice::Auto => {}
+        ColorChoice::Always => FORCE_COLORS.store(true, Ordering::Relaxed),
+        ColorChoice::Never => disable_colors(),
+    }
+}
+
+/// Turns colors off for the rest of the process, for highlights that aren't written to
+/// the terminal
+pub fn disable_colors() {
+    ANSI_SUPPORTED.store(false, Ordering::Relaxed);
+}
+
+/// Whether highlights written to stdout should use ANSI colors
+pub fn use_colors() -> bool {
+    ANSI_SUPPORTED.load(Ordering::Relaxed)
+        && (FORCE_COLORS.load(Ordering::Relaxed) || atty::is(Stream::Stdout))
+}
+
+/// Formats a byte count for humans, e.g. 1.5 MB
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Formats how long ago something happened, e.g. 5 minutes ago
+pub fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    let (count, unit) = match seconds {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86_399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86_400, "day"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{count} {unit}{plural} ago")
+}
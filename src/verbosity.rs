@@ -0,0 +1,38 @@
+/// How much diagnostic output probe prints while indexing and searching.
+///
+/// Diagnostics beyond the default level always go to stderr so they never mix with results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// `--quiet`: no status messages at all, only results and errors
+    Quiet,
+    /// Regular status messages only
+    #[default]
+    Normal,
+    /// `-v`: changed files and timings
+    Verbose,
+    /// `-vv`: additionally traces every chunk produced by the chunker
+    Debug,
+}
+
+impl Verbosity {
+    /// Maps the number of `-v` flags given on the command line to a verbosity level
+    pub fn from_occurrences(count: u8) -> Self {
+        match count {
+            0 => Verbosity::Normal,
+            1 => Verbosity::Verbose,
+            _ => Verbosity::Debug,
+        }
+    }
+
+    pub fn is_quiet(self) -> bool {
+        self == Verbosity::Quiet
+    }
+
+    pub fn is_verbose(self) -> bool {
+        self >= Verbosity::Verbose
+    }
+
+    pub fn is_debug(self) -> bool {
+        self >= Verbosity::Debug
+    }
+}
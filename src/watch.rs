@@ -0,0 +1,245 @@
+use crate::file_scanner::{FileScanner, IndexedFile};
+use crate::paths::{path_key, simplify_path};
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Written into the index directory by `probe watch` and rewritten every `HEARTBEAT_INTERVAL`.
+/// While it is fresh, searches trust the watcher instead of scanning the tree themselves.
+pub const WATCH_LOCK: &str = "watch.lock";
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long the tree has to be quiet before a batch of changes is indexed, so that a save
+/// touching several files (or an editor's write-to-temp-and-rename) is indexed once
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Whether a `probe watch` process is currently keeping the index in `index_dir` up to date
+pub fn watcher_active(index_dir: &Path) -> bool {
+    std::fs::metadata(index_dir.join(WATCH_LOCK))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < HEARTBEAT_INTERVAL * 3)
+}
+
+/// Keeps `WATCH_LOCK` fresh while the watcher runs and removes it when dropped
+pub struct Heartbeat {
+    path: PathBuf,
+    last_beat: Instant,
+}
+
+impl Heartbeat {
+    pub fn start(index_dir: &Path) -> Result<Self> {
+        let path = index_dir.join(WATCH_LOCK);
+        std::fs::create_dir_all(index_dir)?;
+        std::fs::write(&path, std::process::id().to_string())?;
+        Ok(Self {
+            path,
+            last_beat: Instant::now(),
+        })
+    }
+
+    pub fn beat_if_due(&mut self) {
+        if self.last_beat.elapsed() >= HEARTBEAT_INTERVAL {
+            // A rebuild may swap the index directory out from under us, just try again later
+            let _ = std::fs::write(&self.path, std::process::id().to_string());
+            self.last_beat = Instant::now();
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The files a full scan would return, kept in sync with filesystem events by rescanning
+/// only the directories they happen in. Directories the scan doesn't descend into (ignored
+/// ones, `.git`, the index itself) are not tracked, so events inside them are dropped.
+pub struct WatchedTree {
+    root_dir: PathBuf,
+    /// Keyed by `path_key` of the relative path
+    files: HashMap<PathBuf, IndexedFile>,
+    /// Relative paths, the root being the empty path
+    dirs: HashSet<PathBuf>,
+}
+
+impl WatchedTree {
+    pub fn scan(scanner: &FileScanner, root_dir: &Path) -> Self {
+        let root_dir = simplify_path(root_dir);
+        let mut tree = Self {
+            root_dir: root_dir.clone(),
+            files: HashMap::new(),
+            dirs: HashSet::new(),
+        };
+        let (files, dirs) = scanner.scan_dir(&root_dir, true);
+        tree.insert(files, dirs);
+        tree
+    }
+
+    pub fn files(&self) -> Vec<IndexedFile> {
+        self.files.values().cloned().collect()
+    }
+
+    /// Rescans the directories containing `changed` paths (relative to the root) and
+    /// returns the files found in them, which are the ones that may need reindexing.
+    /// Files and directories that disappeared are dropped from the tree.
+    pub fn refresh(
+        &mut self,
+        scanner: &FileScanner,
+        changed: &HashSet<PathBuf>,
+    ) -> Vec<IndexedFile> {
+        // Parents come before their children, so a new directory is already known (and
+        // scanned in full) by the time the events inside it are looked at
+        let dirs: BTreeSet<&Path> = changed.iter().filter_map(|path| path.parent()).collect();
+        let mut candidates = HashMap::new();
+
+        for dir in dirs {
+            if !self.dirs.contains(dir) {
+                continue;
+            }
+            let disk_dir = self.root_dir.join(dir);
+            let (files, subdirs) = if disk_dir.is_dir() {
+                scanner.scan_dir(&disk_dir, false)
+            } else {
+                (Vec::new(), Vec::new())
+            };
+
+            self.files
+                .retain(|_, file| file.relative_path.parent() != Some(dir));
+            let subdirs: HashSet<PathBuf> = subdirs
+                .into_iter()
+                .filter(|subdir| subdir.parent() == Some(dir))
+                .collect();
+            // Removed, renamed or newly ignored directories take their contents with them
+            let gone: Vec<PathBuf> = self
+                .dirs
+                .iter()
+                .filter(|known| known.parent() == Some(dir) && !subdirs.contains(*known))
+                .cloned()
+                .collect();
+            for gone_dir in gone {
+                self.dirs.retain(|known| !known.starts_with(&gone_dir));
+                self.files
+                    .retain(|_, file| !file.relative_path.starts_with(&gone_dir));
+            }
+
+            for subdir in subdirs {
+                if !self.dirs.contains(&subdir) {
+                    let (files, dirs) = scanner.scan_dir(&self.root_dir.join(&subdir), true);
+                    candidates.extend(
+                        files
+                            .iter()
+                            .map(|file| (path_key(&file.relative_path), file.clone())),
+                    );
+                    self.insert(files, dirs);
+                }
+            }
+            candidates.extend(
+                files
+                    .iter()
+                    .map(|file| (path_key(&file.relative_path), file.clone())),
+            );
+            self.insert(files, Vec::new());
+        }
+
+        candidates.into_values().collect()
+    }
+
+    fn insert(&mut self, files: Vec<IndexedFile>, dirs: Vec<PathBuf>) {
+        self.dirs.extend(dirs);
+        self.files.extend(
+            files
+                .into_iter()
+                .map(|file| (path_key(&file.relative_path), file)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn relative_paths(files: &[IndexedFile]) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = files
+            .iter()
+            .map(|file| file.relative_path.clone())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_refresh_tracks_changed_directories() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        // .gitignore only applies inside a git repository
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("src/old")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("src/old/legacy.rs"), "fn legacy() {}").unwrap();
+
+        let scanner = FileScanner::new(root);
+        let mut tree = WatchedTree::scan(&scanner, root);
+        assert_eq!(
+            relative_paths(&tree.files()),
+            vec![
+                PathBuf::from(".gitignore"),
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/old/legacy.rs"),
+            ]
+        );
+
+        // A new directory with a file, a deleted directory and a change in an ignored one
+        fs::create_dir_all(root.join("src/new")).unwrap();
+        fs::write(root.join("src/new/feature.rs"), "fn feature() {}").unwrap();
+        fs::remove_dir_all(root.join("src/old")).unwrap();
+        fs::write(root.join("target/build.rs"), "fn build() {}").unwrap();
+        let changed: HashSet<PathBuf> = [
+            "src/new",
+            "src/new/feature.rs",
+            "src/old/legacy.rs",
+            "src/old",
+            "target/build.rs",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+
+        let candidates = tree.refresh(&scanner, &changed);
+        assert_eq!(
+            relative_paths(&candidates),
+            vec![
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/new/feature.rs")
+            ]
+        );
+        assert_eq!(
+            relative_paths(&tree.files()),
+            vec![
+                PathBuf::from(".gitignore"),
+                PathBuf::from("src/main.rs"),
+                PathBuf::from("src/new/feature.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_marks_watcher_active() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(!watcher_active(temp_dir.path()));
+
+        let heartbeat = Heartbeat::start(temp_dir.path()).unwrap();
+        assert!(watcher_active(temp_dir.path()));
+
+        drop(heartbeat);
+        assert!(!watcher_active(temp_dir.path()));
+    }
+}
@@ -95,89 +95,1283 @@ fn test_search_function_name() {
         .stdout(predicate::str::contains("lib.rs"));
 }
 
+#[test]
+fn test_multi_word_query_without_quotes() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    // Words given as separate arguments are joined into a single query
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "calculate",
+            "sum",
+            "-n",
+            "5",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs"))
+        .stderr(predicate::str::contains("'calculate sum'"));
+}
+
+#[test]
+fn test_query_from_stdin() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    // Query syntax characters in piped text must not break query parsing
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "-"])
+        .write_stdin("error: calculate_sum(a, b) failed\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs"));
+
+    // Piped input is also picked up when no query argument is given
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap()])
+        .write_stdin("calculate_sum")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs"));
+}
+
+#[test]
+fn test_alias_from_probe_yml() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    fs::write(
+        project_path.join("probe.yml"),
+        "stemming:\n  enabled: true\n  language: english\naliases:\n  json: \"-t json -n 1\"\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    // "json" expands to "-t json -n 1", so only config.json can match
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "json", "main"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("main.rs").not());
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "json", "database_url"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("config.json"));
+}
+
+#[test]
+fn test_run_recipe_with_parameters() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("PaymentHandler.java"),
+        "class PaymentHandler {\n    void handle() {\n        charge();\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        project_path.join("Checkout.java"),
+        "class Checkout {\n    void pay(PaymentHandler handler) {\n        handler.handle();\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        project_path.join("probe.yml"),
+        "stemming:\n  enabled: true\n  language: english\nrecipes:\n  handler-for: \"name:{0}Handler --kind class --no-rerank\"\n",
+    )
+    .unwrap();
+
+    // Only the class named PaymentHandler matches, not the method mentioning it
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "run",
+            "handler-for",
+            "Payment",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("PaymentHandler.java"))
+        .stdout(predicate::str::contains("Checkout.java").not());
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "run", "handler-for"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "uses {0} but only 0 argument(s) were given",
+        ));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "run", "missing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected one of: handler-for"));
+}
+
+#[test]
+fn test_show_chunks_filters_and_json() {
+    let temp_dir = TempDir::new().unwrap();
+    let java_file = temp_dir.path().join("Greeter.java");
+    fs::write(
+        &java_file,
+        "class Greeter {\n    void greet() {\n    }\n\n    void getName() {\n    }\n}\n",
+    )
+    .unwrap();
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "show-chunks",
+            "--json",
+            "--kind",
+            "method",
+            "--name",
+            "get*",
+        ])
+        .arg(&java_file)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let files: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let chunks = files[0]["chunks"].as_array().unwrap();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0]["kind"], "method");
+    assert_eq!(chunks[0]["name"], "getName");
+    assert_eq!(chunks[0]["start_line"], 5);
+    assert_eq!(chunks[0]["end_line"], 6);
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["show-chunks", "--kind", "lambda"])
+        .arg(&java_file)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown chunk kind 'lambda'"));
+}
+
+#[test]
+fn test_show_chunks_summary() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("Greeter.java"),
+        "class Greeter {\n    void greet() {\n    }\n\n    void getName() {\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(temp_dir.path().join("notes.txt"), "plain notes\n").unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["show-chunks", "--summary"])
+        .arg(temp_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "java: 1 files, 3 chunks (1 class, 2 method)",
+        ))
+        .stdout(predicate::str::contains(
+            "Files without structural chunking (1):",
+        ))
+        .stdout(predicate::str::contains("notes.txt"));
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["show-chunks", "--summary", "--json"])
+        .arg(temp_dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["languages"]["java"]["kinds"]["method"], 2);
+    assert_eq!(summary["fallback_files"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_symbols_lists_chunk_locations() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("Greeter.java"),
+        "class Greeter {\n    void greet() {\n    }\n\n    void getName() {\n    }\n}\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("symbols")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Greeter.java:1 class Greeter"))
+        .stdout(predicate::str::contains("Greeter.java:2 method greet"))
+        .stdout(predicate::str::contains("Greeter.java:5 method getName"));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .args([
+            "symbols",
+            "Greeter.java",
+            "--type",
+            "method",
+            "--prefix",
+            "GET",
+        ])
+        .assert()
+        .success()
+        .stdout("Greeter.java:5 method getName\n");
+}
+
+#[test]
+fn test_lsp_answers_workspace_symbols() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("Greeter.java"),
+        "class Greeter {\n    void greet() {\n    }\n}\n",
+    )
+    .unwrap();
+
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"workspace/symbol","params":{"query":"greet"}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"shutdown"}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ];
+    let input: String = messages
+        .iter()
+        .map(|message| format!("Content-Length: {}\r\n\r\n{message}", message.len()))
+        .collect();
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .current_dir(temp_dir.path())
+        .arg("lsp")
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // Only framed responses are written to stdout
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let responses: Vec<serde_json::Value> = stdout
+        .split("Content-Length: ")
+        .filter(|part| !part.is_empty())
+        .map(|part| serde_json::from_str(part.split_once("\r\n\r\n").unwrap().1).unwrap())
+        .collect();
+    assert_eq!(responses.len(), 3);
+    let symbols = responses[1]["result"].as_array().unwrap();
+    assert_eq!(symbols[0]["name"], "greet");
+    assert_eq!(symbols[0]["kind"], 6);
+    assert!(symbols[0]["location"]["uri"]
+        .as_str()
+        .unwrap()
+        .ends_with("/Greeter.java"));
+    assert_eq!(symbols[1]["name"], "Greeter");
+}
+
+#[test]
+fn test_color_always_highlights_syntax() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("Greeter.java"),
+        "class Greeter {\n    void greet() {\n        String greeting = \"hello\";\n    }\n}\n",
+    )
+    .unwrap();
+
+    let search = |color: &str| {
+        let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .unwrap()
+            .args([
+                "-d",
+                project_path.to_str().unwrap(),
+                "--no-rerank",
+                "--color",
+                color,
+                "greeting",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let colored = search("always");
+    assert!(colored.contains("\x1b[1;33mgreeting\x1b[0m"));
+    assert!(colored.contains("\x1b[32m\"hello\"\x1b[0m"));
+    assert!(!search("auto").contains('\x1b'));
+}
+
+#[test]
+fn test_snippet_lines_are_numbered() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("Greeter.java"),
+        "class Greeter {\n    void greet() {\n        String greeting = \"hello\";\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        project_path.join("notes.txt"),
+        "one\ntwo\nthree\nfour needle\nfive\nsix\nseven\n",
+    )
+    .unwrap();
+
+    let search = |args: &[&str]| {
+        let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .unwrap()
+            .args(["-d", project_path.to_str().unwrap(), "--no-rerank"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // The enclosing class is shown above the method without a number
+    let output = search(&["greeting"]);
+    assert!(output.contains(
+        "class Greeter {\n2:    void greet() {\n3:        String greeting = \"hello\";\n4:    }"
+    ));
+
+    let output = search(&["-C", "1", "needle"]);
+    assert!(output.contains("3:three\n4:four needle\n5:five\n"));
+    assert!(!output.contains("two"));
+}
+
+#[test]
+fn test_vimgrep_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("Greeter.java"),
+        "class Greeter {\n    void greet() {\n        String greeting = \"hello\";\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        project_path.join("notes.txt"),
+        "one\ntwo\nthree\nfour needle\n",
+    )
+    .unwrap();
+
+    let search = |query: &str| {
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .unwrap()
+            .args(["-d", project_path.to_str().unwrap(), "--no-rerank"])
+            .args(["--format", "vimgrep", "--color", "always", query])
+            .output()
+            .unwrap()
+    };
+
+    // One line per result at the first match, without colors or status messages
+    let output = search("greeting");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Greeter.java:3:16: String greeting = \"hello\";\n"));
+    assert!(!stdout.contains('\x1b'));
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("Found"));
+
+    let stdout = String::from_utf8(search("needle").stdout).unwrap();
+    assert!(stdout.contains("notes.txt:4:6: four needle\n"));
+}
+
+#[test]
+fn test_csv_and_ndjson_formats() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("Greeter.java"),
+        "class Greeter {\n    void greet() {\n        String greeting = \"hello, world\";\n    }\n}\n",
+    )
+    .unwrap();
+
+    let search = |format: &str, query: &str| {
+        let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .unwrap()
+            .args(["-d", project_path.to_str().unwrap(), "--no-rerank"])
+            .args(["--format", format, "--color", "always", query])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // A header row, then one record per result with the snippet quoted on one line
+    let csv = search("csv", "greeting");
+    let mut rows = csv.lines();
+    assert_eq!(
+        rows.next(),
+        Some("path,start_line,end_line,score,chunk_name,chunk_type,snippet")
+    );
+    let row = rows.next().unwrap();
+    assert!(row.starts_with("Greeter.java,"), "{row}");
+    assert!(
+        row.contains("String greeting = \"\"hello, world\"\";"),
+        "{row}"
+    );
+    assert!(!csv.contains('\x1b'));
+
+    let tsv = search("tsv", "greeting");
+    assert!(tsv.starts_with("path\tstart_line\t"));
+    assert!(tsv.contains("String greeting = \"hello, world\";"));
+
+    let ndjson = search("ndjson", "greeting");
+    let record: serde_json::Value = serde_json::from_str(ndjson.lines().next().unwrap()).unwrap();
+    assert_eq!(record["path"], "Greeter.java");
+    assert!(record["start_line"].as_u64().unwrap() >= 1);
+    assert!(record["score"].is_number());
+    assert!(record["snippet"]
+        .as_str()
+        .unwrap()
+        .contains("String greeting = \"hello, world\";"));
+
+    // Without results there is still a header to load
+    assert_eq!(
+        search("csv", "zzqqxx"),
+        "path,start_line,end_line,score,chunk_name,chunk_type,snippet\n"
+    );
+    assert_eq!(search("ndjson", "zzqqxx"), "");
+}
+
+#[test]
+fn test_verbose_ndjson_keeps_status_off_stdout() {
+    let temp_dir = copy_test_data_to_temp();
+
+    // The first search indexes the project, and -v reports it on stderr
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", temp_dir.path().to_str().unwrap(), "--no-rerank", "-v"])
+        .args(["--format", "ndjson", "calculate_sum"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Indexing"));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.is_empty());
+    for line in stdout.lines() {
+        let record: Result<serde_json::Value, _> = serde_json::from_str(line);
+        assert!(record.is_ok(), "not a JSON record: {line}");
+    }
+}
+
+#[test]
+fn test_expand_shows_neighboring_chunks() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("Cart.java"),
+        "class Cart {\n    void add(Item item) {\n        items.add(item);\n    }\n\n    void remove(Item item) {\n        items.remove(item);\n    }\n\n    void checkout() {\n        payments.charge(total());\n    }\n}\n",
+    )
+    .unwrap();
+
+    let search = |format: &str| {
+        let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .unwrap()
+            .args(["-d", project_path.to_str().unwrap(), "--no-rerank"])
+            .args(["--expand", "1", "--format", format, "payments"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let text = search("text");
+    assert!(text.contains("  ~ lines 6-8: method remove\n"), "{text}");
+    assert!(text.contains("7:        items.remove(item);"), "{text}");
+
+    let ndjson = search("ndjson");
+    let record: serde_json::Value = serde_json::from_str(ndjson.lines().next().unwrap()).unwrap();
+    assert_eq!(record["chunk_name"], "checkout");
+    assert_eq!(record["neighbors"][0]["chunk_name"], "remove");
+    assert_eq!(record["neighbors"][0]["start_line"], 6);
+}
+
+#[test]
+fn test_misspelled_query_falls_back_to_fuzzy_matches() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("Payments.java"),
+        "class Payments {\n    void processRefund(int amount) {\n    }\n}\n",
+    )
+    .unwrap();
+
+    let search = |args: &[&str]| {
+        Command::cargo_bin(env!("CARGO_PKG_NAME"))
+            .unwrap()
+            .args(["-d", project_path.to_str().unwrap(), "--no-rerank"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = search(&["refnud"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Payments.java (lines 2-3) [fuzzy match]"));
+
+    let output = search(&["--no-fuzzy", "refnud"]);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("No results found for 'refnud'"));
+}
+
+#[test]
+fn test_models_list_path_and_remove() {
+    let cache_dir = TempDir::new().unwrap();
+    let model_dir = cache_dir.path().join("models--org--small");
+    fs::create_dir_all(model_dir.join("blobs")).unwrap();
+    fs::write(model_dir.join("blobs/abc123"), vec![0u8; 2048]).unwrap();
+
+    let models = |args: &[&str]| {
+        let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        command
+            .env("FASTEMBED_CACHE_PATH", cache_dir.path())
+            .arg("models")
+            .args(args);
+        command
+    };
+
+    models(&["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("org/small\t2.0 KB"))
+        .stdout(predicate::str::contains("Total: 2.0 KB"));
+    models(&["path", "org/small"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("models--org--small"));
+
+    models(&["remove"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--all"));
+    models(&["remove", "org/small"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed org/small"));
+    assert!(!model_dir.exists());
+    models(&["remove", "org/small"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not downloaded"));
+    models(&["list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No models downloaded"));
+}
+
+#[test]
+fn test_bench_prints_json_report() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("Greeter.java"),
+        "class Greeter {\n    void greet() {\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(project_path.join("notes.txt"), "greet everyone\n").unwrap();
+
+    let output = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "--no-rerank",
+            "bench",
+            "--query",
+            "greet",
+            "--runs",
+            "3",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["files"], 2);
+    assert_eq!(report["stemming"], "english");
+    assert!(report["rebuild_ms"].as_f64().unwrap() > 0.0);
+    assert!(report["warm_query_ms"].as_f64().is_some());
+    assert!(report["rerank_ms"].is_null());
+}
+
+#[test]
+fn test_verify_reports_index_drift() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+    let greeter = project_path.join("Greeter.java");
+    fs::write(&greeter, "class Greeter {\n    void greet() {\n    }\n}\n").unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "verify"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Index matches the working tree."));
+
+    fs::write(
+        &greeter,
+        "class Greeter {\n    void greet() {\n    }\n\n    void wave() {\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(project_path.join("notes.txt"), "new notes\n").unwrap();
+    fs::remove_file(project_path.join("config.json")).unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "verify"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "stale: Greeter.java (2 chunks indexed, 3 in source)",
+        ))
+        .stdout(predicate::str::contains("missing: notes.txt"))
+        .stdout(predicate::str::contains("orphaned: config.json"));
+}
+
 #[test]
 fn test_search_json_content() {
     let temp_dir = copy_test_data_to_temp();
     let project_path = temp_dir.path();
 
-    // Rebuild index
+    // Rebuild index
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    // Search for "database_url" - should find config.json
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "database_url"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("config.json"));
+}
+
+#[test]
+fn test_search_subdirectory() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    // Rebuild index
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    // Search for "HashMap" - should find utils/helper.rs
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "HashMap"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("helper.rs"));
+}
+
+#[test]
+fn test_gitignore_respected() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    // Rebuild index
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    // Search for "binary" - should NOT find binary.exe (ignored by .gitignore)
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "binary"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("binary.exe").not());
+}
+
+#[test]
+fn test_gitignore_log_files() {
+    let temp_dir = copy_gitignore_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    // Rebuild index
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    // Search for "indexed" - should find source.rs but NOT ignored.log
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "indexed"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("source.rs"))
+        .stdout(predicate::str::contains("ignored.log").not());
+}
+
+#[test]
+fn test_probe_yml_ignore_globs_respected() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    fs::write(
+        project_path.join("probe.yml"),
+        "stemming:\n  enabled: true\n  language: english\nindex:\n  ignore:\n    - \"utils/**\"\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    // HashMap only appears in utils/helper.rs, which is excluded by probe.yml
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "HashMap"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("helper.rs").not());
+}
+
+#[test]
+fn test_profile_index_with_own_filters() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    fs::write(
+        project_path.join("probe.yml"),
+        "stemming:\n  enabled: true\n  language: english\nprofiles:\n  docs:\n    include:\n      - \"*.md\"\n    stemming:\n      enabled: false\n      language: english\n",
+    )
+    .unwrap();
+    let probe = |args: &[&str]| {
+        let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        command
+            .args(["-d", project_path.to_str().unwrap()])
+            .args(args);
+        command
+    };
+
+    probe(&["--profile", "docs", "rebuild"]).assert().success();
+    assert!(project_path.join(".probe/docs/meta.json").exists());
+
+    // The docs profile only indexes markdown files
+    probe(&["--profile", "docs", "HashMap"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("README.md"))
+        .stdout(predicate::str::contains("helper.rs").not());
+
+    // Rebuilding the main index keeps the profile's index inside it
+    probe(&["rebuild"]).assert().success();
+    assert!(project_path.join(".probe/docs/meta.json").exists());
+    probe(&["HashMap"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("helper.rs"));
+
+    probe(&["--profile", "code", "HashMap"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown profile 'code'"));
+}
+
+#[test]
+fn test_history_last_and_rerun() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+    let probe = |args: &[&str]| {
+        let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        command
+            .args(["-d", project_path.to_str().unwrap()])
+            .args(args);
+        command
+    };
+
+    probe(&["last"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No searches recorded yet"));
+
+    probe(&["--no-rerank", "HashMap"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("helper.rs"));
+    probe(&["--no-rerank", "-n", "1", "main"])
+        .assert()
+        .success();
+    assert!(project_path.join(".probe/history.json").exists());
+
+    probe(&["history"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("   1  "))
+        .stdout(predicate::str::contains("--no-rerank HashMap"))
+        .stdout(predicate::str::contains("--no-rerank -n 1 main"));
+
+    // The first search's results are shown again as they were found
+    probe(&["last", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("helper.rs"));
+
+    probe(&["!1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("helper.rs"))
+        .stderr(predicate::str::contains("probe -d"));
+    probe(&["history"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("   3  "));
+
+    // The history survives rebuilds
+    probe(&["rebuild"]).assert().success();
+    probe(&["last"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("helper.rs"));
+
+    probe(&["history", "--clear"]).assert().success();
+    probe(&["!9"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No search 9 in the history"));
+}
+
+#[test]
+fn test_refine_searches_previous_results() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+    let probe = |args: &[&str]| {
+        let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        command
+            .args(["-d", project_path.to_str().unwrap(), "--no-rerank"])
+            .args(args);
+        command
+    };
+
+    probe(&["--refine", "input"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No searches recorded yet"));
+
+    probe(&["input"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs"))
+        .stdout(predicate::str::contains("helper.rs"));
+
+    // Only the files found for calculate_sum are searched for input
+    probe(&["-n", "5", "calculate_sum"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("helper.rs").not());
+    probe(&["--refine", "input"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs"))
+        .stdout(predicate::str::contains("helper.rs").not());
+
+    probe(&["-n", "5", "nonexistentxyz"]).assert().success();
+    probe(&["--refine", "input"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("found nothing to refine"));
+}
+
+#[test]
+fn test_custom_index_dir() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+    let index_dir = TempDir::new().unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "--index-dir",
+            index_dir.path().to_str().unwrap(),
+            "rebuild",
+        ])
+        .assert()
+        .success();
+
+    assert!(index_dir.path().join("metadata.bin").exists());
+    assert!(!project_path.join(".probe").exists());
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "--index-dir",
+            index_dir.path().to_str().unwrap(),
+            "calculate_sum",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs"));
+}
+
+#[test]
+fn test_rebuild_swaps_in_staged_index() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
         .args(["-d", project_path.to_str().unwrap(), "rebuild"])
         .assert()
         .success();
 
-    // Search for "database_url" - should find config.json
+    // Leftovers of an interrupted rebuild are discarded rather than indexed or reused
+    let staging_dir = project_path.join(".probe.rebuild");
+    fs::create_dir_all(&staging_dir).unwrap();
+    fs::write(staging_dir.join("leftover.txt"), "calculate_sum").unwrap();
+
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(["-d", project_path.to_str().unwrap(), "database_url"])
+        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    assert!(project_path.join(".probe").join("metadata.bin").exists());
+    assert!(!staging_dir.exists());
+    assert!(!project_path.join(".probe.old").exists());
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "calculate_sum"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("config.json"));
+        .stdout(predicate::str::contains("lib.rs"))
+        .stdout(predicate::str::contains("leftover").not());
 }
 
 #[test]
-fn test_search_subdirectory() {
+fn test_rebuild_resume_requires_interrupted_rebuild() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild", "--resume"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No interrupted rebuild to resume"));
+}
+
+#[test]
+fn test_skipped_files_are_reported() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+    fs::write(project_path.join("bundle.min.js"), "x".repeat(9000)).unwrap();
+    fs::write(project_path.join("latin1.txt"), b"caf\xe9 calculate_sum").unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "rebuild", "-v"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Skipped 2 files (1 long lines, 1 not UTF-8)",
+        ))
+        .stderr(predicate::str::contains("skipped: bundle.min.js"));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "stats"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Skipped files: 2"));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "stats", "--skipped"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "bundle.min.js: has lines longer than 8096 bytes",
+        ))
+        .stdout(predicate::str::contains("latin1.txt: not valid UTF-8"));
+
+    // Fixing the file takes it off the report on the next update
+    fs::write(
+        project_path.join("latin1.txt"),
+        "cafe au lait calculate_sum",
+    )
+    .unwrap();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "calculate_sum"])
+        .assert()
+        .success();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", project_path.to_str().unwrap(), "stats", "--skipped"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("latin1.txt").not());
+}
+
+#[test]
+fn test_no_index_scans_the_tree_directly() {
+    let temp_dir = copy_test_data_to_temp();
+    let project_path = temp_dir.path();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "--no-index",
+            "calculate_sum",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs"))
+        .stderr(predicate::str::contains("Searched without an index"));
+
+    assert!(!project_path.join(".probe").exists());
+}
+
+#[test]
+fn test_dupes_reports_copied_methods() {
+    let temp_dir = TempDir::new().unwrap();
+    let method = |class: &str| {
+        format!(
+            "class {class} {{\n    int sumOfSquares(int[] values) {{\n        int sum = 0;\n        for (int value : values) {{\n            sum += value * value;\n        }}\n        return sum;\n    }}\n}}\n"
+        )
+    };
+    fs::write(temp_dir.path().join("Stats.java"), method("Stats")).unwrap();
+    fs::write(temp_dir.path().join("Copy.java"), method("Copy")).unwrap();
+    fs::write(
+        temp_dir.path().join("Greeter.java"),
+        "class Greeter {\n    String greet(String name) {\n        if (name == null) {\n            throw new IllegalArgumentException();\n        }\n        return \"Hello \" + name;\n    }\n}\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", temp_dir.path().to_str().unwrap(), "dupes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 similar chunks"))
+        .stdout(predicate::str::contains("Copy.java:2-8 sumOfSquares"))
+        .stdout(predicate::str::contains("Stats.java:2-8 sumOfSquares"))
+        .stdout(predicate::str::contains("Greeter").not());
+}
+
+#[test]
+fn test_outline_with_and_without_index() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = temp_dir.path().join("Shop.java");
+    fs::write(
+        &file,
+        "class Shop {\n    void open() {\n        run();\n    }\n\n    class Cart {\n        int total() {\n            return 0;\n        }\n    }\n}\n",
+    )
+    .unwrap();
+    let expected = "class Shop (lines 1-11)\n  method open (lines 2-4)\n  class Cart (lines 6-10)\n    method total (lines 7-9)\n";
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            temp_dir.path().to_str().unwrap(),
+            "outline",
+            file.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", temp_dir.path().to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            temp_dir.path().to_str().unwrap(),
+            "outline",
+            "Shop.java",
+        ])
+        .assert()
+        .success()
+        .stdout(expected);
+}
+
+#[test]
+fn test_graph_exports_calls_between_methods() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("Shop.java"),
+        "class Shop {\n    void open() {\n        new Cart().total();\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("Cart.java"),
+        "class Cart {\n    int total() {\n        return 0;\n    }\n}\n",
+    )
+    .unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", temp_dir.path().to_str().unwrap(), "graph"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("digraph calls {"))
+        .stdout(predicate::str::contains(
+            "\"Shop.java:Shop.open\" -> \"Cart.java:Cart.total\";",
+        ));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            temp_dir.path().to_str().unwrap(),
+            "graph",
+            "--format",
+            "json",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "\"from\": \"Shop.java:Shop.open\"",
+        ));
+}
+
+#[test]
+fn test_corrupted_index_is_quarantined_and_rebuilt() {
     let temp_dir = copy_test_data_to_temp();
     let project_path = temp_dir.path();
 
-    // Rebuild index
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
         .args(["-d", project_path.to_str().unwrap(), "rebuild"])
         .assert()
         .success();
 
-    // Search for "HashMap" - should find utils/helper.rs
+    fs::write(project_path.join(".probe").join("meta.json"), "{ truncated").unwrap();
+
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(["-d", project_path.to_str().unwrap(), "HashMap"])
+        .args(["-d", project_path.to_str().unwrap(), "calculate_sum"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("helper.rs"));
+        .stdout(predicate::str::contains("lib.rs"))
+        .stderr(predicate::str::contains("could not be read"));
+
+    assert!(project_path
+        .join(".probe.corrupt")
+        .join("meta.json")
+        .exists());
+    assert!(project_path.join(".probe").join("metadata.bin").exists());
 }
 
 #[test]
-fn test_gitignore_respected() {
+fn test_verbose_flag_reports_timings() {
     let temp_dir = copy_test_data_to_temp();
     let project_path = temp_dir.path();
 
-    // Rebuild index
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .args(["-d", project_path.to_str().unwrap(), "rebuild", "-v"])
         .assert()
-        .success();
+        .success()
+        .stderr(predicate::str::contains("Rebuild took"))
+        .stderr(predicate::str::contains("[chunker]").not());
 
-    // Search for "binary" - should NOT find binary.exe (ignored by .gitignore)
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(["-d", project_path.to_str().unwrap(), "binary"])
+        .args(["-d", project_path.to_str().unwrap(), "-vv", "rebuild"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("binary.exe").not());
+        .stderr(predicate::str::contains("[chunker]"));
 }
 
 #[test]
-fn test_gitignore_log_files() {
-    let temp_dir = copy_gitignore_test_data_to_temp();
+fn test_quiet_flag_suppresses_status_output() {
+    let temp_dir = copy_test_data_to_temp();
     let project_path = temp_dir.path();
 
-    // Rebuild index
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(["-d", project_path.to_str().unwrap(), "rebuild"])
+        .args(["-d", project_path.to_str().unwrap(), "--quiet", "rebuild"])
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    // Trigger an incremental update so indexing status would normally be printed
+    fs::write(
+        project_path.join("new_file.rs"),
+        "pub fn quiet_function() {}\n",
+    )
+    .unwrap();
 
-    // Search for "indexed" - should find source.rs but NOT ignored.log
     Command::cargo_bin(env!("CARGO_PKG_NAME"))
         .unwrap()
-        .args(["-d", project_path.to_str().unwrap(), "indexed"])
+        .args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "-q",
+            "--no-rerank",
+            "quiet_function",
+        ])
         .assert()
         .success()
-        .stdout(predicate::str::contains("source.rs"))
-        .stdout(predicate::str::contains("ignored.log").not());
+        .stdout(predicate::str::contains("new_file.rs"))
+        .stdout(predicate::str::contains("Indexing").not())
+        .stderr(predicate::str::is_empty());
 }
 
 #[test]
@@ -220,6 +1414,10 @@ fn test_stats_command() {
         .assert()
         .success()
         .stdout(predicate::str::contains("Files in index:"))
+        .stdout(predicate::str::contains("Chunks in index:"))
+        .stdout(predicate::str::contains("  by extension: "))
+        .stdout(predicate::str::contains("Last indexed: just now"))
+        .stdout(predicate::str::contains("Index size: "))
         .stdout(predicate::str::contains("Index directory:"));
 }
 
@@ -421,3 +1619,479 @@ fn test_reindexing_same_project_via_absolute_and_dot_paths_does_not_duplicate_re
         "Expected rendered paths to be stored relative to the project root.\nOutput:\n{stdout}"
     );
 }
+
+#[test]
+fn test_search_registered_repos() {
+    let config_dir = TempDir::new().unwrap();
+    let config_path = config_dir.path().join("config.yaml");
+    let shop = TempDir::new().unwrap();
+    fs::write(
+        shop.path().join("Cart.java"),
+        "class Cart {\n    int checkoutTotal() {\n        return 0;\n    }\n}\n",
+    )
+    .unwrap();
+    let billing = TempDir::new().unwrap();
+    fs::write(
+        billing.path().join("Invoice.java"),
+        "class Invoice {\n    void checkoutInvoice() {\n    }\n}\n",
+    )
+    .unwrap();
+
+    let probe = |args: &[&str]| {
+        let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        command.args(["--config", config_path.to_str().unwrap(), "--no-rerank"]);
+        command.args(args);
+        command
+    };
+
+    probe(&[
+        "repos",
+        "add",
+        shop.path().to_str().unwrap(),
+        "--name",
+        "shop",
+    ])
+    .assert()
+    .success();
+    probe(&[
+        "repos",
+        "add",
+        billing.path().to_str().unwrap(),
+        "--name",
+        "billing",
+    ])
+    .assert()
+    .success();
+    probe(&["repos", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("billing\t").and(predicate::str::contains("shop\t")));
+
+    probe(&["--repo", "shop", "checkout"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Cart.java"))
+        .stdout(predicate::str::contains("Invoice.java").not());
+
+    probe(&["--all-repos", "-n", "5", "checkout"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[shop] "))
+        .stdout(predicate::str::contains("Cart.java"))
+        .stdout(predicate::str::contains("[billing] "))
+        .stdout(predicate::str::contains("Invoice.java"));
+
+    // Directories given with -d are searched together like registered repositories
+    probe(&[
+        "-d",
+        shop.path().to_str().unwrap(),
+        "-d",
+        billing.path().to_str().unwrap(),
+        "-n",
+        "5",
+        "checkout",
+    ])
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("Cart.java"))
+    .stdout(predicate::str::contains("Invoice.java"));
+
+    probe(&["repos", "remove", "billing"]).assert().success();
+    probe(&["--repo", "billing", "checkout"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown repository 'billing'"));
+}
+
+#[test]
+fn test_search_several_directories() {
+    let shop = TempDir::new().unwrap();
+    fs::write(
+        shop.path().join("Cart.java"),
+        "class Cart {\n    int checkoutTotal() {\n        return 0;\n    }\n}\n",
+    )
+    .unwrap();
+    let billing = TempDir::new().unwrap();
+    fs::write(
+        billing.path().join("Invoice.java"),
+        "class Invoice {\n    void checkoutInvoice() {\n    }\n}\n",
+    )
+    .unwrap();
+    // A repository whose probe.yml can't be read can't be searched
+    let broken = TempDir::new().unwrap();
+    fs::write(broken.path().join("probe.yml"), "index: [unclosed\n").unwrap();
+    let (shop_dir, billing_dir, broken_dir) = (
+        shop.path().to_str().unwrap(),
+        billing.path().to_str().unwrap(),
+        broken.path().to_str().unwrap(),
+    );
+
+    // Results are labeled with the directory they were found in, and the broken repository
+    // only costs a warning
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", shop_dir, "-d", billing_dir, "-d", broken_dir])
+        .args(["--no-rerank", "-n", "5", "checkout"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("[{shop_dir}] {shop_dir}")))
+        .stdout(predicate::str::contains("Cart.java"))
+        .stdout(predicate::str::contains(format!(
+            "[{billing_dir}] {billing_dir}"
+        )))
+        .stdout(predicate::str::contains("Invoice.java"))
+        .stderr(predicate::str::contains(format!(
+            "Warning: failed to search repository '{broken_dir}'"
+        )));
+}
+
+#[test]
+fn test_search_path_filters() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    for path in [
+        "src/billing/Invoice.java",
+        "src/generated/InvoiceDto.java",
+        "tests/InvoiceTest.java",
+    ] {
+        let file = project_path.join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(
+            &file,
+            "class Invoice {\n    int invoiceTotal() { return 0; }\n}\n",
+        )
+        .unwrap();
+    }
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "--no-rerank",
+            "-n",
+            "10",
+            "--path",
+            "src/**",
+            "--exclude-path",
+            "**/generated/**",
+            "invoice",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Invoice.java"))
+        .stdout(predicate::str::contains("InvoiceDto.java").not())
+        .stdout(predicate::str::contains("InvoiceTest.java").not());
+}
+
+#[test]
+fn test_index_export_and_import() {
+    let original = copy_test_data_to_temp();
+    let archive = TempDir::new().unwrap();
+    let archive_path = archive.path().join("probe-index.tar.zst");
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", original.path().to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            original.path().to_str().unwrap(),
+            "index",
+            "export",
+            archive_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    // A fresh checkout has new modification times and one edited file
+    let checkout = copy_test_data_to_temp();
+    fs::write(
+        checkout.path().join("main.rs"),
+        "fn main() {\n    println!(\"edited after export\");\n}\n",
+    )
+    .unwrap();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            checkout.path().to_str().unwrap(),
+            "index",
+            "import",
+            archive_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("1 changed files will be indexed"));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", checkout.path().to_str().unwrap(), "stats", "--status"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("main.rs"))
+        .stdout(predicate::str::contains("lib.rs").not());
+
+    // Anything else is rejected before the current index is touched
+    fs::write(archive.path().join("bogus.tar.zst"), "not an archive").unwrap();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            checkout.path().to_str().unwrap(),
+            "index",
+            "import",
+            archive.path().join("bogus.tar.zst").to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not an index exported by"));
+}
+
+#[test]
+fn test_index_import_rewrites_path_prefix() {
+    // CI indexes the whole repository, the developer opens the project in `app/` only
+    let repository = TempDir::new().unwrap();
+    let app_dir = repository.path().join("app");
+    copy_dir_recursively(Path::new(get_test_data_path()), &app_dir).unwrap();
+    let archive = TempDir::new().unwrap();
+    let archive_path = archive.path().join("probe-index.tar.zst");
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", repository.path().to_str().unwrap(), "rebuild"])
+        .assert()
+        .success();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            repository.path().to_str().unwrap(),
+            "index",
+            "export",
+            archive_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let checkout = copy_test_data_to_temp();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            checkout.path().to_str().unwrap(),
+            "index",
+            "import",
+            archive_path.to_str().unwrap(),
+            "--rewrite-prefix",
+            "app/=",
+        ])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("it matches the working tree"));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", checkout.path().to_str().unwrap(), "calculate_sum"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs"))
+        .stdout(predicate::str::contains("app/").not());
+}
+
+#[test]
+fn test_index_import_drops_files_missing_locally() {
+    // CI indexed a generated file that the developer's checkout doesn't have
+    let original = copy_test_data_to_temp();
+    fs::write(
+        original.path().join("generated.rs"),
+        "pub fn generated_only_helper() {}\n",
+    )
+    .unwrap();
+    let archive = TempDir::new().unwrap();
+    let archive_path = archive.path().join("probe-index.tar.zst");
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            original.path().to_str().unwrap(),
+            "index",
+            "export",
+            archive_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    let checkout = copy_test_data_to_temp();
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            checkout.path().to_str().unwrap(),
+            "index",
+            "import",
+            archive_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Removed 1 files missing"))
+        .stderr(predicate::str::contains("it matches the working tree"));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            checkout.path().to_str().unwrap(),
+            "--no-rerank",
+            "generated_only_helper",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("generated.rs").not());
+}
+
+#[test]
+fn test_remote_index_is_fetched_on_first_use() {
+    let published = copy_test_data_to_temp();
+    let archive = TempDir::new().unwrap();
+    let archive_path = archive.path().join("nightly.tar.zst");
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            published.path().to_str().unwrap(),
+            "index",
+            "export",
+            archive_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    assert!(archive.path().join("nightly.tar.zst.sha256").exists());
+
+    let checkout = copy_test_data_to_temp();
+    fs::write(
+        checkout.path().join("probe.yml"),
+        format!(
+            "stemming:\n  enabled: true\n  language: english\nindex:\n  remote: '{}'\n",
+            archive_path.display()
+        ),
+    )
+    .unwrap();
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args([
+            "-d",
+            checkout.path().to_str().unwrap(),
+            "--no-rerank",
+            "calculate_sum",
+        ])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Downloading prebuilt index"))
+        .stdout(predicate::str::contains("lib.rs"));
+
+    Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["-d", checkout.path().to_str().unwrap(), "stats", "--status"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Index is up to date."));
+}
+
+#[test]
+fn test_why_explains_scores() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("PaymentService.java"),
+        "class PaymentService {\n    void processPayment() {\n        charge();\n    }\n\n    void refund() {\n        log();\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        project_path.join("PaymentServiceTest.java"),
+        "class PaymentServiceTest {\n    void testProcessPayment() {\n        service.processPayment();\n    }\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        project_path.join("Invoice.java"),
+        "class Invoice {\n    void refund() {\n        log();\n    }\n}\n",
+    )
+    .unwrap();
+
+    let why = |target: &str| {
+        let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        command.args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "--no-rerank",
+            "why",
+            "payment",
+            target,
+        ]);
+        command
+    };
+
+    // Line 2 is in processPayment, so only that chunk is explained
+    why("PaymentService.java:2")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "PaymentService.java:2-4 method processPayment",
+        ))
+        .stdout(predicate::str::contains("matched terms:"))
+        .stdout(predicate::str::contains("full-text rank: "))
+        .stdout(predicate::str::contains("refund").not());
+
+    // Method declarations include their class, so the query has to miss the class too
+    why("Invoice.java:2")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("does not match the query"));
+
+    why("PaymentServiceTest.java:2")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "penalty: x0.5 (path contains \"test\")",
+        ));
+
+    why("Missing.java")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Missing.java is not indexed"));
+}
+
+#[test]
+fn test_explain_prints_score_breakdown() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    fs::write(
+        project_path.join("PaymentServiceTest.java"),
+        "class PaymentServiceTest {\n    void testProcessPayment() {\n        service.processPayment();\n    }\n}\n",
+    )
+    .unwrap();
+
+    let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    command
+        .args([
+            "-d",
+            project_path.to_str().unwrap(),
+            "--no-rerank",
+            "--explain",
+            "-n",
+            "1",
+            "payment",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("matched terms:"))
+        .stdout(predicate::str::contains("full-text score: "))
+        .stdout(predicate::str::contains(
+            "penalty: x0.5 (path contains \"test\")",
+        ))
+        .stdout(predicate::str::contains("final score: "))
+        .stdout(predicate::str::contains("reranker score").not());
+}
@@ -1,4 +1,6 @@
-use probe::{search_index::SearchIndex, IndexedFile};
+use probe::config::QueryConfig;
+use probe::query_expansion::QueryExpansion;
+use probe::{search_index::SearchIndex, IndexedFile, SearchOptions};
 use std::fs;
 use tantivy::tokenizer::Language;
 use tempfile::TempDir;
@@ -122,3 +124,450 @@ fn test_tantivy_query_parsing() {
         }
     }
 }
+
+#[test]
+fn test_unicode_identifiers_and_comments() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    let mut index = SearchIndex::new(&index_dir, Language::English, true).unwrap();
+
+    let test_file = temp_dir.path().join("notes.txt");
+    fs::write(
+        &test_file,
+        "int berechneGröße(int breite) { return breite; } // 🚀\n// 缓存清理逻辑\n",
+    )
+    .unwrap();
+    index
+        .index_files(
+            [IndexedFile {
+                disk_path: test_file,
+                relative_path: "notes.txt".into(),
+            }],
+            1,
+        )
+        .unwrap()
+        .for_each(drop);
+
+    // Accented identifiers are split on case boundaries like ASCII ones
+    for query in ["größe", "Größe", "berechne"] {
+        assert_eq!(
+            index.search(query, 10, None, 3).unwrap().len(),
+            1,
+            "Should find the file for '{query}'"
+        );
+    }
+
+    // CJK text is searchable without spaces between words
+    assert_eq!(index.search("清理", 10, None, 3).unwrap().len(), 1);
+    assert!(index.search("rocket", 10, None, 3).unwrap().is_empty());
+}
+
+#[test]
+fn test_highlighting_multi_byte_text() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    let mut index = SearchIndex::new(&index_dir, Language::English, true).unwrap();
+
+    let java_file = temp_dir.path().join("Café.java");
+    fs::write(
+        &java_file,
+        "class Café {\n    // Überprüfung der Größe 😀 ✓\n    void prüfeGröße() {\n        String s = \"naïve résumé 😀 size\";\n    }\n}\n",
+    )
+    .unwrap();
+    let text_file = temp_dir.path().join("notes.txt");
+    fs::write(&text_file, "ééééé\n😀😀😀\n前言 size\nété\n日本語 size ✓\n").unwrap();
+
+    index
+        .index_files(
+            [
+                IndexedFile {
+                    disk_path: java_file,
+                    relative_path: "Café.java".into(),
+                },
+                IndexedFile {
+                    disk_path: text_file,
+                    relative_path: "notes.txt".into(),
+                },
+            ],
+            1,
+        )
+        .unwrap()
+        .for_each(drop);
+
+    for query in ["size", "größe", "résumé", "日本"] {
+        let results = index.search(query, 10, None, 1).unwrap();
+        assert!(!results.is_empty(), "Should find results for '{query}'");
+        for result in results {
+            assert!(!result.snippet.is_empty());
+        }
+    }
+}
+
+#[test]
+fn test_exact_and_regex_queries() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    let mut index = SearchIndex::new(&index_dir, Language::English, true).unwrap();
+
+    let files = [
+        ("ordered.txt", "retry the request later"),
+        ("shuffled.txt", "later, request the retry"),
+        ("getter.txt", "String getUserName() { return name; }"),
+    ];
+    for (name, content) in files {
+        fs::write(temp_dir.path().join(name), content).unwrap();
+    }
+    index
+        .index_files(
+            files.map(|(name, _)| IndexedFile {
+                disk_path: temp_dir.path().join(name),
+                relative_path: name.into(),
+            }),
+            1,
+        )
+        .unwrap()
+        .for_each(drop);
+
+    let options = SearchOptions {
+        limit: 10,
+        ..Default::default()
+    };
+    let paths = |results: Vec<probe::SearchResult>| {
+        let mut paths: Vec<_> = results
+            .into_iter()
+            .map(|result| result.path.to_string_lossy().into_owned())
+            .collect();
+        paths.sort();
+        paths
+    };
+
+    // Words match independently by default, exact queries only in order
+    assert_eq!(
+        index
+            .search("retry the request", 10, None, 3)
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(
+        paths(index.search_exact("retry the request", &options).unwrap()),
+        vec!["ordered.txt"]
+    );
+    assert_eq!(
+        paths(index.search_exact("Retry", &options).unwrap()),
+        vec!["ordered.txt", "shuffled.txt"]
+    );
+
+    // Regexes match single words, camelCase identifiers are split into their parts
+    assert_eq!(
+        paths(index.search_regex("req.*", &options).unwrap()),
+        vec!["ordered.txt", "shuffled.txt"]
+    );
+    assert_eq!(
+        paths(index.search_regex("User.*", &options).unwrap()),
+        vec!["getter.txt"]
+    );
+    assert!(index
+        .search_regex("getuser.*", &options)
+        .unwrap()
+        .is_empty());
+    assert!(index.search_regex("(unclosed", &options).is_err());
+}
+
+#[test]
+fn test_field_prefixes() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    let mut index = SearchIndex::new(&index_dir, Language::English, true).unwrap();
+
+    let files = [
+        (
+            "src/handlers/RetryHandler.java",
+            "class RetryHandler {\n    void retry() {\n    }\n}\n",
+        ),
+        (
+            "src/test/RetryTest.java",
+            "class RetryTest {\n    void testRetry() {\n    }\n}\n",
+        ),
+    ];
+    for (name, content) in files {
+        let path = temp_dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    index
+        .index_files(
+            files.map(|(name, _)| IndexedFile {
+                disk_path: temp_dir.path().join(name),
+                relative_path: name.into(),
+            }),
+            1,
+        )
+        .unwrap()
+        .for_each(drop);
+
+    let search = |index: &mut SearchIndex, query: &str| {
+        let mut found: Vec<_> = index
+            .search(query, 10, None, 3)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.chunk_name.unwrap_or_default())
+            .collect();
+        found.sort();
+        found
+    };
+    assert_eq!(
+        search(&mut index, "+type:class retry"),
+        vec!["RetryHandler", "RetryTest"]
+    );
+    assert_eq!(search(&mut index, "name:retry"), vec!["retry"]);
+    assert_eq!(
+        search(&mut index, "path:HANDLERS retry"),
+        vec!["RetryHandler", "retry"]
+    );
+    assert_eq!(
+        search(&mut index, "retry -path:test +type:method"),
+        vec!["retry"]
+    );
+    // Only a path filter lists every chunk of the matching files
+    assert_eq!(search(&mut index, "path:test").len(), 2);
+}
+
+#[test]
+fn test_search_iter_stops_early() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    let mut index = SearchIndex::new(&index_dir, Language::English, true).unwrap();
+
+    let names: Vec<_> = (0..5).map(|i| format!("needle{i}.txt")).collect();
+    for name in &names {
+        fs::write(temp_dir.path().join(name), "a needle in the haystack").unwrap();
+    }
+    index
+        .index_files(
+            names.iter().map(|name| IndexedFile {
+                disk_path: temp_dir.path().join(name),
+                relative_path: name.into(),
+            }),
+            1,
+        )
+        .unwrap()
+        .for_each(drop);
+
+    let options = SearchOptions {
+        limit: 10,
+        ..Default::default()
+    };
+    let first: Vec<_> = index
+        .search_iter("needle", &options)
+        .unwrap()
+        .take(2)
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+    assert_eq!(first.len(), 2);
+    assert!(first[0].snippet.contains("needle"));
+
+    let all = index.search_iter("needle", &options).unwrap().count();
+    assert_eq!(
+        all,
+        index.search_with_options("needle", &options).unwrap().len()
+    );
+}
+
+#[test]
+fn test_index_files_commits_in_batches() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    let mut index = SearchIndex::new(&index_dir, Language::English, true)
+        .unwrap()
+        .with_commit_batch_size(2);
+
+    let names: Vec<_> = (0..5).map(|i| format!("batch{i}.txt")).collect();
+    for name in &names {
+        fs::write(temp_dir.path().join(name), "one chunk per file").unwrap();
+    }
+    let files: Vec<_> = names
+        .iter()
+        .map(|name| IndexedFile {
+            disk_path: temp_dir.path().join(name),
+            relative_path: name.into(),
+        })
+        .collect();
+
+    assert_eq!(index.index_files(files.clone(), 1).unwrap().count(), 5);
+    let stats = index.segment_stats().unwrap();
+    assert_eq!(stats.docs, 5);
+    assert!(stats.segments > 1);
+
+    // Reindexing replaces the documents committed by earlier batches
+    assert_eq!(index.index_files(files, 1).unwrap().count(), 5);
+    assert_eq!(index.segment_stats().unwrap().docs, 5);
+}
+
+#[test]
+fn test_index_files_returns_skipped_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    let mut index = SearchIndex::new(&index_dir, Language::English, true).unwrap();
+
+    fs::write(temp_dir.path().join("notes.txt"), "plain text").unwrap();
+    fs::write(temp_dir.path().join("tool.bin"), b"\x7fELF\x02\x01\x00\x00").unwrap();
+    let files: Vec<_> = ["notes.txt", "tool.bin"]
+        .iter()
+        .map(|name| IndexedFile {
+            disk_path: temp_dir.path().join(name),
+            relative_path: name.into(),
+        })
+        .collect();
+
+    // Skipped files are returned too, so that callers can track them
+    let mut processed: Vec<_> = index
+        .index_files(files, 2)
+        .unwrap()
+        .map(|file| file.relative_path.to_string_lossy().into_owned())
+        .collect();
+    processed.sort();
+    assert_eq!(processed, vec!["notes.txt", "tool.bin"]);
+    let skipped: Vec<_> = index
+        .skipped_files()
+        .iter()
+        .map(|file| file.path.to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(skipped, vec!["tool.bin"]);
+}
+
+#[test]
+fn test_index_files_streams_under_memory_limit() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    // Leaves the smallest writer budget and one large file's text between commits
+    let mut index = SearchIndex::new(&index_dir, Language::English, true)
+        .unwrap()
+        .with_memory_limit(Some(1));
+
+    let line = "filler text that takes up room in the file\n";
+    let content = format!("needle\n{}", line.repeat(300 * 1024 / line.len()));
+    for i in 0..4 {
+        fs::write(temp_dir.path().join(format!("large{i}.txt")), &content).unwrap();
+    }
+    // Files come one at a time, without a known count
+    let files = fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .map(|disk_path| IndexedFile {
+            relative_path: disk_path.file_name().unwrap().into(),
+            disk_path,
+        });
+
+    assert_eq!(index.index_files(files, 2).unwrap().count(), 4);
+    assert!(index.segment_stats().unwrap().segments > 1);
+    let options = SearchOptions {
+        limit: 10,
+        ..Default::default()
+    };
+    assert_eq!(index.search_iter("needle", &options).unwrap().count(), 4);
+}
+
+#[test]
+fn test_query_expansion() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    let index = SearchIndex::new(&index_dir, Language::English, true).unwrap();
+    let mut index = index.with_query_expansion(QueryExpansion::from_config(&QueryConfig {
+        builtin_abbreviations: true,
+        ..Default::default()
+    }));
+
+    let files = [
+        (
+            "ConfigParser.java",
+            "class ConfigParser {\n    void read() {\n    }\n}\n",
+        ),
+        (
+            "DatabasePool.java",
+            "class DatabasePool {\n    void open() {\n    }\n}\n",
+        ),
+    ];
+    for (name, content) in files {
+        fs::write(temp_dir.path().join(name), content).unwrap();
+    }
+    index
+        .index_files(
+            files.map(|(name, _)| IndexedFile {
+                disk_path: temp_dir.path().join(name),
+                relative_path: name.into(),
+            }),
+            1,
+        )
+        .unwrap()
+        .for_each(drop);
+
+    let search = |index: &mut SearchIndex, query: &str, no_query_expansion: bool| {
+        let options = SearchOptions {
+            limit: 10,
+            no_query_expansion,
+            fuzzy_fallback: false,
+            ..Default::default()
+        };
+        let mut found: Vec<_> = index
+            .search_with_options(query, &options)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.path.display().to_string())
+            .collect();
+        found.sort();
+        found.dedup();
+        found
+    };
+
+    // The words of an identifier match in another order, abbreviations what they stand for
+    assert_eq!(
+        search(&mut index, "parseConfig", false),
+        vec!["ConfigParser.java"]
+    );
+    assert_eq!(search(&mut index, "db", false), vec!["DatabasePool.java"]);
+
+    assert!(search(&mut index, "parseConfig", true).is_empty());
+    assert!(search(&mut index, "db", true).is_empty());
+}
+
+#[test]
+fn test_doc_comments_are_searched() {
+    let temp_dir = TempDir::new().unwrap();
+    let index_dir = temp_dir.path().join("test_index");
+    let mut index = SearchIndex::new(&index_dir, Language::English, true).unwrap();
+
+    let content = indoc::indoc! {r#"
+        class Payments {
+            /**
+             * Sends the money back to the customer's card.
+             */
+            void reverse(Charge charge) {
+                gateway.cancel(charge.id);
+            }
+        }
+    "#};
+    fs::write(temp_dir.path().join("Payments.java"), content).unwrap();
+    index
+        .index_files(
+            vec![IndexedFile {
+                disk_path: temp_dir.path().join("Payments.java"),
+                relative_path: "Payments.java".into(),
+            }],
+            1,
+        )
+        .unwrap()
+        .for_each(drop);
+
+    // The JavaDoc of a method is neither in its declaration nor its body
+    let options = SearchOptions {
+        fuzzy_fallback: false,
+        ..Default::default()
+    };
+    let results = index
+        .search_with_options("customer money", &options)
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].chunk_name.as_deref(), Some("reverse"));
+}
@@ -1,9 +1,13 @@
-use probe::config::Config;
+use probe::config::{ChunkingConfig, Config};
 use probe::reranker::RerankerConfig;
 use probe::search_engine::SearchEngine;
+use probe::{ChunkType, CodeChunker, SearchOptions};
 use std::fs;
+use std::path::Path;
 use tempfile::TempDir;
 
+const JAVA_SOURCE: &str = "class Greeter {\n    void greet() {\n        hello();\n    }\n}\n";
+
 #[test]
 fn test_stemming_functionality() {
     let temp_dir = TempDir::new().unwrap();
@@ -107,6 +111,110 @@ stemming:
     fs::remove_file(&config_file).unwrap();
 }
 
+#[test]
+fn test_no_stem_query_override() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let test_file = temp_dir.path().join("test.java");
+    fs::write(
+        &test_file,
+        "public void handleCarriers() { List<String> runners = new ArrayList<>(); }",
+    )
+    .unwrap();
+
+    // Stemming is enabled in the project config (the default)
+    let engine = SearchEngine::new(temp_dir.path()).unwrap();
+    engine.rebuild_index().unwrap();
+
+    let reranker_config = RerankerConfig {
+        enabled: false,
+        ..Default::default()
+    };
+    let no_stem = SearchOptions {
+        limit: 10,
+        no_stem: true,
+        ..Default::default()
+    };
+
+    let results = engine
+        .search_with_options("carrier", &no_stem, reranker_config.clone())
+        .unwrap();
+    assert!(
+        results.is_empty(),
+        "Should not find 'carriers' when searching for 'carrier' with --no-stem"
+    );
+
+    let results = engine
+        .search_with_options("carriers", &no_stem, reranker_config.clone())
+        .unwrap();
+    assert!(!results.is_empty(), "Should find exact match 'carriers'");
+
+    // Without the override the configured stemming still applies
+    let results = engine
+        .search_with_options(
+            "carrier",
+            &SearchOptions {
+                limit: 10,
+                ..Default::default()
+            },
+            reranker_config,
+        )
+        .unwrap();
+    assert!(!results.is_empty());
+}
+
+#[test]
+fn test_stem_query_override() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let test_file = temp_dir.path().join("test.java");
+    fs::write(
+        &test_file,
+        "public void handleCarriers() { List<String> runners = new ArrayList<>(); }",
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("probe.yml"),
+        "stemming:\n  enabled: false\n  language: english\n",
+    )
+    .unwrap();
+
+    let engine = SearchEngine::new(temp_dir.path()).unwrap();
+    engine.rebuild_index().unwrap();
+
+    let reranker_config = RerankerConfig {
+        enabled: false,
+        ..Default::default()
+    };
+    let stem = SearchOptions {
+        limit: 10,
+        stem: true,
+        ..Default::default()
+    };
+
+    // Stemming is off in the project config, but the query turns it back on
+    let results = engine
+        .search_with_options("carrier", &stem, reranker_config.clone())
+        .unwrap();
+    assert!(
+        !results.is_empty(),
+        "Should find 'carriers' when searching for 'carrier' with --stem"
+    );
+    assert!(results[0].path.ends_with("test.java"));
+
+    let results = engine
+        .search_with_options(
+            "carrier",
+            &SearchOptions {
+                limit: 10,
+                ..Default::default()
+            },
+            reranker_config,
+        )
+        .unwrap();
+    assert!(results.is_empty());
+}
+
 #[test]
 fn test_config_loading() {
     let temp_dir = TempDir::new().unwrap();
@@ -204,3 +312,139 @@ stemming:
     // Clean up temp file
     fs::remove_file(&config_file).unwrap();
 }
+
+#[test]
+fn test_stemming_overrides_per_path() {
+    let temp_dir = TempDir::new().unwrap();
+
+    fs::create_dir_all(temp_dir.path().join("docs")).unwrap();
+    fs::write(
+        temp_dir.path().join("docs/guide.md"),
+        "Alle Zeitungen werden archiviert.\n",
+    )
+    .unwrap();
+    fs::write(
+        temp_dir.path().join("Test.java"),
+        "public void handleCarriers() { List<String> runners = new ArrayList<>(); }",
+    )
+    .unwrap();
+
+    let reranker_config = RerankerConfig {
+        enabled: false,
+        ..Default::default()
+    };
+
+    // Without overrides the German plural isn't reduced by the English stemmer
+    let engine = SearchEngine::new(temp_dir.path()).unwrap();
+    engine.ensure_index_updated().unwrap();
+    let results = engine
+        .search_with_reranker("Zeitung", Some(10), None, reranker_config.clone(), 3)
+        .unwrap();
+    assert!(results.is_empty());
+
+    fs::write(
+        temp_dir.path().join("probe.yml"),
+        r#"
+stemming:
+  enabled: true
+  language: english
+  overrides:
+    - pattern: "docs/**/*.md"
+      language: german
+"#,
+    )
+    .unwrap();
+
+    // The new language isn't in the existing index, so the next update rebuilds it
+    let engine = SearchEngine::new(temp_dir.path()).unwrap();
+    engine.ensure_index_updated().unwrap();
+
+    let results = engine
+        .search_with_reranker("Zeitung", Some(10), None, reranker_config.clone(), 3)
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].path, Path::new("docs/guide.md"));
+    assert!(results[0].snippet.contains("Zeitungen"));
+
+    // Files outside the pattern keep the default language
+    let results = engine
+        .search_with_reranker("carrier", Some(10), None, reranker_config, 3)
+        .unwrap();
+    assert!(!results.is_empty());
+    assert_eq!(results[0].path, Path::new("Test.java"));
+}
+
+#[test]
+fn test_stemming_override_rejects_unknown_language() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("probe.yml"),
+        r#"
+stemming:
+  enabled: true
+  language: english
+  overrides:
+    - pattern: "*.md"
+      language: klingon
+"#,
+    )
+    .unwrap();
+
+    assert!(SearchEngine::new(temp_dir.path()).is_err());
+}
+
+#[test]
+fn test_chunking_config_disables_language() {
+    let config_content = r#"
+stemming:
+  enabled: true
+  language: english
+chunking:
+  disabled_languages: [java]
+"#;
+    let config: Config = serde_yaml::from_str(config_content).unwrap();
+    let mut chunker = CodeChunker::with_config(&config.chunking).unwrap();
+
+    let chunks = chunker
+        .chunk_code_for_indexing(Path::new("Greeter.java"), JAVA_SOURCE)
+        .unwrap();
+
+    assert_eq!(
+        chunks.len(),
+        1,
+        "Disabled language should fall back to a single file chunk"
+    );
+    assert_eq!(chunks[0].chunk_type, ChunkType::Other);
+}
+
+#[test]
+fn test_chunking_config_maps_extra_extension() {
+    let config_content = r#"
+stemming:
+  enabled: true
+  language: english
+chunking:
+  extensions:
+    .jav: java
+"#;
+    let config: Config = serde_yaml::from_str(config_content).unwrap();
+    let mut chunker = CodeChunker::with_config(&config.chunking).unwrap();
+
+    let chunks = chunker
+        .chunk_code_for_indexing(Path::new("Greeter.jav"), JAVA_SOURCE)
+        .unwrap();
+
+    assert!(chunks
+        .iter()
+        .any(|chunk| chunk.chunk_type == ChunkType::Method && chunk.name == "greet"));
+}
+
+#[test]
+fn test_chunking_config_rejects_unknown_language() {
+    let mut chunking = ChunkingConfig::default();
+    chunking
+        .extensions
+        .insert("pyx".to_string(), "cobol".to_string());
+
+    assert!(CodeChunker::with_config(&chunking).is_err());
+}
@@ -77,9 +77,9 @@ if __name__ == "__main__":
         "Should contain the matched term"
     );
 
-    // Should include some context lines
+    // Should include numbered context lines around the first match, `numbers` on line 30
     assert!(
-        output_str.contains("def") || output_str.contains("result"),
+        output_str.contains("27:    a = 10") && output_str.contains("32:    print"),
         "Should show context lines around the match"
     );
 }
@@ -177,7 +177,7 @@ fn test_file_size_limit() {
         .assert()
         .success();
 
-    let output_str = String::from_utf8_lossy(&output.get_output().stdout);
+    let output_str = String::from_utf8_lossy(&output.get_output().stderr);
 
     // The large file should be skipped silently (no error message)
     // Only the normal file should be indexed
@@ -205,7 +205,7 @@ fn test_long_line_limit() {
         .assert()
         .success();
 
-    let output_str = String::from_utf8_lossy(&output.get_output().stdout);
+    let output_str = String::from_utf8_lossy(&output.get_output().stderr);
 
     // The long line file should be skipped silently
     // Only the normal file should be indexed